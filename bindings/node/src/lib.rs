@@ -0,0 +1,92 @@
+//! Node.js bindings for payback built with napi-rs.
+//! Instances and plans are passed in the same csv formats as to the command line interface.
+use napi::{Error, Result};
+use napi_derive::napi;
+
+use payback::graph::Graph;
+use payback::graph_parser::{deserialize_plan, parse_instance, ParseOptions};
+use payback::probleminstance::{ProblemInstance, SolvingMethods};
+use payback::verification::verify_plan;
+
+/// Person of an instance with the amount they receive (positive) or pay (negative).
+#[napi(object)]
+pub struct Person {
+    pub name: String,
+    pub weight: i64,
+}
+
+/// Payment of `amount` from `from` to `to`.
+#[napi(object)]
+pub struct Transaction {
+    pub from: String,
+    pub to: String,
+    pub amount: i64,
+}
+
+/// Result of `verify`.
+#[napi(object)]
+pub struct VerificationReport {
+    pub settled: bool,
+    pub transactions: u32,
+    pub lower_bound: u32,
+    pub unsettled: Vec<Person>,
+}
+
+fn to_graph(instance: String) -> Result<Graph> {
+    parse_instance(instance.as_bytes(), ParseOptions::default())
+        .map_err(|e| Error::from_reason(e.to_string()))
+}
+
+/// Parses an instance in one of the csv formats and returns the balance of every person.
+#[napi]
+pub fn parse(instance: String) -> Result<Vec<Person>> {
+    Ok(to_graph(instance)?
+        .balances()
+        .into_iter()
+        .map(|(name, weight)| Person { name, weight })
+        .collect())
+}
+
+/// Solves an instance in one of the csv formats with the given method, e.g.
+/// 'dp-greedy-satisfaction'. Defaults to 'approx-star-expand'.
+#[napi]
+pub fn solve(instance: String, method: Option<String>) -> Result<Vec<Transaction>> {
+    let method = match method {
+        Some(m) => m.parse::<SolvingMethods>().map_err(Error::from_reason)?,
+        None => SolvingMethods::ApproxStarExpand,
+    };
+    let instance = ProblemInstance::from(to_graph(instance)?);
+    instance
+        .solve_with(method)
+        .map(|settlement| {
+            settlement
+                .into_iter()
+                .map(|t| Transaction {
+                    from: t.payer,
+                    to: t.payee,
+                    amount: t.amount,
+                })
+                .collect()
+        })
+        .ok_or(Error::from_reason("No result was found."))
+}
+
+/// Checks if the plan settles the instance. The plan is either csv with
+/// 'NodeNameFrom,NodeNameTo,amount' records or a json list of objects with the keys 'from', 'to'
+/// and 'amount'.
+#[napi]
+pub fn verify(instance: String, plan: String) -> Result<VerificationReport> {
+    let graph = to_graph(instance)?;
+    let plan = deserialize_plan(&plan).map_err(Error::from_reason)?;
+    let report = verify_plan(&graph, &plan);
+    Ok(VerificationReport {
+        settled: report.is_settled(),
+        transactions: report.transactions as u32,
+        lower_bound: report.lower_bound as u32,
+        unsettled: report
+            .unsettled
+            .into_iter()
+            .map(|(name, weight)| Person { name, weight })
+            .collect(),
+    })
+}
@@ -0,0 +1,128 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use tracing::debug;
+
+use crate::graph::Graph;
+
+/// Options for [`anonymize()`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AnonymizeOptions {
+    /// Salt mixed into the pseudonyms, so they can't be guessed by hashing common names.
+    /// The same name and salt always give the same pseudonym.
+    pub salt: String,
+    /// Maximal change of every weight. Without it, the weights are kept.
+    pub perturb: Option<i64>,
+    /// Seed of the random number generator for perturbing the weights. Without a seed, a random
+    /// one is chosen.
+    pub seed: Option<u64>,
+}
+
+/// 64 bit FNV-1a hash with the finalizer of SplitMix64, so similar names get unrelated hashes.
+/// Opposed to the hasher of the standard library, it is stable across platforms and releases.
+fn stable_hash(data: &[u8]) -> u64 {
+    let mut hash = data.iter().fold(0xcbf29ce484222325, |hash: u64, b| {
+        (hash ^ u64::from(*b)).wrapping_mul(0x100000001b3)
+    });
+    hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d049bb133111eb);
+    hash ^ (hash >> 31)
+}
+
+/// Stable pseudonym of a name like 'p-1a2b3c4d'.
+pub fn pseudonym(name: &str, salt: &str) -> String {
+    let hash = stable_hash(format!("{}\0{}", salt, name).as_bytes());
+    format!("p-{:08x}", hash >> 32)
+}
+
+/// Replaces the names of all people by stable pseudonyms and optionally perturbs their weights.
+/// The perturbation moves amounts between neighbouring people, so the weights still add up to the
+/// same sum and solvable instances stay solvable. Every weight changes by at most twice
+/// [`AnonymizeOptions::perturb`].
+///
+/// Returns the anonymized graph and every name with its pseudonym.
+///
+/// Example:
+/// ```
+/// use payback::anonymize::{anonymize, AnonymizeOptions};
+/// use payback::graph::Graph;
+///
+/// let graph = Graph::from(vec![("Alice".to_string(), -3), ("Bob".to_string(), 3)]);
+/// let options = AnonymizeOptions { perturb: Some(2), seed: Some(1), ..Default::default() };
+/// let (anonymized, mapping) = anonymize(&graph, &options);
+/// assert_eq!(anonymized.balances().iter().map(|(_, w)| w).sum::<i64>(), 0);
+/// assert!(anonymized.balances().iter().all(|(n, _)| n.starts_with("p-")));
+/// assert_eq!(mapping.len(), 2);
+/// ```
+pub fn anonymize(graph: &Graph, options: &AnonymizeOptions) -> (Graph, Vec<(String, String)>) {
+    let mut balances = graph.balances();
+    let mut mapping: Vec<(String, String)> = vec![];
+    for (name, _) in balances.iter_mut() {
+        let mut alias = pseudonym(name, &options.salt);
+        // Collisions are very unlikely, but would merge people.
+        while mapping.iter().any(|(_, a)| *a == alias) {
+            alias = pseudonym(&alias, &options.salt);
+        }
+        mapping.push((name.to_owned(), alias.to_owned()));
+        *name = alias;
+    }
+    if let Some(max) = options.perturb.filter(|m| *m > 0) {
+        let mut rng = match options.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        let n = balances.len();
+        for i in 0..n.saturating_sub(1) {
+            let delta = rng.gen_range(-max..=max);
+            balances[i].1 += delta;
+            balances[(i + 1) % n].1 -= delta;
+        }
+    }
+    debug!("Anonymized {} people", mapping.len());
+    (Graph::from(balances), mapping)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::anonymize::{anonymize, pseudonym, AnonymizeOptions};
+    use crate::graph::Graph;
+    use tracing::debug;
+    use tracing_subscriber::EnvFilter;
+
+    fn init() {
+        let _ = tracing_subscriber::fmt()
+            .with_env_filter(
+                EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("debug")),
+            )
+            .with_test_writer()
+            .try_init();
+    }
+
+    #[test]
+    fn test_anonymize() {
+        init();
+        debug!("Running 'test_anonymize'");
+        assert_eq!(pseudonym("Alice", ""), pseudonym("Alice", ""));
+        assert_ne!(pseudonym("Alice", ""), pseudonym("Alice", "salt"));
+        assert_ne!(pseudonym("Alice", ""), pseudonym("Bob", ""));
+
+        let graph: Graph = "A,B,5\nB,C,3\nC,D,1".to_string().try_into().unwrap();
+        let (anonymized, mapping) = anonymize(&graph, &AnonymizeOptions::default());
+        let mut original: Vec<i64> = graph.balances().iter().map(|(_, w)| *w).collect();
+        let mut weights: Vec<i64> = anonymized.balances().iter().map(|(_, w)| *w).collect();
+        original.sort();
+        weights.sort();
+        assert_eq!(original, weights);
+        assert!(mapping.iter().all(|(n, a)| *a == pseudonym(n, "")));
+
+        let options = AnonymizeOptions {
+            perturb: Some(3),
+            seed: Some(42),
+            ..Default::default()
+        };
+        let (perturbed, _) = anonymize(&graph, &options);
+        assert_eq!(perturbed.balances().iter().map(|(_, w)| w).sum::<i64>(), 0);
+        for ((_, w), (_, p)) in graph.balances().iter().zip(perturbed.balances()) {
+            assert!((w - p).abs() <= 6);
+        }
+    }
+}
@@ -1,8 +1,8 @@
-use log::debug;
-use std::collections::HashMap;
+use std::collections::BTreeMap;
+use tracing::{debug, instrument, trace};
 
 use crate::graph::{Edge, NamedNode};
-use crate::probleminstance::{ProblemInstance, Solution};
+use crate::probleminstance::{EdgeSolution, ProblemInstance};
 
 /// Algorithm approximating the payback problem by building a tree.
 /// Has a approximation factor of 2. The proposed solution has at most twice as many edges as the
@@ -12,21 +12,19 @@ use crate::probleminstance::{ProblemInstance, Solution};
 ///
 /// * `instance` - The problem instance which should be solved
 /// * `approx_solver` - Approximation algorithm used to solve partition, which have no zero sum
-/// subset
+///   subset
 ///
 /// Example:
 /// ```
 /// use payback::graph::Graph;
-/// use payback::probleminstance::{ProblemInstance, Solution, SolvingMethods};
+/// use payback::probleminstance::{ProblemInstance, SolvingMethods};
 ///
 /// let instance: ProblemInstance = Graph::from(vec![-2, -1, 1, 2]).into();
-/// let solution: Solution = instance.solve_with(SolvingMethods::ApproxStarExpand);
+/// let solution = instance.solve_with(SolvingMethods::ApproxStarExpand);
 /// ```
-pub(crate) fn star_expand(instance: &ProblemInstance) -> Solution {
-    debug!(
-        "Running 'star_expand' for graph: {:?}",
-        instance.g.to_string()
-    );
+#[instrument(level = "debug", skip_all, fields(vertices = instance.g.vertices.len()))]
+pub(crate) fn star_expand(instance: &ProblemInstance) -> EdgeSolution {
+    trace!(graph = %instance.g, "Running 'star_expand'");
     if !instance.is_solvable() {
         None
     } else {
@@ -35,29 +33,26 @@ pub(crate) fn star_expand(instance: &ProblemInstance) -> Solution {
         match v_max {
             None => None,
             Some(v) => {
-                let edges: HashMap<Edge, f64> = instance
+                let edges: BTreeMap<Edge, i64> = instance
                     .g
                     .vertices
                     .iter()
-                    .filter(|u| u != &v)
+                    .filter(|u| u != &v && u.weight != 0)
                     .map(|u| {
                         total_transaction_amount += u.weight.abs();
                         if u.weight > 0 {
-                            (Edge { u: u.id, v: v.id }, u.weight as f64)
+                            (Edge { u: u.id, v: v.id }, u.weight)
                         } else {
-                            (Edge { u: v.id, v: u.id }, -u.weight as f64)
+                            (Edge { u: v.id, v: u.id }, -u.weight)
                         }
                     })
                     .collect();
+                trace!(?edges, "Caculated approximation");
                 debug!(
-                    "Caculated Approximation for graph {:?} with edges {:?}",
-                    instance.g.to_string(),
-                    edges
-                );
-                debug!(
-                    "The total amount of transactions is: {:?} VS Optimum: {:?}",
+                    transactions = edges.len(),
                     total_transaction_amount,
-                    instance.optimal_transaction_amount()
+                    optimum = instance.optimal_transaction_amount(),
+                    "Caculated approximation"
                 );
                 Some(edges)
             }
@@ -73,27 +68,29 @@ pub(crate) fn star_expand(instance: &ProblemInstance) -> Solution {
 ///
 /// * `instance` - The problem instance which should be solved
 /// * `approx_solver` - Approximation algorithm used to solve partition, which have no zero sum
-/// subset
+///   subset
 ///
 /// Example:
 /// ```
 /// use payback::graph::Graph;
-/// use payback::probleminstance::{ProblemInstance, Solution, SolvingMethods};
+/// use payback::probleminstance::{ProblemInstance, SolvingMethods};
 ///
 /// let instance: ProblemInstance = Graph::from(vec![-2, -1, 1, 2]).into();
-/// let solution: Solution = instance.solve_with(SolvingMethods::ApproxGreedySatisfaction);
+/// let solution = instance.solve_with(SolvingMethods::ApproxGreedySatisfaction);
 /// ```
-pub(crate) fn greedy_satisfaction(instance: &ProblemInstance) -> Solution {
-    debug!(
-        "Running 'greedy_satisfaction' for graph: {:?}",
-        instance.g.to_string()
-    );
+#[instrument(level = "debug", skip_all, fields(vertices = instance.g.vertices.len()))]
+pub(crate) fn greedy_satisfaction(instance: &ProblemInstance) -> EdgeSolution {
+    trace!(graph = %instance.g, "Running 'greedy_satisfaction'");
     if !instance.is_solvable() {
         None
     } else {
-        let mut sol = HashMap::new();
-        let (mut neg_vertices, mut pos_vertices): (Vec<&NamedNode>, Vec<&NamedNode>) =
-            instance.g.vertices.iter().partition(|v| v.weight < 0_i64);
+        let mut sol = BTreeMap::new();
+        let (mut neg_vertices, mut pos_vertices): (Vec<&NamedNode>, Vec<&NamedNode>) = instance
+            .g
+            .vertices
+            .iter()
+            .filter(|v| v.weight != 0)
+            .partition(|v| v.weight < 0_i64);
         let mut side_capacities = 0;
         if let Some(x) = neg_vertices.first() {
             side_capacities = x.weight;
@@ -109,14 +106,14 @@ pub(crate) fn greedy_satisfaction(instance: &ProblemInstance) -> Solution {
             match side_capacities.cmp(&0_i64) {
                 std::cmp::Ordering::Less => {
                     if p.weight <= -side_capacities {
-                        sol.insert(Edge { u: p.id, v: n.id }, p.weight as f64);
+                        sol.insert(Edge { u: p.id, v: n.id }, p.weight);
                         side_capacities += p.weight;
                         if side_capacities == 0 {
                             neg_vertices.remove(0);
                         }
                         pos_vertices.remove(0);
                     } else {
-                        sol.insert(Edge { u: p.id, v: n.id }, side_capacities as f64);
+                        sol.insert(Edge { u: p.id, v: n.id }, -side_capacities);
                         side_capacities += p.weight;
                         neg_vertices.remove(0);
                     }
@@ -126,14 +123,14 @@ pub(crate) fn greedy_satisfaction(instance: &ProblemInstance) -> Solution {
                 }
                 std::cmp::Ordering::Greater => {
                     if -n.weight <= side_capacities {
-                        sol.insert(Edge { u: p.id, v: n.id }, n.weight.abs() as f64);
+                        sol.insert(Edge { u: p.id, v: n.id }, n.weight.abs());
                         side_capacities += n.weight;
                         if side_capacities == 0 {
                             pos_vertices.remove(0);
                         }
                         neg_vertices.remove(0);
                     } else {
-                        sol.insert(Edge { u: p.id, v: n.id }, side_capacities as f64);
+                        sol.insert(Edge { u: p.id, v: n.id }, side_capacities);
                         side_capacities += n.weight;
                         pos_vertices.remove(0);
                     }
@@ -153,12 +150,15 @@ mod tests {
     use crate::graph::Edge;
     use crate::graph::Graph;
     use crate::probleminstance::ProblemInstance;
-    use env_logger::Env;
-    use log::debug;
+    use tracing::debug;
+    use tracing_subscriber::EnvFilter;
 
     fn init() {
-        let _ = env_logger::Builder::from_env(Env::default().default_filter_or("debug"))
-            .is_test(true)
+        let _ = tracing_subscriber::fmt()
+            .with_env_filter(
+                EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("debug")),
+            )
+            .with_test_writer()
             .try_init();
     }
 
@@ -201,10 +201,10 @@ mod tests {
         let nb = instance.g.get_node_from_name("B".to_owned()).unwrap();
         let nc = instance.g.get_node_from_name("C".to_owned()).unwrap();
         let nd = instance.g.get_node_from_name("D".to_owned()).unwrap();
-        let res: HashMap<Edge, f64> = HashMap::from([
-            (Edge { u: nc.id, v: na.id }, 1.0_f64),
-            (Edge { u: nc.id, v: nd.id }, 4.0_f64),
-            (Edge { u: nb.id, v: nc.id }, 2.0_f64),
+        let res: HashMap<Edge, i64> = HashMap::from([
+            (Edge { u: nc.id, v: na.id }, 1),
+            (Edge { u: nc.id, v: nd.id }, 4),
+            (Edge { u: nb.id, v: nc.id }, 2),
         ]);
         let sol = sol_opt.unwrap();
         debug!("Solution:        {:?}", sol);
@@ -261,6 +261,6 @@ mod tests {
             graph_string, sol
         );
         assert!(sol.is_some());
-        assert_eq!(sol.unwrap().into_iter().map(|(_, v)| v).sum::<f64>(), 5_f64);
+        assert_eq!(sol.unwrap().into_values().sum::<i64>(), 5);
     }
 }
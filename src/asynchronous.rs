@@ -0,0 +1,228 @@
+//! Async wrappers around the solvers. Only available with the `tokio` feature.
+//!
+//! The solvers are run on the blocking thread pool of tokio, so they don't block the executor.
+//! If a solve times out, is cancelled or its future is dropped, the exact methods are stopped and
+//! their result is discarded. The approximations can't be interrupted, so they finish in the
+//! background.
+use std::fmt::Display;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::probleminstance::{Cancel, MethodOptions, ProblemInstance, SolvingMethods};
+use crate::settlement::Settlement;
+
+/// Reasons why an async solve didn't return a solution.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AsyncSolveError {
+    /// The solve took longer than the given timeout.
+    TimedOut(Duration),
+    /// The cancellation future completed before the solve.
+    Cancelled,
+    /// The solver panicked.
+    Panicked(String),
+}
+
+impl Display for AsyncSolveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AsyncSolveError::TimedOut(timeout) => {
+                write!(f, "Solving took longer than {:?}.", timeout)
+            }
+            AsyncSolveError::Cancelled => write!(f, "Solving was cancelled."),
+            AsyncSolveError::Panicked(e) => write!(f, "Solver panicked: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for AsyncSolveError {}
+
+/// Solves the instance with the method without blocking the executor.
+///
+/// Example:
+/// ```
+/// use payback::asynchronous::solve_async;
+/// use payback::graph::Graph;
+/// use payback::probleminstance::{ProblemInstance, SolvingMethods};
+/// use std::sync::Arc;
+///
+/// # tokio::runtime::Runtime::new().unwrap().block_on(async {
+/// let graph = Graph::from(vec![("A".to_string(), -1), ("B".to_string(), 1)]);
+/// let instance = Arc::new(ProblemInstance::from(graph));
+/// let solution = solve_async(instance.clone(), SolvingMethods::DPGreedySatisfaction).await;
+/// assert!(solution.unwrap().is_some());
+/// # });
+/// ```
+pub async fn solve_async(
+    instance: Arc<ProblemInstance>,
+    method: SolvingMethods,
+) -> Result<Option<Settlement>, AsyncSolveError> {
+    solve_stoppable(instance, method, Arc::new(AtomicBool::new(false))).await
+}
+
+/// Sets the flag, when it is dropped.
+struct StopOnDrop(Arc<AtomicBool>);
+
+impl Drop for StopOnDrop {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Like [`solve_async()`], but the exact methods stop once `cancel` is set or the future is
+/// dropped. They then return the approximation of the method or a better solution found so far.
+async fn solve_stoppable(
+    instance: Arc<ProblemInstance>,
+    method: SolvingMethods,
+    cancel: Arc<AtomicBool>,
+) -> Result<Option<Settlement>, AsyncSolveError> {
+    let _stop = StopOnDrop(cancel.clone());
+    tokio::task::spawn_blocking(move || {
+        let options = MethodOptions {
+            cancel: Cancel::new(Some(&cancel)),
+            ..Default::default()
+        };
+        instance.settlement(&instance.solve_with_options(method, &(), &options))
+    })
+    .await
+    .map_err(|e| AsyncSolveError::Panicked(e.to_string()))
+}
+
+/// Like [`solve_async()`], but fails with [`AsyncSolveError::TimedOut`] if solving takes longer
+/// than `timeout`.
+pub async fn solve_with_timeout(
+    instance: Arc<ProblemInstance>,
+    method: SolvingMethods,
+    timeout: Duration,
+) -> Result<Option<Settlement>, AsyncSolveError> {
+    let cancel = Arc::new(AtomicBool::new(false));
+    tokio::time::timeout(timeout, solve_stoppable(instance, method, cancel.clone()))
+        .await
+        .map_err(|_| {
+            cancel.store(true, Ordering::Relaxed);
+            AsyncSolveError::TimedOut(timeout)
+        })?
+}
+
+/// Like [`solve_async()`], but fails with [`AsyncSolveError::Cancelled`] as soon as `cancel`
+/// completes, e.g. a `tokio::sync::oneshot::Receiver` or a shutdown signal.
+pub async fn solve_cancellable<F: Future>(
+    instance: Arc<ProblemInstance>,
+    method: SolvingMethods,
+    cancel: F,
+) -> Result<Option<Settlement>, AsyncSolveError> {
+    let stop = Arc::new(AtomicBool::new(false));
+    // A completed cancellation wins over a solution, which is ready at the same time.
+    tokio::select! {
+        biased;
+        _ = cancel => {
+            stop.store(true, Ordering::Relaxed);
+            Err(AsyncSolveError::Cancelled)
+        }
+        solution = solve_stoppable(instance, method, stop.clone()) => solution,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::asynchronous::{
+        solve_async, solve_cancellable, solve_with_timeout, AsyncSolveError,
+    };
+    use crate::generator::{generate, GeneratorConfig};
+    use crate::probleminstance::{ProblemInstance, SolvingMethods};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::sync::oneshot;
+    use tracing::debug;
+    use tracing_subscriber::EnvFilter;
+
+    fn init() {
+        let _ = tracing_subscriber::fmt()
+            .with_env_filter(
+                EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("debug")),
+            )
+            .with_test_writer()
+            .try_init();
+    }
+
+    #[tokio::test]
+    async fn test_async_solving() {
+        init();
+        debug!("Running 'test_async_solving'");
+        let small = Arc::new(ProblemInstance::from(generate(&GeneratorConfig {
+            nodes: 6,
+            seed: Some(1),
+            ..Default::default()
+        })));
+        let solution = solve_async(small.clone(), SolvingMethods::DPGreedySatisfaction)
+            .await
+            .unwrap();
+        assert!(solution.is_some());
+        assert!(solve_with_timeout(
+            small.clone(),
+            SolvingMethods::ApproxStarExpand,
+            Duration::from_secs(10)
+        )
+        .await
+        .is_ok());
+        assert_eq!(
+            solve_cancellable(small, SolvingMethods::DPGreedySatisfaction, async {}).await,
+            Err(AsyncSolveError::Cancelled)
+        );
+
+        let large = Arc::new(ProblemInstance::from(generate(&GeneratorConfig {
+            nodes: 9,
+            seed: Some(1),
+            ..Default::default()
+        })));
+        assert_eq!(
+            solve_with_timeout(
+                large,
+                SolvingMethods::PartitioningGreedySatisfaction,
+                Duration::from_millis(1)
+            )
+            .await,
+            Err(AsyncSolveError::TimedOut(Duration::from_millis(1)))
+        );
+    }
+
+    /// Waits until nothing but the caller holds the instance, i.e. the solver returned.
+    async fn solver_returned(instance: &Arc<ProblemInstance>) -> bool {
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while Arc::strong_count(instance) > 1 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .is_ok()
+    }
+
+    #[tokio::test]
+    async fn test_async_solving_stops() {
+        init();
+        debug!("Running 'test_async_solving_stops'");
+        // Solving this instance exactly takes about a minute, far longer than the test waits.
+        let large = Arc::new(ProblemInstance::from(generate(&GeneratorConfig {
+            nodes: 12,
+            seed: Some(1),
+            ..Default::default()
+        })));
+        let method = SolvingMethods::PartitioningStarExpand;
+
+        // Dropping the future stops the solver.
+        let solve = solve_async(large.clone(), method);
+        assert!(tokio::time::timeout(Duration::from_millis(50), solve)
+            .await
+            .is_err());
+        assert!(solver_returned(&large).await);
+
+        // So does cancelling it.
+        let (cancel, cancelled) = oneshot::channel::<()>();
+        let solve = tokio::spawn(solve_cancellable(large.clone(), method, cancelled));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        cancel.send(()).unwrap();
+        assert_eq!(solve.await.unwrap(), Err(AsyncSolveError::Cancelled));
+        assert!(solver_returned(&large).await);
+    }
+}
@@ -0,0 +1,140 @@
+use serde_derive::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::i18n::{Lang, Text};
+
+/// Largest total of the deltas, which is still due to floating point errors.
+const TOLERANCE: f64 = 1e-6;
+
+/// Differences between the rounded and the exact balances of people, which accumulate whenever
+/// amounts are split or converted into whole amounts. A positive delta means, that the person
+/// receives more or owes less than the exact amount.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct RoundingAudit {
+    pub deltas: BTreeMap<String, f64>,
+}
+
+impl RoundingAudit {
+    /// Adds the difference between the rounded and the exact balance of the person.
+    pub fn add(&mut self, name: &str, delta: f64) {
+        *self.deltas.entry(name.to_owned()).or_default() += delta;
+    }
+
+    /// Adds the deltas of another audit.
+    pub fn merge(&mut self, other: RoundingAudit) {
+        for (name, delta) in other.deltas {
+            self.add(&name, delta);
+        }
+    }
+
+    /// Sum of all deltas, which is the amount created or lost by rounding.
+    pub fn total(&self) -> f64 {
+        self.deltas.values().sum()
+    }
+
+    /// Whether rounding only moved amounts between people without changing their total.
+    pub fn is_balanced(&self) -> bool {
+        self.total().abs() < TOLERANCE
+    }
+
+    /// Lists the people, who absorbed rounding differences, and the total discrepancy.
+    ///
+    /// Example:
+    /// ```
+    /// use payback::audit::RoundingAudit;
+    /// use payback::i18n::Lang;
+    ///
+    /// let mut audit = RoundingAudit::default();
+    /// audit.add("A", 0.5);
+    /// audit.add("B", -0.5);
+    /// assert_eq!(
+    ///     audit.report(Lang::En),
+    ///     "Rounding audit:\n  \"A\": +0.50\n  \"B\": -0.50\n  Total: 0.00\n"
+    /// );
+    /// ```
+    pub fn report(&self, lang: Lang) -> String {
+        let mut text = format!("{}:\n", lang.text(Text::RoundingAudit));
+        let absorbed: Vec<(&String, &f64)> = self
+            .deltas
+            .iter()
+            .filter(|(_, d)| d.abs() >= TOLERANCE)
+            .collect();
+        if absorbed.is_empty() {
+            text += &format!("  {}\n", lang.text(Text::NothingRounded));
+        }
+        for (name, delta) in absorbed {
+            text += &format!("  {:?}: {:+.2}\n", name, delta);
+        }
+        let total = if self.is_balanced() {
+            0.0
+        } else {
+            self.total()
+        };
+        text + &format!("  {}: {:.2}\n", lang.text(Text::Total), total)
+    }
+
+    /// Reads an audit written by [`RoundingAudit::save()`].
+    pub fn load(path: &Path) -> Result<RoundingAudit, String> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("Unable to read rounding audit {:?}: {}", path, e))?;
+        serde_json::from_str(&content)
+            .map_err(|e| format!("Invalid rounding audit {:?}: {}", path, e))
+    }
+
+    /// Writes the deltas as json, so the audit can be kept with the books.
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        std::fs::write(
+            path,
+            serde_json::to_string_pretty(self).expect("Audits are serializable."),
+        )
+        .map_err(|e| format!("Unable to write rounding audit {:?}: {}", path, e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::audit::RoundingAudit;
+    use crate::i18n::Lang;
+    use tracing::debug;
+    use tracing_subscriber::EnvFilter;
+
+    fn init() {
+        let _ = tracing_subscriber::fmt()
+            .with_env_filter(
+                EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("debug")),
+            )
+            .with_test_writer()
+            .try_init();
+    }
+
+    #[test]
+    fn test_rounding_audit_file() {
+        init();
+        debug!("Running 'test_rounding_audit_file'");
+        let mut audit = RoundingAudit::default();
+        audit.add("A", -1.0 / 3.0);
+        audit.add("B", 1.0 / 3.0);
+        let mut other = RoundingAudit::default();
+        other.add("A", 0.25);
+        audit.merge(other);
+        let path = std::env::temp_dir().join(format!("payback-audit-{}.json", std::process::id()));
+        audit.save(&path).unwrap();
+        let read = RoundingAudit::load(&path).unwrap();
+        assert_eq!(read, audit);
+        assert_eq!(read.report(Lang::En), audit.report(Lang::En));
+        std::fs::remove_file(&path).unwrap();
+
+        // Writing into a missing directory fails instead of losing the audit silently.
+        let missing = std::env::temp_dir()
+            .join(format!("payback-audit-missing-{}", std::process::id()))
+            .join("audit.json");
+        let error = audit.save(&missing).unwrap_err();
+        debug!("Error: {}", error);
+        assert!(error.starts_with("Unable to write rounding audit"));
+        assert!(!missing.exists());
+        assert!(RoundingAudit::load(&missing)
+            .unwrap_err()
+            .starts_with("Unable to read rounding audit"));
+    }
+}
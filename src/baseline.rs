@@ -0,0 +1,248 @@
+use serde_derive::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::path::Path;
+use tracing::debug;
+
+use crate::comparison::MethodResult;
+use crate::probleminstance::SolvingMethods;
+
+/// Runtimes below this many milliseconds are never regressions, since they are dominated by
+/// noise.
+const MIN_RUNTIME_MS: f64 = 1.0;
+
+/// Result of a method on an instance of a bench run.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BaselineEntry {
+    /// Name of the instance, e.g. the name of an instance of a corpus.
+    pub instance: String,
+    /// Name of the method, e.g. 'dp-greedy-satisfaction'.
+    pub method: String,
+    pub elapsed_ms: f64,
+    /// Number of transactions or `None`, if the method found no solution or timed out.
+    pub transactions: Option<usize>,
+}
+
+/// Timing and quality of the methods of a bench run, against which later runs are compared to
+/// detect regressions. Stored as json.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Baseline {
+    pub entries: Vec<BaselineEntry>,
+}
+
+/// Method, which got worse on an instance compared to the baseline.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Regression {
+    /// The method took longer than the baseline by more than the threshold.
+    Slower {
+        instance: String,
+        method: String,
+        baseline_ms: f64,
+        current_ms: f64,
+    },
+    /// The method needs more transactions than in the baseline or found no solution anymore.
+    WorseSolution {
+        instance: String,
+        method: String,
+        baseline: Option<usize>,
+        current: Option<usize>,
+    },
+}
+
+impl Display for Regression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Regression::Slower {
+                instance,
+                method,
+                baseline_ms,
+                current_ms,
+            } => write!(
+                f,
+                "'{}' on '{}' took {:.3} ms instead of {:.3} ms (+{:.0}%).",
+                method,
+                instance,
+                current_ms,
+                baseline_ms,
+                (current_ms / baseline_ms - 1.0) * 100.0
+            ),
+            Regression::WorseSolution {
+                instance,
+                method,
+                baseline,
+                current,
+            } => {
+                let transactions =
+                    |t: &Option<usize>| t.map_or("none".to_owned(), |t| t.to_string());
+                write!(
+                    f,
+                    "'{}' on '{}' found {} transactions instead of {}.",
+                    method,
+                    instance,
+                    transactions(current),
+                    transactions(baseline)
+                )
+            }
+        }
+    }
+}
+
+impl Baseline {
+    /// Adds the results of a bench run on the instance.
+    pub fn add(&mut self, instance: &str, results: &BTreeMap<SolvingMethods, MethodResult>) {
+        self.entries
+            .extend(results.iter().map(|(method, result)| BaselineEntry {
+                instance: instance.to_owned(),
+                method: method.name().to_owned(),
+                elapsed_ms: result.metrics.elapsed.as_secs_f64() * 1000.0,
+                transactions: result.metrics.transactions,
+            }));
+    }
+
+    /// Reads the baseline from the file. Returns `None`, if there is no file yet.
+    pub fn load(path: &Path) -> Result<Option<Baseline>, String> {
+        match std::fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content)
+                .map(Some)
+                .map_err(|e| format!("Invalid baseline {:?}: {}", path, e)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(format!("Unable to read baseline {:?}: {}", path, e)),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        std::fs::write(
+            path,
+            serde_json::to_string_pretty(self).expect("Baselines are serializable."),
+        )
+        .map_err(|e| format!("Unable to write baseline {:?}: {}", path, e))
+    }
+
+    /// Regressions of the current run compared to the baseline. A method is slower, if it takes
+    /// more than `threshold` times longer relative to the baseline, e.g. 0.2 for 20%. Any
+    /// additional transaction is a regression. Methods and instances, which aren't in both runs,
+    /// aren't compared.
+    ///
+    /// Example:
+    /// ```
+    /// use payback::baseline::{Baseline, BaselineEntry, Regression};
+    ///
+    /// let entry = |elapsed_ms, transactions| BaselineEntry {
+    ///     instance: "small".to_owned(),
+    ///     method: "dp-greedy-satisfaction".to_owned(),
+    ///     elapsed_ms,
+    ///     transactions: Some(transactions),
+    /// };
+    /// let baseline = Baseline { entries: vec![entry(10.0, 3)] };
+    /// let current = Baseline { entries: vec![entry(11.0, 3)] };
+    /// assert!(baseline.compare(&current, 0.2).is_empty());
+    /// let current = Baseline { entries: vec![entry(20.0, 3)] };
+    /// assert!(matches!(baseline.compare(&current, 0.2)[..], [Regression::Slower { .. }]));
+    /// ```
+    pub fn compare(&self, current: &Baseline, threshold: f64) -> Vec<Regression> {
+        let baseline: BTreeMap<(&str, &str), &BaselineEntry> = self
+            .entries
+            .iter()
+            .map(|e| ((e.instance.as_str(), e.method.as_str()), e))
+            .collect();
+        let mut regressions = vec![];
+        for entry in &current.entries {
+            let Some(base) = baseline.get(&(entry.instance.as_str(), entry.method.as_str())) else {
+                debug!(entry.instance, entry.method, "Not in the baseline");
+                continue;
+            };
+            let worse = match (base.transactions, entry.transactions) {
+                (Some(b), Some(c)) => c > b,
+                (Some(_), None) => true,
+                (None, _) => false,
+            };
+            if worse {
+                regressions.push(Regression::WorseSolution {
+                    instance: entry.instance.to_owned(),
+                    method: entry.method.to_owned(),
+                    baseline: base.transactions,
+                    current: entry.transactions,
+                });
+            }
+            if entry.elapsed_ms >= MIN_RUNTIME_MS
+                && entry.elapsed_ms > base.elapsed_ms * (1.0 + threshold)
+            {
+                regressions.push(Regression::Slower {
+                    instance: entry.instance.to_owned(),
+                    method: entry.method.to_owned(),
+                    baseline_ms: base.elapsed_ms,
+                    current_ms: entry.elapsed_ms,
+                });
+            }
+        }
+        regressions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::baseline::{Baseline, Regression};
+    use crate::comparison::solve_methods;
+    use crate::graph::Graph;
+    use crate::probleminstance::{ProblemInstance, SolvingMethods};
+    use crate::solver_config::SolverConfig;
+    use tracing::debug;
+    use tracing_subscriber::EnvFilter;
+
+    fn init() {
+        let _ = tracing_subscriber::fmt()
+            .with_env_filter(
+                EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("debug")),
+            )
+            .with_test_writer()
+            .try_init();
+    }
+
+    #[test]
+    fn test_baseline() {
+        init();
+        debug!("Running 'test_baseline'");
+        let instance = ProblemInstance::from(Graph::from(vec![-5, -3, -2, 1, 4, 5]));
+        let results = solve_methods(
+            &instance,
+            &[
+                SolvingMethods::ApproxStarExpand,
+                SolvingMethods::DPGreedySatisfaction,
+            ],
+            &SolverConfig::default(),
+        );
+        let mut baseline = Baseline::default();
+        baseline.add("small", &results);
+        assert_eq!(baseline.entries.len(), 2);
+        baseline.entries[1].elapsed_ms = 10.0;
+
+        let path = std::env::temp_dir().join(format!("payback-baseline-{}", std::process::id()));
+        assert_eq!(Baseline::load(&path), Ok(None));
+        baseline.save(&path).unwrap();
+        let loaded = Baseline::load(&path).unwrap().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.entries.len(), 2);
+        assert!(loaded.compare(&baseline, 0.2).is_empty());
+
+        // Solutions with more transactions and slower runs beyond the threshold are regressions.
+        let mut current = baseline.clone();
+        current.entries[1].transactions = Some(5);
+        current.entries[1].elapsed_ms = 20.0;
+        let regressions = baseline.compare(&current, 0.2);
+        debug!("Regressions: {:?}", regressions);
+        assert!(matches!(
+            regressions[..],
+            [
+                Regression::WorseSolution {
+                    current: Some(5),
+                    ..
+                },
+                Regression::Slower { .. }
+            ]
+        ));
+        assert!(regressions[0]
+            .to_string()
+            .contains("5 transactions instead of 4"));
+        assert_eq!(baseline.compare(&current, 1.5).len(), 1);
+    }
+}
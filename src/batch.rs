@@ -0,0 +1,259 @@
+use serde_json::json;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use tracing::{debug, info, instrument, Span};
+
+use crate::compression::read_input;
+use crate::graph::Graph;
+use crate::payments::net_payments;
+use crate::probleminstance::ProblemInstance;
+use crate::settlement::Settlement;
+use crate::solver_config::SolverConfig;
+
+/// Plan of a single instance of a batch or the reason it couldn't be solved.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BatchResult {
+    /// File name of the instance without its extension.
+    pub name: String,
+    pub path: PathBuf,
+    pub outcome: Result<Settlement, String>,
+}
+
+impl BatchResult {
+    /// Json object with the keys 'name' and 'transactions' or 'error'.
+    pub fn to_json(&self) -> serde_json::Value {
+        match &self.outcome {
+            Ok(settlement) => json!({
+                "name": self.name,
+                "transactions": settlement
+                    .transactions()
+                    .iter()
+                    .map(|t| json!({"from": t.payer, "to": t.payee, "amount": t.amount}))
+                    .collect::<Vec<_>>(),
+            }),
+            Err(e) => json!({"name": self.name, "error": e}),
+        }
+    }
+}
+
+/// Json object summarizing the batch with the number of 'instances', 'solved' instances and
+/// 'transactions' and the list of 'results' with the keys 'name' and 'transactions' holding
+/// the number of transactions or 'error'.
+pub fn summary_json(results: &[BatchResult]) -> serde_json::Value {
+    json!({
+        "instances": results.len(),
+        "solved": results.iter().filter(|r| r.outcome.is_ok()).count(),
+        "transactions": results
+            .iter()
+            .filter_map(|r| r.outcome.as_ref().ok())
+            .map(|ts| ts.len())
+            .sum::<usize>(),
+        "results": results
+            .iter()
+            .map(|r| match &r.outcome {
+                Ok(ts) => json!({"name": r.name, "transactions": ts.len()}),
+                Err(e) => json!({"name": r.name, "error": e}),
+            })
+            .collect::<Vec<_>>(),
+    })
+}
+
+/// Checks if the name matches the pattern, where '*' matches any sequence of characters and
+/// '?' any single character.
+fn matches_pattern(pattern: &[char], name: &[char]) -> bool {
+    match (pattern.first(), name.first()) {
+        (None, None) => true,
+        (Some('*'), _) => {
+            matches_pattern(&pattern[1..], name)
+                || (!name.is_empty() && matches_pattern(pattern, &name[1..]))
+        }
+        (Some('?'), Some(_)) => matches_pattern(&pattern[1..], &name[1..]),
+        (Some(p), Some(n)) => p == n && matches_pattern(&pattern[1..], &name[1..]),
+        _ => false,
+    }
+}
+
+/// Files of the instances given by a directory or a glob like 'groups/*.csv'. A directory
+/// stands for all its '.csv' files. Wildcards are only supported in the file name. The paths
+/// are sorted, so batches are processed in a stable order.
+///
+/// Example:
+/// ```
+/// use payback::batch::instance_paths;
+/// use std::path::Path;
+///
+/// assert!(instance_paths(Path::new("does/not/exist/*.csv")).is_err());
+/// ```
+pub fn instance_paths(input: &Path) -> Result<Vec<PathBuf>, String> {
+    let (dir, pattern): (&Path, Vec<char>) = if input.is_dir() {
+        (input, "*.csv".chars().collect())
+    } else {
+        let pattern = input
+            .file_name()
+            .ok_or(format!("{:?} is no directory or glob.", input))?
+            .to_string_lossy()
+            .chars()
+            .collect();
+        let dir = input
+            .parent()
+            .filter(|d| !d.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+        (dir, pattern)
+    };
+    let mut paths = std::fs::read_dir(dir)
+        .map_err(|e| format!("Unable to read directory {:?}: {}", dir, e))?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.is_file())
+        .filter(|path| {
+            path.file_name().is_some_and(|name| {
+                matches_pattern(
+                    &pattern,
+                    &name.to_string_lossy().chars().collect::<Vec<_>>(),
+                )
+            })
+        })
+        .collect::<Vec<PathBuf>>();
+    paths.sort();
+    Ok(paths)
+}
+
+/// Solves every instance on its own with the config on up to `jobs` threads. The timeout of the
+/// config applies to every instance. A failing instance doesn't abort the batch, but is
+/// reported with its error. The results are in the order of the paths.
+pub fn solve_batch(paths: &[PathBuf], config: &SolverConfig, jobs: usize) -> Vec<BatchResult> {
+    solve_batch_with(paths, |instance| Ok((instance, config.to_owned())), jobs)
+}
+
+/// Like [`solve_batch()`], but `configure` prepares every parsed instance and gives the config
+/// to solve it with, e.g. to cap its transfers or to recommend a method for it. An instance is
+/// reported as failed, if `configure` fails.
+#[instrument(level = "debug", skip_all, fields(instances = paths.len(), jobs))]
+pub fn solve_batch_with<F>(paths: &[PathBuf], configure: F, jobs: usize) -> Vec<BatchResult>
+where
+    F: Fn(ProblemInstance) -> Result<(ProblemInstance, SolverConfig), String> + Sync,
+{
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<BatchResult>>> = Mutex::new(vec![None; paths.len()]);
+    // Spans of the workers are nested in the span of the batch.
+    let span = Span::current();
+    std::thread::scope(|scope| {
+        for _ in 0..jobs.clamp(1, paths.len().max(1)) {
+            scope.spawn(|| {
+                let _entered = span.enter();
+                // Every worker takes the next unsolved instance until none is left.
+                loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(path) = paths.get(index) else {
+                        break;
+                    };
+                    let result = solve_path(path, &configure);
+                    results
+                        .lock()
+                        .expect("Workers don't panic while holding the lock.")[index] =
+                        Some(result);
+                }
+            });
+        }
+    });
+    results
+        .into_inner()
+        .expect("Workers don't panic while holding the lock.")
+        .into_iter()
+        .flatten()
+        .collect()
+}
+
+type Configure<'a> =
+    dyn Fn(ProblemInstance) -> Result<(ProblemInstance, SolverConfig), String> + Sync + 'a;
+
+fn solve_path(path: &Path, configure: &Configure) -> BatchResult {
+    let name = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let outcome = solve_file(path, configure);
+    match &outcome {
+        Ok(ts) => info!("Solved '{}' with {} transactions", name, ts.len()),
+        Err(e) => info!("Unable to solve '{}': {}", name, e),
+    }
+    BatchResult {
+        name,
+        path: path.to_owned(),
+        outcome,
+    }
+}
+
+fn solve_file(path: &Path, configure: &Configure) -> Result<Settlement, String> {
+    let data = read_input(path)?;
+    let graph: Graph = net_payments(&data)?.try_into()?;
+    let instance = ProblemInstance::from(graph);
+    if !instance.is_solvable() {
+        return Err(format!(
+            "The weights add up to {} instead of 0.",
+            instance.imbalance()
+        ));
+    }
+    let (instance, config) = configure(instance)?;
+    let sol = instance
+        .solve_edges_with_config(&config)
+        .map_err(|e| e.to_string())?;
+    debug!("Solution of {:?}: {:?}", path, sol);
+    instance
+        .settlement(&sol)
+        .ok_or("No result was found.".to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::batch::{instance_paths, solve_batch, summary_json};
+    use crate::solver_config::SolverConfig;
+    use tracing::debug;
+    use tracing_subscriber::EnvFilter;
+
+    fn init() {
+        let _ = tracing_subscriber::fmt()
+            .with_env_filter(
+                EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("debug")),
+            )
+            .with_test_writer()
+            .try_init();
+    }
+
+    #[test]
+    fn test_solve_batch() {
+        init();
+        debug!("Running 'test_solve_batch'");
+        let dir = std::env::temp_dir().join(format!("payback-batch-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("b.csv"), "A,B,5\nB,C,5").unwrap();
+        std::fs::write(dir.join("a.csv"), "A,-2\nB,2").unwrap();
+        std::fs::write(dir.join("broken.csv"), "A,-2\nB,3").unwrap();
+        std::fs::write(dir.join("notes.txt"), "A,-2\nB,2").unwrap();
+
+        let paths = instance_paths(&dir).unwrap();
+        assert_eq!(
+            paths,
+            ["a.csv", "b.csv", "broken.csv"].map(|name| dir.join(name))
+        );
+        assert_eq!(
+            instance_paths(&dir.join("b*.csv")).unwrap(),
+            ["b.csv", "broken.csv"].map(|name| dir.join(name))
+        );
+        assert_eq!(instance_paths(&dir.join("?.*")).unwrap().len(), 2);
+
+        let results = solve_batch(&paths, &SolverConfig::default(), 2);
+        assert_eq!(results, solve_batch(&paths, &SolverConfig::default(), 1));
+        let summary = summary_json(&results);
+        debug!("Summary: {}", summary);
+        assert_eq!(summary["instances"], 3);
+        assert_eq!(summary["solved"], 2);
+        assert_eq!(summary["transactions"], 2);
+        assert_eq!(
+            results[1].to_json()["transactions"][0],
+            serde_json::json!({"from": "A", "to": "C", "amount": 5})
+        );
+        assert!(results[2].outcome.is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,243 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+use itertools::Itertools;
+use tracing::{debug, instrument, trace, Span};
+
+use crate::approximation::greedy_satisfaction;
+use crate::dynamic_program::patcas_dp;
+use crate::events::{SolverEvent, SolverObserver};
+use crate::graph::{Edge, Graph, NamedNode};
+use crate::probleminstance::{Cancel, EdgeSolution, ProblemInstance};
+
+/// Number of people partitioned by a single run of the dynamic program.
+const WINDOW: usize = 12;
+/// Name of the person balancing a window, which isn't a number like the other names.
+const PLACEHOLDER: &str = "placeholder";
+
+/// Algorithm approximating the payback problem by solving a coarser instance exactly.
+/// The weights are rounded to multiples of the bucket size, so more groups of people settle
+/// among themselves. People with opposite rounded weights are paired and the remaining people
+/// are partitioned by the dynamic program of 'DPGreedySatisfaction' in small windows.
+/// Every group is then settled with its exact weights. Groups, whose exact weights don't add up
+/// to zero, are merged with groups with the opposite residual or into one group. So the amounts
+/// are exact, while larger buckets can lead to more transactions.
+/// Has a approximation factor of 2.
+///
+/// * `instance` - The problem instance which should be solved
+/// * `bucket_size` - Granularity of the rounded weights. Defaults to 1, which keeps the weights.
+/// * `observer` - Receives the events of the solver
+///
+/// Example:
+/// ```
+/// use payback::graph::Graph;
+/// use payback::probleminstance::{ProblemInstance, SolvingMethods};
+///
+/// let instance: ProblemInstance = Graph::from(vec![-2, -1, 1, 2]).into();
+/// let solution = instance.solve_with(SolvingMethods::ApproxBucketedDP);
+/// ```
+#[instrument(
+    level = "debug",
+    skip_all,
+    fields(vertices = instance.g.vertices.len(), bucket_size, groups)
+)]
+pub(crate) fn bucketed_dp(
+    instance: &ProblemInstance,
+    bucket_size: Option<i64>,
+    observer: &dyn SolverObserver,
+) -> EdgeSolution {
+    if !instance.is_solvable() {
+        return None;
+    }
+    let vertices: Vec<&NamedNode> = instance
+        .g
+        .vertices
+        .iter()
+        .filter(|v| v.weight != 0)
+        .collect();
+    let bucket_size = bucket_size.unwrap_or(1).max(1);
+    Span::current().record("bucket_size", bucket_size);
+    let rounded = round_weights(&vertices, bucket_size);
+    trace!(?rounded, "Rounded weights");
+
+    // Groups of positions in `vertices`, which settle among themselves after rounding.
+    let mut groups: Vec<Vec<usize>> = vec![];
+    let mut open: HashMap<i64, Vec<usize>> = HashMap::new();
+    for (i, w) in rounded.iter().enumerate() {
+        match open.get_mut(&-w).and_then(|js| js.pop()) {
+            Some(j) => groups.push(vec![j, i]),
+            None => open.entry(*w).or_default().push(i),
+        }
+    }
+    let rest: Vec<usize> = open.into_values().flatten().sorted().collect();
+    groups.extend(partition_rounded(rest, &rounded));
+
+    // Repair the groups, whose exact weights don't add up to zero, by merging groups with
+    // opposite residuals. The groups left are merged into one.
+    let residual = |g: &Vec<usize>| g.iter().map(|i| vertices[*i].weight).sum::<i64>();
+    let (mut settled, unsettled): (Vec<Vec<usize>>, Vec<Vec<usize>>) =
+        groups.into_iter().partition(|g| residual(g) == 0);
+    let mut open: HashMap<i64, Vec<Vec<usize>>> = HashMap::new();
+    for group in unsettled {
+        let r = residual(&group);
+        match open.get_mut(&-r).and_then(|gs| gs.pop()) {
+            Some(other) => settled.push(other.into_iter().chain(group).collect()),
+            None => open.entry(r).or_default().push(group),
+        }
+    }
+    let merged: Vec<usize> = open.into_values().flatten().flatten().collect();
+    if !merged.is_empty() {
+        settled.push(merged);
+    }
+    Span::current().record("groups", settled.len());
+
+    let mut solution: BTreeMap<Edge, i64> = BTreeMap::new();
+    for group in settled {
+        let people: Vec<&NamedNode> = group.iter().map(|i| vertices[*i]).collect();
+        observer.on_event(&SolverEvent::PartitionDiscovered {
+            people: people.iter().map(|v| v.name.to_owned()).collect(),
+        });
+        solution.extend(greedy_satisfaction(&ProblemInstance::from(Graph::from(
+            people,
+        )))?);
+    }
+    Some(solution)
+}
+
+/// Partitions the people by their rounded weights into groups, which add up to zero. Since the
+/// dynamic program is only fast for few people, it is run on windows of [`WINDOW`] people. A
+/// window is balanced by a placeholder person, whose group is returned to the pool, while the
+/// other groups are kept. People, for which no group is found, form the last group.
+fn partition_rounded(people: Vec<usize>, rounded: &[i64]) -> Vec<Vec<usize>> {
+    // Alternate the signs, so windows can contain groups.
+    let (positive, negative): (Vec<usize>, Vec<usize>) =
+        people.into_iter().partition(|i| rounded[*i] > 0);
+    let mut pool: VecDeque<usize> = positive.into_iter().interleave(negative).collect();
+    let mut groups = vec![];
+    let mut failures = 0;
+    while pool.len() > WINDOW && failures * WINDOW < pool.len() {
+        let window: Vec<usize> = pool.drain(..WINDOW).collect();
+        let (found, left) = partition_window(&window, rounded);
+        failures = if found.is_empty() { failures + 1 } else { 0 };
+        groups.extend(found);
+        pool.extend(left);
+    }
+    let pool: Vec<usize> = pool.into();
+    if pool.len() <= WINDOW {
+        let (found, left) = partition_window(&pool, rounded);
+        groups.extend(found);
+        groups.push(left);
+    } else {
+        groups.push(pool);
+    }
+    groups.retain(|g| !g.is_empty());
+    debug!(groups = groups.len(), "Partitioned the rounded instance");
+    groups
+}
+
+/// Groups of the window found by the dynamic program and the people in the group of the
+/// placeholder, which balances the window.
+fn partition_window(window: &[usize], rounded: &[i64]) -> (Vec<Vec<usize>>, Vec<usize>) {
+    let mut people: Vec<(String, i64)> = window
+        .iter()
+        .map(|i| (i.to_string(), rounded[*i]))
+        .collect();
+    let imbalance: i64 = people.iter().map(|(_, w)| w).sum();
+    if imbalance != 0 {
+        people.push((PLACEHOLDER.to_owned(), -imbalance));
+    }
+    let coarse = ProblemInstance::from(Graph::from(people));
+    // The bounds of the rounded instance aren't bounds of the instance, so its events are
+    // dropped.
+    let sol = patcas_dp(&coarse, &greedy_satisfaction, &(), Cancel::default());
+    let (left, found): (Vec<Vec<String>>, Vec<Vec<String>>) = coarse
+        .partitioning(&sol)
+        .unwrap_or_default()
+        .into_iter()
+        .partition(|g| g.iter().any(|name| name == PLACEHOLDER));
+    let positions =
+        |g: Vec<String>| -> Vec<usize> { g.iter().filter_map(|name| name.parse().ok()).collect() };
+    trace!(?found, ?left, "Partitioned window");
+    (
+        found.into_iter().map(positions).collect(),
+        left.into_iter().flat_map(positions).collect(),
+    )
+}
+
+/// Rounds the weights to multiples of the bucket size in units of the bucket size. Rounded
+/// weights keep their sign and add up to zero, which is restored by changing the weights with
+/// the largest rounding error.
+fn round_weights(vertices: &[&NamedNode], bucket_size: i64) -> Vec<i64> {
+    let mut rounded: Vec<i64> = vertices
+        .iter()
+        .map(|v| {
+            let buckets = (v.weight.abs() + bucket_size / 2) / bucket_size;
+            v.weight.signum() * buckets.max(1)
+        })
+        .collect();
+    let mut imbalance: i64 = rounded.iter().sum();
+    while imbalance != 0 {
+        // Shrinking one side or growing the other one. Growing is always possible.
+        let step = -imbalance.signum();
+        // Error of the weight after the step, which must keep the sign of the weight.
+        let best = (0..rounded.len())
+            .filter(|i| (rounded[*i] + step).signum() == vertices[*i].weight.signum())
+            .min_by_key(|i| {
+                ((rounded[*i] + step) as i128 * bucket_size as i128 - vertices[*i].weight as i128)
+                    .abs()
+            });
+        let i = best.expect("Solvable instances have weights on both sides.");
+        rounded[i] += step;
+        imbalance += step;
+    }
+    rounded
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bucketing::{bucketed_dp, round_weights};
+    use crate::graph::{Graph, NamedNode};
+    use crate::probleminstance::{ProblemInstance, SolvingMethods};
+    use crate::testing::is_valid_solution;
+    use tracing::debug;
+    use tracing_subscriber::EnvFilter;
+
+    fn init() {
+        let _ = tracing_subscriber::fmt()
+            .with_env_filter(
+                EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("debug")),
+            )
+            .with_test_writer()
+            .try_init();
+    }
+
+    #[test]
+    fn test_bucketed_dp() {
+        init();
+        debug!("Running 'test_bucketed_dp'");
+        let graph = Graph::from(vec![-101, -99, -52, 48, 98, 103, 3]);
+        let vertices: Vec<&NamedNode> = graph.vertices.iter().collect();
+        let rounded = round_weights(&vertices, 50);
+        debug!("Rounded weights: {:?}", rounded);
+        assert_eq!(rounded.iter().sum::<i64>(), 0);
+        assert!(rounded
+            .iter()
+            .zip(&vertices)
+            .all(|(r, v)| r.signum() == v.weight.signum()));
+
+        let instance = ProblemInstance::from(graph.clone());
+        let sol = bucketed_dp(&instance, Some(50), &());
+        debug!("Solution: {:?}", sol);
+        assert!(is_valid_solution(&instance.g, &instance.settlement(&sol)));
+        // Exactly solving the groups of the rounded instance loses at most the merged group.
+        let optimum = instance.solve_edges_with(SolvingMethods::DPGreedySatisfaction);
+        assert!(sol.unwrap().len() <= optimum.unwrap().len() + 2);
+
+        // Without rounding, the partitioning is exact.
+        let graph = Graph::from(vec![-5, -3, -2, 1, 4, 5]);
+        let instance = ProblemInstance::from(graph.clone());
+        let sol = bucketed_dp(&instance, Some(1), &());
+        assert!(is_valid_solution(&instance.g, &instance.settlement(&sol)));
+        assert_eq!(sol.map(|s| s.len()), instance.min_transactions());
+        assert!(bucketed_dp(&ProblemInstance::from(Graph::from(vec![-1, 2])), None, &()).is_none());
+    }
+}
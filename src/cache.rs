@@ -0,0 +1,367 @@
+use itertools::Itertools;
+use serde_derive::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use tracing::{debug, instrument, warn};
+
+use crate::canonical::CanonicalForm;
+use crate::constraints::Soft;
+use crate::graph::Metadata;
+use crate::probleminstance::{
+    is_cancelled, Cancel, EdgeSolution, NamedSolution, ProblemInstance, SolvingMethods,
+};
+use crate::solver_config::{Objective, ObjectiveWeights, SolveError, SolverConfig};
+use crate::verification::{verify_plan, Payment};
+
+/// Position of a person in the canonical form. People without a weight aren't in the canonical
+/// form and keep their names.
+type Position = Result<usize, String>;
+
+/// Instance and options of the solver, which change the solution, hashed by
+/// [`SolutionCache::key()`].
+#[derive(Serialize)]
+struct Key<'a> {
+    version: &'a str,
+    weights: String,
+    metadata: Vec<(Position, &'a Metadata)>,
+    debts: Vec<(Position, Position, i64)>,
+    names: Option<&'a Vec<String>>,
+    method: SolvingMethods,
+    objective: Objective,
+    objective_weights: ObjectiveWeights,
+    seed: Option<u64>,
+    time_budget: Option<Duration>,
+    bucket_size: Option<i64>,
+    cluster_by: Option<&'a str>,
+    single_payment: bool,
+    forbidden: &'a [(String, String)],
+    preferred: &'a [(String, String)],
+    max_transactions: BTreeMap<&'a String, &'a usize>,
+    max_transactions_per_person: Option<usize>,
+    max_amount: Option<i64>,
+    fixed: &'a [Payment],
+    hub: Option<&'a str>,
+    groups: &'a [Vec<String>],
+    soft: &'a Soft,
+    /// The stages are trait objects, so they are only known by their debug output.
+    pipeline: String,
+    transfer_costs: Vec<(&'a (String, String), &'a f64)>,
+}
+
+/// Solutions stored on disk by a hash of the instance and the options of the solver, so solving
+/// an unchanged instance again returns the stored solution. Solutions are stored for the
+/// [`CanonicalForm`] of the instance, so they are shared by instances with renamed people.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SolutionCache {
+    pub dir: PathBuf,
+}
+
+impl SolutionCache {
+    pub fn new(dir: PathBuf) -> Self {
+        SolutionCache { dir }
+    }
+
+    /// Default location of the cache, which is 'payback' in the cache directory of the user.
+    pub fn default_dir() -> Option<PathBuf> {
+        dirs::cache_dir().map(|d| d.join("payback"))
+    }
+
+    /// Hash of the instance and the options, which change the solution. The instance is
+    /// canonicalized, so the order and the names of the people and the order of the records
+    /// don't matter. Names are only part of the key, if constraints refer to them. The timeout
+    /// and the observer aren't part of the key.
+    pub fn key(instance: &ProblemInstance, config: &SolverConfig) -> String {
+        let form = CanonicalForm::of(instance);
+        let positions = form.positions();
+        let position = |name: &str| positions.get(name).copied().ok_or(name.to_owned());
+        let constraints = &config.constraints;
+        let key = Key {
+            version: env!("CARGO_PKG_VERSION"),
+            weights: form.hash(),
+            metadata: instance
+                .g
+                .metadata
+                .iter()
+                .map(|(name, m)| (position(name), m))
+                .sorted()
+                .collect(),
+            debts: instance
+                .g
+                .debts()
+                .unwrap_or_default()
+                .iter()
+                .map(|d| (position(&d.from), position(&d.to), d.amount))
+                .sorted()
+                .collect(),
+            names: (!constraints.is_empty()).then_some(&form.names),
+            method: config.method,
+            objective: config.objective,
+            objective_weights: config.objective_weights,
+            seed: config.seed,
+            time_budget: config.time_budget,
+            bucket_size: config.bucket_size,
+            cluster_by: config.cluster_by.as_deref(),
+            single_payment: config.single_payment,
+            forbidden: &constraints.forbidden,
+            preferred: &constraints.preferred,
+            max_transactions: constraints.max_transactions.iter().collect(),
+            max_transactions_per_person: constraints.max_transactions_per_person,
+            max_amount: constraints.max_amount,
+            fixed: &constraints.fixed,
+            hub: constraints.hub.as_deref(),
+            groups: &constraints.groups,
+            soft: &constraints.soft,
+            pipeline: format!("{:?}", config.pipeline),
+            transfer_costs: config.transfer_costs.sorted(),
+        };
+        let json = serde_json::to_string(&key).expect("Keys are serializable.");
+        hex::encode(Sha256::digest(json.as_bytes()))
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", key))
+    }
+
+    /// Stored solution of the instance, if there is one, which still settles the instance.
+    pub(crate) fn get(
+        &self,
+        instance: &ProblemInstance,
+        config: &SolverConfig,
+    ) -> Option<EdgeSolution> {
+        let path = self.path(&Self::key(instance, config));
+        let content = std::fs::read_to_string(&path).ok()?;
+        let invalid = |reason: &str| warn!("Ignoring cache entry {:?}, {}.", path, reason);
+        let transactions: Vec<(usize, usize, i64)> = serde_json::from_str(&content)
+            .inspect_err(|e| invalid(&e.to_string()))
+            .ok()?;
+        let Some(solution) = CanonicalForm::of(instance).resolve_positions(&transactions) else {
+            invalid("which references unknown people");
+            return None;
+        };
+        let solution = Some(solution);
+        let plan: Vec<Payment> = instance
+            .solution_transactions(&solution)?
+            .into_iter()
+            .map(|(from, to, amount)| Payment { from, to, amount })
+            .collect();
+        if !verify_plan(&instance.g, &plan).is_settled() {
+            invalid("which doesn't settle the instance");
+            return None;
+        }
+        debug!("Read cached solution {:?}", path);
+        Some(solution)
+    }
+
+    /// Stores the solution of the instance. Instances without a solution aren't stored.
+    pub(crate) fn put(
+        &self,
+        instance: &ProblemInstance,
+        config: &SolverConfig,
+        solution: &EdgeSolution,
+    ) -> Result<(), String> {
+        let Some(solution) = solution else {
+            return Ok(());
+        };
+        let transactions = CanonicalForm::of(instance).to_positions(solution);
+        let path = self.path(&Self::key(instance, config));
+        std::fs::create_dir_all(&self.dir)
+            .and_then(|_| {
+                std::fs::write(
+                    &path,
+                    serde_json::to_string(&transactions).expect("Transactions are serializable."),
+                )
+            })
+            .map_err(|e| format!("Unable to write cache entry {:?}: {}", path, e))?;
+        debug!("Wrote cached solution {:?}", path);
+        Ok(())
+    }
+
+    /// Returns the stored solution or solves the instance with the config and stores the
+    /// solution. Failing to store the solution is only logged. Randomized methods without a
+    /// seed or with a time budget return another solution on every run, so they neither read
+    /// nor store solutions.
+    #[instrument(level = "debug", skip_all, fields(dir = ?self.dir))]
+    pub(crate) fn solve(
+        &self,
+        instance: &ProblemInstance,
+        config: &SolverConfig,
+    ) -> Result<EdgeSolution, SolveError> {
+        if config.method.is_randomized() && (config.seed.is_none() || config.time_budget.is_some())
+        {
+            debug!("Not caching the solution of a randomized method");
+            return instance.solve_edges_with_config(config);
+        }
+        if let Some(solution) = self.get(instance, config) {
+            return Ok(solution);
+        }
+        let start = Instant::now();
+        let solution = instance.solve_edges_with_config(config)?;
+        let timed_out =
+            config.fallback_on_timeout && config.timeout.is_some_and(|t| start.elapsed() >= t);
+        if timed_out || is_cancelled(Cancel::new(config.cancel.as_deref())) {
+            // The approximation replaced the method, so the solution isn't the one of the key.
+            return Ok(solution);
+        }
+        if let Err(e) = self.put(instance, config, &solution) {
+            warn!("{}", e);
+        }
+        Ok(solution)
+    }
+
+    /// Solves the instance like [`SolutionCache::solve()`] and references the people of the
+    /// solution by their names.
+    pub fn solve_named(
+        &self,
+        instance: &ProblemInstance,
+        config: &SolverConfig,
+    ) -> Result<NamedSolution, SolveError> {
+        Ok(instance.named_solution(&self.solve(instance, config)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cache::SolutionCache;
+    use crate::constraints::Constraints;
+    use crate::graph::Graph;
+    use crate::probleminstance::{ProblemInstance, SolvingMethods};
+    use crate::solver_config::SolverConfig;
+    use crate::transfer_costs::TransferCosts;
+    use tracing::debug;
+    use tracing_subscriber::EnvFilter;
+
+    fn init() {
+        let _ = tracing_subscriber::fmt()
+            .with_env_filter(
+                EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("debug")),
+            )
+            .with_test_writer()
+            .try_init();
+    }
+
+    #[test]
+    fn test_solution_cache() {
+        init();
+        debug!("Running 'test_solution_cache'");
+        let dir = std::env::temp_dir().join(format!("payback-cache-{}", std::process::id()));
+        let cache = SolutionCache::new(dir.to_owned());
+        let instance = ProblemInstance::from(Graph::from(vec![
+            ("A".to_owned(), -3),
+            ("B".to_owned(), -2),
+            ("C".to_owned(), 5),
+        ]));
+        let config = SolverConfig {
+            method: SolvingMethods::DPGreedySatisfaction,
+            ..Default::default()
+        };
+        assert_eq!(cache.get(&instance, &config), None);
+        let sol = cache.solve(&instance, &config).unwrap();
+        assert_eq!(cache.get(&instance, &config), Some(sol.to_owned()));
+        assert_eq!(
+            cache.solve_named(&instance, &config).unwrap(),
+            instance.named_solution(&sol)
+        );
+
+        // The order of the people doesn't change the key, but the method does.
+        let reordered = ProblemInstance::from(Graph::from(vec![
+            ("C".to_owned(), 5),
+            ("B".to_owned(), -2),
+            ("A".to_owned(), -3),
+        ]));
+        assert_eq!(
+            SolutionCache::key(&instance, &config),
+            SolutionCache::key(&reordered, &config)
+        );
+        assert_eq!(
+            reordered.named_solution(&cache.get(&reordered, &config).unwrap()),
+            instance.named_solution(&sol)
+        );
+        let other = SolverConfig {
+            method: SolvingMethods::ApproxStarExpand,
+            ..Default::default()
+        };
+        assert_eq!(cache.get(&instance, &other), None);
+
+        // Renamed people share the stored solution.
+        let renamed = ProblemInstance::from(Graph::from(vec![
+            ("X".to_owned(), -2),
+            ("Y".to_owned(), -3),
+            ("Z".to_owned(), 5),
+        ]));
+        assert_eq!(
+            renamed.solution_transactions(&cache.get(&renamed, &config).unwrap()),
+            Some(vec![
+                ("X".to_owned(), "Z".to_owned(), 2),
+                ("Y".to_owned(), "Z".to_owned(), 3)
+            ])
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_randomized_not_cached() {
+        init();
+        debug!("Running 'test_randomized_not_cached'");
+        let dir = std::env::temp_dir().join(format!("payback-cache-random-{}", std::process::id()));
+        let cache = SolutionCache::new(dir.to_owned());
+        let instance = ProblemInstance::from(Graph::from(vec![-3, -2, 1, 4]));
+        let config = SolverConfig {
+            method: SolvingMethods::ApproxGenetic,
+            ..Default::default()
+        };
+        assert!(cache.solve(&instance, &config).unwrap().is_some());
+        assert_eq!(cache.get(&instance, &config), None);
+        assert!(!dir.exists());
+
+        // With a seed, the solution is the same on every run and is stored.
+        let config = SolverConfig {
+            seed: Some(1),
+            ..config
+        };
+        let sol = cache.solve(&instance, &config).unwrap();
+        assert_eq!(cache.get(&instance, &config), Some(sol));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_key() {
+        init();
+        debug!("Running 'test_key'");
+        let instance = ProblemInstance::from(Graph::from(vec![
+            ("A".to_owned(), -3),
+            ("B".to_owned(), -2),
+            ("C".to_owned(), 5),
+        ]));
+        let config = SolverConfig::default();
+        let key = SolutionCache::key(&instance, &config);
+        assert_eq!(key, SolutionCache::key(&instance, &config));
+        let forbidden = SolverConfig {
+            constraints: Constraints {
+                forbidden: vec![("A".to_owned(), "C".to_owned())],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let capped = SolverConfig {
+            constraints: Constraints {
+                max_transactions_per_person: Some(1),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let costs = SolverConfig {
+            transfer_costs: TransferCosts::parse(",C\nA,2").unwrap(),
+            ..Default::default()
+        };
+        let keys = [&forbidden, &capped, &costs].map(|c| SolutionCache::key(&instance, c));
+        assert!(keys.iter().all(|k| *k != key));
+        assert_ne!(keys[0], keys[1]);
+        let other_costs = SolverConfig {
+            transfer_costs: TransferCosts::parse(",C\nA,3").unwrap(),
+            ..Default::default()
+        };
+        assert_ne!(SolutionCache::key(&instance, &other_costs), keys[2]);
+    }
+}
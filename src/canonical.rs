@@ -0,0 +1,205 @@
+use sha2::{Digest, Sha256};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use tracing::debug;
+
+use crate::graph::{Edge, NamedNode};
+use crate::probleminstance::{EdgeSolution, ProblemInstance};
+use crate::solver_config::SolveError;
+
+/// Form of an instance, which doesn't depend on the names and the order of the people. Instances
+/// with the same multiset of weights have the same canonical form up to the names, so a solution
+/// of one instance is a solution of the other after renaming.
+///
+/// Example:
+/// ```
+/// use payback::canonical::CanonicalForm;
+/// use payback::graph::Graph;
+/// use payback::probleminstance::ProblemInstance;
+///
+/// let a = CanonicalForm::of(&ProblemInstance::from(Graph::from(vec![
+///     ("A".to_owned(), 2),
+///     ("B".to_owned(), -2),
+/// ])));
+/// let b = CanonicalForm::of(&ProblemInstance::from(Graph::from(vec![
+///     ("C".to_owned(), -2),
+///     ("D".to_owned(), 2),
+/// ])));
+/// assert_eq!(a.weights, vec![-2, 2]);
+/// assert_eq!(a.names, vec!["B", "A"]);
+/// assert_eq!(a.hash(), b.hash());
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CanonicalForm {
+    /// Weights other than zero in ascending order.
+    pub weights: Vec<i64>,
+    /// Names of the people in the order of their weights. People with the same weight are
+    /// ordered by their names.
+    pub names: Vec<String>,
+    /// Ids of the people in the instance in the order of their weights.
+    ids: Vec<usize>,
+}
+
+impl CanonicalForm {
+    pub fn of(instance: &ProblemInstance) -> Self {
+        let mut people: Vec<&NamedNode> = instance
+            .g
+            .vertices
+            .iter()
+            .filter(|v| v.weight != 0)
+            .collect();
+        people.sort_by(|a, b| a.weight.cmp(&b.weight).then_with(|| a.name.cmp(&b.name)));
+        CanonicalForm {
+            weights: people.iter().map(|v| v.weight).collect(),
+            names: people.iter().map(|v| v.name.to_owned()).collect(),
+            ids: people.iter().map(|v| v.id).collect(),
+        }
+    }
+
+    /// Stable hash of the weights, which is the same for every instance with the same multiset
+    /// of weights.
+    pub fn hash(&self) -> String {
+        let mut hasher = Sha256::new();
+        for w in &self.weights {
+            hasher.update(w.to_le_bytes());
+        }
+        hex::encode(hasher.finalize())
+    }
+
+    /// Position of every person in the canonical form by their name.
+    pub fn positions(&self) -> HashMap<&str, usize> {
+        self.names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| (name.as_str(), i))
+            .collect()
+    }
+
+    /// Transactions between the positions of the canonical form, which is independent of the
+    /// names. Transactions of people not in the canonical form are left out.
+    pub(crate) fn to_positions(&self, solution: &BTreeMap<Edge, i64>) -> Vec<(usize, usize, i64)> {
+        let positions: HashMap<usize, usize> = self
+            .ids
+            .iter()
+            .enumerate()
+            .map(|(i, id)| (*id, i))
+            .collect();
+        solution
+            .iter()
+            .filter_map(|(e, w)| Some((*positions.get(&e.v)?, *positions.get(&e.u)?, *w)))
+            .collect()
+    }
+
+    /// Solution of the instance of the canonical form from transactions between positions given
+    /// as '(payer, payee, amount)'. Returns `None` for positions outside of the form.
+    pub(crate) fn resolve_positions(
+        &self,
+        transactions: &[(usize, usize, i64)],
+    ) -> Option<BTreeMap<Edge, i64>> {
+        let mut solution = BTreeMap::new();
+        for (from, to, amount) in transactions {
+            let edge = Edge {
+                u: *self.ids.get(*to)?,
+                v: *self.ids.get(*from)?,
+            };
+            *solution.entry(edge).or_default() += amount;
+        }
+        Some(solution)
+    }
+}
+
+type Memoized = HashMap<String, (CanonicalForm, BTreeMap<Edge, i64>)>;
+
+/// Solutions of the parts of an instance by the hash of their canonical form, so parts with the
+/// same weights are only solved once.
+#[derive(Debug, Default)]
+pub(crate) struct SolutionMemo {
+    solutions: RefCell<Memoized>,
+}
+
+impl SolutionMemo {
+    /// Returns the solution of a part with the same weights or solves the part with `solve`.
+    /// Parts with metadata are always solved, since solvers can use the metadata of people.
+    pub(crate) fn solve(
+        &self,
+        part: &ProblemInstance,
+        solve: impl Fn(&ProblemInstance) -> Result<EdgeSolution, SolveError>,
+    ) -> Result<EdgeSolution, SolveError> {
+        if !part.g.metadata.is_empty() {
+            return solve(part);
+        }
+        let form = CanonicalForm::of(part);
+        let hash = form.hash();
+        if let Some((known, solution)) = self.solutions.borrow().get(&hash) {
+            debug!(hash, "Reusing the solution of a part with the same weights");
+            return Ok(form.resolve_positions(&known.to_positions(solution)));
+        }
+        let solution = solve(part)?;
+        if let Some(s) = &solution {
+            self.solutions
+                .borrow_mut()
+                .insert(hash, (form, s.to_owned()));
+        }
+        Ok(solution)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::canonical::CanonicalForm;
+    use crate::graph::Graph;
+    use crate::probleminstance::{ProblemInstance, SolvingMethods};
+    use tracing::debug;
+    use tracing_subscriber::EnvFilter;
+
+    fn init() {
+        let _ = tracing_subscriber::fmt()
+            .with_env_filter(
+                EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("debug")),
+            )
+            .with_test_writer()
+            .try_init();
+    }
+
+    #[test]
+    fn test_canonical_form() {
+        init();
+        debug!("Running 'test_canonical_form'");
+        let instance = ProblemInstance::from(Graph::from(vec![
+            ("A".to_owned(), -3),
+            ("B".to_owned(), 0),
+            ("C".to_owned(), 5),
+            ("D".to_owned(), -2),
+        ]));
+        let renamed = ProblemInstance::from(Graph::from(vec![
+            ("X".to_owned(), 5),
+            ("Y".to_owned(), -2),
+            ("Z".to_owned(), -3),
+        ]));
+        let form = CanonicalForm::of(&instance);
+        let other = CanonicalForm::of(&renamed);
+        debug!("Canonical forms: {:?}, {:?}", form, other);
+        assert_eq!(form.weights, vec![-3, -2, 5]);
+        assert_eq!(form.names, vec!["A", "D", "C"]);
+        assert_eq!(form.hash(), other.hash());
+        assert_ne!(
+            form.hash(),
+            CanonicalForm::of(&ProblemInstance::from(Graph::from(vec![-3, -1, 4]))).hash()
+        );
+
+        // A solution of one instance is a solution of the other after renaming.
+        let sol = instance
+            .solve_edges_with(SolvingMethods::DPGreedySatisfaction)
+            .unwrap();
+        let translated = other.resolve_positions(&form.to_positions(&sol)).unwrap();
+        let transactions = renamed.solution_transactions(&Some(translated)).unwrap();
+        debug!("Translated transactions: {:?}", transactions);
+        assert_eq!(
+            transactions,
+            vec![
+                ("Y".to_owned(), "X".to_owned(), 2),
+                ("Z".to_owned(), "X".to_owned(), 3)
+            ]
+        );
+    }
+}
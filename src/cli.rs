@@ -0,0 +1,1663 @@
+//! Runners of the subcommands of the command line interface, which turn the parsed arguments
+//! into the output of payback.
+use crate::audit::RoundingAudit;
+use crate::cache::SolutionCache;
+use crate::comparison::MethodResult;
+use crate::config::Config;
+use crate::constraints::Constraints;
+use crate::currency::{CurrencyTransactions, Rates};
+use crate::events::{format_bytes, SearchSummary, SolverEvent};
+use crate::graph::{Graph, Metadata};
+use crate::graph_parser::InstanceFormat;
+use crate::i18n::{Lang, Text};
+use crate::ledger::{Ledger, LedgerEvent, PlannedPayment};
+use crate::pipeline::{Pipeline, Postprocessor, Preprocessor};
+use crate::probleminstance::{DotOptions, EdgeSolution, ProblemInstance, SolvingMethods};
+use crate::provenance::Provenance;
+use crate::solver_config::{Objective, ObjectiveWeights, SolveError, SolverConfig};
+use crate::transfer_costs::TransferCosts;
+use crate::verification::Payment;
+#[cfg(feature = "webhook")]
+use crate::webhook;
+use crate::{
+    anonymize, baseline, batch, comparison, compression, corpus, currency, dates, decimals, diff,
+    generator, graph_parser, interest, metadata, payments, recommend, recurring, rounds, streaming,
+    units, verification,
+};
+#[cfg(feature = "grpc")]
+use crate::{grpc, jobs};
+use crate::{
+    AnonymizeArgs, Args, BatchArgs, BenchArgs, CliError, Commands, ConvertArgs, GenerateArgs,
+    LedgerAction, LedgerArgs, MethodChoice, OutputFormat, SolverArgs, VerifyArgs,
+};
+use clap::parser::ValueSource;
+use clap::{ArgMatches, CommandFactory, ValueEnum};
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use tracing::{debug, debug_span, info, warn};
+
+pub(crate) fn run_command(
+    mut args: Args,
+    matches: &ArgMatches,
+    config: &Config,
+) -> Result<String, CliError> {
+    // Values from the config only replace defaults, but not values given by the user.
+    if matches.value_source("method") == Some(ValueSource::DefaultValue) {
+        if let Some(method) = &config.method {
+            args.method = MethodChoice::from_str(method, true)
+                .map_err(|e| format!("Invalid method in config: {}", e))?;
+        }
+    }
+    if matches.value_source("output") == Some(ValueSource::DefaultValue) {
+        if let Some(output) = &config.output {
+            args.output = OutputFormat::from_str(output, true)
+                .map_err(|e| format!("Invalid output in config: {}", e))?;
+        }
+    }
+    let out = match args.command {
+        Some(Commands::Generate(generate_args)) => run_generate(generate_args),
+        Some(Commands::Bench(bench_args)) => run_bench(bench_args),
+        Some(Commands::Batch(batch_args)) => run_batch(*batch_args),
+        Some(Commands::Convert(convert_args)) => run_convert(convert_args),
+        Some(Commands::Verify(verify_args)) => run_verify(verify_args),
+        Some(Commands::Anonymize(anonymize_args)) => run_anonymize(anonymize_args),
+        Some(Commands::Diff { old, new }) => {
+            diff::diff_instances(&old.read()?, &new.read()?).map(|d| d.to_string())
+        }
+        Some(Commands::Ledger(ledger_args)) => run_ledger(ledger_args),
+        Some(Commands::Stream {
+            ref input,
+            capacity,
+        }) => run_stream(input, capacity, args.lang()),
+        Some(Commands::Methods) => Ok(methods_table()),
+        Some(Commands::Completions { shell }) => {
+            let mut buf: Vec<u8> = vec![];
+            clap_complete::generate(shell, &mut Args::command(), "payback", &mut buf);
+            String::from_utf8(buf).map_err(|e| e.to_string())
+        }
+        #[cfg(feature = "grpc")]
+        Some(Commands::Serve {
+            addr,
+            workers,
+            job_timeout,
+        }) => run_serve(addr, workers, job_timeout),
+        None if args.check => return run_check(args),
+        None => return run_solve(args, config),
+    };
+    out.map_err(CliError::from)
+}
+
+#[cfg(feature = "grpc")]
+fn run_serve(
+    addr: std::net::SocketAddr,
+    workers: Option<u64>,
+    job_timeout: Duration,
+) -> Result<String, String> {
+    let workers = workers.map_or_else(jobs::default_workers, |w| w as usize);
+    let jobs = jobs::JobQueue::new(workers, job_timeout);
+    tokio::runtime::Runtime::new()
+        .map_err(|e| e.to_string())?
+        .block_on(grpc::serve(addr, jobs))
+        .map(|_| "Server stopped.".to_string())
+        .map_err(|e| e.to_string())
+}
+
+/// Plan of an instance as text with its transactions grouped by currency.
+struct Plan {
+    text: String,
+    transactions: Vec<CurrencyTransactions>,
+    audit: RoundingAudit,
+}
+
+fn run_solve(mut args: Args, config: &Config) -> Result<String, CliError> {
+    let data = args
+        .file
+        .as_ref()
+        .ok_or("No input file was given.")?
+        .read()?;
+    // Formats other than csv are solved by their balances in the csv node format, so every
+    // option works the same for them. Edge records between numbered people like '1,2,5' would
+    // be read as nodes with a currency.
+    let format = match args.input_format {
+        Some(InstanceFormat::NodesCsv) => None,
+        Some(format) => Some(format),
+        None => graph_parser::detect_format(&data),
+    };
+    let data = match format {
+        Some(format) => graph_parser::deserialize_graph(&data, format)
+            .and_then(|graph| graph_parser::serialize_graph(&graph, InstanceFormat::NodesCsv))
+            .map_err(CliError::InvalidInput)?,
+        None => data,
+    };
+    let provenance = args.provenance.then(|| {
+        let mut provenance = match args.method {
+            MethodChoice::Method(method) => Provenance::new(method, data.as_bytes()),
+            MethodChoice::Recommend => Provenance {
+                method: "recommend".to_owned(),
+                ..Provenance::new(SolvingMethods::default(), data.as_bytes())
+            },
+        };
+        match args.solver.objective {
+            Objective::MinTransactions => {}
+            Objective::WeightedSum => {
+                provenance.objective = format!(
+                    "weighted-sum(alpha={},beta={})",
+                    args.solver.alpha, args.solver.beta
+                )
+            }
+            Objective::MinEffort => provenance.objective = "min-effort".to_owned(),
+            Objective::MinTransferCost => provenance.objective = "min-transfer-cost".to_owned(),
+            Objective::MinTransactionsThenAmount => {
+                provenance.objective = "min-transactions-then-amount".to_owned()
+            }
+        }
+        provenance.seed = args.solver.seed;
+        provenance
+    });
+    let preprocess = debug_span!("preprocess").entered();
+    let (data, mut metadata) = metadata::split_metadata(&data)?;
+    args.decimals = match config.decimals {
+        Some(places) if places <= decimals::MAX_DECIMALS => places,
+        Some(places) => {
+            return Err(CliError::InvalidInput(format!(
+                "The config has {} decimal places, but at most {} are supported.",
+                places,
+                decimals::MAX_DECIMALS
+            )))
+        }
+        None => decimals::decimal_places(&data).map_err(CliError::InvalidInput)?,
+    };
+    let data = decimals::to_minor_units_rounded(&data, args.decimals, config.rounding)
+        .map_err(CliError::InvalidInput)?;
+    args.unit =
+        units::instance_unit(&metadata, args.unit.as_deref()).map_err(CliError::InvalidInput)?;
+    config.add_payment_links(&mut metadata);
+    let mut audit = RoundingAudit::default();
+    let data = match &args.recurring {
+        Some(path) => recurring::add_recurring_audited(
+            &data,
+            // Recurring expenses with more decimal places than the instance are rounded like it.
+            &recurring::parse_recurring(&decimals::to_minor_units_rounded(
+                &std::fs::read_to_string(path)
+                    .map_err(|e| format!("Unable to read recurring expenses {:?}: {}", path, e))?,
+                args.decimals,
+                config.rounding,
+            )?)?,
+            args.from,
+            args.to,
+            &mut audit,
+        )?,
+        None => data,
+    };
+    let rate = args.interest.or(config.interest);
+    let late_fee = args.late_fee.or(config.late_fee);
+    let mut accrued_text = String::new();
+    let data = if rate.is_some() || late_fee.is_some() {
+        let terms = interest::AccrualTerms {
+            interest: rate.unwrap_or_default(),
+            late_fee: decimals::whole_to_minor(late_fee.unwrap_or_default(), args.decimals)?,
+            grace_days: args.grace_days.or(config.grace_days).unwrap_or_default(),
+        };
+        let settlement = match args.settle_on.or(args.to) {
+            Some(date) => date,
+            None => interest::latest_date(&data)?
+                .ok_or("Interest and late fees need records with a date column.")?,
+        };
+        let (data, debts) = interest::accrue(&data, settlement, &terms)?;
+        if !debts.is_empty() {
+            let lang = args.lang();
+            accrued_text = format!("{}:\n", lang.text(Text::Accrued));
+            for debt in &debts {
+                accrued_text += &format!(
+                    "  {} + {} ({})\n",
+                    lang.transaction(&debt.debtor, &debt.creditor, debt.principal, args.decimals),
+                    lang.amount(debt.accrued, args.decimals),
+                    debt.date.format(dates::DATE_FORMAT)
+                );
+            }
+            accrued_text += &format!(
+                "  {}: {}\n\n",
+                lang.text(Text::Total),
+                lang.amount(debts.iter().map(|d| d.accrued).sum(), args.decimals)
+            );
+        }
+        data
+    } else {
+        data
+    };
+    preprocess.exit();
+    let (text, transactions) = match args.group_by {
+        None => {
+            let plan = solve_data(
+                &dates::filter_by_date(&data, args.from, args.to)?,
+                &metadata,
+                &args,
+                config,
+            )?;
+            audit.merge(plan.audit);
+            (plan.text, plan.transactions)
+        }
+        Some(period) => {
+            let lang = args.lang();
+            let mut text = String::new();
+            let mut summary = String::new();
+            let mut transactions = vec![];
+            for (label, period_data) in dates::group_by_period(&data, args.from, args.to, period)? {
+                let plan = solve_data(&period_data, &metadata, &args, config)?;
+                text += &format!("{}:\n{}\n", label, plan.text);
+                summary += &format!(
+                    "  {}: {} {}\n",
+                    label,
+                    plan.transactions
+                        .iter()
+                        .map(|(_, ts)| ts.len())
+                        .sum::<usize>(),
+                    lang.text(Text::Transactions)
+                );
+                transactions.extend(plan.transactions);
+                audit.merge(plan.audit);
+            }
+            text += &format!(
+                "{}:\n{}  {}: {} {}\n{}:\n",
+                lang.text(Text::Summary),
+                summary,
+                lang.text(Text::Total),
+                transactions.iter().map(|(_, ts)| ts.len()).sum::<usize>(),
+                lang.text(Text::Transactions),
+                lang.text(Text::CumulativeBalances)
+            );
+            text += &cumulative_balances(&transactions, lang, args.decimals);
+            (text, transactions)
+        }
+    };
+    #[cfg(feature = "webhook")]
+    if let Some(webhook) = args.webhook.webhook() {
+        webhook.post(&webhook::transactions_json(
+            &transactions,
+            args.decimals,
+            provenance.as_ref(),
+        ))?;
+    }
+    #[cfg(not(feature = "webhook"))]
+    let _ = transactions;
+    if let Some(path) = &args.audit_file {
+        audit.save(path)?;
+    }
+    // The report of accrued amounts would break the dot output.
+    let text = match args.output {
+        OutputFormat::Transactions if args.audit_rounding => {
+            accrued_text + &text + "\n" + &audit.report(args.lang())
+        }
+        OutputFormat::Transactions => accrued_text + &text,
+        OutputFormat::Dot | OutputFormat::Csv => text,
+    };
+    Ok(match provenance {
+        Some(p) => {
+            let marker = match args.output {
+                OutputFormat::Dot => "//",
+                OutputFormat::Transactions | OutputFormat::Csv => "#",
+            };
+            format!("{}\n{}", p.comment(marker), text)
+        }
+        None => text,
+    })
+}
+
+/// Schedule of the transactions spread over the rounds of the arguments. The suffix follows
+/// every amount.
+fn rounds_text(
+    instance: &ProblemInstance,
+    sol: &EdgeSolution,
+    args: &Args,
+    suffix: Option<&str>,
+) -> Result<String, CliError> {
+    let lang = args.lang();
+    let transactions = instance
+        .solution_transactions(sol)
+        .ok_or_else(|| CliError::NoResult(lang.text(Text::NoResult).to_owned()))?;
+    let mut caps = HashMap::new();
+    for v in &instance.g.vertices {
+        let Some((_, cap)) = instance
+            .metadata(&v.name)
+            .and_then(|m| m.iter().find(|(k, _)| k == "cap"))
+        else {
+            continue;
+        };
+        let cap = cap.parse::<i64>().map_err(|_| {
+            CliError::InvalidInput(format!("The cap {:?} of {:?} is no integer.", cap, v.name))
+        })?;
+        caps.insert(
+            v.name.to_owned(),
+            decimals::whole_to_minor(cap, args.decimals).map_err(CliError::InvalidInput)?,
+        );
+    }
+    let limits = rounds::RoundLimits {
+        rounds: args.rounds.unwrap_or(1) as usize,
+        cap: args
+            .round_cap
+            .map(|cap| decimals::whole_to_minor(cap, args.decimals))
+            .transpose()
+            .map_err(CliError::InvalidInput)?,
+        caps,
+    };
+    let schedule =
+        rounds::schedule_rounds(&transactions, &limits).map_err(CliError::InvalidInput)?;
+    Ok(schedule
+        .iter()
+        .enumerate()
+        .map(|(i, round)| {
+            format!("{} {}:\n", lang.text(Text::Round), i + 1)
+                + &round
+                    .iter()
+                    .map(|p| {
+                        format!(
+                            "  {}{}\n",
+                            lang.transaction(&p.from, &p.to, p.amount, args.decimals),
+                            suffix.unwrap_or_default()
+                        )
+                    })
+                    .collect::<String>()
+        })
+        .collect())
+}
+
+/// Transactions as csv records 'from,to,amount' with the currency as additional column, if
+/// given. Amounts in minor units are written with `decimals` many decimal places.
+fn transactions_csv(
+    transactions: &[(String, String, i64)],
+    decimals: u32,
+    currency: Option<&str>,
+) -> Result<String, String> {
+    let mut wtr = csv::WriterBuilder::new()
+        .has_headers(false)
+        .from_writer(vec![]);
+    for (from, to, amount) in transactions {
+        let amount = decimals::format_minor(*amount, decimals, '.');
+        let mut record = vec![from.as_str(), to.as_str(), amount.as_str()];
+        record.extend(currency);
+        wtr.write_record(&record).map_err(|e| e.to_string())?;
+    }
+    String::from_utf8(wtr.into_inner().map_err(|e| e.to_string())?).map_err(|e| e.to_string())
+}
+
+/// Balances of every person in each currency and their exact value in the base currency.
+fn conversions_text(
+    rates: &Rates,
+    balances: &[currency::CurrencyBalance],
+    args: &Args,
+) -> Result<String, String> {
+    let lang = args.lang();
+    let factor = 10_f64.powi(args.decimals as i32);
+    Ok(format!("{}:\n", lang.text(Text::Conversions))
+        + &rates
+            .conversions(balances)?
+            .iter()
+            .map(|(b, converted)| {
+                format!(
+                    "  {:?}: {} {} = {:.prec$} {}\n",
+                    b.name,
+                    lang.amount(b.weight, args.decimals),
+                    b.currency,
+                    converted / factor,
+                    rates.base,
+                    prec = args.decimals.max(2) as usize
+                )
+            })
+            .collect::<String>()
+        + "\n")
+}
+
+/// Net amount every person received through the transactions per currency.
+fn cumulative_balances(transactions: &[CurrencyTransactions], lang: Lang, decimals: u32) -> String {
+    let mut balances: Vec<((String, Option<String>), i64)> = vec![];
+    for (currency, ts) in transactions {
+        for (from, to, amount) in ts {
+            for (name, delta) in [(from, -amount), (to, *amount)] {
+                let key = (name.to_owned(), currency.to_owned());
+                match balances.iter_mut().find(|(k, _)| *k == key) {
+                    Some((_, b)) => *b += delta,
+                    None => balances.push((key, delta)),
+                }
+            }
+        }
+    }
+    balances
+        .iter()
+        .map(|((name, currency), b)| match currency {
+            Some(c) => format!("  {:?}: {} {}\n", name, lang.amount(*b, decimals), c),
+            None => format!("  {:?}: {}\n", name, lang.amount(*b, decimals)),
+        })
+        .collect()
+}
+
+fn solve_data(
+    data: &str,
+    metadata: &HashMap<String, Metadata>,
+    args: &Args,
+    config: &Config,
+) -> Result<Plan, CliError> {
+    let constraints = args.solver.constraints()?;
+    let transfer_costs = args.solver.transfer_costs()?;
+    let parse = debug_span!("parse", bytes = data.len()).entered();
+    let data = &payments::net_payments(data)?;
+    if args.strict {
+        if let Some(debt) = graph_parser::negative_debts(data).first() {
+            return Err(CliError::InvalidInput(format!(
+                "The debt of {:?} to {:?} is negative: {}",
+                debt.from, debt.to, debt.amount
+            )));
+        }
+    }
+    let mut audit = RoundingAudit::default();
+    let mut conversions = String::new();
+    // Instances with their currency. Without conversion, every currency is solved on its own.
+    let instances: Vec<(Option<String>, Graph)> = match currency::parse_currency_balances(data) {
+        Some(_) if args.unit.is_some() => {
+            return Err(CliError::InvalidInput(format!(
+                "Amounts in {:?} can't have currencies.",
+                args.unit.as_deref().unwrap_or_default()
+            )))
+        }
+        Some(balances) => match args.currency.rates()? {
+            Some(rates) => {
+                if args.currency.show_conversions
+                    && matches!(args.output, OutputFormat::Transactions)
+                {
+                    conversions = conversions_text(&rates, &balances, args)?;
+                }
+                vec![(
+                    Some(rates.base.to_owned()),
+                    rates.convert_audited(&balances, &mut audit)?,
+                )]
+            }
+            None => currency::split_by_currency(&balances)
+                .into_iter()
+                .map(|(c, g)| (Some(c), g))
+                .collect(),
+        },
+        None => vec![(
+            args.unit.to_owned().or(config.currency.to_owned()),
+            data.to_string()
+                .try_into()
+                .map_err(|e: &str| CliError::InvalidInput(e.to_owned()))?,
+        )],
+    };
+    parse.exit();
+    let per_currency = instances.len() > 1;
+    let lang = args.lang();
+    let mut plan = Plan {
+        text: conversions,
+        transactions: vec![],
+        audit,
+    };
+    for (currency, graph) in instances {
+        let graph = graph.with_metadata(metadata.to_owned());
+        let graph = match &args.scale {
+            Some(scale) => scale.scale_graph(&graph).map_err(CliError::InvalidInput)?,
+            None => graph,
+        };
+        let graph = config.apply_aliases(graph);
+        let graph = if args.include.is_empty() && args.exclude.is_empty() {
+            graph
+        } else {
+            let keep = |name: &str| {
+                (args.include.is_empty() || args.include.iter().any(|n| n == name))
+                    && !args.exclude.iter().any(|n| n == name)
+            };
+            // Balances of the left out people, which aren't settled by the plan. Csv records
+            // can't hold them.
+            let excluded = graph
+                .balances()
+                .into_iter()
+                .filter(|(n, _)| !keep(n))
+                .filter(|_| !matches!(args.output, OutputFormat::Csv));
+            for (name, weight) in excluded {
+                plan.text += &format!(
+                    "{}{:?} {} {}{}\n",
+                    match args.output {
+                        OutputFormat::Dot => "// ",
+                        OutputFormat::Transactions | OutputFormat::Csv => "",
+                    },
+                    name,
+                    lang.text(Text::Excluded),
+                    lang.amount(weight, args.decimals),
+                    currency
+                        .as_ref()
+                        .map(|c| format!(" {}", c))
+                        .unwrap_or_default()
+                );
+            }
+            graph.restrict(keep)
+        };
+        let max_amount = max_transfer(&args.solver, &constraints, args.decimals)
+            .map_err(CliError::InvalidInput)?;
+        let instance = ProblemInstance::from(graph)
+            .with_decimals(args.decimals)
+            .with_max_amount(max_amount);
+        if !instance.is_solvable() {
+            return Err(CliError::Unbalanced {
+                imbalance: instance.imbalance(),
+                currency: currency.filter(|_| per_currency),
+            });
+        }
+        if args.bound_only {
+            if per_currency {
+                plan.text += &format!("{}:\n", currency.as_deref().unwrap_or_default());
+            }
+            let bounds = instance
+                .transaction_bounds()
+                .ok_or_else(|| CliError::NoResult(lang.text(Text::NoResult).to_owned()))?;
+            plan.text += &format!("{}\n", bounds);
+            continue;
+        }
+        // Every currency is solved with its own config and timeout, so a currency falling back
+        // after its timeout doesn't cancel the currencies after it.
+        let config = solver_config(
+            args.method,
+            args.timeout,
+            &args.solver,
+            &constraints,
+            transfer_costs.as_ref(),
+            args.stats,
+            &instance,
+        )?;
+        let cache = solution_cache(args);
+        let sol = match &cache {
+            Some(cache) => cache.solve(&instance, &config),
+            None => instance.solve_edges_with_config(&config),
+        }
+        .map_err(|e| match e {
+            SolveError::TimedOut(timeout) => CliError::TimedOut(timeout),
+            SolveError::Panicked => CliError::Other(e.to_string()),
+            SolveError::Infeasible(_) | SolveError::SearchLimit(_) => {
+                CliError::NoResult(e.to_string())
+            }
+        })?;
+        if let Some(path) = &args.trace {
+            instance
+                .search_trace(config.method)
+                .ok_or_else(|| {
+                    CliError::InvalidInput(format!(
+                        "Only exact methods can trace their search, but {:?} is used.",
+                        config.method.name()
+                    ))
+                })?
+                .write(path)?;
+        }
+        let _output = debug_span!("output", format = ?args.output).entered();
+        let settlement = instance.settlement(&sol);
+        if per_currency && !matches!(args.output, OutputFormat::Csv) {
+            plan.text += &format!("{}:\n", currency.as_deref().unwrap_or_default());
+        }
+        plan.text += &match args.output {
+            // Transactions in several currencies keep their currency like in the input.
+            OutputFormat::Csv => transactions_csv(
+                &instance
+                    .solution_transactions(&sol)
+                    .ok_or_else(|| CliError::NoResult(lang.text(Text::NoResult).to_owned()))?,
+                args.decimals,
+                currency.as_deref().filter(|_| per_currency),
+            )?,
+            OutputFormat::Dot => instance.solution_to_dot_string_with(
+                &settlement,
+                &DotOptions {
+                    show_debts: args.show_debts,
+                },
+            )?,
+            OutputFormat::Transactions => {
+                let suffix = currency.as_ref().map(|c| format!(" {}", c));
+                match &args.template {
+                    _ if args.rounds.is_some() => {
+                        rounds_text(&instance, &sol, args, suffix.as_deref())?
+                    }
+                    Some(template) => instance
+                        .solution_template_string(&settlement, template)?
+                        .lines()
+                        .map(|l| format!("{}{}\n", l, suffix.as_deref().unwrap_or_default()))
+                        .collect(),
+                    None => instance
+                        .solution_transactions(&sol)
+                        .ok_or_else(|| CliError::NoResult(lang.text(Text::NoResult).to_owned()))?
+                        .iter()
+                        .map(|(from, to, amount)| {
+                            // The payment link of the payee follows the amount with its currency.
+                            format!(
+                                "{}{}{}\n",
+                                lang.transaction(from, to, *amount, args.decimals),
+                                suffix.as_deref().unwrap_or_default(),
+                                instance
+                                    .payment_link(from, to, *amount)
+                                    .map(|l| format!(" {}", l))
+                                    .unwrap_or_default()
+                            )
+                        })
+                        .collect(),
+                }
+            }
+        };
+        #[cfg(feature = "qr")]
+        {
+            plan.text += &args.qr.render(&instance, &sol, lang)?;
+        }
+        plan.transactions.push((
+            currency.filter(|_| per_currency),
+            instance
+                .solution_transactions(&sol)
+                .ok_or_else(|| CliError::NoResult(lang.text(Text::NoResult).to_owned()))?,
+        ));
+    }
+    Ok(plan)
+}
+
+/// Cache of the plans, unless `--no-cache` is given. The statistics of `--stats` are only
+/// collected while solving, so they skip the cache, too.
+fn solution_cache(args: &Args) -> Option<SolutionCache> {
+    SolutionCache::default_dir()
+        .filter(|_| !args.no_cache && !args.stats)
+        .map(SolutionCache::new)
+}
+
+/// Largest amount of a single transfer in minor units given by '--max-amount' or the
+/// constraints.
+fn max_transfer(
+    solver: &SolverArgs,
+    constraints: &Constraints,
+    decimals: u32,
+) -> Result<Option<i64>, String> {
+    solver
+        .max_amount
+        .or(constraints.max_amount)
+        .map(|max| decimals::whole_to_minor(max, decimals))
+        .transpose()
+}
+
+/// Options of the solver given by the arguments.
+fn solver_config(
+    method: MethodChoice,
+    timeout: Option<Duration>,
+    solver: &SolverArgs,
+    constraints: &Constraints,
+    transfer_costs: Option<&TransferCosts>,
+    stats: bool,
+    instance: &ProblemInstance,
+) -> Result<SolverConfig, String> {
+    if solver.objective == Objective::MinTransferCost && transfer_costs.is_none() {
+        return Err("The 'min-transfer-cost' objective requires --transfer-costs.".to_owned());
+    }
+    let method = match method {
+        MethodChoice::Method(method) => method,
+        MethodChoice::Recommend => {
+            let history = match recommend::History::default_path() {
+                Some(path) => recommend::History::load(&path)?,
+                None => recommend::History::default(),
+            };
+            let method = recommend::recommend(
+                &history,
+                instance,
+                timeout.unwrap_or(Duration::from_secs(1)),
+            );
+            info!("Recommended method: {:?}", method);
+            method
+        }
+    };
+    Ok(SolverConfig {
+        method,
+        timeout,
+        fallback_on_timeout: solver.fallback,
+        cancel: interrupt_flag(),
+        threads: solver.threads.map(|t| t as usize),
+        bucket_size: solver.bucket_size,
+        cluster_by: solver.cluster_by.to_owned(),
+        seed: solver.seed,
+        time_budget: solver.time_budget,
+        single_payment: solver.single_payment,
+        constraints: {
+            let mut constraints = constraints.to_owned();
+            constraints.max_transactions_per_person = solver
+                .max_transactions_per_person
+                .or(constraints.max_transactions_per_person);
+            constraints.forbidden.extend(solver.forbid.iter().cloned());
+            constraints.preferred.extend(solver.prefer.iter().cloned());
+            // The instance holds the largest amount in minor units like the plan.
+            constraints.max_amount = instance.max_amount();
+            constraints
+        },
+        transfer_costs: transfer_costs.cloned().unwrap_or_default(),
+        pipeline: Pipeline {
+            pre: solver
+                .preprocess
+                .iter()
+                .map(|step| Arc::new(*step) as Arc<dyn Preprocessor>)
+                .collect(),
+            post: solver
+                .postprocess
+                .iter()
+                .map(|step| Arc::new(*step) as Arc<dyn Postprocessor>)
+                .collect(),
+        },
+        objective: solver.objective,
+        objective_weights: ObjectiveWeights {
+            alpha: solver.alpha,
+            beta: solver.beta,
+        },
+        observer: Some(if stats {
+            let people = instance.g.vertices.iter().filter(|v| v.weight != 0).count();
+            let estimate = method.estimated_memory(people);
+            Arc::new(move |event: &SolverEvent| {
+                log_solver_event(event);
+                if let SolverEvent::Finished(summary) = event {
+                    eprint!("{}", stats_text(summary, estimate));
+                }
+            })
+        } else {
+            Arc::new(log_solver_event)
+        }),
+    })
+}
+
+/// Statistics of a solve printed by '--stats'.
+fn stats_text(summary: &SearchSummary, estimated_memory: Option<usize>) -> String {
+    format!(
+        "Transactions: {}\nLower bound: {}\nIncumbents: {}\nPartitions: {}\nFallback: {}\n\
+         Peak memory: {}\nEstimated memory: {}\nElapsed: {:?}\n",
+        summary
+            .transactions
+            .map_or("-".to_owned(), |t| t.to_string()),
+        summary.lower_bound,
+        summary.incumbents,
+        summary.partitions,
+        summary.fallback,
+        format_bytes(summary.peak_memory),
+        estimated_memory.map_or("-".to_owned(), format_bytes),
+        summary.elapsed
+    )
+}
+
+/// Flag set by the first Ctrl-C, which stops the exact methods, so the plan of their
+/// approximation is printed. A second Ctrl-C terminates payback as usual.
+#[cfg(unix)]
+fn interrupt_flag() -> Option<Arc<AtomicBool>> {
+    static INTERRUPTED: OnceLock<Arc<AtomicBool>> = OnceLock::new();
+    extern "C" fn on_interrupt(_: libc::c_int) {
+        if let Some(flag) = INTERRUPTED.get() {
+            flag.store(true, Ordering::Relaxed);
+        }
+        // SAFETY: Restoring the default handler is async-signal-safe.
+        unsafe { libc::signal(libc::SIGINT, libc::SIG_DFL) };
+    }
+    let flag = INTERRUPTED.get_or_init(|| {
+        // SAFETY: The handler only touches an atomic and the signal disposition.
+        unsafe {
+            libc::signal(
+                libc::SIGINT,
+                on_interrupt as extern "C" fn(libc::c_int) as libc::sighandler_t,
+            )
+        };
+        Arc::new(AtomicBool::new(false))
+    });
+    Some(flag.clone())
+}
+
+#[cfg(not(unix))]
+fn interrupt_flag() -> Option<Arc<AtomicBool>> {
+    None
+}
+
+fn log_solver_event(event: &SolverEvent) {
+    match event {
+        SolverEvent::FallbackTriggered { reason } => {
+            warn!("{} Using the approximation instead.", reason)
+        }
+        SolverEvent::Finished(summary) => info!(
+            transactions = summary.transactions,
+            lower_bound = summary.lower_bound,
+            incumbents = summary.incumbents,
+            partitions = summary.partitions,
+            peak_memory = summary.peak_memory,
+            elapsed = ?summary.elapsed,
+            "Finished solving"
+        ),
+        event => debug!(?event, "Solver progress"),
+    }
+}
+
+/// Validates the input. Warnings are printed before the result, while errors fail with all
+/// issues.
+fn run_check(args: Args) -> Result<String, CliError> {
+    let file = args.file.ok_or("No input file was given.")?;
+    let issues = graph_parser::validate_instance(&file.read()?);
+    if issues.iter().any(|i| i.is_error()) {
+        return Err(CliError::Check(issues));
+    }
+    Ok(issues
+        .iter()
+        .map(|i| format!("{}\n", i))
+        .chain(["Instance is valid.".to_owned()])
+        .collect())
+}
+
+fn run_generate(args: GenerateArgs) -> Result<String, String> {
+    graph_parser::serialize_graph_to_string(&generator::generate(&(&args).into()))
+}
+
+fn run_anonymize(args: AnonymizeArgs) -> Result<String, String> {
+    // Metadata like IBANs and dates are dropped, since they could identify people.
+    let (data, _) = metadata::split_metadata(&args.input.read()?)?;
+    let graph: Graph = dates::filter_by_date(&data, None, None)?.try_into()?;
+    let options = anonymize::AnonymizeOptions {
+        salt: args.salt,
+        perturb: args.perturb,
+        seed: args.seed,
+    };
+    let (anonymized, mapping) = anonymize::anonymize(&graph, &options);
+    if let Some(path) = args.mapping {
+        let mut wtr = csv::Writer::from_path(&path)
+            .map_err(|e| format!("Unable to write mapping {:?}: {}", path, e))?;
+        mapping
+            .iter()
+            .try_for_each(|(name, alias)| wtr.write_record([name, alias]))
+            .and_then(|_| wtr.flush().map_err(csv::Error::from))
+            .map_err(|e| format!("Unable to write mapping {:?}: {}", path, e))?;
+    }
+    graph_parser::serialize_graph(&anonymized, InstanceFormat::NodesCsv)
+}
+
+fn run_convert(args: ConvertArgs) -> Result<String, String> {
+    let data = args.input.read()?;
+    let graph: Graph = match args.from {
+        Some(format) => graph_parser::deserialize_graph(&data, format)?,
+        None => data.try_into()?,
+    };
+    let out = graph_parser::serialize_graph(&graph, args.to)?;
+    match args.output {
+        Some(path) => std::fs::write(&path, out)
+            .map(|_| format!("Written instance to {:?}.", path))
+            .map_err(|e| e.to_string()),
+        None => Ok(out),
+    }
+}
+
+fn run_verify(args: VerifyArgs) -> Result<String, String> {
+    let graph: Graph = args.instance.read()?.try_into()?;
+    let plan = graph_parser::deserialize_plan(&args.plan.read()?)?;
+    let report = verification::verify_plan(&graph, &plan);
+    if report.is_settled() {
+        Ok(report.to_string())
+    } else {
+        Err(report.to_string())
+    }
+}
+
+fn run_ledger(args: LedgerArgs) -> Result<String, String> {
+    let path = args.ledger.as_path();
+    let mut ledger = Ledger::load(path)?;
+    match args.action {
+        LedgerAction::Expense {
+            payer,
+            amount,
+            participants,
+        } => ledger
+            .append(
+                path,
+                LedgerEvent::ExpenseAdded {
+                    payer,
+                    amount,
+                    participants,
+                },
+            )
+            .map(|_| "Recorded expense.".to_string()),
+        LedgerAction::Pay { from, to, amount } => ledger
+            .append(
+                path,
+                LedgerEvent::PaymentRecorded(Payment { from, to, amount }),
+            )
+            .map(|_| "Recorded payment.".to_string()),
+        LedgerAction::Balances { at } => graph_parser::serialize_graph(
+            &ledger.replay(at.unwrap_or(ledger.entries.len())),
+            InstanceFormat::NodesCsv,
+        ),
+        LedgerAction::Settle { method, dry_run } => {
+            let instance = ProblemInstance::from(ledger.balances());
+            let sol = instance.solve_edges_with(method);
+            let plan = instance.solution_string(&instance.settlement(&sol))?;
+            if !dry_run {
+                let payments = settlement_payments(&instance, &sol)?;
+                ledger.append(path, LedgerEvent::SettlementExecuted { payments })?;
+            }
+            Ok(plan)
+        }
+        LedgerAction::Plan { method } => {
+            let instance = ProblemInstance::from(ledger.balances());
+            let payments = settlement_payments(&instance, &instance.solve_edges_with(method))?;
+            Ok(planned_payments_string(
+                &ledger.propose_plan(path, payments)?,
+            ))
+        }
+        LedgerAction::Paid { ids } => {
+            let paid = ids
+                .iter()
+                .map(|id| ledger.mark_paid(path, id))
+                .collect::<Result<Vec<PlannedPayment>, String>>()?;
+            Ok(format!(
+                "Marked as paid:\n{}",
+                planned_payments_string(&paid)
+            ))
+        }
+        LedgerAction::Open => Ok(planned_payments_string(&ledger.open_payments())),
+        LedgerAction::Log => ledger
+            .entries
+            .iter()
+            .map(|e| serde_json::to_string(e).map_err(|e| e.to_string()))
+            .collect::<Result<Vec<String>, String>>()
+            .map(|lines| lines.join("\n")),
+        LedgerAction::Compact => {
+            let events = ledger.entries.len();
+            ledger.compact().write(path)?;
+            Ok(format!("Compacted {} events.", events))
+        }
+    }
+}
+
+/// Converts the settlement of a solution into payments.
+fn settlement_payments(
+    instance: &ProblemInstance,
+    sol: &EdgeSolution,
+) -> Result<Vec<Payment>, String> {
+    Ok(instance
+        .settlement(sol)
+        .ok_or("No result was found.")?
+        .into_iter()
+        .map(Payment::from)
+        .collect())
+}
+
+fn planned_payments_string(payments: &[PlannedPayment]) -> String {
+    payments
+        .iter()
+        .map(|p| {
+            format!(
+                "{}: {:?} to {:?}: {}\n",
+                p.id, p.payment.from, p.payment.to, p.payment.amount
+            )
+        })
+        .collect()
+}
+
+fn methods_table() -> String {
+    let mut table = format!(
+        "{:<40} | {:<13} | {:<18} | {:<13}\n",
+        "Method", "Type", "Runtime", "Max. vertices"
+    );
+    for method in SolvingMethods::value_variants() {
+        let value = method.to_possible_value();
+        table += &format!(
+            "{:<40} | {:<13} | {:<18} | {:<13}\n",
+            value
+                .as_ref()
+                .map_or(format!("{:?}", method), |v| v.get_name().to_owned()),
+            if method.is_exact() {
+                "exact"
+            } else {
+                "approximation"
+            },
+            method.complexity(),
+            method
+                .max_feasible_vertices()
+                .map_or("-".to_owned(), |n| n.to_string()),
+        );
+        if let Some(help) = value.as_ref().and_then(|v| v.get_help()) {
+            table += &format!("    {}\n", help);
+        }
+    }
+    table
+}
+
+fn run_bench(args: BenchArgs) -> Result<String, String> {
+    let instances: Vec<(String, Graph)> = match (args.file, args.corpus) {
+        (Some(file), _) => vec![("file".to_owned(), file.read()?.try_into()?)],
+        (None, Some(corpus)) => corpus::load_corpus(corpus)
+            .into_iter()
+            .map(|c| (c.name.to_owned(), c.graph))
+            .collect(),
+        (None, None) => vec![(
+            "generated".to_owned(),
+            generator::generate(&(&args.generate).into()),
+        )],
+    };
+    let history = recommend::History::default_path().filter(|_| !args.no_history);
+    let mut tables = vec![];
+    let mut current = baseline::Baseline::default();
+    for (name, graph) in instances {
+        let instance = ProblemInstance::from(graph);
+        let results = bench_results(&instance, &args.methods, args.timeout);
+        if let Some(path) = &history {
+            recommend::History::append(path, &recommend::bench_records(&instance, &results))?;
+        }
+        current.add(&name, &results);
+        tables.push(format!(
+            "Instance '{}' with {} vertices:\n{}",
+            name,
+            instance.vertex_count(),
+            bench_table(results)
+        ));
+    }
+    let tables = tables.join("\n");
+    let Some(path) = &args.baseline else {
+        return Ok(tables);
+    };
+    let Some(baseline) = baseline::Baseline::load(path)? else {
+        current.save(path)?;
+        return Ok(format!("{}\nWritten baseline to {:?}.", tables, path));
+    };
+    let regressions = baseline.compare(&current, args.regression_threshold);
+    if args.update_baseline {
+        current.save(path)?;
+    }
+    if regressions.is_empty() {
+        return Ok(format!(
+            "{}\nNo regressions compared to {:?}.",
+            tables, path
+        ));
+    }
+    // The tables are part of the error, so they are printed once with it like on success.
+    Err(format!(
+        "{}\n{} regressions compared to {:?}:\n{}",
+        tables,
+        regressions.len(),
+        path,
+        regressions
+            .iter()
+            .map(|r| format!("  {}", r))
+            .collect::<Vec<_>>()
+            .join("\n")
+    ))
+}
+
+fn run_stream(input: &std::path::Path, capacity: usize, lang: Lang) -> Result<String, String> {
+    let reader: Box<dyn std::io::Read> = if input == std::path::Path::new("-") {
+        Box::new(std::io::stdin())
+    } else {
+        Box::new(
+            std::fs::File::open(input)
+                .map_err(|e| format!("Unable to read instance {:?}: {}", input, e))?,
+        )
+    };
+    let mut records = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .from_reader(compression::decompressing_reader(reader)?);
+    let balances = records.records().map(|r| {
+        let r = r.map_err(|e| e.to_string())?;
+        match (r.get(0), r.get(1).map(|w| w.trim().parse::<i64>())) {
+            (Some(name), Some(Ok(weight))) if r.len() == 2 => Ok((name.to_owned(), weight)),
+            _ => Err(format!(
+                "Expected records 'NodeName,weight', but got '{}'.",
+                r.iter().collect::<Vec<_>>().join(",")
+            )),
+        }
+    });
+    let mut out = std::io::BufWriter::new(std::io::stdout().lock());
+    let count = streaming::settle_stream(balances, capacity, |p| {
+        writeln!(out, "{}", lang.transaction(&p.from, &p.to, p.amount, 0))
+            .map_err(|e| e.to_string())
+    })?;
+    out.flush().map_err(|e| e.to_string())?;
+    Ok(format!("{} {}", count, lang.text(Text::Transactions)))
+}
+
+fn run_batch(args: BatchArgs) -> Result<String, String> {
+    let paths = batch::instance_paths(&args.input)?;
+    if paths.is_empty() {
+        return Err(format!("No instances were found in {:?}.", args.input));
+    }
+    let constraints = args.solver.constraints()?;
+    let transfer_costs = args.solver.transfer_costs()?;
+    let results = batch::solve_batch_with(
+        &paths,
+        |instance| {
+            let max_amount = max_transfer(&args.solver, &constraints, instance.decimals())?;
+            let instance = instance.with_max_amount(max_amount);
+            let config = solver_config(
+                MethodChoice::Method(args.method),
+                args.timeout,
+                &args.solver,
+                &constraints,
+                transfer_costs.as_ref(),
+                false,
+                &instance,
+            )?;
+            Ok((instance, config))
+        },
+        args.jobs.unwrap_or_else(|| {
+            std::thread::available_parallelism().map_or(1, std::num::NonZeroUsize::get)
+        }),
+    );
+    std::fs::create_dir_all(&args.out_dir)
+        .map_err(|e| format!("Unable to create directory {:?}: {}", args.out_dir, e))?;
+    let write = |name: &str, json: serde_json::Value| {
+        let path = args.out_dir.join(format!("{}.json", name));
+        std::fs::write(&path, format!("{:#}\n", json))
+            .map_err(|e| format!("Unable to write {:?}: {}", path, e))
+    };
+    for result in &results {
+        write(&result.name, result.to_json())?;
+    }
+    let summary = batch::summary_json(&results);
+    write("summary", summary.to_owned())?;
+    let failed: Vec<&str> = results
+        .iter()
+        .filter(|r| r.outcome.is_err())
+        .map(|r| r.name.as_str())
+        .collect();
+    if !failed.is_empty() {
+        return Err(format!(
+            "{} of {} instances couldn't be solved: {}",
+            failed.len(),
+            results.len(),
+            failed.join(", ")
+        ));
+    }
+    Ok(format!(
+        "Solved {} instances with {} transactions.",
+        summary["instances"], summary["transactions"]
+    ))
+}
+
+/// Solves the instance with every given method. If no methods are given, all feasible methods
+/// are used.
+fn bench_results(
+    instance: &ProblemInstance,
+    methods: &[SolvingMethods],
+    timeout: Option<Duration>,
+) -> BTreeMap<SolvingMethods, MethodResult> {
+    let config = SolverConfig {
+        timeout,
+        ..Default::default()
+    };
+    if methods.is_empty() {
+        comparison::solve_all_methods(instance, &config)
+    } else {
+        comparison::solve_methods(instance, methods, &config)
+    }
+}
+
+fn bench_table(results: BTreeMap<SolvingMethods, MethodResult>) -> String {
+    let mut table = format!(
+        "{:<40} | {:>12} | {:>12} | {:>12}\n",
+        "Method", "Time [ms]", "Transactions", "Total amount"
+    );
+    for (method, result) in results {
+        let name = method
+            .to_possible_value()
+            .map_or(format!("{:?}", method), |v| v.get_name().to_owned());
+        let metrics = result.metrics;
+        let (transactions, total) = match (metrics.transactions, metrics.total_amount) {
+            (Some(transactions), Some(total)) => (transactions.to_string(), total.to_string()),
+            _ => match result.solution {
+                Err(SolveError::TimedOut(_)) => ("timeout".to_owned(), "-".to_owned()),
+                Err(SolveError::Panicked) => ("panicked".to_owned(), "-".to_owned()),
+                Err(_) => ("failed".to_owned(), "-".to_owned()),
+                Ok(_) => ("-".to_owned(), "-".to_owned()),
+            },
+        };
+        table += &format!(
+            "{:<40} | {:>12.3} | {:>12} | {:>12}\n",
+            name,
+            metrics.elapsed.as_secs_f64() * 1000.0,
+            transactions,
+            total
+        );
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::audit::RoundingAudit;
+    use crate::cli::{run_command, solution_cache};
+    use crate::config::Config;
+    use crate::{Args, CliError};
+    use clap::{CommandFactory, FromArgMatches};
+    use std::time::Duration;
+    use tracing::debug;
+    use tracing_subscriber::EnvFilter;
+
+    fn init() {
+        let _ = tracing_subscriber::fmt()
+            .with_env_filter(
+                EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("debug")),
+            )
+            .with_test_writer()
+            .try_init();
+    }
+
+    /// Parses the arguments after the name of the binary.
+    fn parse(args: &[&str]) -> (Args, clap::ArgMatches) {
+        let matches = Args::command()
+            .try_get_matches_from(std::iter::once("payback").chain(args.iter().copied()))
+            .unwrap();
+        (Args::from_arg_matches(&matches).unwrap(), matches)
+    }
+
+    /// Runs payback on the data, which is written to a file of the name, with the arguments
+    /// following the file.
+    fn run(name: &str, data: &str, args: &[&str]) -> Result<String, CliError> {
+        let path = std::env::temp_dir().join(format!("payback-{}-{}", name, std::process::id()));
+        std::fs::write(&path, data).unwrap();
+        let path = path.to_string_lossy().into_owned();
+        let (args, matches) = parse(&[&[path.as_str(), "--no-cache"], args].concat());
+        let out = run_command(args, &matches, &Config::default());
+        std::fs::remove_file(&path).unwrap();
+        out
+    }
+
+    /// Timeout of the tests, which solve quick and slow instances with the same options. The
+    /// quick ones take milliseconds, while the partitioning methods take minutes for twelve
+    /// people, so the timeout isn't raced on loaded machines.
+    const TIMEOUT: &str = "5";
+
+    /// Ledger of twelve people in January, which the partitioning methods can't solve quickly,
+    /// and of four people in February, who settle with two transactions.
+    const LEDGER: &str = "A,-9,2024-01-02\nB,-8,2024-01-02\nC,-7,2024-01-03\nD,-5,2024-01-04\n\
+        E,-3,2024-01-05\nF,-1,2024-01-06\nG,2,2024-01-07\nH,4,2024-01-08\nI,6,2024-01-09\n\
+        J,7,2024-01-10\nK,10,2024-01-11\nL,4,2024-01-12\nP,-2,2024-02-01\nQ,-1,2024-02-01\n\
+        R,1,2024-02-02\nS,2,2024-02-02\n";
+
+    #[test]
+    fn test_group_by_after_timeout() {
+        init();
+        debug!("Running 'test_group_by_after_timeout'");
+        let args = [
+            "transactions",
+            "partitioning-star-expand",
+            "--group-by",
+            "month",
+            "--timeout",
+            TIMEOUT,
+            "--fallback",
+        ];
+        let out = run("group-by", LEDGER, &args).unwrap();
+        // January falls back to the star, but February is still solved optimally instead of
+        // starting out cancelled.
+        assert!(out.contains("2024-01: 11 transactions"), "{}", out);
+        assert!(out.contains("2024-02: 2 transactions"), "{}", out);
+        assert!(out.contains("Total: 13 transactions"), "{}", out);
+    }
+
+    #[test]
+    fn test_fallback() {
+        init();
+        debug!("Running 'test_fallback'");
+        let january = LEDGER.lines().take(12).collect::<Vec<_>>().join("\n");
+        let args = [
+            "transactions",
+            "partitioning-star-expand",
+            "--timeout",
+            "0.1",
+        ];
+        assert_eq!(
+            run("timeout", &january, &args),
+            Err(CliError::TimedOut(Duration::from_millis(100)))
+        );
+        let out = run("fallback", &january, &[&args[..], &["--fallback"]].concat()).unwrap();
+        assert_eq!(out.lines().count(), 11);
+    }
+
+    #[test]
+    fn test_audit_file() {
+        init();
+        debug!("Running 'test_audit_file'");
+        let path = std::env::temp_dir().join(format!("payback-audit-cli-{}", std::process::id()));
+        let audit_file = path.to_string_lossy().into_owned();
+        let out = run("audit", "A,-1\nB,1", &["--audit-file", &audit_file]).unwrap();
+        assert_eq!(out, "\"A\" to \"B\": 1\n");
+        assert!(RoundingAudit::load(&path).unwrap().is_balanced());
+        std::fs::remove_file(&path).unwrap();
+
+        // The plan isn't printed, if its audit is lost.
+        let missing = path.join("audit.json").to_string_lossy().into_owned();
+        assert!(matches!(
+            run("audit-missing", "A,-1\nB,1", &["--audit-file", &missing]),
+            Err(CliError::Other(e)) if e.starts_with("Unable to write rounding audit")
+        ));
+    }
+
+    #[test]
+    fn test_batch_options() {
+        init();
+        debug!("Running 'test_batch_options'");
+        let dir = std::env::temp_dir().join(format!("payback-batch-cli-{}", std::process::id()));
+        let out_dir = dir.join("results");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("pairs.csv"), "A,-2\nB,-2\nC,2\nD,2").unwrap();
+        // Twelve people, whom the partitioning methods can't settle within the timeout.
+        std::fs::write(
+            dir.join("slow.csv"),
+            "A,-9\nB,-8\nC,-7\nD,-5\nE,-3\nF,-1\nG,2\nH,4\nI,6\nJ,7\nK,10\nL,4",
+        )
+        .unwrap();
+        let batch = |options: &[&str]| {
+            let input = dir.join("*.csv").to_string_lossy().into_owned();
+            let out_dir = out_dir.to_string_lossy().into_owned();
+            let (args, matches) = parse(
+                &[
+                    &["batch", &input, "--out-dir", &out_dir],
+                    &["-m", "partitioning-star-expand", "--timeout", TIMEOUT],
+                    options,
+                ]
+                .concat(),
+            );
+            run_command(args, &matches, &Config::default())
+        };
+        let plan = |name: &str| -> serde_json::Value {
+            let json = std::fs::read_to_string(out_dir.join(format!("{}.json", name))).unwrap();
+            serde_json::from_str(&json).unwrap()
+        };
+
+        assert!(matches!(
+            batch(&[]),
+            Err(CliError::Other(e)) if e.ends_with("couldn't be solved: slow")
+        ));
+        // The slow instance falls back to the approximation, while the constraints apply to
+        // both.
+        assert_eq!(
+            batch(&["--fallback", "--forbid", "A,C", "--max-amount", "1"]),
+            Ok("Solved 2 instances with 60 transactions.".to_owned())
+        );
+        assert_eq!(
+            plan("pairs")["transactions"],
+            serde_json::json!([
+                {"from": "B", "to": "C", "amount": 1},
+                {"from": "B", "to": "C", "amount": 1},
+                {"from": "A", "to": "D", "amount": 1},
+                {"from": "A", "to": "D", "amount": 1},
+            ])
+        );
+        assert!(matches!(
+            batch(&["--objective", "min-transfer-cost"]),
+            Err(CliError::Other(e)) if e.starts_with("2 of 2 instances")
+        ));
+        assert_eq!(
+            plan("pairs")["error"],
+            "The 'min-transfer-cost' objective requires --transfer-costs."
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_bench() {
+        init();
+        debug!("Running 'test_bench'");
+        let path = std::env::temp_dir().join(format!("payback-bench-{}", std::process::id()));
+        std::fs::write(
+            &path,
+            "A,-9\nB,-8\nC,-7\nD,-5\nE,-3\nF,-1\nG,2\nH,4\nI,6\nJ,7\nK,10\nL,4",
+        )
+        .unwrap();
+        let file = path.to_string_lossy().into_owned();
+        let (args, matches) = parse(&[
+            "bench",
+            &file,
+            "-m",
+            "dp-star-expand,partitioning-star-expand",
+            "--timeout",
+            TIMEOUT,
+            "--no-history",
+        ]);
+        let out = run_command(args, &matches, &Config::default()).unwrap();
+        debug!("Bench:\n{}", out);
+        let rows: Vec<Vec<&str>> = out
+            .lines()
+            .skip(2)
+            .map(|row| row.split('|').map(str::trim).collect())
+            .collect();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0][0], "partitioning-star-expand");
+        assert_eq!(rows[0][2..], ["timeout", "-"]);
+        assert_eq!(rows[1][0], "dp-star-expand");
+        assert_eq!(rows[1][2..], ["8", "39"]);
+
+        // A baseline with fewer transactions is a regression, which is reported with the table.
+        let baseline =
+            std::env::temp_dir().join(format!("payback-baseline-{}", std::process::id()));
+        std::fs::write(
+            &baseline,
+            r#"{"entries": [
+                {"instance": "file", "method": "dp-star-expand", "elapsed_ms": 1e9, "transactions": 7}
+            ]}"#,
+        )
+        .unwrap();
+        let (args, matches) = parse(&[
+            "bench",
+            &file,
+            "-m",
+            "dp-star-expand",
+            "--no-history",
+            "--baseline",
+            &baseline.to_string_lossy(),
+        ]);
+        let error = run_command(args, &matches, &Config::default()).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(&baseline).unwrap();
+        let Some(report) = error.report(false, false) else {
+            panic!("The regressions aren't reported.");
+        };
+        debug!("Regressions:\n{}", report);
+        assert_eq!(report.matches("Instance 'file'").count(), 1);
+        assert!(report.contains("1 regressions compared to"));
+        assert!(report.contains("'dp-star-expand' on 'file' found 8 transactions instead of 7."));
+    }
+
+    #[test]
+    fn test_currencies() {
+        init();
+        debug!("Running 'test_currencies'");
+        let out = run("currencies", "A,B,5,EUR\nC,D,3,USD\n", &[]).unwrap();
+        assert_eq!(
+            out,
+            "EUR:\n\"A\" to \"B\": 5 EUR\nUSD:\n\"C\" to \"D\": 3 USD\n"
+        );
+        // Every currency is solved on its own, so monthly plans keep them apart as well.
+        let out = run(
+            "currencies-by-month",
+            "A,B,5,EUR,2024-01-01\nC,D,3,USD,2024-02-01\n",
+            &["--group-by", "month"],
+        )
+        .unwrap();
+        assert!(
+            out.starts_with("2024-01:\n\"A\" to \"B\": 5 EUR\n"),
+            "{}",
+            out
+        );
+        assert!(out.contains("2024-02:\n\"C\" to \"D\": 3 USD\n"), "{}", out);
+        assert_eq!(
+            run("currencies-unbalanced", "A,5,EUR\nB,-5,EUR\nC,3,USD\n", &[]),
+            Err(CliError::Unbalanced {
+                imbalance: 3,
+                currency: Some("USD".to_owned()),
+            })
+        );
+    }
+
+    #[test]
+    fn test_currencies_after_timeout() {
+        init();
+        debug!("Running 'test_currencies_after_timeout'");
+        // The people of January pay in EUR and the people of February in USD.
+        let data = LEDGER
+            .lines()
+            .map(|l| match l.split_once(",2024-01") {
+                Some((record, _)) => format!("{},EUR\n", record),
+                None => format!("{},USD\n", l.split_once(",2024").unwrap().0),
+            })
+            .collect::<String>();
+        let args = [
+            "transactions",
+            "partitioning-star-expand",
+            "--timeout",
+            TIMEOUT,
+            "--fallback",
+        ];
+        let out = run("currencies-timeout", &data, &args).unwrap();
+        let (eur, usd) = out.split_once("USD:\n").unwrap();
+        assert_eq!(eur.lines().count(), 12, "{}", out);
+        assert_eq!(usd.lines().count(), 2, "{}", out);
+    }
+
+    #[test]
+    fn test_cache_flags() {
+        init();
+        debug!("Running 'test_cache_flags'");
+        let path = std::env::temp_dir().join(format!("payback-cache-flags-{}", std::process::id()));
+        std::fs::write(&path, "A,5\nB,-5\n").unwrap();
+        let path = path.to_string_lossy().into_owned();
+        let cached =
+            |args: &[&str]| solution_cache(&parse(&[&[path.as_str()], args].concat()).0).is_some();
+        assert!(!cached(&["--no-cache"]));
+        // The statistics are collected while solving, so a cached plan has none.
+        assert!(!cached(&["--stats"]));
+        assert_eq!(
+            cached(&[]),
+            crate::cache::SolutionCache::default_dir().is_some()
+        );
+    }
+
+    #[test]
+    fn test_check() {
+        init();
+        debug!("Running 'test_check'");
+        let check = |name, data| run(name, data, &["--check"]);
+        assert_eq!(
+            check("check-valid", "A,B,1\nA,B,2\n"),
+            Ok(
+                "Warning: The debt from \"A\" to \"B\" appears multiple times. Only the last one \
+                is used.\nInstance is valid."
+                    .to_owned()
+            )
+        );
+        let error = check("check-unbalanced", "A,-1\nB,2\n").unwrap_err();
+        assert_eq!(error.exit_code(), crate::exit_code::UNSOLVABLE);
+        assert_eq!(
+            error.report(false, false),
+            Some(
+                "Error: The instance is invalid:\n  Error: The weights add up to 1 instead of 0."
+                    .to_owned()
+            )
+        );
+        assert_eq!(error.report(false, true), None);
+        let error = check("check-invalid", "A;B;1\n").unwrap_err();
+        assert_eq!(error.exit_code(), crate::exit_code::PARSE_ERROR);
+        let json: serde_json::Value =
+            serde_json::from_str(&error.report(true, false).unwrap()).unwrap();
+        assert_eq!(json["error"]["kind"], "invalid_input");
+        assert_eq!(json["error"]["issues"].as_array().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_unreadable_input() {
+        init();
+        debug!("Running 'test_unreadable_input'");
+        let missing = std::env::temp_dir().join(format!("payback-missing-{}", std::process::id()));
+        let missing = missing.to_string_lossy().into_owned();
+        // The file is read by the command, so its failure isn't reported as a usage error.
+        for args in [
+            vec![missing.as_str(), "--no-cache"],
+            vec!["convert", missing.as_str(), "--to", "edges-csv"],
+            vec!["diff", missing.as_str(), missing.as_str()],
+        ] {
+            let (args, matches) = parse(&args);
+            let error = run_command(args, &matches, &Config::default()).unwrap_err();
+            assert!(error.to_string().starts_with("Unable to read"), "{}", error);
+            assert_eq!(error.exit_code(), crate::exit_code::OTHER);
+        }
+    }
+
+    #[test]
+    fn test_unreadable_constraints() {
+        init();
+        debug!("Running 'test_unreadable_constraints'");
+        let missing = std::env::temp_dir().join(format!(
+            "payback-missing-constraints-{}.toml",
+            std::process::id()
+        ));
+        let error = run(
+            "unreadable-constraints",
+            "A,-1\nB,1\n",
+            &["--constraints", &missing.to_string_lossy()],
+        )
+        .unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .starts_with("Unable to read constraints file"),
+            "{}",
+            error
+        );
+        assert_eq!(error.exit_code(), crate::exit_code::OTHER);
+    }
+
+    #[test]
+    fn test_unreadable_transfer_costs() {
+        init();
+        debug!("Running 'test_unreadable_transfer_costs'");
+        let missing =
+            std::env::temp_dir().join(format!("payback-missing-costs-{}.csv", std::process::id()));
+        let error = run(
+            "unreadable-transfer-costs",
+            "A,-1\nB,1\n",
+            &["--transfer-costs", &missing.to_string_lossy()],
+        )
+        .unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .starts_with("Unable to read transfer costs"),
+            "{}",
+            error
+        );
+        assert_eq!(error.exit_code(), crate::exit_code::OTHER);
+    }
+
+    #[test]
+    fn test_objective_weights() {
+        init();
+        debug!("Running 'test_objective_weights'");
+        let path = std::env::temp_dir().join(format!("payback-weights-{}", std::process::id()));
+        std::fs::write(&path, "A,-1\nB,1").unwrap();
+        let file = path.to_string_lossy().into_owned();
+        let parses = |args: &[&str]| {
+            let args = [&["payback", &file, "--objective", "weighted-sum"], args].concat();
+            Args::command().try_get_matches_from(args).is_ok()
+        };
+        assert!(parses(&["--alpha", "0", "--beta", "0.5"]));
+        assert!(!parses(&["--alpha", "-1"]));
+        assert!(!parses(&["--beta=-0.5"]));
+        assert!(!parses(&["--beta", "NaN"]));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_quiet() {
+        init();
+        debug!("Running 'test_quiet'");
+        let error = run("quiet", "A,5\nB,-3\n", &["--quiet"]).unwrap_err();
+        assert_eq!(error.exit_code(), crate::exit_code::UNSOLVABLE);
+        assert_eq!(error.report(false, true), None);
+        assert_eq!(error.report(true, true), None);
+        assert_eq!(
+            error.report(false, false),
+            Some("Error: The weights add up to 2 instead of 0.".to_owned())
+        );
+        assert!(error
+            .report(true, false)
+            .unwrap()
+            .contains("\"unbalanced\""));
+        // The plan is printed with --quiet as well.
+        assert_eq!(
+            run("quiet-plan", "A,5\nB,-5\n", &["--quiet"]),
+            Ok("\"B\" to \"A\": 5\n".to_owned())
+        );
+    }
+}
@@ -0,0 +1,155 @@
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+use tracing::{debug, instrument};
+
+use crate::probleminstance::{ProblemInstance, SolvingMethods};
+use crate::settlement::Settlement;
+use crate::solver_config::{SolveError, SolverConfig};
+
+/// Measurements of a single solving method on an instance.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Metrics {
+    /// Wall clock time until the method returned or timed out.
+    pub elapsed: Duration,
+    /// Number of transactions of the solution, if one was found.
+    pub transactions: Option<usize>,
+    /// Sum of the amounts of all transactions, if a solution was found.
+    pub total_amount: Option<i64>,
+    /// Cost of the solution under the objective of the config, if a solution was found.
+    pub cost: Option<f64>,
+}
+
+/// Solution of a single solving method with its metrics.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MethodResult {
+    pub solution: Result<Option<Settlement>, SolveError>,
+    pub metrics: Metrics,
+}
+
+/// Solves the instance with every method feasible for its size. The options of the config
+/// apply to every method, so its timeout limits every method on its own. The method of the
+/// config is ignored.
+///
+/// Example:
+/// ```
+/// use payback::comparison::solve_all_methods;
+/// use payback::graph::Graph;
+/// use payback::probleminstance::{ProblemInstance, SolvingMethods};
+/// use payback::solver_config::SolverConfig;
+///
+/// let instance: ProblemInstance = Graph::from(vec![-2, -1, 1, 2]).into();
+/// let results = solve_all_methods(&instance, &SolverConfig::default());
+/// let dp = &results[&SolvingMethods::DPGreedySatisfaction];
+/// assert_eq!(dp.metrics.transactions, Some(2));
+/// ```
+pub fn solve_all_methods(
+    instance: &ProblemInstance,
+    config: &SolverConfig,
+) -> BTreeMap<SolvingMethods, MethodResult> {
+    let methods: Vec<SolvingMethods> = SolvingMethods::ALL
+        .into_iter()
+        .filter(|m| m.is_feasible_for(instance.vertex_count()))
+        .collect();
+    solve_methods(instance, &methods, config)
+}
+
+/// Solves the instance with every given method like [`solve_all_methods()`], even if a method
+/// isn't feasible for the size of the instance. Exact methods can take very long or run out of
+/// memory on large instances, even with a timeout.
+#[instrument(level = "debug", skip_all, fields(methods = methods.len()))]
+pub fn solve_methods(
+    instance: &ProblemInstance,
+    methods: &[SolvingMethods],
+    config: &SolverConfig,
+) -> BTreeMap<SolvingMethods, MethodResult> {
+    methods
+        .iter()
+        .map(|method| {
+            let config = SolverConfig {
+                method: *method,
+                ..config.to_owned()
+            };
+            let start = Instant::now();
+            let solution = instance.solve_edges_with_config(&config);
+            let elapsed = start.elapsed();
+            let found = solution.as_ref().ok().and_then(|sol| sol.as_ref());
+            let metrics = Metrics {
+                elapsed,
+                transactions: found.map(|sol| sol.len()),
+                total_amount: found.map(|sol| sol.values().map(|w| w.abs()).sum()),
+                cost: solution
+                    .as_ref()
+                    .ok()
+                    .and_then(|sol| config.objective.cost(&config.objective_weights, sol)),
+            };
+            debug!(?method, ?metrics, "Solved with method");
+            let solution = solution.map(|sol| instance.settlement(&sol));
+            (*method, MethodResult { solution, metrics })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::comparison::{solve_all_methods, solve_methods};
+    use crate::graph::Graph;
+    use crate::probleminstance::{ProblemInstance, SolvingMethods};
+    use crate::solver_config::SolverConfig;
+    use tracing::debug;
+    use tracing_subscriber::EnvFilter;
+
+    fn init() {
+        let _ = tracing_subscriber::fmt()
+            .with_env_filter(
+                EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("debug")),
+            )
+            .with_test_writer()
+            .try_init();
+    }
+
+    #[test]
+    fn test_solve_all_methods() {
+        init();
+        debug!("Running 'test_solve_all_methods'");
+        let instance = ProblemInstance::from(Graph::from(vec![-5, -3, -2, 1, 4, 5]));
+        let config = SolverConfig {
+            timeout: Some(Duration::from_secs(60)),
+            ..Default::default()
+        };
+        let results = solve_all_methods(&instance, &config);
+        debug!("Results: {:?}", results);
+        assert_eq!(results.len(), SolvingMethods::ALL.len());
+        #[cfg(feature = "cli")]
+        for method in SolvingMethods::ALL {
+            let value = clap::ValueEnum::to_possible_value(&method).unwrap();
+            assert_eq!(value.get_name(), method.name());
+        }
+        for (method, result) in &results {
+            assert!(result.solution.is_ok());
+            if method.is_exact() {
+                assert_eq!(result.metrics.transactions, Some(4));
+            }
+            assert_eq!(result.metrics.total_amount.map(|a| a >= 10), Some(true));
+        }
+
+        // Exact methods aren't feasible for large instances.
+        let weights: Vec<i64> = (1..=15).flat_map(|w| [w, -w]).collect();
+        let instance = ProblemInstance::from(Graph::from(weights));
+        let results = solve_all_methods(&instance, &SolverConfig::default());
+        assert!(results.keys().all(|m| !m.is_exact()));
+        let results = solve_methods(
+            &instance,
+            &[SolvingMethods::ApproxStarExpand],
+            &SolverConfig::default(),
+        );
+        assert_eq!(results.len(), 1);
+        assert_eq!(
+            results[&SolvingMethods::ApproxStarExpand]
+                .metrics
+                .transactions,
+            Some(29)
+        );
+    }
+}
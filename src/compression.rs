@@ -0,0 +1,122 @@
+use flate2::read::MultiGzDecoder;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+use tracing::debug;
+
+/// Magic bytes at the start of gzip data.
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+/// Magic bytes at the start of zstd data.
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+const ZSTD_UNSUPPORTED: &str =
+    "Zstd compressed inputs aren't supported. Decompress them with 'zstd -d' first.";
+
+/// Decompresses gzip data, which is detected by its magic bytes, so the extension of the file
+/// doesn't matter. Other data is returned unchanged. Zstd data is detected, but not supported.
+///
+/// Example:
+/// ```
+/// use payback::compression::decompress;
+///
+/// assert_eq!(decompress(b"A,-1\nB,1".to_vec()).unwrap(), b"A,-1\nB,1");
+/// ```
+pub fn decompress(data: Vec<u8>) -> Result<Vec<u8>, String> {
+    if data.starts_with(&GZIP_MAGIC) {
+        let mut decompressed = vec![];
+        MultiGzDecoder::new(data.as_slice())
+            .read_to_end(&mut decompressed)
+            .map_err(|e| format!("Unable to decompress gzip input: {}", e))?;
+        debug!(
+            compressed = data.len(),
+            decompressed = decompressed.len(),
+            "Decompressed gzip input"
+        );
+        Ok(decompressed)
+    } else if data.starts_with(&ZSTD_MAGIC) {
+        Err(ZSTD_UNSUPPORTED.to_owned())
+    } else {
+        Ok(data)
+    }
+}
+
+/// Wraps the reader, so gzip data is decompressed while it is read. Opposed to [`decompress()`],
+/// the data never has to fit into the memory at once.
+pub fn decompressing_reader<'a>(reader: impl Read + 'a) -> Result<Box<dyn Read + 'a>, String> {
+    let mut reader = BufReader::new(reader);
+    let start = reader
+        .fill_buf()
+        .map_err(|e| format!("Unable to read input: {}", e))?;
+    if start.starts_with(&GZIP_MAGIC) {
+        Ok(Box::new(MultiGzDecoder::new(reader)))
+    } else if start.starts_with(&ZSTD_MAGIC) {
+        Err(ZSTD_UNSUPPORTED.to_owned())
+    } else {
+        Ok(Box::new(reader))
+    }
+}
+
+/// Reads the file and decompresses it, if it is compressed. Use '-' to read the stdin.
+pub fn read_input(path: &Path) -> Result<String, String> {
+    let data = if path == Path::new("-") {
+        let mut data = vec![];
+        std::io::stdin()
+            .read_to_end(&mut data)
+            .map_err(|e| format!("Unable to read stdin: {}", e))?;
+        data
+    } else {
+        std::fs::read(path).map_err(|e| format!("Unable to read {:?}: {}", path, e))?
+    };
+    String::from_utf8(decompress(data)?)
+        .map_err(|_| format!("Input {:?} is not valid UTF-8.", path))
+}
+
+#[cfg(test)]
+mod tests {
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::{Read, Write};
+
+    use crate::compression::{decompress, decompressing_reader, read_input};
+    use tracing::debug;
+    use tracing_subscriber::EnvFilter;
+
+    fn init() {
+        let _ = tracing_subscriber::fmt()
+            .with_env_filter(
+                EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("debug")),
+            )
+            .with_test_writer()
+            .try_init();
+    }
+
+    #[test]
+    fn test_decompress() {
+        init();
+        debug!("Running 'test_decompress'");
+        let data = b"A,B,5\nB,C,3\n";
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(data).unwrap();
+        let compressed = encoder.finish().unwrap();
+        assert_ne!(compressed, data);
+        assert_eq!(decompress(compressed.to_owned()).unwrap(), data);
+
+        let path =
+            std::env::temp_dir().join(format!("payback-input-{}.csv.gz", std::process::id()));
+        std::fs::write(&path, &compressed).unwrap();
+        let read = read_input(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(read.unwrap(), "A,B,5\nB,C,3\n");
+
+        assert!(decompress(compressed[..compressed.len() / 2].to_vec()).is_err());
+        assert!(decompress(vec![0x28, 0xb5, 0x2f, 0xfd, 0]).is_err());
+
+        // Readers decompress while reading and pass other data through.
+        for input in [compressed.as_slice(), data.as_slice()] {
+            let mut read = String::new();
+            decompressing_reader(input)
+                .unwrap()
+                .read_to_string(&mut read)
+                .unwrap();
+            assert_eq!(read, "A,B,5\nB,C,3\n");
+        }
+    }
+}
@@ -0,0 +1,187 @@
+use itertools::Itertools;
+use serde_derive::Deserialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tracing::debug;
+
+use crate::decimals::Rounding;
+use crate::graph::{Debt, Graph, Metadata};
+use crate::metadata::PAYMENT_KEYS;
+
+/// Defaults for the command line interface read from a toml file.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Name of the default solving method, e.g. 'dp-greedy-satisfaction'.
+    pub method: Option<String>,
+    /// Name of the default output format, e.g. 'transactions'.
+    pub output: Option<String>,
+    /// Currency appended to every amount in the transactions output.
+    pub currency: Option<String>,
+    /// Alternative names of people mapped to their canonical name.
+    pub aliases: HashMap<String, String>,
+    /// Templates of payment links of people, e.g. 'https://paypal.me/bob/{amount}'. Links given
+    /// in the metadata of a person take precedence.
+    pub payment_links: HashMap<String, String>,
+    /// Default simple interest in percent per year accrued on dated debts.
+    pub interest: Option<f64>,
+    /// Default fee added to dated debts older than the grace period.
+    pub late_fee: Option<i64>,
+    /// Default number of days after the date of a debt, in which nothing accrues.
+    pub grace_days: Option<i64>,
+    /// Decimal places of the amounts. Defaults to the most decimal places of the input.
+    pub decimals: Option<u32>,
+    /// How amounts with more decimal places are rounded, e.g. 'half-even'. By default, they are
+    /// rejected.
+    pub rounding: Rounding,
+}
+
+/// Default location of the config file, which is 'payback/config.toml' in the config directory of
+/// the user.
+pub fn default_config_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|d| d.join("payback").join("config.toml"))
+}
+
+/// Loads the config from the given path. Without a path, the config is read from
+/// [`default_config_path()`], if it exists.
+pub fn load_config(path: Option<&Path>) -> Result<Config, String> {
+    let path = match path {
+        Some(p) => p.to_path_buf(),
+        None => match default_config_path() {
+            Some(p) if p.exists() => p,
+            _ => return Ok(Config::default()),
+        },
+    };
+    debug!("Reading config file {:?}", path);
+    let content = std::fs::read_to_string(&path)
+        .map_err(|e| format!("Unable to read config file {:?}: {}", path, e))?;
+    toml::from_str(&content).map_err(|e| format!("Invalid config file {:?}: {}", path, e))
+}
+
+impl Config {
+    /// Adds the payment links of the config to the metadata of the people without a link.
+    pub fn add_payment_links(&self, metadata: &mut HashMap<String, Metadata>) {
+        for (name, link) in &self.payment_links {
+            let fields = metadata.entry(name.to_owned()).or_default();
+            if !fields
+                .iter()
+                .any(|(k, _)| PAYMENT_KEYS.contains(&k.as_str()))
+            {
+                fields.push((PAYMENT_KEYS[0].to_owned(), link.to_owned()));
+            }
+        }
+    }
+
+    /// Renames every person according to the aliases. People sharing the same canonical name are
+    /// merged into one.
+    pub fn apply_aliases(&self, graph: Graph) -> Graph {
+        if self.aliases.is_empty() {
+            return graph;
+        }
+        let mut merged: Vec<(String, i64)> = vec![];
+        let mut metadata: HashMap<String, Metadata> = HashMap::new();
+        for (name, fields) in graph.metadata.into_iter().sorted() {
+            let name = self.aliases.get(&name).unwrap_or(&name);
+            metadata.entry(name.to_owned()).or_default().extend(fields);
+        }
+        for v in graph.vertices {
+            let name = self.aliases.get(&v.name).unwrap_or(&v.name);
+            match merged.iter_mut().find_position(|(n, _)| n == name) {
+                Some((_, (_, w))) => *w += v.weight,
+                None => merged.push((name.to_owned(), v.weight)),
+            }
+        }
+        let merged = Graph::from(merged).with_metadata(metadata);
+        match graph.debts {
+            Some(debts) => merged.with_debts(self.merge_debts(debts)),
+            None => merged,
+        }
+    }
+
+    /// Renames the people of the debts. Debts between the same people are summed up and debts of
+    /// a person to themself are dropped.
+    fn merge_debts(&self, debts: Vec<Debt>) -> Vec<Debt> {
+        let mut merged: Vec<Debt> = vec![];
+        for d in debts {
+            let from = self.aliases.get(&d.from).unwrap_or(&d.from);
+            let to = self.aliases.get(&d.to).unwrap_or(&d.to);
+            if from == to {
+                continue;
+            }
+            match merged.iter_mut().find(|m| m.from == *from && m.to == *to) {
+                Some(m) => m.amount += d.amount,
+                None => merged.push(Debt {
+                    from: from.to_owned(),
+                    to: to.to_owned(),
+                    amount: d.amount,
+                }),
+            }
+        }
+        merged
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::Config;
+    use crate::decimals::{to_minor_units_rounded, Rounding};
+    use crate::graph::Graph;
+    use tracing::debug;
+    use tracing_subscriber::EnvFilter;
+
+    fn init() {
+        let _ = tracing_subscriber::fmt()
+            .with_env_filter(
+                EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("debug")),
+            )
+            .with_test_writer()
+            .try_init();
+    }
+
+    #[test]
+    fn test_config() {
+        init();
+        debug!("Running 'test_config'");
+        let config: Config = toml::from_str(
+            r#"
+            method = "dp-greedy-satisfaction"
+            currency = "EUR"
+            decimals = 2
+            rounding = "half-even"
+
+            [aliases]
+            Bobby = "Bob"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.method, Some("dp-greedy-satisfaction".to_string()));
+        assert_eq!(config.output, None);
+        assert!(toml::from_str::<Config>("unknown = 1").is_err());
+        assert!(toml::from_str::<Config>("rounding = \"nearest\"").is_err());
+        assert_eq!(Config::default().rounding, Rounding::Reject);
+
+        assert_eq!(config.decimals, Some(2));
+        assert_eq!(config.rounding, Rounding::HalfEven);
+        assert_eq!(
+            to_minor_units_rounded("A,-1.005\nB,1.015", 2, config.rounding).unwrap(),
+            "A,-100\nB,102\n"
+        );
+
+        let graph: Graph = vec![
+            ("Alice".to_string(), -3),
+            ("Bob".to_string(), 1),
+            ("Bobby".to_string(), 2),
+        ]
+        .into();
+        let graph = config.apply_aliases(graph);
+        let weights: Vec<(String, i64)> = graph
+            .vertices
+            .into_iter()
+            .map(|v| (v.name, v.weight))
+            .collect();
+        assert_eq!(
+            weights,
+            vec![("Alice".to_string(), -3), ("Bob".to_string(), 3)]
+        );
+    }
+}
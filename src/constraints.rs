@@ -0,0 +1,939 @@
+use itertools::Itertools;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::Path;
+use tracing::{debug, instrument};
+
+use crate::graph::{Edge, Graph, NamedNode};
+use crate::probleminstance::{EdgeSolution, ProblemInstance};
+use crate::settlement::{transfers, MAX_TRANSFERS};
+use crate::solver_config::SolveError;
+use crate::verification::Payment;
+
+/// Requirements on the plan read from a toml file, e.g.
+/// ```toml
+/// hub = "Alice"
+/// max_amount = 500
+/// groups = [["Alice", "Bob"], ["Carol", "Dave"]]
+/// forbidden = [["Alice", "Bob"]]
+/// preferred = [["Carol", "Dave"]]
+///
+/// max_transactions_per_person = 3
+///
+/// [max_transactions]
+/// Bob = 2
+///
+/// [[fixed]]
+/// from = "Carol"
+/// to = "Dave"
+/// amount = 20
+///
+/// [soft]
+/// forbidden = 2
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Constraints {
+    /// Pairs of people, who can't pay each other in either direction.
+    pub forbidden: Vec<(String, String)>,
+    /// Pairs of people, who would rather pay each other, e.g. because they already share a
+    /// payment app. Plans with more transactions between them win ties of
+    /// [`crate::solver_config::Objective::MinTransactions`].
+    pub preferred: Vec<(String, String)>,
+    /// Largest number of transactions, in which a person pays or is paid.
+    pub max_transactions: HashMap<String, usize>,
+    /// Largest number of transactions of every person without an entry in
+    /// [`Constraints::max_transactions`].
+    pub max_transactions_per_person: Option<usize>,
+    /// Largest amount of a single transaction. Instances with
+    /// [`ProblemInstance::with_max_amount()`] split larger transactions, so they meet it.
+    pub max_amount: Option<i64>,
+    /// Payments, which are part of the plan in any case, e.g. standing orders. The rest of the
+    /// balances is settled by the solver.
+    pub fixed: Vec<Payment>,
+    /// Person, with whom everybody in the group of the person settles directly.
+    pub hub: Option<String>,
+    /// Groups of people, who settle among themselves. The balances of every group must add up to
+    /// zero after the fixed payments. People in no group form another group.
+    pub groups: Vec<Vec<String>>,
+    /// Limits, which the plan may break for a penalty instead of being infeasible.
+    pub soft: Soft,
+}
+
+/// Penalties of the limits, which are soft. Only forbidden pairs and the largest numbers of
+/// transactions can be soft. A plan may break a soft limit, if it then takes fewer transactions
+/// plus penalties than the other plans compared by [`Limits::cheapest()`]. No solver optimizes
+/// the penalties. These are only some plans found by a bounded search, so there may be cheaper
+/// ones. Limits without a penalty must be met. Largest amounts never make a plan infeasible,
+/// since larger transactions are split, but the transfers count against the transactions of the
+/// people. Fixed payments, the hub and the groups are always met.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Soft {
+    /// Penalty of every transaction between a forbidden pair.
+    pub forbidden: Option<usize>,
+    /// Penalty of every transaction of a person beyond their largest number of transactions.
+    pub max_transactions: Option<usize>,
+}
+
+impl Soft {
+    pub fn is_empty(&self) -> bool {
+        *self == Soft::default()
+    }
+}
+
+/// Reads the constraints from a toml file.
+pub fn load_constraints(path: &Path) -> Result<Constraints, String> {
+    debug!("Reading constraints file {:?}", path);
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| format!("Unable to read constraints file {:?}: {}", path, e))?;
+    toml::from_str(&content).map_err(|e| format!("Invalid constraints file {:?}: {}", path, e))
+}
+
+impl Constraints {
+    pub fn is_empty(&self) -> bool {
+        *self == Constraints::default()
+    }
+
+    /// Largest number of transactions of the person, if it is limited.
+    pub fn max_transactions_of(&self, name: &str) -> Option<usize> {
+        self.max_transactions
+            .get(name)
+            .copied()
+            .or(self.max_transactions_per_person)
+    }
+
+    /// Solves the instance under the fixed payments, the hub and the groups. Every group is
+    /// solved on its own by `solve` and rearranged by `rewrite`, while the group of the hub is
+    /// settled by the hub. If the solution of a group exceeds the number of transactions of a
+    /// person or pays between a forbidden pair, the group is settled by [`bounded_settlement()`]
+    /// instead. With [`Constraints::soft`] limits, the plan with the fewest transactions plus
+    /// penalties among the solution and the bounded settlements is taken. Rearrangements, which
+    /// break any limit, are dropped. The other constraints are only checked by
+    /// [`Constraints::check()`].
+    ///
+    /// The bounded settlements are found by a search, which only tries some plans, so groups
+    /// settled by them aren't guaranteed to take the fewest transactions or penalties. There is
+    /// no exact model of the limits. This search stops after a number of steps and fails with
+    /// [`SolveError::SearchLimit`], even if there is a plan within the limits.
+    #[instrument(level = "debug", skip_all, fields(groups = self.groups.len()))]
+    pub(crate) fn solve(
+        &self,
+        instance: &ProblemInstance,
+        solve: impl Fn(&ProblemInstance) -> Result<EdgeSolution, SolveError>,
+        rewrite: impl Fn(&ProblemInstance, EdgeSolution) -> EdgeSolution,
+    ) -> Result<EdgeSolution, SolveError> {
+        let g = &instance.g;
+        let id = |name: &str| {
+            g.vertices
+                .iter()
+                .find(|v| v.name == name)
+                .map(|v| v.id)
+                .ok_or_else(|| SolveError::Infeasible(format!("Unknown person {:?}.", name)))
+        };
+        let mut weights: HashMap<usize, i64> =
+            g.vertices.iter().map(|v| (v.id, v.weight)).collect();
+        let mut solution: BTreeMap<Edge, i64> = BTreeMap::new();
+        for p in &self.fixed {
+            let (from, to) = (id(&p.from)?, id(&p.to)?);
+            *weights.entry(from).or_default() += p.amount;
+            *weights.entry(to).or_default() -= p.amount;
+            *solution.entry(Edge { u: to, v: from }).or_default() += p.amount;
+        }
+        // Transactions left to every person with a limit after the fixed payments. Forbidden
+        // pairs with people, who aren't in the instance, don't limit the plan.
+        let known = |name: &str| g.vertices.iter().find(|v| v.name == name).map(|v| v.id);
+        let mut limits = Limits {
+            capacity: g
+                .vertices
+                .iter()
+                .filter_map(|v| Some((v.id, self.max_transactions_of(&v.name)?)))
+                .collect(),
+            forbidden: self
+                .forbidden
+                .iter()
+                .filter_map(|(a, b)| Some((known(a)?, known(b)?)))
+                .collect(),
+            max_amount: instance.max_amount(),
+            soft: self.soft.to_owned(),
+        };
+        for (e, amount) in &solution {
+            let transfers = limits.transfers(*amount);
+            for id in [e.u, e.v] {
+                if let Some(c) = limits.capacity.get_mut(&id) {
+                    *c = c.saturating_sub(transfers);
+                }
+            }
+        }
+
+        let mut assigned: HashMap<usize, usize> = HashMap::new();
+        for (i, group) in self.groups.iter().enumerate() {
+            for name in group {
+                if assigned.insert(id(name)?, i).is_some() {
+                    return Err(SolveError::Infeasible(format!(
+                        "{:?} is in more than one group.",
+                        name
+                    )));
+                }
+            }
+        }
+        let hub = self.hub.as_deref().map(id).transpose()?;
+        let mut groups: Vec<Vec<NamedNode>> = vec![vec![]; self.groups.len() + 1];
+        for v in &g.vertices {
+            groups[assigned.get(&v.id).copied().unwrap_or(self.groups.len())].push(NamedNode {
+                weight: weights[&v.id],
+                ..v.to_owned()
+            });
+        }
+        for group in groups.iter().filter(|vs| vs.iter().any(|v| v.weight != 0)) {
+            let residual: i64 = group.iter().map(|v| v.weight).sum();
+            if residual != 0 {
+                return Err(SolveError::Infeasible(format!(
+                    "The balances of the group {:?} add up to {} instead of zero.",
+                    group.iter().map(|v| &v.name).collect::<Vec<_>>(),
+                    residual
+                )));
+            }
+            let edges = match hub.filter(|h| group.iter().any(|v| v.id == *h)) {
+                Some(hub) => {
+                    let edges = star(hub, group);
+                    if limits.penalty(&edges).is_none() {
+                        return Err(SolveError::Infeasible(format!(
+                            "{:?} can't settle with everybody in the group within the limits.",
+                            self.hub.as_deref().unwrap_or_default()
+                        )));
+                    }
+                    edges
+                }
+                None => {
+                    let sub = ProblemInstance::from(
+                        Graph::from(group.to_owned()).with_metadata(g.metadata.to_owned()),
+                    );
+                    let Some(edges) = solve(&sub)? else {
+                        return Ok(None);
+                    };
+                    if limits.violated_by(&edges) {
+                        debug!("Solution of the group exceeds the limits");
+                        limits.cheapest(group, edges)?
+                    } else {
+                        // The rearrangements of the objectives don't know the limits.
+                        match rewrite(&sub, Some(edges.to_owned())) {
+                            Some(rewritten) if !limits.violated_by(&rewritten) => rewritten,
+                            _ => {
+                                debug!(
+                                    "Keeping the solution, since rearranging exceeds the limits"
+                                );
+                                edges
+                            }
+                        }
+                    }
+                }
+            };
+            for (e, w) in edges {
+                *solution.entry(e).or_default() += w;
+            }
+        }
+        solution.retain(|_, w| *w != 0);
+        Ok(Some(solution))
+    }
+
+    /// Checks that the solution meets the forbidden pairs and the caps, unless they are
+    /// [`Constraints::soft`]. Preferred pairs are no requirement.
+    pub(crate) fn check(
+        &self,
+        instance: &ProblemInstance,
+        solution: &EdgeSolution,
+    ) -> Result<(), String> {
+        // Check the amounts before the plan is split, so tiny amounts don't exhaust the memory.
+        if let (Some(max), Some(solution)) = (instance.max_amount(), solution) {
+            let count = solution
+                .values()
+                .map(|amount| transfers(*amount, max))
+                .fold(0, u64::saturating_add);
+            if count > MAX_TRANSFERS {
+                return Err(format!(
+                    "Splitting the plan into transfers of at most {} takes {} transfers, which \
+                     is more than {}.",
+                    max, count, MAX_TRANSFERS
+                ));
+            }
+        }
+        let Some(transactions) = instance.solution_transactions(solution) else {
+            return Ok(());
+        };
+        let mut counts: HashMap<&str, usize> = HashMap::new();
+        for (from, to, amount) in &transactions {
+            if self.soft.forbidden.is_none()
+                && self
+                    .forbidden
+                    .iter()
+                    .any(|(a, b)| (a == from && b == to) || (a == to && b == from))
+            {
+                return Err(format!(
+                    "{:?} pays {:?}, but they can't pay each other.",
+                    from, to
+                ));
+            }
+            if let Some(max) = self.max_amount.filter(|max| amount > max) {
+                return Err(format!(
+                    "{:?} pays {:?} {}, which is more than {}.",
+                    from, to, amount, max
+                ));
+            }
+            *counts.entry(from).or_default() += 1;
+            *counts.entry(to).or_default() += 1;
+        }
+        for (name, count) in counts.iter().sorted() {
+            let Some(max) = self
+                .max_transactions_of(name)
+                .filter(|_| self.soft.max_transactions.is_none())
+            else {
+                continue;
+            };
+            if *count > max {
+                return Err(format!(
+                    "{:?} takes part in {} transactions, but at most {} are allowed.",
+                    name, count, max
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Limits of the plan of a group searched by [`bounded_settlement()`]. People are referenced by
+/// their ids.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct Limits {
+    /// Transactions left to a person. People without an entry have no limit.
+    pub capacity: HashMap<usize, usize>,
+    /// Pairs of people, who can't pay each other in either direction.
+    pub forbidden: HashSet<(usize, usize)>,
+    /// Largest amount of a transfer. Larger transactions are split like
+    /// [`ProblemInstance::with_max_amount()`], so every transfer counts against the capacity.
+    pub max_amount: Option<i64>,
+    /// Penalties of breaking the capacity or the forbidden pairs instead of being infeasible.
+    pub soft: Soft,
+}
+
+impl Limits {
+    /// Limits without the soft ones, which every plan must meet.
+    pub(crate) fn hard(&self) -> Limits {
+        Limits {
+            capacity: match self.soft.max_transactions {
+                Some(_) => HashMap::new(),
+                None => self.capacity.to_owned(),
+            },
+            forbidden: match self.soft.forbidden {
+                Some(_) => HashSet::new(),
+                None => self.forbidden.to_owned(),
+            },
+            max_amount: self.max_amount,
+            soft: Soft::default(),
+        }
+    }
+
+    /// Sum of the penalties of the soft limits broken by the transfers. `None`, if they break a
+    /// limit, which isn't soft.
+    pub(crate) fn penalty(&self, edges: &BTreeMap<Edge, i64>) -> Option<usize> {
+        if self.hard().violated_by(edges) {
+            return None;
+        }
+        let forbidden = edges.keys().filter(|e| !self.allows(e.u, e.v)).count();
+        let excess: usize = self
+            .counts(edges)
+            .iter()
+            .map(|(id, count)| count.saturating_sub(self.capacity(*id)))
+            .sum();
+        let penalty = |count: usize, penalty: Option<usize>| {
+            count.saturating_mul(penalty.unwrap_or_default())
+        };
+        Some(
+            penalty(forbidden, self.soft.forbidden)
+                .saturating_add(penalty(excess, self.soft.max_transactions)),
+        )
+    }
+
+    /// Plan of the group with the fewest transactions plus penalties among the `solution`,
+    /// the [`bounded_settlement()`] within all limits and the one within the hard limits. Plans
+    /// within all limits win ties. This is a heuristic, since the searches only try some plans.
+    /// Fails with [`SolveError::SearchLimit`], if one of the searches gave up, because a plan
+    /// with fewer transactions plus penalties may then be missed, and with
+    /// [`SolveError::Infeasible`], if none of the plans meets the hard limits.
+    pub(crate) fn cheapest(
+        &self,
+        group: &[NamedNode],
+        solution: BTreeMap<Edge, i64>,
+    ) -> Result<BTreeMap<Edge, i64>, SolveError> {
+        let bounded = bounded_settlement(group, self);
+        if self.soft.is_empty() {
+            return bounded;
+        }
+        let hard = bounded_settlement(group, &self.hard());
+        if let Some(e) = [&bounded, &hard]
+            .into_iter()
+            .filter_map(|plan| plan.as_ref().err())
+            .find(|e| matches!(e, SolveError::SearchLimit(_)))
+        {
+            debug!("Not comparing the penalties, since a search gave up");
+            return Err(e.to_owned());
+        }
+        bounded
+            .into_iter()
+            .chain([solution])
+            .chain(hard)
+            .filter_map(|plan| Some((plan.len().saturating_add(self.penalty(&plan)?), plan)))
+            .min_by_key(|(cost, _)| *cost)
+            .map(|(_, plan)| plan)
+            .ok_or_else(|| {
+                SolveError::Infeasible("No plan settles the group within the limits.".to_owned())
+            })
+    }
+
+    fn capacity(&self, id: usize) -> usize {
+        self.capacity.get(&id).copied().unwrap_or(usize::MAX)
+    }
+
+    /// Whether the people can pay each other.
+    pub(crate) fn allows(&self, a: usize, b: usize) -> bool {
+        !self.forbidden.contains(&(a, b)) && !self.forbidden.contains(&(b, a))
+    }
+
+    /// Number of transfers, into which a transaction of the amount is split.
+    pub(crate) fn transfers(&self, amount: i64) -> usize {
+        self.max_amount.map_or(1, |max| {
+            transfers(amount, max).try_into().unwrap_or(usize::MAX)
+        })
+    }
+
+    /// Whether the transfers exceed the capacity of a person or pay between a forbidden pair.
+    pub(crate) fn violated_by(&self, edges: &BTreeMap<Edge, i64>) -> bool {
+        edges.keys().any(|e| !self.allows(e.u, e.v))
+            || self
+                .counts(edges)
+                .iter()
+                .any(|(id, count)| *count > self.capacity(*id))
+    }
+
+    /// Number of transfers of every person.
+    fn counts(&self, edges: &BTreeMap<Edge, i64>) -> HashMap<usize, usize> {
+        let mut counts: HashMap<usize, usize> = HashMap::new();
+        for (e, amount) in edges {
+            let transfers = self.transfers(*amount);
+            *counts.entry(e.u).or_default() += transfers;
+            *counts.entry(e.v).or_default() += transfers;
+        }
+        counts
+    }
+}
+
+/// Most steps of the search of [`bounded_settlement()`] before it gives up.
+const SEARCH_STEPS: usize = 200_000;
+
+/// Settles the group within the limits. Every transaction settles the payer or the payee like
+/// 'GreedySatisfaction'. If people can't pay each other, the money is routed through somebody
+/// else, who passes it on. Plans with fewer transactions are searched first, but not all plans
+/// are tried, so the plan may take more transactions than needed. Fails with
+/// [`SolveError::Infeasible`], if there is no plan, or with [`SolveError::SearchLimit`], if the
+/// search takes too long.
+pub(crate) fn bounded_settlement(
+    group: &[NamedNode],
+    limits: &Limits,
+) -> Result<BTreeMap<Edge, i64>, SolveError> {
+    let mut people: Vec<(usize, i64, usize)> = group
+        .iter()
+        .map(|v| (v.id, v.weight, limits.capacity(v.id)))
+        .collect();
+    let mut steps = 0;
+    // Routing money through others can take more than one transaction per person.
+    for limit in 0..=2 * people.len() {
+        let mut edges = vec![];
+        if settle(&mut people, limits, &mut edges, limit, &mut steps) {
+            let mut plan: BTreeMap<Edge, i64> = BTreeMap::new();
+            for (e, w) in edges {
+                *plan.entry(e).or_default() += w;
+            }
+            return Ok(plan);
+        }
+        if steps > SEARCH_STEPS {
+            debug!(steps, "Giving up the search for a bounded settlement");
+            return Err(SolveError::SearchLimit(SEARCH_STEPS));
+        }
+    }
+    Err(SolveError::Infeasible(
+        "No plan settles the group within the limits.".to_owned(),
+    ))
+}
+
+/// Depth first search of [`bounded_settlement()`] with at most `limit` more transactions.
+/// People are tuples of id, weight and transactions left.
+fn settle(
+    people: &mut [(usize, i64, usize)],
+    limits: &Limits,
+    edges: &mut Vec<(Edge, i64)>,
+    limit: usize,
+    steps: &mut usize,
+) -> bool {
+    *steps += 1;
+    if *steps > SEARCH_STEPS {
+        return false;
+    }
+    let open: Vec<usize> = (0..people.len()).filter(|&i| people[i].1 != 0).collect();
+    if open.is_empty() {
+        return true;
+    }
+    // Every transaction settles at most two people.
+    if limit == 0 || open.iter().any(|&i| people[i].2 == 0) || open.len() > 2 * limit {
+        return false;
+    }
+    // The person with the fewest transactions left has the fewest choices.
+    let &x = open
+        .iter()
+        .min_by_key(|&&i| (people[i].2, -people[i].1.abs()))
+        .expect("Open people aren't empty.");
+    let allowed = |y: usize| y != x && people[y].2 > 0 && limits.allows(people[x].0, people[y].0);
+    let mut partners: Vec<usize> = open
+        .iter()
+        .copied()
+        .filter(|&y| allowed(y) && people[y].1.signum() == -people[x].1.signum())
+        .collect();
+    // Partners with the same amount settle both at once.
+    partners.sort_by_key(|&y| (people[y].1 != -people[x].1, -people[y].1.abs()));
+    let direct = partners.len();
+    // Others pass the money on, so they need at least two transactions.
+    partners.extend((0..people.len()).filter(|&y| {
+        allowed(y) && people[y].2 > 1 && people[y].1.signum() != -people[x].1.signum()
+    }));
+    for (i, y) in partners.into_iter().enumerate() {
+        let amount = if i < direct {
+            people[x].1.abs().min(people[y].1.abs())
+        } else {
+            people[x].1.abs()
+        };
+        let transfers = limits.transfers(amount);
+        if people[x].2 < transfers || people[y].2 < transfers {
+            continue;
+        }
+        let (payer, payee) = if people[x].1 < 0 { (x, y) } else { (y, x) };
+        people[payer].1 += amount;
+        people[payee].1 -= amount;
+        people[x].2 -= transfers;
+        people[y].2 -= transfers;
+        edges.push((
+            Edge {
+                u: people[payee].0,
+                v: people[payer].0,
+            },
+            amount,
+        ));
+        if settle(people, limits, edges, limit - 1, steps) {
+            return true;
+        }
+        edges.pop();
+        people[payer].1 -= amount;
+        people[payee].1 += amount;
+        people[x].2 += transfers;
+        people[y].2 += transfers;
+    }
+    false
+}
+
+/// Settles everybody in the group directly with the hub.
+pub(crate) fn star(hub: usize, group: &[NamedNode]) -> BTreeMap<Edge, i64> {
+    group
+        .iter()
+        .filter(|v| v.id != hub && v.weight != 0)
+        .map(|v| {
+            if v.weight > 0 {
+                (Edge { u: v.id, v: hub }, v.weight)
+            } else {
+                (Edge { u: hub, v: v.id }, -v.weight)
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, HashSet};
+
+    use crate::constraints::{bounded_settlement, Constraints, Limits, Soft};
+    use crate::generator::{generate, GeneratorConfig};
+    use crate::graph::{Graph, NamedNode};
+    use crate::probleminstance::{ProblemInstance, SolvingMethods};
+    use crate::solver_config::{Objective, SolveError, SolverConfig};
+    use crate::verification::{verify_plan, Payment};
+    use tracing::debug;
+    use tracing_subscriber::EnvFilter;
+
+    fn init() {
+        let _ = tracing_subscriber::fmt()
+            .with_env_filter(
+                EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("debug")),
+            )
+            .with_test_writer()
+            .try_init();
+    }
+
+    #[test]
+    fn test_constraints() {
+        init();
+        debug!("Running 'test_constraints'");
+        let constraints: Constraints = toml::from_str(
+            r#"
+            hub = "A"
+            groups = [["A", "B", "C"]]
+
+            [max_transactions]
+            A = 2
+
+            [[fixed]]
+            from = "D"
+            to = "E"
+            amount = 5
+            "#,
+        )
+        .unwrap();
+        let instance = ProblemInstance::from(Graph::from(vec![
+            ("A".to_owned(), 3),
+            ("B".to_owned(), -1),
+            ("C".to_owned(), -2),
+            ("D".to_owned(), -5),
+            ("E".to_owned(), 2),
+            ("F".to_owned(), 3),
+        ]));
+        let config = SolverConfig {
+            method: SolvingMethods::DPGreedySatisfaction,
+            constraints: constraints.to_owned(),
+            ..Default::default()
+        };
+        let sol = instance.solve_edges_with_config(&config).unwrap();
+        let plan: Vec<Payment> = instance
+            .solution_transactions(&sol)
+            .unwrap()
+            .into_iter()
+            .map(|(from, to, amount)| Payment { from, to, amount })
+            .collect();
+        debug!("Plan: {:?}", plan);
+        assert!(verify_plan(&instance.g, &plan).is_settled());
+        assert!(plan.contains(&Payment {
+            from: "D".to_owned(),
+            to: "E".to_owned(),
+            amount: 5
+        }));
+        assert_eq!(plan.iter().filter(|p| p.to == "A").count(), 2);
+
+        let config = SolverConfig {
+            constraints: Constraints {
+                forbidden: vec![("B".to_owned(), "A".to_owned())],
+                ..constraints
+            },
+            ..config
+        };
+        assert!(matches!(
+            instance.solve_edges_with_config(&config),
+            Err(SolveError::Infeasible(_))
+        ));
+
+        // The approximation settles everybody with one person, which the limit doesn't allow.
+        let instance = ProblemInstance::from(Graph::from(vec![
+            ("A".to_owned(), 1),
+            ("B".to_owned(), 2),
+            ("C".to_owned(), 3),
+            ("D".to_owned(), -4),
+            ("E".to_owned(), -2),
+        ]));
+        let config = SolverConfig {
+            method: SolvingMethods::ApproxStarExpand,
+            constraints: Constraints {
+                max_transactions_per_person: Some(2),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let sol = instance.solve_edges_with_config(&config).unwrap();
+        assert!(config.constraints.check(&instance, &sol).is_ok());
+        assert_eq!(sol.unwrap().len(), 3);
+
+        // "E" can only pay "D", who passes the money on.
+        let config = SolverConfig {
+            method: SolvingMethods::DPGreedySatisfaction,
+            constraints: Constraints {
+                forbidden: ["A", "B", "C"]
+                    .iter()
+                    .map(|n| ("E".to_owned(), n.to_string()))
+                    .collect(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let sol = instance.solve_edges_with_config(&config).unwrap();
+        assert!(config.constraints.check(&instance, &sol).is_ok());
+        let transactions = instance.solution_transactions(&sol).unwrap();
+        assert!(transactions.contains(&("E".to_owned(), "D".to_owned(), 2)));
+    }
+
+    #[test]
+    fn test_soft_constraints() {
+        init();
+        debug!("Running 'test_soft_constraints'");
+        let constraints: Constraints = toml::from_str(
+            r#"
+            forbidden = [["A", "B"]]
+
+            [soft]
+            forbidden = 2
+            "#,
+        )
+        .unwrap();
+        assert_eq!(constraints.soft.forbidden, Some(2));
+        // The only plan pays between the forbidden pair, which is fine for a penalty.
+        let instance =
+            ProblemInstance::from(Graph::from(vec![("A".to_owned(), -1), ("B".to_owned(), 1)]));
+        let config = SolverConfig {
+            constraints: constraints.to_owned(),
+            ..Default::default()
+        };
+        let sol = instance.solve_edges_with_config(&config).unwrap();
+        assert!(constraints.check(&instance, &sol).is_ok());
+        assert_eq!(
+            instance.solution_transactions(&sol).unwrap(),
+            [("A".to_owned(), "B".to_owned(), 1)]
+        );
+        let hard = Constraints {
+            soft: Soft::default(),
+            ..constraints
+        };
+        assert!(hard.check(&instance, &sol).is_err());
+
+        // Passing the money of "E" on through "D" takes one more transaction, which is cheaper
+        // than a high penalty, but not than a low one.
+        let instance = ProblemInstance::from(Graph::from(vec![
+            ("A".to_owned(), 1),
+            ("B".to_owned(), 2),
+            ("C".to_owned(), 3),
+            ("D".to_owned(), -4),
+            ("E".to_owned(), -2),
+        ]));
+        let forbidden: Vec<(String, String)> = ["A", "B", "C"]
+            .iter()
+            .map(|n| ("E".to_owned(), n.to_string()))
+            .collect();
+        let solve = |penalty: Option<usize>| {
+            let config = SolverConfig {
+                method: SolvingMethods::DPGreedySatisfaction,
+                constraints: Constraints {
+                    forbidden: forbidden.to_owned(),
+                    soft: Soft {
+                        forbidden: penalty,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            instance
+                .solution_transactions(&instance.solve_edges_with_config(&config).unwrap())
+                .unwrap()
+        };
+        let pays_e = |transactions: &[(String, String, i64)]| {
+            transactions
+                .iter()
+                .filter(|(from, _, _)| from == "E")
+                .map(|(_, to, _)| to.to_owned())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(pays_e(&solve(None)), ["D"]);
+        assert_eq!(pays_e(&solve(Some(5))), ["D"]);
+        let cheap = solve(Some(0));
+        assert_ne!(pays_e(&cheap), ["D"]);
+        assert_eq!(
+            cheap.len(),
+            instance
+                .solve_edges_with(SolvingMethods::DPGreedySatisfaction)
+                .unwrap()
+                .len()
+        );
+    }
+
+    #[test]
+    fn test_max_amount_constraint() {
+        init();
+        debug!("Running 'test_max_amount_constraint'");
+        // Amounts are in cents, so 5.00 is split into transfers of at most 2.00.
+        let instance = ProblemInstance::from(Graph::from(vec![
+            ("A".to_owned(), -500),
+            ("B".to_owned(), 500),
+        ]))
+        .with_decimals(2)
+        .with_max_amount(Some(200));
+        let config = SolverConfig {
+            constraints: Constraints {
+                max_amount: instance.max_amount(),
+                max_transactions_per_person: Some(3),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let sol = instance.solve_edges_with_config(&config).unwrap();
+        assert_eq!(instance.solution_transactions(&sol).unwrap().len(), 3);
+
+        // The transfers after splitting count against the cap of a person.
+        let config = SolverConfig {
+            constraints: Constraints {
+                max_transactions_per_person: Some(2),
+                ..config.constraints
+            },
+            ..config
+        };
+        assert!(matches!(
+            instance.solve_edges_with_config(&config),
+            Err(SolveError::Infeasible(_))
+        ));
+
+        // Too many transfers are rejected before the plan is split.
+        let instance = ProblemInstance::from(Graph::from(vec![
+            ("A".to_owned(), -1_000_000_000_000),
+            ("B".to_owned(), 1_000_000_000_000),
+        ]))
+        .with_max_amount(Some(1));
+        let sol = instance.solve_edges_with(SolvingMethods::ApproxStarExpand);
+        assert!(Constraints::default().check(&instance, &sol).is_err());
+    }
+
+    #[test]
+    fn test_bounded_settlement() {
+        init();
+        debug!("Running 'test_bounded_settlement'");
+        let group: Vec<NamedNode> = [-2, -2, 1, 3]
+            .iter()
+            .enumerate()
+            .map(|(id, &weight)| NamedNode::new(id, id.to_string(), weight))
+            .collect();
+        let limits = Limits {
+            capacity: HashMap::from([(3, 1)]),
+            ..Default::default()
+        };
+        let plan = bounded_settlement(&group, &limits).unwrap();
+        assert_eq!(plan.len(), 3);
+        assert!(!limits.violated_by(&plan));
+        let limits = Limits {
+            capacity: HashMap::from([(3, 2)]),
+            forbidden: HashSet::from([(0, 3)]),
+            ..Default::default()
+        };
+        let plan = bounded_settlement(&group, &limits).unwrap();
+        assert_eq!(plan.len(), 3);
+        assert!(!limits.violated_by(&plan));
+    }
+
+    #[test]
+    fn test_search_limit() {
+        init();
+        debug!("Running 'test_search_limit'");
+        // Nobody, who owes money, may pay anybody, who gets money. Passing money on among the
+        // debtors never settles anybody, so the search runs out of steps instead of proving
+        // that there is no plan.
+        let debtors = ["A", "B", "C", "D", "E", "F"];
+        let creditors = ["P", "Q", "R", "S", "T", "U"];
+        let instance = ProblemInstance::from(Graph::from(
+            debtors
+                .iter()
+                .map(|name| (name.to_string(), -1))
+                .chain(creditors.iter().map(|name| (name.to_string(), 1)))
+                .collect::<Vec<_>>(),
+        ));
+        let config = SolverConfig {
+            constraints: Constraints {
+                forbidden: debtors
+                    .iter()
+                    .flat_map(|a| creditors.iter().map(|b| (a.to_string(), b.to_string())))
+                    .collect(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let result = instance.solve_edges_with_config(&config);
+        debug!("Result: {:?}", result);
+        assert!(matches!(result, Err(SolveError::SearchLimit(_))));
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("within the search budget"));
+
+        // With a soft cap of one transaction, there is no plan within all limits, but the
+        // search within the forbidden pairs alone gives up.
+        let config = SolverConfig {
+            constraints: Constraints {
+                max_transactions_per_person: Some(1),
+                soft: Soft {
+                    max_transactions: Some(1),
+                    ..Default::default()
+                },
+                ..config.constraints
+            },
+            ..config
+        };
+        assert!(matches!(
+            instance.solve_edges_with_config(&config),
+            Err(SolveError::SearchLimit(_))
+        ));
+
+        // Paying the forbidden pairs for a penalty settles everybody, but the search within all
+        // limits gives up, so a cheaper plan may be missed.
+        let config = SolverConfig {
+            constraints: Constraints {
+                max_transactions_per_person: None,
+                soft: Soft {
+                    forbidden: Some(1),
+                    ..Default::default()
+                },
+                ..config.constraints
+            },
+            ..config
+        };
+        assert!(matches!(
+            instance.solve_edges_with_config(&config),
+            Err(SolveError::SearchLimit(_))
+        ));
+    }
+
+    #[test]
+    fn test_rewrites_keep_constraints() {
+        init();
+        debug!("Running 'test_rewrites_keep_constraints'");
+        for seed in 0..20 {
+            let instance = ProblemInstance::from(generate(&GeneratorConfig {
+                nodes: 7,
+                groups: 2,
+                seed: Some(seed),
+                ..Default::default()
+            }));
+            for (objective, single_payment) in [
+                (Objective::MinEffort, false),
+                (Objective::MinTransactionsThenAmount, false),
+                (Objective::MinTransactions, true),
+            ] {
+                let config = SolverConfig {
+                    method: SolvingMethods::DPGreedySatisfaction,
+                    objective,
+                    single_payment,
+                    ..Default::default()
+                };
+                // Forbid a pair, which the rearranged plan pays between.
+                let sol = instance.solve_edges_with_config(&config).unwrap();
+                let (from, to, _) = instance.solution_transactions(&sol).unwrap()[0].to_owned();
+                let config = SolverConfig {
+                    constraints: Constraints {
+                        forbidden: vec![(from, to)],
+                        ..Default::default()
+                    },
+                    ..config
+                };
+                let sol = instance.solve_edges_with_config(&config).unwrap();
+                assert!(config.constraints.check(&instance, &sol).is_ok());
+                assert!(sol.is_some());
+            }
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use itertools::Itertools;
+
+use crate::canonical::CanonicalForm;
+use crate::graph::Graph;
+use crate::probleminstance::ProblemInstance;
+
+/// Named collections of reference instances bundled with the crate.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum Corpus {
+    /// Hand crafted instances with known structure and generated instances of up to 18 vertices.
+    Standard,
+}
+
+/// Instance of a [`Corpus`] with its name.
+#[derive(Clone, Debug)]
+pub struct CorpusInstance {
+    pub name: &'static str,
+    pub graph: Graph,
+}
+
+const STANDARD: [(&str, &str); 10] = [
+    (
+        "readme_edges",
+        include_str!("../corpus/standard/readme_edges.csv"),
+    ),
+    ("mixed_6", include_str!("../corpus/standard/mixed_6.csv")),
+    ("chain_6", include_str!("../corpus/standard/chain_6.csv")),
+    ("star_7", include_str!("../corpus/standard/star_7.csv")),
+    ("pairs_8", include_str!("../corpus/standard/pairs_8.csv")),
+    (
+        "uniform_10_2",
+        include_str!("../corpus/standard/uniform_10_2.csv"),
+    ),
+    (
+        "uniform_12_3",
+        include_str!("../corpus/standard/uniform_12_3.csv"),
+    ),
+    (
+        "exponential_14_4",
+        include_str!("../corpus/standard/exponential_14_4.csv"),
+    ),
+    (
+        "uniform_16_1",
+        include_str!("../corpus/standard/uniform_16_1.csv"),
+    ),
+    (
+        "exponential_18_6",
+        include_str!("../corpus/standard/exponential_18_6.csv"),
+    ),
+];
+
+/// Loads all instances of the given corpus ordered by their size.
+/// The instances are bundled with the crate, so they are the same across all versions and
+/// machines. Instances with the same weights as an earlier instance are left out.
+///
+/// * `corpus` - The corpus which should be loaded
+///
+/// Example:
+/// ```
+/// use payback::corpus::{load_corpus, Corpus};
+/// use payback::probleminstance::{ProblemInstance, SolvingMethods};
+///
+/// for instance in load_corpus(Corpus::Standard) {
+///     let solution = ProblemInstance::from(instance.graph).solve_with(SolvingMethods::ApproxStarExpand);
+/// }
+/// ```
+pub fn load_corpus(corpus: Corpus) -> Vec<CorpusInstance> {
+    let instances: &[(&str, &str)] = match corpus {
+        Corpus::Standard => &STANDARD,
+    };
+    instances
+        .iter()
+        .map(|(name, data)| CorpusInstance {
+            name,
+            graph: data
+                .to_string()
+                .try_into()
+                .expect("Bundled corpus instances are valid."),
+        })
+        .unique_by(|c| CanonicalForm::of(&ProblemInstance::from(c.graph.to_owned())).hash())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::corpus::{load_corpus, Corpus};
+    use crate::probleminstance::ProblemInstance;
+    use tracing::debug;
+    use tracing_subscriber::EnvFilter;
+
+    fn init() {
+        let _ = tracing_subscriber::fmt()
+            .with_env_filter(
+                EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("debug")),
+            )
+            .with_test_writer()
+            .try_init();
+    }
+
+    #[test]
+    fn test_load_corpus() {
+        init();
+        debug!("Running 'test_load_corpus'");
+        let corpus = load_corpus(Corpus::Standard);
+        assert_eq!(corpus.len(), 10);
+        for instance in corpus {
+            assert!(
+                ProblemInstance::from(instance.graph).is_solvable(),
+                "Instance '{}' is not solvable.",
+                instance.name
+            );
+        }
+    }
+}
@@ -0,0 +1,280 @@
+use csv::ReaderBuilder;
+use itertools::Itertools;
+use serde_derive::Deserialize;
+use std::collections::HashMap;
+use tracing::debug;
+
+use crate::audit::RoundingAudit;
+use crate::graph::Graph;
+
+#[derive(Debug, Deserialize)]
+struct CurrencyNodeRecord {
+    name: String,
+    weight: i64,
+    currency: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct CurrencyEdgeRecord {
+    from: String,
+    to: String,
+    weight: i64,
+    currency: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct RateRecord {
+    currency: String,
+    rate: f64,
+}
+
+/// Transactions in the form of payer, payee and amount with their currency.
+pub type CurrencyTransactions = (Option<String>, Vec<(String, String, i64)>);
+
+/// Balance of a person in one currency.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CurrencyBalance {
+    pub name: String,
+    pub currency: String,
+    pub weight: i64,
+}
+
+fn deserialize<T: serde::de::DeserializeOwned>(data: &str) -> Result<Vec<T>, csv::Error> {
+    ReaderBuilder::new()
+        .has_headers(false)
+        .from_reader(data.as_bytes())
+        .deserialize()
+        .collect()
+}
+
+/// Parses an instance, whose records have a currency as additional last column. Either nodes with
+/// 'NodeName,weight,currency' or edges with 'NodeNameFrom,NodeNameTo,weight,currency'.
+/// Returns `None` if the records have no currency column.
+pub fn parse_currency_balances(data: &str) -> Option<Vec<CurrencyBalance>> {
+    let records: Vec<((String, String), i64)> =
+        if let Ok(nodes) = deserialize::<CurrencyNodeRecord>(data) {
+            nodes
+                .into_iter()
+                .map(|n| ((n.name, n.currency), n.weight))
+                .collect()
+        } else if let Ok(edges) = deserialize::<CurrencyEdgeRecord>(data) {
+            edges
+                .into_iter()
+                .flat_map(|e| {
+                    [
+                        ((e.from, e.currency.to_owned()), -e.weight),
+                        ((e.to, e.currency), e.weight),
+                    ]
+                })
+                .collect()
+        } else {
+            return None;
+        };
+    let mut balances: Vec<CurrencyBalance> = vec![];
+    for ((name, currency), weight) in records {
+        match balances
+            .iter_mut()
+            .find(|b| b.name == name && b.currency == currency)
+        {
+            Some(b) => b.weight += weight,
+            None => balances.push(CurrencyBalance {
+                name,
+                currency,
+                weight,
+            }),
+        }
+    }
+    Some(balances)
+}
+
+/// Splits the balances into one graph per currency in the order the currencies first appear.
+pub fn split_by_currency(balances: &[CurrencyBalance]) -> Vec<(String, Graph)> {
+    balances
+        .iter()
+        .map(|b| &b.currency)
+        .unique()
+        .map(|currency| {
+            let weights: Vec<(String, i64)> = balances
+                .iter()
+                .filter(|b| &b.currency == currency)
+                .map(|b| (b.name.to_owned(), b.weight))
+                .collect();
+            (currency.to_owned(), Graph::from(weights))
+        })
+        .collect()
+}
+
+/// Exchange rates into a base currency.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rates {
+    pub base: String,
+    /// Value of one unit of a currency in the base currency.
+    rates: HashMap<String, f64>,
+}
+
+impl Rates {
+    pub fn new(base: String) -> Self {
+        Rates {
+            rates: HashMap::from([(base.to_owned(), 1.0)]),
+            base,
+        }
+    }
+
+    /// Sets the value of one unit of the currency in the base currency.
+    pub fn insert(&mut self, currency: String, rate: f64) -> Result<(), String> {
+        if !(rate.is_finite() && rate > 0.0) {
+            return Err(format!("Rate {} of {} must be positive.", rate, currency));
+        }
+        if currency == self.base && rate != 1.0 {
+            return Err(format!(
+                "The rate of the base currency {} must be 1.",
+                currency
+            ));
+        }
+        self.rates.insert(currency, rate);
+        Ok(())
+    }
+
+    /// Reads rates from csv records 'currency,rate'.
+    pub fn insert_csv(&mut self, data: &str) -> Result<(), String> {
+        deserialize::<RateRecord>(data)
+            .map_err(|e| format!("Invalid rates: {}", e))?
+            .into_iter()
+            .try_for_each(|r| self.insert(r.currency, r.rate))
+    }
+
+    pub fn get(&self, currency: &str) -> Option<f64> {
+        self.rates.get(currency).copied()
+    }
+
+    /// Converts the balances into the base currency and merges the balances of each person.
+    /// Converted amounts are rounded, such that the weights still add up to zero.
+    pub fn convert(&self, balances: &[CurrencyBalance]) -> Result<Graph, String> {
+        self.convert_audited(balances, &mut RoundingAudit::default())
+    }
+
+    /// Exact value of every balance in the base currency before rounding.
+    ///
+    /// Example:
+    /// ```
+    /// use payback::currency::{parse_currency_balances, Rates};
+    ///
+    /// let balances = parse_currency_balances("A,B,1000,CZK\nC,D,10,EUR").unwrap();
+    /// let mut rates = Rates::new("EUR".to_string());
+    /// rates.insert("CZK".to_string(), 0.04).unwrap();
+    /// let conversions = rates.conversions(&balances).unwrap();
+    /// assert_eq!(conversions[0].0.currency, "CZK");
+    /// assert_eq!(conversions[0].1, -40.0);
+    /// ```
+    pub fn conversions(
+        &self,
+        balances: &[CurrencyBalance],
+    ) -> Result<Vec<(CurrencyBalance, f64)>, String> {
+        balances
+            .iter()
+            .map(|b| {
+                let rate = self.get(&b.currency).ok_or(format!(
+                    "No exchange rate from {} into {} is given.",
+                    b.currency, self.base
+                ))?;
+                Ok((b.to_owned(), b.weight as f64 * rate))
+            })
+            .collect()
+    }
+
+    /// Converts the balances like [`Rates::convert()`] and adds the differences between the
+    /// rounded and the exact converted balances to the audit.
+    pub fn convert_audited(
+        &self,
+        balances: &[CurrencyBalance],
+        audit: &mut RoundingAudit,
+    ) -> Result<Graph, String> {
+        let mut converted: Vec<(String, f64)> = vec![];
+        for (b, weight) in self.conversions(balances)? {
+            match converted.iter_mut().find(|(n, _)| *n == b.name) {
+                Some((_, w)) => *w += weight,
+                None => converted.push((b.name, weight)),
+            }
+        }
+        debug!("Converted balances into {}: {:?}", self.base, converted);
+        let rounded = round_preserving_sum(converted.to_owned());
+        for ((name, exact), (_, weight)) in converted.iter().zip(&rounded) {
+            audit.add(name, *weight as f64 - exact);
+        }
+        Ok(Graph::from(rounded))
+    }
+}
+
+/// Rounds the weights with the largest remainder method, such that the rounded weights add up to
+/// the rounded sum of the weights.
+fn round_preserving_sum(weights: Vec<(String, f64)>) -> Vec<(String, i64)> {
+    let target = weights.iter().map(|(_, w)| w).sum::<f64>().round() as i64;
+    let mut rounded: Vec<(String, i64)> = weights
+        .iter()
+        .map(|(n, w)| (n.to_owned(), w.floor() as i64))
+        .collect();
+    let missing = target - rounded.iter().map(|(_, w)| w).sum::<i64>();
+    weights
+        .iter()
+        .enumerate()
+        .sorted_by(|(_, (_, a)), (_, (_, b))| (b - b.floor()).total_cmp(&(a - a.floor())))
+        .take(missing.max(0) as usize)
+        .for_each(|(i, _)| rounded[i].1 += 1);
+    rounded
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::currency::{parse_currency_balances, split_by_currency, Rates};
+    use tracing::debug;
+    use tracing_subscriber::EnvFilter;
+
+    fn init() {
+        let _ = tracing_subscriber::fmt()
+            .with_env_filter(
+                EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("debug")),
+            )
+            .with_test_writer()
+            .try_init();
+    }
+
+    #[test]
+    fn test_currency_conversion() {
+        init();
+        debug!("Running 'test_currency_conversion'");
+        assert_eq!(parse_currency_balances("A,-1\nB,1"), None);
+        assert_eq!(parse_currency_balances("A,B,1"), None);
+        let balances =
+            parse_currency_balances("A,B,1000,CZK\nC,D,10,EUR\nB,C,10,CZK\nD,A,1,EUR").unwrap();
+        assert_eq!(balances.len(), 6);
+        let split = split_by_currency(&balances);
+        assert_eq!(split.len(), 2);
+        assert_eq!(split[0].0, "CZK");
+        assert_eq!(
+            split[0].1.balances(),
+            vec![
+                ("A".to_string(), -1000),
+                ("B".to_string(), 990),
+                ("C".to_string(), 10)
+            ]
+        );
+
+        let mut rates = Rates::new("EUR".to_string());
+        assert!(rates.convert(&balances).is_err());
+        assert!(rates.insert("CZK".to_string(), -1.0).is_err());
+        rates.insert_csv("CZK,0.04").unwrap();
+        let graph = rates.convert(&balances).unwrap();
+        let weights = graph.balances();
+        debug!("Converted: {:?}", weights);
+        assert_eq!(weights.iter().map(|(_, w)| w).sum::<i64>(), 0);
+        assert_eq!(
+            weights,
+            vec![
+                ("A".to_string(), -39),
+                ("B".to_string(), 40),
+                ("C".to_string(), -10),
+                ("D".to_string(), 9)
+            ]
+        );
+    }
+}
@@ -0,0 +1,239 @@
+use chrono::{Datelike, NaiveDate};
+use csv::{ReaderBuilder, StringRecord, WriterBuilder};
+use itertools::Itertools;
+use tracing::{debug, instrument};
+
+/// Format of the optional date column.
+pub const DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// Length of the periods for [`group_by_period()`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum Period {
+    /// ISO weeks starting on monday, e.g. '2024-W05'.
+    Week,
+    /// Calendar months, e.g. '2024-01'.
+    Month,
+    /// Quarters of the year, e.g. '2024-Q1'.
+    Quarter,
+}
+
+impl Period {
+    /// Label of the period containing the date. Labels are ordered chronologically.
+    pub fn label(&self, date: NaiveDate) -> String {
+        match self {
+            Period::Week => {
+                let week = date.iso_week();
+                format!("{}-W{:02}", week.year(), week.week())
+            }
+            Period::Month => format!("{}-{:02}", date.year(), date.month()),
+            Period::Quarter => format!("{}-Q{}", date.year(), date.month0() / 3 + 1),
+        }
+    }
+}
+
+/// Record of an instance with its optional date, which was given as last column.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DatedRecord {
+    /// Columns of the record without the date.
+    pub fields: Vec<String>,
+    pub date: Option<NaiveDate>,
+}
+
+/// Parses a date in the format 'YYYY-MM-DD'.
+pub fn parse_date(s: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(s.trim(), DATE_FORMAT)
+        .map_err(|e| format!("Invalid date '{}': {}", s, e))
+}
+
+/// Splits off the date column of every record. Records of any format can have a date as
+/// additional last column, e.g. 'NodeNameFrom,NodeNameTo,weight,2024-01-31'.
+/// Returns `None` if no record has a date.
+pub fn parse_dated_records(data: &str) -> Result<Option<Vec<DatedRecord>>, String> {
+    let records = ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(data.as_bytes())
+        .records()
+        .collect::<Result<Vec<StringRecord>, csv::Error>>()
+        .map_err(|e| e.to_string())?;
+    let dated: Vec<DatedRecord> = records
+        .iter()
+        .map(|r| {
+            let mut fields: Vec<String> = r.iter().map(|f| f.to_owned()).collect();
+            let date = fields
+                .last()
+                .and_then(|f| NaiveDate::parse_from_str(f.trim(), DATE_FORMAT).ok());
+            if date.is_some() {
+                fields.pop();
+            }
+            DatedRecord { fields, date }
+        })
+        .collect();
+    if dated.iter().all(|r| r.date.is_none()) {
+        return Ok(None);
+    }
+    Ok(Some(dated))
+}
+
+/// Serializes the records without their dates, so they can be parsed like any instance.
+pub fn records_to_csv(records: &[DatedRecord]) -> Result<String, String> {
+    let mut wtr = WriterBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_writer(vec![]);
+    records
+        .iter()
+        .try_for_each(|r| wtr.write_record(&r.fields))
+        .map_err(|e| e.to_string())?;
+    wtr.into_inner()
+        .map_err(|e| e.to_string())
+        .and_then(|bytes| String::from_utf8(bytes).map_err(|e| e.to_string()))
+}
+
+/// Removes the date column from the records and keeps only records dated between `from` and
+/// `to`, both inclusive. Without a bound, all records are kept. If a bound is given, every record
+/// needs a date. Inputs without dates are returned unchanged, if no bound is given.
+///
+/// Example:
+/// ```
+/// use payback::dates::{filter_by_date, parse_date};
+///
+/// let data = "A,B,1,2024-01-31\nA,C,2,2024-02-01";
+/// let to = parse_date("2024-01-31").unwrap();
+/// assert_eq!(filter_by_date(data, None, Some(to)).unwrap(), "A,B,1\n");
+/// ```
+#[instrument(level = "debug", skip(data))]
+pub fn filter_by_date(
+    data: &str,
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+) -> Result<String, String> {
+    let Some(records) = parse_dated_records(data)? else {
+        return match (from, to) {
+            (None, None) => Ok(data.to_owned()),
+            _ => Err("The records have no date column to filter by.".to_string()),
+        };
+    };
+    if from.is_some() || to.is_some() {
+        if let Some(r) = records.iter().find(|r| r.date.is_none()) {
+            return Err(format!("Record '{}' has no date.", r.fields.join(",")));
+        }
+    }
+    let kept: Vec<DatedRecord> = records
+        .into_iter()
+        .filter(|r| {
+            r.date
+                .is_none_or(|d| from.is_none_or(|f| f <= d) && to.is_none_or(|t| d <= t))
+        })
+        .collect();
+    debug!(
+        "Kept {} records between {:?} and {:?}",
+        kept.len(),
+        from,
+        to
+    );
+    records_to_csv(&kept)
+}
+
+/// Splits the records dated between `from` and `to` into one instance per period without the
+/// date column. Every record needs a date. The periods are ordered chronologically and periods
+/// without records are left out.
+#[instrument(level = "debug", skip(data))]
+pub fn group_by_period(
+    data: &str,
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+    period: Period,
+) -> Result<Vec<(String, String)>, String> {
+    let records =
+        parse_dated_records(data)?.ok_or("The records have no date column to group by.")?;
+    let mut dated: Vec<(NaiveDate, DatedRecord)> = vec![];
+    for r in records {
+        match r.date {
+            Some(d) if from.is_none_or(|f| f <= d) && to.is_none_or(|t| d <= t) => {
+                dated.push((d, r))
+            }
+            Some(_) => {}
+            None => return Err(format!("Record '{}' has no date.", r.fields.join(","))),
+        }
+    }
+    dated.sort_by_key(|(d, _)| *d);
+    dated
+        .into_iter()
+        .group_by(|(d, _)| period.label(*d))
+        .into_iter()
+        .map(|(label, group)| {
+            let records: Vec<DatedRecord> = group.map(|(_, r)| r).collect();
+            records_to_csv(&records).map(|csv| (label, csv))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dates::{filter_by_date, group_by_period, parse_date, parse_dated_records, Period};
+    use tracing::debug;
+    use tracing_subscriber::EnvFilter;
+
+    fn init() {
+        let _ = tracing_subscriber::fmt()
+            .with_env_filter(
+                EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("debug")),
+            )
+            .with_test_writer()
+            .try_init();
+    }
+
+    #[test]
+    fn test_filter_by_date() {
+        init();
+        debug!("Running 'test_filter_by_date'");
+        let data = "A,B,1,2024-01-01\nB,C,2,EUR,2024-01-15\nC,A,3,2024-02-01\n";
+        assert_eq!(parse_dated_records(data).unwrap().unwrap().len(), 3);
+        assert_eq!(parse_dated_records("A,B,1").unwrap(), None);
+
+        let from = parse_date("2024-01-15").ok();
+        let to = parse_date("2024-01-31").ok();
+        assert_eq!(
+            filter_by_date(data, None, None).unwrap(),
+            "A,B,1\nB,C,2,EUR\nC,A,3\n"
+        );
+        assert_eq!(filter_by_date(data, from, to).unwrap(), "B,C,2,EUR\n");
+        assert_eq!(
+            filter_by_date(data, from, None).unwrap(),
+            "B,C,2,EUR\nC,A,3\n"
+        );
+        assert_eq!(filter_by_date("A,B,1", None, None).unwrap(), "A,B,1");
+        assert!(filter_by_date("A,B,1", from, None).is_err());
+        assert!(filter_by_date("A,B,1\nA,C,1,2024-01-01", None, to).is_err());
+        assert!(parse_date("2024-13-01").is_err());
+    }
+
+    #[test]
+    fn test_group_by_period() {
+        init();
+        debug!("Running 'test_group_by_period'");
+        let data = "C,A,3,2024-04-01\nA,B,1,2024-01-01\nB,C,2,2024-01-15\n";
+        assert_eq!(
+            group_by_period(data, None, None, Period::Month).unwrap(),
+            vec![
+                ("2024-01".to_string(), "A,B,1\nB,C,2\n".to_string()),
+                ("2024-04".to_string(), "C,A,3\n".to_string())
+            ]
+        );
+        let weeks = group_by_period(data, None, None, Period::Week).unwrap();
+        assert_eq!(weeks.len(), 3);
+        assert_eq!(weeks[0].0, "2024-W01");
+        let quarters =
+            group_by_period(data, parse_date("2024-01-10").ok(), None, Period::Quarter).unwrap();
+        assert_eq!(
+            quarters,
+            vec![
+                ("2024-Q1".to_string(), "B,C,2\n".to_string()),
+                ("2024-Q2".to_string(), "C,A,3\n".to_string())
+            ]
+        );
+        assert!(group_by_period("A,B,1", None, None, Period::Month).is_err());
+    }
+}
@@ -0,0 +1,301 @@
+use csv::{ReaderBuilder, StringRecord, WriterBuilder};
+use serde_derive::Deserialize;
+use tracing::debug;
+
+/// Most decimal places of an amount. Amounts are stored in minor units as `i64`, so every further
+/// place lowers the largest amount tenfold.
+pub const MAX_DECIMALS: u32 = 6;
+
+/// How amounts with more decimal places than the minor units are rounded. Halves and directions
+/// are taken by the absolute amount, so negative amounts round like positive ones.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Rounding {
+    /// Amounts with more decimal places are an error.
+    #[default]
+    Reject,
+    /// Round halves away from zero, e.g. '0.125' to '0.13'.
+    HalfUp,
+    /// Round halves to the even neighbour, e.g. '0.125' to '0.12'.
+    HalfEven,
+    /// Round towards zero, e.g. '-0.129' to '-0.12'.
+    Down,
+    /// Round away from zero, e.g. '0.121' to '0.13'.
+    Up,
+}
+
+/// Whether the field is a decimal number like '-12', '12.5' or '.5'.
+fn is_number(field: &str) -> bool {
+    let digits = field.strip_prefix('-').unwrap_or(field);
+    let (integer, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+    (!integer.is_empty() || !fraction.is_empty())
+        && integer.chars().all(|c| c.is_ascii_digit())
+        && fraction.chars().all(|c| c.is_ascii_digit())
+}
+
+/// Position of the amount in a record, which is the first number after the name.
+fn amount_index(record: &StringRecord) -> Option<usize> {
+    (1..record.len()).find(|&i| is_number(record[i].trim()))
+}
+
+fn records(data: &str) -> Result<Vec<StringRecord>, String> {
+    ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(data.as_bytes())
+        .records()
+        .collect::<Result<Vec<StringRecord>, csv::Error>>()
+        .map_err(|e| e.to_string())
+}
+
+/// Most decimal places of the amounts of the csv records, e.g. 2 for 'A,-12.34\nB,12.34'.
+/// The amount of a record is its first number after the name. Fails if an amount has more than
+/// [`MAX_DECIMALS`] places.
+pub fn decimal_places(data: &str) -> Result<u32, String> {
+    let mut decimals = 0;
+    for record in records(data)? {
+        let Some(i) = amount_index(&record) else {
+            continue;
+        };
+        let places = record[i]
+            .trim()
+            .split_once('.')
+            .map_or(0, |(_, fraction)| fraction.len());
+        if places > MAX_DECIMALS as usize {
+            return Err(format!(
+                "The amount {} has more than {} decimal places.",
+                record[i].trim(),
+                MAX_DECIMALS
+            ));
+        }
+        decimals = decimals.max(places as u32);
+    }
+    Ok(decimals)
+}
+
+/// Parses a decimal number into minor units with `decimals` many decimal places, e.g. '12.3'
+/// into 1230 with 2 places. Fails if the number has more places or overflows.
+///
+/// Example:
+/// ```
+/// use payback::decimals::parse_minor;
+///
+/// assert_eq!(parse_minor("-12.3", 2), Ok(-1230));
+/// assert_eq!(parse_minor("5", 2), Ok(500));
+/// assert!(parse_minor("0.125", 2).is_err());
+/// ```
+pub fn parse_minor(amount: &str, decimals: u32) -> Result<i64, String> {
+    parse_minor_rounded(amount, decimals, Rounding::Reject)
+}
+
+/// Parses a decimal number into minor units like [`parse_minor()`], but rounds numbers with more
+/// places by the `rounding`.
+///
+/// Example:
+/// ```
+/// use payback::decimals::{parse_minor_rounded, Rounding};
+///
+/// assert_eq!(parse_minor_rounded("0.125", 2, Rounding::HalfUp), Ok(13));
+/// assert_eq!(parse_minor_rounded("-0.125", 2, Rounding::HalfEven), Ok(-12));
+/// assert_eq!(parse_minor_rounded("0.129", 2, Rounding::Down), Ok(12));
+/// assert!(parse_minor_rounded("0.125", 2, Rounding::Reject).is_err());
+/// ```
+pub fn parse_minor_rounded(amount: &str, decimals: u32, rounding: Rounding) -> Result<i64, String> {
+    let invalid = || {
+        format!(
+            "Expected an amount with at most {} decimal places, got '{}'.",
+            decimals, amount
+        )
+    };
+    let trimmed = amount.trim();
+    if !is_number(trimmed) {
+        return Err(invalid());
+    }
+    let (negative, digits) = match trimmed.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, trimmed),
+    };
+    let (integer, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+    if fraction.len() > decimals as usize && rounding == Rounding::Reject {
+        return Err(invalid());
+    }
+    let (kept, rest) = fraction.split_at(fraction.len().min(decimals as usize));
+    let too_large = || format!("The amount {} is too large.", trimmed);
+    let minor = format!("{}{:0<width$}", integer, kept, width = decimals as usize)
+        .parse::<i64>()
+        .map_err(|_| too_large())?;
+    let first = rest.bytes().next().unwrap_or(b'0');
+    let beyond_half = rest.bytes().skip(1).any(|d| d != b'0');
+    let round_up = match rounding {
+        _ if rest.bytes().all(|d| d == b'0') => false,
+        Rounding::Reject | Rounding::Down => false,
+        Rounding::Up => true,
+        Rounding::HalfUp => first >= b'5',
+        Rounding::HalfEven => first > b'5' || (first == b'5' && (beyond_half || minor % 2 == 1)),
+    };
+    let minor = match round_up {
+        true => minor.checked_add(1).ok_or_else(too_large)?,
+        false => minor,
+    };
+    Ok(if negative { -minor } else { minor })
+}
+
+/// Formats an amount in minor units with `decimals` many decimal places and the separator, e.g.
+/// 1230 with 2 places as '12.30'. Amounts without decimal places are formatted as integers.
+///
+/// Example:
+/// ```
+/// use payback::decimals::format_minor;
+///
+/// assert_eq!(format_minor(-5, 2, '.'), "-0.05");
+/// assert_eq!(format_minor(1230, 2, ','), "12,30");
+/// assert_eq!(format_minor(42, 0, '.'), "42");
+/// ```
+pub fn format_minor(amount: i64, decimals: u32, separator: char) -> String {
+    if decimals == 0 {
+        return amount.to_string();
+    }
+    let factor = 10_u64.pow(decimals);
+    let abs = amount.unsigned_abs();
+    format!(
+        "{}{}{}{:0width$}",
+        if amount < 0 { "-" } else { "" },
+        abs / factor,
+        separator,
+        abs % factor,
+        width = decimals as usize
+    )
+}
+
+/// Formats an amount in minor units for payment links and bank transfers, which expect cents,
+/// e.g. 42 whole units as '42.00'.
+pub fn format_money(amount: i64, decimals: u32) -> String {
+    match 2_u32.checked_sub(decimals) {
+        // Amounts with fewer places are padded, which never overflows for small factors.
+        Some(missing) => format_minor(amount.saturating_mul(10_i64.pow(missing)), 2, '.'),
+        None => format_minor(amount, decimals, '.'),
+    }
+}
+
+/// Multiplies a whole amount, e.g. of an option, into minor units.
+pub fn whole_to_minor(amount: i64, decimals: u32) -> Result<i64, String> {
+    amount
+        .checked_mul(10_i64.pow(decimals))
+        .ok_or(format!("The amount {} is too large.", amount))
+}
+
+/// Replaces the amount of every csv record by the amount in minor units with `decimals` many
+/// decimal places, so the solvers can work with whole numbers. The amount of a record is its
+/// first number after the name, see [`decimal_places()`]. Without decimal places, the data is
+/// returned unchanged. Amounts with more places are an error, see [`to_minor_units_rounded()`]
+/// to round them instead.
+///
+/// Example:
+/// ```
+/// use payback::decimals::{decimal_places, to_minor_units};
+///
+/// let data = "A,B,12.5,EUR\nB,C,3,EUR";
+/// assert_eq!(decimal_places(data), Ok(1));
+/// assert_eq!(to_minor_units(data, 1).unwrap(), "A,B,125,EUR\nB,C,30,EUR\n");
+/// ```
+pub fn to_minor_units(data: &str, decimals: u32) -> Result<String, String> {
+    to_minor_units_rounded(data, decimals, Rounding::Reject)
+}
+
+/// Replaces the amounts of the csv records by minor units like [`to_minor_units()`], but rounds
+/// amounts with more than `decimals` many places by the `rounding`.
+///
+/// Example:
+/// ```
+/// use payback::decimals::{to_minor_units_rounded, Rounding};
+///
+/// let data = "A,-10.005\nB,10.005";
+/// assert_eq!(
+///     to_minor_units_rounded(data, 2, Rounding::HalfUp).unwrap(),
+///     "A,-1001\nB,1001\n"
+/// );
+/// ```
+pub fn to_minor_units_rounded(
+    data: &str,
+    decimals: u32,
+    rounding: Rounding,
+) -> Result<String, String> {
+    if decimals == 0 && (rounding == Rounding::Reject || decimal_places(data) == Ok(0)) {
+        return Ok(data.to_owned());
+    }
+    debug!(decimals, "Converting amounts to minor units");
+    let mut wtr = WriterBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_writer(vec![]);
+    for record in records(data)? {
+        let mut fields: Vec<String> = record.iter().map(str::to_owned).collect();
+        if let Some(i) = amount_index(&record) {
+            fields[i] = parse_minor_rounded(&fields[i], decimals, rounding)?.to_string();
+        }
+        wtr.write_record(&fields).map_err(|e| e.to_string())?;
+    }
+    String::from_utf8(wtr.into_inner().map_err(|e| e.to_string())?).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::decimals::{
+        decimal_places, format_minor, format_money, parse_minor, parse_minor_rounded,
+        to_minor_units, to_minor_units_rounded, Rounding,
+    };
+    use crate::graph::Graph;
+    use crate::probleminstance::{ProblemInstance, SolvingMethods};
+    use tracing::debug;
+    use tracing_subscriber::EnvFilter;
+
+    fn init() {
+        let _ = tracing_subscriber::fmt()
+            .with_env_filter(
+                EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("debug")),
+            )
+            .with_test_writer()
+            .try_init();
+    }
+
+    #[test]
+    fn test_decimals() {
+        init();
+        debug!("Running 'test_decimals'");
+        let data = "Anna,-12.34,iban=DE02\nBen,2.3\nCarl,10.04";
+        let decimals = decimal_places(data).unwrap();
+        assert_eq!(decimals, 2);
+        let minor = to_minor_units(data, decimals).unwrap();
+        assert_eq!(minor, "Anna,-1234,iban=DE02\nBen,230\nCarl,1004\n");
+        assert_eq!(decimal_places("A,B,2024-01-02,5"), Ok(0));
+        assert!(decimal_places("A,1.0000001").is_err());
+        assert!(parse_minor("99999999999999999.9", 2).is_err());
+        assert!(parse_minor("1.2.3", 2).is_err());
+
+        let minor = to_minor_units("Anna,-12.34\nBen,2.3\nCarl,10.04", decimals).unwrap();
+        let instance = ProblemInstance::from(Graph::try_from(minor).unwrap());
+        let sol = instance.solve_edges_with(SolvingMethods::DPGreedySatisfaction);
+        let transactions = instance.solution_transactions(&sol).unwrap();
+        assert_eq!(
+            transactions
+                .iter()
+                .map(|(_, _, amount)| format_minor(*amount, decimals, '.'))
+                .collect::<Vec<_>>(),
+            ["2.30", "10.04"]
+        );
+        assert_eq!(format_minor(i64::MIN, 2, '.'), "-92233720368547758.08");
+        assert_eq!(format_money(5, 1), "0.50");
+        assert_eq!(format_money(5, 3), "0.005");
+
+        let round = |amount, rounding| parse_minor_rounded(amount, 2, rounding).unwrap();
+        assert_eq!(round("0.135", Rounding::HalfEven), 14);
+        assert_eq!(round("0.1251", Rounding::HalfEven), 13);
+        assert_eq!(round("-0.125", Rounding::HalfUp), -13);
+        assert_eq!(round("-0.121", Rounding::Up), -13);
+        assert_eq!(round("0.1200", Rounding::Up), 12);
+        assert_eq!(
+            to_minor_units_rounded("A,-1.5\nB,1.5", 0, Rounding::HalfEven).unwrap(),
+            "A,-2\nB,2\n"
+        );
+    }
+}
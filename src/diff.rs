@@ -0,0 +1,195 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+
+pub use crate::graph::Debt;
+use crate::graph::Graph;
+
+/// Balance of a person in the old and in the new instance. People missing in one instance have a
+/// balance of 0 there.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BalanceChange {
+    pub name: String,
+    pub old: i64,
+    pub new: i64,
+}
+
+/// Differences between two instances found by [`diff_instances()`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct InstanceDiff {
+    /// People whose balance changed ordered by name.
+    pub balances: Vec<BalanceChange>,
+    /// Debts only in the new instance.
+    pub added_debts: Vec<Debt>,
+    /// Debts only in the old instance.
+    pub removed_debts: Vec<Debt>,
+    /// Debts in both instances with a different amount, given with the new amount.
+    pub changed_debts: Vec<(Debt, i64)>,
+}
+
+impl InstanceDiff {
+    pub fn is_empty(&self) -> bool {
+        self.balances.is_empty()
+            && self.added_debts.is_empty()
+            && self.removed_debts.is_empty()
+            && self.changed_debts.is_empty()
+    }
+}
+
+impl Display for InstanceDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_empty() {
+            return write!(f, "The instances are equal.");
+        }
+        if !self.balances.is_empty() {
+            writeln!(f, "Balance changes:")?;
+            for c in &self.balances {
+                writeln!(
+                    f,
+                    "  {:?}: {} -> {} ({:+})",
+                    c.name,
+                    c.old,
+                    c.new,
+                    c.new - c.old
+                )?;
+            }
+        }
+        for (title, debts) in [
+            ("New debts", &self.added_debts),
+            ("Removed debts", &self.removed_debts),
+        ] {
+            if !debts.is_empty() {
+                writeln!(f, "{}:", title)?;
+                for d in debts {
+                    writeln!(f, "  {:?} owes {:?} {}", d.from, d.to, d.amount)?;
+                }
+            }
+        }
+        if !self.changed_debts.is_empty() {
+            writeln!(f, "Changed debts:")?;
+            for (d, new) in &self.changed_debts {
+                writeln!(f, "  {:?} owes {:?} {} -> {}", d.from, d.to, d.amount, new)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Compares two instances given in one of the csv formats. Balance changes are always reported.
+/// Debts are only compared if both instances are in the csv edge format.
+///
+/// Example:
+/// ```
+/// use payback::diff::diff_instances;
+///
+/// let diff = diff_instances("A,B,3", "A,B,5\nA,C,1").unwrap();
+/// assert_eq!(diff.balances.len(), 3);
+/// assert_eq!(diff.added_debts.len(), 1);
+/// assert_eq!(diff.changed_debts.len(), 1);
+/// ```
+pub fn diff_instances(old: &str, new: &str) -> Result<InstanceDiff, String> {
+    let old_graph: Graph = old.to_string().try_into()?;
+    let new_graph: Graph = new.to_string().try_into()?;
+    let mut changes: Vec<BalanceChange> = old_graph
+        .balances()
+        .into_iter()
+        .map(|(name, old)| BalanceChange { name, old, new: 0 })
+        .collect();
+    for (name, weight) in new_graph.balances() {
+        match changes.iter_mut().find(|c| c.name == name) {
+            Some(c) => c.new = weight,
+            None => changes.push(BalanceChange {
+                name,
+                old: 0,
+                new: weight,
+            }),
+        }
+    }
+    changes.sort_by(|a, b| a.name.cmp(&b.name));
+    let mut diff = InstanceDiff {
+        balances: changes.into_iter().filter(|c| c.old != c.new).collect(),
+        ..Default::default()
+    };
+    if let (Some(old_debts), Some(new_debts)) = (old_graph.debts(), new_graph.debts()) {
+        let lookup = |debts: &[Debt]| -> HashMap<(String, String), i64> {
+            debts
+                .iter()
+                .map(|d| ((d.from.to_owned(), d.to.to_owned()), d.amount))
+                .collect()
+        };
+        let old_lookup = lookup(old_debts);
+        let new_lookup = lookup(new_debts);
+        for d in old_debts {
+            match new_lookup.get(&(d.from.to_owned(), d.to.to_owned())) {
+                None => diff.removed_debts.push(d.to_owned()),
+                Some(new) if *new != d.amount => diff.changed_debts.push((d.to_owned(), *new)),
+                Some(_) => {}
+            }
+        }
+        for d in new_debts {
+            if !old_lookup.contains_key(&(d.from.to_owned(), d.to.to_owned())) {
+                diff.added_debts.push(d.to_owned());
+            }
+        }
+    }
+    Ok(diff)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::diff::{diff_instances, BalanceChange, Debt};
+    use tracing::debug;
+    use tracing_subscriber::EnvFilter;
+
+    fn init() {
+        let _ = tracing_subscriber::fmt()
+            .with_env_filter(
+                EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("debug")),
+            )
+            .with_test_writer()
+            .try_init();
+    }
+
+    #[test]
+    fn test_diff_instances() {
+        init();
+        debug!("Running 'test_diff_instances'");
+        let diff = diff_instances("A,B,3\nB,C,2", "A,B,3\nB,C,4\nC,D,1").unwrap();
+        debug!("Diff:\n{}", diff);
+        assert_eq!(
+            diff.balances,
+            vec![
+                BalanceChange {
+                    name: "B".to_string(),
+                    old: 1,
+                    new: -1
+                },
+                BalanceChange {
+                    name: "C".to_string(),
+                    old: 2,
+                    new: 3
+                },
+                BalanceChange {
+                    name: "D".to_string(),
+                    old: 0,
+                    new: 1
+                },
+            ]
+        );
+        assert_eq!(
+            diff.added_debts,
+            vec![Debt {
+                from: "C".to_string(),
+                to: "D".to_string(),
+                amount: 1
+            }]
+        );
+        assert!(diff.removed_debts.is_empty());
+        assert_eq!(diff.changed_debts.len(), 1);
+
+        // Debts are not compared for the node format.
+        let diff = diff_instances("A,-1\nB,1", "A,B,1").unwrap();
+        assert!(diff.is_empty());
+        assert_eq!(diff.to_string(), "The instances are equal.");
+        assert!(diff_instances("A", "A,B,1").is_err());
+    }
+}
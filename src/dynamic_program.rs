@@ -1,79 +1,91 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 use itertools::Itertools;
-use log::debug;
+use tracing::{debug, instrument, trace, Span};
 
 use crate::{
+    events::{MemoryStructure, ProgressMeter, SolverEvent, SolverObserver},
     graph::{Edge, Graph, NamedNode},
-    probleminstance::{ProblemInstance, Solution},
+    probleminstance::{is_cancelled, Cancel, EdgeSolution, ProblemInstance},
+    trace::SearchTrace,
 };
 
 type Table = HashMap<(u128, u128), (usize, Option<(u128, u128)>)>;
 
+/// Approximate number of bytes of an entry of the [`Table`] including the control byte of the
+/// hash map.
+pub(crate) const TABLE_ENTRY_BYTES: usize =
+    std::mem::size_of::<((u128, u128), (usize, Option<(u128, u128)>))>() + 1;
+
 /// Algorithm solving the payback problem via a dynamic program.
 /// Based on algorithm by [Patcas](https://www.cs.ubbcluj.ro/~studia-i/contents/2009-2/10-Patcas.pdf).
 /// The algorithm has a runtime of O*(3^n).
 ///
 /// * `instance` - The problem instance which should be solved
 /// * `approx_solver` - Approximation algorithm used to solve partition, which have no zero sum
-/// subset
+///   subset
+/// * `observer` - Receives the events of the solver
+/// * `cancel` - Stops the dynamic program without a solution, once it is set
 ///
 /// Example:
 /// ```
 /// use payback::graph::Graph;
-/// use payback::probleminstance::{ProblemInstance, Solution, SolvingMethods};
+/// use payback::probleminstance::{ProblemInstance, SolvingMethods};
 ///
 /// let instance: ProblemInstance = Graph::from(vec![-2, -1, 1, 2]).into();
-/// let solution: Solution = instance.solve_with(SolvingMethods::DPStarExpand);
+/// let solution = instance.solve_with(SolvingMethods::DPStarExpand);
 /// ```
+#[instrument(
+    level = "debug",
+    skip_all,
+    fields(vertices = instance.g.vertices.len(), table_size, partitions)
+)]
 pub(crate) fn patcas_dp(
     instance: &ProblemInstance,
-    approx_solver: &dyn Fn(&ProblemInstance) -> Solution,
-) -> Solution {
-    if !instance.is_solvable() {
-        return None;
-    }
-
-    // Initialise all needed data for pre and post processing.
-    let index_to_node: HashMap<usize, &NamedNode> = instance
-        .g
-        .vertices
-        .iter()
-        .filter(|v| v.weight != 0)
-        .enumerate()
-        .collect();
-    let weights: Vec<i64> = index_to_node
-        .iter()
-        .sorted_by(|(i1, _), (i2, _)| i1.cmp(i2))
-        .map(|(_, v)| v.weight)
-        .collect_vec();
-    // Initialise the algorithms parameters.
-    let (v_left, v_right): (Vec<_>, Vec<_>) =
-        index_to_node.iter().partition(|(_, n)| n.weight >= 0);
-    debug!("Left and right nodes: {:?} ----- {:?}", v_left, v_right);
-    let n_left: u128 = expand_number(&v_left.into_iter().map(|(i, _)| *i).collect_vec());
-    let n_right: u128 = expand_number(&v_right.into_iter().map(|(i, _)| *i).collect_vec());
+    approx_solver: &dyn Fn(&ProblemInstance) -> EdgeSolution,
+    observer: &dyn SolverObserver,
+    cancel: Cancel,
+) -> EdgeSolution {
+    let (index_to_node, weights, n_left, n_right) = dp_parameters(instance)?;
     let table: &mut Table = &mut HashMap::new();
     // Execute the dynamic program.
-    let _ = dp(n_left, n_right, &weights, table);
+    if let Some(optimum) = dp(n_left, n_right, &weights, table, observer, cancel) {
+        observer.on_event(&SolverEvent::BoundImproved {
+            lower_bound: optimum,
+        });
+    }
+    if is_cancelled(cancel) {
+        return None;
+    }
+    Span::current().record("table_size", table.len());
+    observer.on_event(&SolverEvent::MemoryUsed {
+        structure: MemoryStructure::DpTable,
+        bytes: table.capacity() * TABLE_ENTRY_BYTES,
+    });
 
     let solution_partition = table_extract_partitioning(n_left, n_right, table)
         .into_iter()
         .map(|x| {
             one_indices(x)
                 .into_iter()
-                .map(|i| index_to_node[&i])
+                .map(|i| index_to_node[i])
                 .collect_vec()
         })
         .collect_vec();
-    debug!(
+    Span::current().record("partitions", solution_partition.len());
+    trace!(
         "Patcas_dp proposes following partitioning: {:?}",
         solution_partition
     );
 
-    let solution: &mut HashMap<Edge, f64> = &mut HashMap::new();
+    let solution: &mut BTreeMap<Edge, i64> = &mut BTreeMap::new();
     solution_partition
         .into_iter()
+        .inspect(|s| {
+            observer.on_event(&SolverEvent::PartitionDiscovered {
+                people: s.iter().map(|v| v.name.to_owned()).collect(),
+            })
+        })
         .map(|s| approx_solver(&ProblemInstance::from(Graph::from(s))))
         .for_each(|sol| {
             match sol {
@@ -81,83 +93,251 @@ pub(crate) fn patcas_dp(
                 None => unreachable!("The instance is solvable and the recursion should have only added zero sum subsets."),
             }
         });
+    observer.on_event(&SolverEvent::NewIncumbent {
+        transactions: solution.len(),
+    });
     Some(solution.to_owned())
 }
 
-/// Underlying dynamic program for [`patcas_dp()`].
-fn dp(i: u128, j: u128, weights: &Vec<i64>, table: &mut Table) -> Option<usize> {
-    debug!("Calling dp with {:?}, {:?}", i, j);
+/// Minimal number of transactions of the instance computed by the dynamic program of
+/// [`patcas_dp()`], but without constructing the solution.
+#[instrument(level = "debug", skip_all, fields(vertices = instance.g.vertices.len(), table_size))]
+pub(crate) fn dp_min_transactions(instance: &ProblemInstance) -> Option<usize> {
+    let (_, weights, n_left, n_right) = dp_parameters(instance)?;
+    let table: &mut Table = &mut HashMap::new();
+    let optimum = dp(n_left, n_right, &weights, table, &(), Cancel::default());
+    Span::current().record("table_size", table.len());
+    optimum
+}
+
+/// Table of the dynamic program of [`patcas_dp()`] as a [`SearchTrace`]. Every node is a subset
+/// of the people in the table and points to the two subsets it is split into. The nodes
+/// reachable from the set of all people are chosen.
+pub(crate) fn dp_trace(instance: &ProblemInstance) -> Option<SearchTrace> {
+    let (index_to_node, weights, n_left, n_right) = dp_parameters(instance)?;
+    let table: &mut Table = &mut HashMap::new();
+    dp(n_left, n_right, &weights, table, &(), Cancel::default())?;
+    let names = |x: u128| {
+        one_indices(x)
+            .into_iter()
+            .map(|i| index_to_node[i].name.to_owned())
+            .collect_vec()
+    };
+    let mut trace = SearchTrace::default();
+    // The set of all people comes first and the order is the same on every run.
+    let keys = table
+        .keys()
+        .copied()
+        .sorted_by_key(|(i, j)| (std::cmp::Reverse((i | j).count_ones()), *i, *j))
+        .collect_vec();
+    let ids: HashMap<(u128, u128), usize> = keys
+        .iter()
+        .map(|(i, j)| {
+            (
+                (*i, *j),
+                trace.add_node(names(i | j), Some(table[&(*i, *j)].0)),
+            )
+        })
+        .collect();
+    for (i, j) in &keys {
+        if let (_, Some((a, b))) = table[&(*i, *j)] {
+            for part in [(a, b), (i ^ a, j ^ b)] {
+                if let Some(child) = ids.get(&part) {
+                    trace.add_edge(ids[&(*i, *j)], *child, vec![names(part.0 | part.1)]);
+                }
+            }
+        }
+    }
+    // The table only keeps the best split of every subset, so every split reachable from the
+    // set of all people is part of the solution.
+    trace.edges.iter_mut().for_each(|e| e.chosen = true);
+    if let Some(root) = ids.get(&(n_left, n_right)) {
+        trace.choose_from(*root);
+    }
+    let chosen = trace.nodes.iter().map(|n| n.chosen).collect_vec();
+    trace
+        .edges
+        .iter_mut()
+        .for_each(|e| e.chosen = chosen[e.from]);
+    Some(trace)
+}
+
+/// Vertices with non zero weight, their weights and the numbers encoding the vertices with
+/// positive and negative weights. Returns `None` for unsolvable or too large instances.
+fn dp_parameters(instance: &ProblemInstance) -> Option<(Vec<&NamedNode>, Vec<i64>, u128, u128)> {
+    if !instance.is_solvable() {
+        return None;
+    }
+
+    // Initialise all needed data for pre and post processing.
+    let index_to_node: Vec<&NamedNode> = instance
+        .g
+        .vertices
+        .iter()
+        .filter(|v| v.weight != 0)
+        .collect();
+    if index_to_node.len() > u128::BITS as usize {
+        debug!(
+            "Patcas_dp supports at most {} vertices with non zero weight, but got {}.",
+            u128::BITS,
+            index_to_node.len()
+        );
+        return None;
+    }
+    let weights: Vec<i64> = index_to_node.iter().map(|v| v.weight).collect_vec();
+    // Initialise the algorithms parameters.
+    let (v_left, v_right): (Vec<_>, Vec<_>) = index_to_node
+        .iter()
+        .enumerate()
+        .partition(|(_, n)| n.weight >= 0);
+    trace!("Left and right nodes: {:?} ----- {:?}", v_left, v_right);
+    let n_left: u128 = expand_number(&v_left.into_iter().map(|(i, _)| i).collect_vec());
+    let n_right: u128 = expand_number(&v_right.into_iter().map(|(i, _)| i).collect_vec());
+    Some((index_to_node, weights, n_left, n_right))
+}
+
+/// Splits of the subsets `(i, j)` of [`dp()`], which are tried in the order of
+/// [`number_and_subset()`], and the best split found so far.
+struct Frame {
+    i: u128,
+    j: u128,
+    left: Vec<u128>,
+    right: Vec<u128>,
+    /// Position of the next split in the cartesian product of `left` and `right`.
+    next: usize,
+    best: Option<(usize, Option<(u128, u128)>)>,
+}
+
+impl Frame {
+    fn new(i: u128, j: u128) -> Self {
+        Frame {
+            i,
+            j,
+            left: number_and_subset(i).collect_vec(),
+            right: number_and_subset(j).collect_vec(),
+            next: 0,
+            best: None,
+        }
+    }
+
+    /// Next split `(a, b)`, which is settled as a group, while `(i ^ a, j ^ b)` is split further.
+    fn split(&self) -> Option<(u128, u128)> {
+        let a = self.left.get(self.next / self.right.len().max(1))?;
+        let b = self.right.get(self.next % self.right.len().max(1))?;
+        Some((*a, *b))
+    }
+}
+
+/// Underlying dynamic program for [`patcas_dp()`]. The subsets are solved with an explicit stack
+/// instead of recursion, so large instances don't overflow the stack. A frame waits on the
+/// stack, until the subsets of its next split are in the table. The splits of `(i, j)` tried so
+/// far are reported as progress to the `observer`. Once `cancel` is set, the stack is dropped
+/// and there is no result.
+fn dp(
+    i: u128,
+    j: u128,
+    weights: &[i64],
+    table: &mut Table,
+    observer: &dyn SolverObserver,
+    cancel: Cancel,
+) -> Option<usize> {
+    trace!("Calling dp with {:?}, {:?}", i, j);
     if i == 0 && j == 0 {
         return Some(0);
     }
-
     if let Some((x, _)) = table.get(&(i, j)) {
-        debug!("Table hit -> {:?}", x);
+        trace!("Table hit -> {:?}", x);
         return Some(*x);
     }
-
-    if number_weight(i, weights) != -number_weight(j, weights) {
-        debug!(
+    let balanced = |i: u128, j: u128| number_weight(i, weights) == -number_weight(j, weights);
+    if !balanced(i, j) {
+        trace!(
             "Number weight is not the same: {} VS {}",
             number_weight(i, weights),
             number_weight(j, weights)
         );
         return None;
     }
-    debug!(
-        "{} and {} have the same weight of {}",
-        i,
-        j,
-        number_weight(i, weights)
-    );
 
-    let value = number_and_subset(i)
-        .cartesian_product(number_and_subset(j).collect_vec())
-        .flat_map(|(a, b)| {
-            let val = dp(i ^ a, j ^ b, weights, table).map(|x| {
-                (
-                    x + a.count_ones() as usize + b.count_ones() as usize - 1,
-                    (i != a && j != b).then_some((a, b)),
-                )
-            });
-            debug!(
+    let root = Frame::new(i, j);
+    let mut meter = ProgressMeter::new(observer, root.left.len() * root.right.len());
+    let mut stack = vec![root];
+    while let Some(frame) = stack.last_mut() {
+        if is_cancelled(cancel) {
+            return None;
+        }
+        let pending = loop {
+            let Some((a, b)) = frame.split() else {
+                break None;
+            };
+            let (c, d) = (frame.i ^ a, frame.j ^ b);
+            let value = match table.get(&(c, d)) {
+                _ if c == 0 && d == 0 => Some(0),
+                Some((x, _)) => Some(*x),
+                // Balanced subsets of both sides can be settled as a whole, so they always get
+                // an entry in the table.
+                None if c != 0 && d != 0 && balanced(c, d) => break Some((c, d)),
+                None => None,
+            };
+            trace!(
                 "Size for i: {}, j: {}, a: {}, b: {} -> {:?}",
-                i, j, a, b, val
+                frame.i,
+                frame.j,
+                a,
+                b,
+                value
             );
-            val
-        })
-        .min_by(|(x, _), (y, _)| x.cmp(y));
-    debug!("Minimum partitioning given with: {:?}", value);
-    if let Some(v) = value {
-        table.insert((i, j), v);
+            frame.next += 1;
+            if (frame.i, frame.j) == (i, j) {
+                meter.update(frame.next);
+            }
+            if let Some(x) = value {
+                let size = x + a.count_ones() as usize + b.count_ones() as usize - 1;
+                if frame.best.is_none_or(|(best, _)| size < best) {
+                    frame.best = Some((size, (frame.i != a && frame.j != b).then_some((a, b))));
+                }
+            }
+        };
+        match pending {
+            Some((c, d)) => stack.push(Frame::new(c, d)),
+            None => {
+                let frame = stack.pop().expect("The frame is on the stack.");
+                trace!("Minimum partitioning given with: {:?}", frame.best);
+                if let Some(best) = frame.best {
+                    table.insert((frame.i, frame.j), best);
+                }
+            }
+        }
     }
-    value.map(|v| v.0)
+    table.get(&(i, j)).map(|(x, _)| *x)
 }
 
 /// For a given table from [`dp()`] this function backtracks the table to finde the corresponding
 /// partitioning from the starting point of (i, j).
 fn table_extract_partitioning(i: u128, j: u128, table: &Table) -> Vec<u128> {
-    debug!(
+    trace!(
         "Beginning partitioning extraction with i: {}, j: {} for table: {:?}",
-        i, j, table
+        i,
+        j,
+        table
     );
-    let partitions: &mut Vec<u128> = &mut vec![];
-    _table_extract_rec(i, j, table, partitions);
-    partitions.to_owned()
-}
-
-fn _table_extract_rec(i: u128, j: u128, table: &Table, partitions: &mut Vec<u128>) {
-    if i == 0 || j == 0 {
-        return;
-    }
-    match table.get(&(i, j)) {
-        Some((_, None)) => partitions.push(i + j),
-        Some((_, Some((a, b)))) => {
-            _table_extract_rec(*a, *b, table, partitions);
-            _table_extract_rec(i ^ a, j ^ b, table, partitions)
+    let mut partitions: Vec<u128> = vec![];
+    let mut stack = vec![(i, j)];
+    while let Some((i, j)) = stack.pop() {
+        if i == 0 || j == 0 {
+            continue;
+        }
+        match table.get(&(i, j)) {
+            Some((_, None)) => partitions.push(i + j),
+            Some((_, Some((a, b)))) => {
+                // The split of (a, b) comes first.
+                stack.push((i ^ a, j ^ b));
+                stack.push((*a, *b));
+            }
+            _ => (),
         }
-        _ => (),
     }
+    partitions
 }
 
 /// For every position the number has a one in its binary representation, get the corresponding
@@ -216,18 +396,23 @@ fn expand_number(indices: &[usize]) -> u128 {
 mod tests {
     use std::collections::HashMap;
 
-    use super::{dp, expand_number, number_and_subset, one_indices, Table};
+    use super::{
+        dp, expand_number, number_and_subset, one_indices, table_extract_partitioning, Table,
+    };
     use crate::approximation::star_expand;
-    use crate::dynamic_program::{number_weight, patcas_dp};
+    use crate::dynamic_program::{dp_min_transactions, number_weight, patcas_dp};
     use crate::graph::Graph;
-    use crate::probleminstance::ProblemInstance;
-    use env_logger::Env;
+    use crate::probleminstance::{Cancel, ProblemInstance};
     use itertools::Itertools;
-    use log::debug;
+    use tracing::debug;
+    use tracing_subscriber::EnvFilter;
 
     fn init() {
-        let _ = env_logger::Builder::from_env(Env::default().default_filter_or("debug"))
-            .is_test(true)
+        let _ = tracing_subscriber::fmt()
+            .with_env_filter(
+                EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("debug")),
+            )
+            .with_test_writer()
             .try_init();
     }
 
@@ -262,9 +447,20 @@ mod tests {
         let j = 0b0011;
         let weights = vec![2, 1, -1, -2];
         let table: &mut Table = &mut HashMap::new();
-        dp(i, j, &weights, table);
+        dp(i, j, &weights, table, &(), Cancel::default());
         assert!(table.get(&(i, j)).is_some());
         assert_eq!(table.get(&(i, j)).unwrap().0, 2);
+        let mut partitions = table_extract_partitioning(i, j, table);
+        partitions.sort();
+        assert_eq!(partitions, [0b0110, 0b1001]);
+
+        // Unbalanced subsets have no solution and no entry.
+        let table: &mut Table = &mut HashMap::new();
+        assert_eq!(
+            dp(0b0100, 0b0011, &weights, table, &(), Cancel::default()),
+            None
+        );
+        assert!(table.is_empty());
     }
 
     #[test]
@@ -273,7 +469,7 @@ mod tests {
         let graph: Graph = vec![-1, -1, 1, 1, 2, -2, 3, -3].into();
         debug!("Using graph: {:?}", graph);
         let instance = ProblemInstance::from(graph);
-        let sol = patcas_dp(&instance, &star_expand);
+        let sol = patcas_dp(&instance, &star_expand, &(), Cancel::default());
         assert!(sol.is_some());
         debug!("Proposed solution by solver: {:?}", sol);
         assert_eq!(sol.unwrap().len(), 4);
@@ -281,13 +477,13 @@ mod tests {
         let graph: Graph = vec![-2, -1, 1, 1, 2, -2, 3, -3].into();
         debug!("Using graph: {:?}", graph);
         let instance = ProblemInstance::from(graph);
-        let sol = patcas_dp(&instance, &star_expand);
+        let sol = patcas_dp(&instance, &star_expand, &(), Cancel::default());
         assert!(sol.is_none());
 
         let graph: Graph = vec![6, 3, 2, 1, -4, -8].into();
         debug!("Using graph: {:?}", graph);
         let instance = ProblemInstance::from(graph);
-        let sol = patcas_dp(&instance, &star_expand);
+        let sol = patcas_dp(&instance, &star_expand, &(), Cancel::default());
         assert!(sol.is_some());
         debug!("Proposed solution by solver: {:?}", sol);
         assert_eq!(sol.unwrap().len(), 4);
@@ -295,7 +491,7 @@ mod tests {
         let graph: Graph = vec![6, 3, 2, 1, -4, -8, 0].into();
         debug!("Using graph: {:?}", graph);
         let instance = ProblemInstance::from(graph);
-        let sol = patcas_dp(&instance, &star_expand);
+        let sol = patcas_dp(&instance, &star_expand, &(), Cancel::default());
         assert!(sol.is_some());
         debug!("Proposed solution by solver: {:?}", sol);
         assert_eq!(sol.unwrap().len(), 4);
@@ -303,7 +499,7 @@ mod tests {
         let graph: Graph = vec![1, 1, 1, 1, 1, 1, -6].into();
         debug!("Using graph: {:?}", graph);
         let instance = ProblemInstance::from(graph);
-        let sol = patcas_dp(&instance, &star_expand);
+        let sol = patcas_dp(&instance, &star_expand, &(), Cancel::default());
         assert!(sol.is_some());
         debug!("Proposed solution by solver: {:?}", sol);
         assert_eq!(sol.unwrap().len(), 6);
@@ -311,9 +507,31 @@ mod tests {
         let graph: Graph = vec![9, 4, 1, -6, -6, -2].into();
         debug!("Using graph: {:?}", graph);
         let instance = ProblemInstance::from(graph);
-        let sol = patcas_dp(&instance, &star_expand);
+        let sol = patcas_dp(&instance, &star_expand, &(), Cancel::default());
         assert!(sol.is_some());
         debug!("Proposed solution by solver: {:?}", sol);
         assert_eq!(sol.unwrap().len(), 5);
+
+        let graph: Graph = [1; 65].into_iter().chain([-1; 65]).collect();
+        let instance = ProblemInstance::from(graph);
+        assert!(patcas_dp(&instance, &star_expand, &(), Cancel::default()).is_none());
+    }
+
+    #[test]
+    fn test_dp_min_transactions() {
+        init();
+        debug!("Running 'test_dp_min_transactions'");
+        for weights in [
+            vec![-1, -1, 1, 1, 2, -2, 3, -3],
+            vec![6, 3, 2, 1, -4, -8, 0],
+            vec![9, 4, 1, -6, -6, -2],
+            vec![0, 0],
+        ] {
+            let instance = ProblemInstance::from(Graph::from(weights));
+            let sol = patcas_dp(&instance, &star_expand, &(), Cancel::default()).unwrap();
+            assert_eq!(dp_min_transactions(&instance), Some(sol.len()));
+        }
+        let instance = ProblemInstance::from(Graph::from(vec![-2, 1]));
+        assert_eq!(dp_min_transactions(&instance), None);
     }
 }
@@ -0,0 +1,340 @@
+use std::cell::Cell;
+use std::time::{Duration, Instant};
+
+use crate::settlement::Settlement;
+
+/// Progress of a solver reported to a [`SolverObserver`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum SolverEvent {
+    /// A solution with `transactions` many transactions was found. Every further incumbent has
+    /// fewer transactions.
+    NewIncumbent { transactions: usize },
+    /// Every solution has at least `lower_bound` many transactions.
+    BoundImproved { lower_bound: usize },
+    /// The people settle among themselves in the solution, which will be proposed.
+    PartitionDiscovered { people: Vec<String> },
+    /// The exact solver found no solution, e.g. since the instance has too many people, it took
+    /// longer than the timeout or it was cancelled, and the approximation of the method is used
+    /// instead.
+    FallbackTriggered { reason: String },
+    /// A data structure of the solver holds approximately `bytes` many bytes.
+    MemoryUsed {
+        structure: MemoryStructure,
+        bytes: usize,
+    },
+    /// A solution at least as good as the previous ones. Exact methods report the solution of
+    /// their approximation before they start, so it can be shown until they finish.
+    IncumbentSolution { solution: Settlement },
+    /// Estimated fraction of the search done, which grows from 0 to 1. The partitioning methods
+    /// and the dynamic programs report it while searching, the other methods only report 1 when
+    /// they finish.
+    Progress { fraction: f64 },
+    /// The solver finished. Always the last event.
+    Finished(SearchSummary),
+}
+
+/// Data structures of the solvers, whose memory grows exponentially with the number of people.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MemoryStructure {
+    /// Memo table of the dynamic program.
+    DpTable,
+    /// Zero-sum subsets listed by the branching algorithm.
+    ZeroSumSubsets,
+    /// All partitionings enumerated by the partitioning algorithm.
+    Partitionings,
+}
+
+/// Summary of a solve given by [`SolverEvent::Finished`].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SearchSummary {
+    /// Number of transactions of the solution or `None` if no solution was found.
+    pub transactions: Option<usize>,
+    /// Best lower bound on the number of transactions.
+    pub lower_bound: usize,
+    /// Number of incumbents found while solving.
+    pub incumbents: usize,
+    /// Number of partitions of the proposed solution.
+    pub partitions: usize,
+    /// Whether a fallback was triggered.
+    pub fallback: bool,
+    /// Approximate number of bytes of the largest data structure reported by
+    /// [`SolverEvent::MemoryUsed`].
+    pub peak_memory: usize,
+    pub elapsed: Duration,
+}
+
+/// Receives the events of a solver, e.g. to show the progress in a user interface.
+/// Closures taking a `&SolverEvent` are observers. `()` ignores all events.
+///
+/// Example:
+/// ```
+/// use payback::events::SolverEvent;
+/// use payback::graph::Graph;
+/// use payback::probleminstance::{ProblemInstance, SolvingMethods};
+/// use std::cell::RefCell;
+///
+/// let instance: ProblemInstance = Graph::from(vec![-2, -1, 1, 2]).into();
+/// let events = RefCell::new(vec![]);
+/// let observer = |e: &SolverEvent| events.borrow_mut().push(e.to_owned());
+/// instance.solve_observed(SolvingMethods::DPStarExpand, &observer);
+/// match events.into_inner().last() {
+///     Some(SolverEvent::Finished(summary)) => assert_eq!(summary.transactions, Some(2)),
+///     _ => panic!("The last event is the summary."),
+/// }
+/// ```
+pub trait SolverObserver {
+    fn on_event(&self, event: &SolverEvent);
+}
+
+impl<F: Fn(&SolverEvent)> SolverObserver for F {
+    fn on_event(&self, event: &SolverEvent) {
+        self(event)
+    }
+}
+
+impl SolverObserver for () {
+    fn on_event(&self, _event: &SolverEvent) {}
+}
+
+/// Intermediate state of a solve given to the callback of
+/// [`ProblemInstance::solve_with_progress()`](crate::probleminstance::ProblemInstance::solve_with_progress).
+#[derive(Clone, Debug, PartialEq)]
+pub struct Progress<'a> {
+    /// Best solution found so far.
+    pub incumbent: Option<&'a Settlement>,
+    /// Every solution has at least `lower_bound` many transactions.
+    pub lower_bound: usize,
+    /// Estimated fraction of the search done.
+    pub fraction: f64,
+}
+
+/// Reports the progress of a search with `total` many steps as [`SolverEvent::Progress`], but
+/// at most once per percent, so observers aren't flooded.
+pub(crate) struct ProgressMeter<'a> {
+    observer: &'a dyn SolverObserver,
+    total: usize,
+    percent: usize,
+}
+
+impl<'a> ProgressMeter<'a> {
+    pub(crate) fn new(observer: &'a dyn SolverObserver, total: usize) -> Self {
+        ProgressMeter {
+            observer,
+            total: total.max(1),
+            percent: 0,
+        }
+    }
+
+    /// Reports, that `done` many steps are done.
+    pub(crate) fn update(&mut self, done: usize) {
+        let percent = 100 * done / self.total;
+        if percent > self.percent {
+            self.percent = percent;
+            self.observer.on_event(&SolverEvent::Progress {
+                fraction: done as f64 / self.total as f64,
+            });
+        }
+    }
+}
+
+/// Forwards events to an observer while collecting the [`SearchSummary`]. Lower bounds, which
+/// don't improve the best one, are dropped.
+pub(crate) struct Recorder<'a> {
+    observer: &'a dyn SolverObserver,
+    start: Instant,
+    lower_bound: Cell<usize>,
+    incumbents: Cell<usize>,
+    partitions: Cell<usize>,
+    fallback: Cell<bool>,
+    peak_memory: Cell<usize>,
+}
+
+impl<'a> Recorder<'a> {
+    pub(crate) fn new(observer: &'a dyn SolverObserver) -> Self {
+        Recorder {
+            observer,
+            start: Instant::now(),
+            lower_bound: Cell::new(0),
+            incumbents: Cell::new(0),
+            partitions: Cell::new(0),
+            fallback: Cell::new(false),
+            peak_memory: Cell::new(0),
+        }
+    }
+
+    pub(crate) fn fallback(&self) -> bool {
+        self.fallback.get()
+    }
+
+    /// Emits [`SolverEvent::Finished`].
+    pub(crate) fn finish(&self, transactions: Option<usize>) {
+        self.observer
+            .on_event(&SolverEvent::Finished(SearchSummary {
+                transactions,
+                lower_bound: self.lower_bound.get(),
+                incumbents: self.incumbents.get(),
+                partitions: self.partitions.get(),
+                fallback: self.fallback.get(),
+                peak_memory: self.peak_memory.get(),
+                elapsed: self.start.elapsed(),
+            }));
+    }
+}
+
+impl SolverObserver for Recorder<'_> {
+    fn on_event(&self, event: &SolverEvent) {
+        match event {
+            SolverEvent::NewIncumbent { .. } => self.incumbents.set(self.incumbents.get() + 1),
+            SolverEvent::BoundImproved { lower_bound } => {
+                if *lower_bound <= self.lower_bound.get() {
+                    return;
+                }
+                self.lower_bound.set(*lower_bound)
+            }
+            SolverEvent::PartitionDiscovered { .. } => {
+                self.partitions.set(self.partitions.get() + 1)
+            }
+            SolverEvent::FallbackTriggered { .. } => self.fallback.set(true),
+            SolverEvent::MemoryUsed { bytes, .. } => {
+                self.peak_memory.set(self.peak_memory.get().max(*bytes))
+            }
+            SolverEvent::IncumbentSolution { .. }
+            | SolverEvent::Progress { .. }
+            | SolverEvent::Finished(_) => {}
+        }
+        self.observer.on_event(event)
+    }
+}
+
+/// Formats a number of bytes with a binary unit, e.g. '1.5 KiB'.
+///
+/// Example:
+/// ```
+/// use payback::events::format_bytes;
+///
+/// assert_eq!(format_bytes(512), "512 B");
+/// assert_eq!(format_bytes(3 << 29), "1.5 GiB");
+/// ```
+pub fn format_bytes(bytes: usize) -> String {
+    const UNITS: [&str; 6] = ["B", "KiB", "MiB", "GiB", "TiB", "PiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::{Cell, RefCell};
+
+    use crate::events::{Progress, SolverEvent, SolverObserver};
+    use crate::graph::Graph;
+    use crate::probleminstance::{MethodOptions, ProblemInstance, SolvingMethods};
+    use tracing::debug;
+    use tracing_subscriber::EnvFilter;
+
+    fn init() {
+        let _ = tracing_subscriber::fmt()
+            .with_env_filter(
+                EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("debug")),
+            )
+            .with_test_writer()
+            .try_init();
+    }
+
+    #[test]
+    fn test_solver_events() {
+        init();
+        debug!("Running 'test_solver_events'");
+        let instance: ProblemInstance = Graph::from(vec![-3, -2, -1, 1, 2, 3]).into();
+        for method in [
+            SolvingMethods::ApproxGreedySatisfaction,
+            SolvingMethods::PartitioningStarExpand,
+            SolvingMethods::BranchingPartitionStarExpand,
+            SolvingMethods::DPGreedySatisfaction,
+        ] {
+            let events = RefCell::new(vec![]);
+            let observer = |e: &SolverEvent| events.borrow_mut().push(e.to_owned());
+            let sol = instance.solve_observed(method, &observer);
+            let events = events.into_inner();
+            debug!("Events of {:?}: {:?}", method, events);
+            let Some(SolverEvent::Finished(summary)) = events.last() else {
+                panic!("The last event of {:?} is no summary.", method);
+            };
+            assert_eq!(summary.transactions, sol.map(|s| s.len()));
+            assert!(summary.incumbents >= 1);
+            assert!(summary.lower_bound <= summary.transactions.unwrap());
+            if method.is_exact() {
+                assert_eq!(summary.partitions, 3);
+                assert_eq!(summary.lower_bound, 3);
+                assert!(summary.peak_memory > 0);
+            }
+        }
+        ().on_event(&SolverEvent::BoundImproved { lower_bound: 1 });
+
+        let instance: ProblemInstance = [1; 65]
+            .into_iter()
+            .chain([-1; 65])
+            .collect::<Graph>()
+            .into();
+        let fallback = Cell::new(false);
+        let observer = |e: &SolverEvent| {
+            if let SolverEvent::FallbackTriggered { .. } = e {
+                fallback.set(true)
+            }
+        };
+        // The instance is too large for the DP, which only falls back to its approximation, if
+        // asked to.
+        let method = SolvingMethods::DPGreedySatisfaction;
+        assert_eq!(instance.solve_observed(method, &observer), None);
+        assert!(!fallback.get());
+        let options = MethodOptions {
+            fallback: true,
+            ..Default::default()
+        };
+        let sol = instance.solve_with_options(method, &observer, &options);
+        assert!(fallback.get());
+        assert_eq!(sol.unwrap().len(), 65);
+    }
+
+    #[test]
+    fn test_solve_with_progress() {
+        init();
+        debug!("Running 'test_solve_with_progress'");
+        let instance: ProblemInstance = Graph::from(vec![-5, -3, -2, -1, 1, 2, 3, 5]).into();
+        for method in [
+            SolvingMethods::ApproxStarExpand,
+            SolvingMethods::PartitioningGreedySatisfaction,
+            SolvingMethods::BranchingPartitionGreedySatisfaction,
+            SolvingMethods::DPStarExpand,
+        ] {
+            let progress = RefCell::new(vec![]);
+            let sol = instance.solve_with_progress(method, &|p: &Progress| {
+                progress.borrow_mut().push((
+                    p.incumbent.map(|s| s.len()),
+                    p.lower_bound,
+                    p.fraction,
+                ))
+            });
+            let progress = progress.into_inner();
+            debug!("Progress of {:?}: {:?}", method, progress);
+            assert!(progress.windows(2).all(|w| w[0].2 <= w[1].2));
+            assert_eq!(
+                progress.last(),
+                Some(&(sol.as_ref().map(|s| s.len()), 4, 1.0))
+            );
+            if method.is_exact() {
+                let first = progress.iter().find_map(|p| p.0);
+                assert_eq!(first, Some(7));
+                assert_eq!(sol.unwrap().len(), 4);
+            }
+        }
+    }
+}
@@ -1,77 +1,190 @@
 use itertools::Itertools;
-use log::debug;
-use std::collections::HashMap;
+use std::collections::BTreeMap;
+use tracing::{instrument, trace, Span};
 
+use crate::events::{MemoryStructure, ProgressMeter, SolverEvent, SolverObserver};
 use crate::graph::{Edge, Graph, NamedNode};
-use crate::probleminstance::{ProblemInstance, Solution};
+use crate::probleminstance::{is_cancelled, Cancel, EdgeSolution, ProblemInstance};
+use crate::trace::SearchTrace;
 
 /// Algorithm solving the payback problem naivly by iteration all possible partitionings of the
 /// vertices. Has a runtime of O*(n^n / (ln n)^n). Should not be used.
 ///
 /// * `instance` - The problem instance which should be solved
 /// * `approx_solver` - Approximation algorithm used to solve partition, which have no zero sum
-/// subset
+///   subset
+/// * `observer` - Receives the events of the solver
+/// * `cancel` - Stops the search without a solution, once it is set
 ///
 /// Example:
 /// ```
 /// use payback::graph::Graph;
-/// use payback::probleminstance::{ProblemInstance, Solution, SolvingMethods};
+/// use payback::probleminstance::{ProblemInstance, SolvingMethods};
 ///
 /// let instance: ProblemInstance = Graph::from(vec![-2, -1, 1, 2]).into();
-/// let solution: Solution = instance.solve_with(SolvingMethods::PartitioningStarExpand);
+/// let solution = instance.solve_with(SolvingMethods::PartitioningStarExpand);
 /// ```
+#[instrument(
+    level = "debug",
+    skip_all,
+    fields(vertices = instance.g.vertices.len(), partitionings, explored)
+)]
 pub(crate) fn naive_all_partitioning(
     instance: &ProblemInstance,
-    approx_solver: &dyn Fn(&ProblemInstance) -> Solution,
-) -> Solution {
-    let mut partitionings = collect_all_partitionigns(&instance.g.vertices);
+    approx_solver: &dyn Fn(&ProblemInstance) -> EdgeSolution,
+    observer: &dyn SolverObserver,
+    cancel: Cancel,
+) -> EdgeSolution {
+    let mut partitionings = collect_all_partitionigns(&instance.g.vertices, cancel);
     partitionings.sort_by_key(|a| std::cmp::Reverse(a.len()));
+    let span = Span::current();
+    span.record("partitionings", partitionings.len());
+    observer.on_event(&SolverEvent::MemoryUsed {
+        structure: MemoryStructure::Partitionings,
+        bytes: partitionings_bytes(&partitionings),
+    });
+    let mut explored = 0_usize;
+    let mut meter = ProgressMeter::new(observer, partitionings.len());
+    let n = instance.g.vertices.len();
     let solution = partitionings
         .iter()
-        .find_map(|x| partition_solver(x, approx_solver));
+        .take_while(|_| !is_cancelled(cancel))
+        .find_map(|x| {
+            explored += 1;
+            meter.update(explored);
+            // All partitionings with more partitions failed and every partition saves one
+            // transaction.
+            observer.on_event(&SolverEvent::BoundImproved {
+                lower_bound: n - x.len(),
+            });
+            let solution = partition_solver(x, approx_solver)?;
+            x.iter()
+                .filter(|p| p.iter().any(|v| v.weight != 0))
+                .for_each(|p| {
+                    observer.on_event(&SolverEvent::PartitionDiscovered {
+                        people: p.iter().map(|v| v.name.to_owned()).collect(),
+                    })
+                });
+            observer.on_event(&SolverEvent::NewIncumbent {
+                transactions: solution.len(),
+            });
+            Some(solution)
+        });
+    span.record("explored", explored);
     solution
 }
 
+/// Partitionings tried by [`naive_all_partitioning()`] as a [`SearchTrace`]. The node of all
+/// people points to a node for every partitioning tried, of which only the last one settles
+/// every partition.
+pub(crate) fn partitioning_trace(
+    instance: &ProblemInstance,
+    approx_solver: &dyn Fn(&ProblemInstance) -> EdgeSolution,
+) -> SearchTrace {
+    let mut partitionings = collect_all_partitionigns(&instance.g.vertices, Cancel::default());
+    partitionings.sort_by_key(|a| std::cmp::Reverse(a.len()));
+    let names = |vs: &[&NamedNode]| {
+        vs.iter()
+            .filter(|v| v.weight != 0)
+            .map(|v| v.name.to_owned())
+            .collect_vec()
+    };
+    let mut trace = SearchTrace::default();
+    let all = instance.g.vertices.iter().collect_vec();
+    let root = trace.add_node(names(&all), None);
+    for x in &partitionings {
+        let solution = partition_solver(x, approx_solver);
+        let transactions = solution.as_ref().map(|s| s.len());
+        let node = trace.add_node(names(&all), transactions);
+        let edge = trace.add_edge(
+            root,
+            node,
+            x.iter()
+                .map(|p| names(p))
+                .filter(|p| !p.is_empty())
+                .collect(),
+        );
+        if solution.is_some() {
+            trace.nodes[root].transactions = transactions;
+            trace.edges[edge].chosen = true;
+            break;
+        }
+    }
+    trace.choose_from(root);
+    trace
+}
+
+/// Approximate number of bytes of the partitionings without the vertices they reference.
+fn partitionings_bytes<T>(partitionings: &Vec<Vec<Vec<&T>>>) -> usize {
+    let vec = std::mem::size_of::<Vec<()>>();
+    partitionings.capacity() * vec
+        + partitionings
+            .iter()
+            .map(|x| {
+                x.capacity() * vec
+                    + x.iter()
+                        .map(|p| p.capacity() * std::mem::size_of::<&T>())
+                        .sum::<usize>()
+            })
+            .sum::<usize>()
+}
+
 fn partition_solver(
     partitioning: &Vec<Vec<&NamedNode>>,
-    approx_solver: &dyn Fn(&ProblemInstance) -> Solution,
-) -> Solution {
-    let mut acc: HashMap<Edge, f64> = HashMap::new();
+    approx_solver: &dyn Fn(&ProblemInstance) -> EdgeSolution,
+) -> EdgeSolution {
+    let mut acc: BTreeMap<Edge, i64> = BTreeMap::new();
     for partition in partitioning {
         let instance: ProblemInstance = Graph::from(partition.to_vec()).into();
-        let result: Solution = approx_solver(&instance);
+        let result: EdgeSolution = approx_solver(&instance);
         match result {
             Some(map) => {
                 acc.extend(map);
             }
             None => {
-                debug!(
+                trace!(
                     "Partitioning {:?} failed due to partition {:?}",
-                    partitioning, partition
+                    partitioning,
+                    partition
                 );
                 return None;
             }
         }
     }
-    debug!(
+    trace!(
         "Found solution for partitioning {:?}. Edges: {:?}",
-        partitioning, acc
+        partitioning,
+        acc
     );
     Some(acc)
 }
 
-fn collect_all_partitionigns<'a, T>(items: &'a [T]) -> Vec<Vec<Vec<&'a T>>> {
+/// All partitionings of the items. Once `cancel` is set, the enumeration stops and only the
+/// partitionings enumerated so far are returned.
+fn collect_all_partitionigns<'a, T>(items: &'a [T], cancel: Cancel) -> Vec<Vec<Vec<&'a T>>> {
     let mut acc: Vec<Vec<Vec<&'a T>>> = Vec::new();
-    iterate_all_partitionings(&mut Vec::new(), items, &mut |x| {
-        acc.push(x.to_owned());
-    });
+    iterate_all_partitionings(
+        &mut Vec::new(),
+        items,
+        &mut |x| {
+            acc.push(x.to_owned());
+        },
+        cancel,
+    );
     acc
 }
 
-fn iterate_all_partitionings<'a, T, F>(head: &mut Vec<Vec<&'a T>>, rest: &'a [T], f: &mut F)
-where
+fn iterate_all_partitionings<'a, T, F>(
+    head: &mut Vec<Vec<&'a T>>,
+    rest: &'a [T],
+    f: &mut F,
+    cancel: Cancel,
+) where
     F: FnMut(&mut Vec<Vec<&'a T>>),
 {
+    if is_cancelled(cancel) {
+        return;
+    }
     if rest.is_empty() {
         f(head)
     } else {
@@ -80,13 +193,13 @@ where
             if let Some(x) = head.get_mut(i) {
                 x.append(&mut first.iter().collect_vec());
             }
-            iterate_all_partitionings(head, tail, f);
+            iterate_all_partitionings(head, tail, f, cancel);
             if let Some(x) = head.get_mut(i) {
                 x.pop();
             }
         }
         head.push(first.iter().collect_vec());
-        iterate_all_partitionings(head, tail, f);
+        iterate_all_partitionings(head, tail, f, cancel);
         head.pop();
     }
 }
@@ -99,13 +212,16 @@ mod tests {
     use crate::exact_partitioning::collect_all_partitionigns;
     use crate::exact_partitioning::naive_all_partitioning;
     use crate::graph::Graph;
-    use crate::probleminstance::ProblemInstance;
-    use env_logger::Env;
-    use log::debug;
+    use crate::probleminstance::{Cancel, ProblemInstance};
+    use tracing::debug;
+    use tracing_subscriber::EnvFilter;
 
     fn init() {
-        let _ = env_logger::Builder::from_env(Env::default().default_filter_or("debug"))
-            .is_test(true)
+        let _ = tracing_subscriber::fmt()
+            .with_env_filter(
+                EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("debug")),
+            )
+            .with_test_writer()
             .try_init();
     }
 
@@ -116,7 +232,7 @@ mod tests {
         let graph: Graph = vec![-1, -1, 1, 1, 2, -2, 3, -3].into();
         debug!("Using graph: {:?}", graph);
         let instance = ProblemInstance::from(graph);
-        let sol = naive_all_partitioning(&instance, &greedy_satisfaction);
+        let sol = naive_all_partitioning(&instance, &greedy_satisfaction, &(), Cancel::default());
         assert!(sol.is_some());
         debug!("Proposed solution by solver: {:?}", sol);
         assert!(sol.unwrap().len() == 4);
@@ -124,7 +240,7 @@ mod tests {
         let graph: Graph = vec![6, 3, 2, 1, -4, -8].into();
         debug!("Using graph: {:?}", graph);
         let instance = ProblemInstance::from(graph);
-        let sol = naive_all_partitioning(&instance, &star_expand);
+        let sol = naive_all_partitioning(&instance, &star_expand, &(), Cancel::default());
         assert!(sol.is_some());
         debug!("Proposed solution by solver: {:?}", sol);
         assert_eq!(sol.unwrap().len(), 4);
@@ -132,7 +248,7 @@ mod tests {
         let graph: Graph = vec![6, 3, 2, 1, -4, -8, 0].into();
         debug!("Using graph: {:?}", graph);
         let instance = ProblemInstance::from(graph);
-        let sol = naive_all_partitioning(&instance, &star_expand);
+        let sol = naive_all_partitioning(&instance, &star_expand, &(), Cancel::default());
         assert!(sol.is_some());
         debug!("Proposed solution by solver: {:?}", sol);
         assert_eq!(sol.unwrap().len(), 4);
@@ -140,7 +256,7 @@ mod tests {
         let graph: Graph = vec![1, 1, 1, 1, 1, 1, -6].into();
         debug!("Using graph: {:?}", graph);
         let instance = ProblemInstance::from(graph);
-        let sol = naive_all_partitioning(&instance, &star_expand);
+        let sol = naive_all_partitioning(&instance, &star_expand, &(), Cancel::default());
         assert!(sol.is_some());
         debug!("Proposed solution by solver: {:?}", sol);
         assert_eq!(sol.unwrap().len(), 6);
@@ -148,7 +264,7 @@ mod tests {
         let graph: Graph = vec![9, 4, 1, -6, -6, -2].into();
         debug!("Using graph: {:?}", graph);
         let instance = ProblemInstance::from(graph);
-        let sol = naive_all_partitioning(&instance, &star_expand);
+        let sol = naive_all_partitioning(&instance, &star_expand, &(), Cancel::default());
         assert!(sol.is_some());
         debug!("Proposed solution by solver: {:?}", sol);
         assert_eq!(sol.unwrap().len(), 5);
@@ -159,7 +275,7 @@ mod tests {
         init();
         debug!("Running 'test_partitionings'");
         let v: Vec<i64> = vec![1, 2, 3];
-        let acc = collect_all_partitionigns(&v);
+        let acc = collect_all_partitionigns(&v, Cancel::default());
         debug!("All partitionings of '{:?}': {:?}", v, acc);
         assert!(acc.len() == 5);
         let calulated: HashSet<Vec<Vec<&i64>>> = acc.into_iter().collect();
@@ -175,7 +291,7 @@ mod tests {
         assert_eq!(calulated, res);
 
         let v: Vec<i64> = vec![1, 2, 3, 4];
-        let acc = collect_all_partitionigns(&v);
+        let acc = collect_all_partitionigns(&v, Cancel::default());
         debug!("All partitionings of '{:?}': {:?}", v, acc);
         let res: HashSet<Vec<Vec<&i64>>> = vec![
             vec![vec![&1, &2, &3, &4]],
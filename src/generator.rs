@@ -0,0 +1,181 @@
+use itertools::Itertools;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use tracing::debug;
+
+use crate::graph::Graph;
+
+/// Distribution from which the absolute vertex weights of a generated instance are drawn.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum WeightDistribution {
+    /// Every weight between 1 and the maximal weight is equally likely.
+    Uniform,
+    /// Small weights are common while large weights are rare.
+    Exponential,
+}
+
+/// Parameters for [`generate()`].
+#[derive(Clone, Debug)]
+pub struct GeneratorConfig {
+    /// Number of vertices of the generated graph.
+    pub nodes: usize,
+    /// Number of planted zero-sum groups. Every group contains at least two vertices.
+    pub groups: usize,
+    /// Upper bound for the absolute weight drawn for a vertex. The last vertex of every group
+    /// balances the group and can therefore exceed this bound.
+    pub max_weight: i64,
+    /// Distribution of the absolute vertex weights.
+    pub distribution: WeightDistribution,
+    /// Seed of the random number generator. Without a seed, a random one is chosen.
+    pub seed: Option<u64>,
+}
+
+impl Default for GeneratorConfig {
+    fn default() -> Self {
+        GeneratorConfig {
+            nodes: 10,
+            groups: 1,
+            max_weight: 100,
+            distribution: WeightDistribution::Uniform,
+            seed: None,
+        }
+    }
+}
+
+/// Generates a random balanced instance.
+/// The vertices are split into `groups` groups, whose weights add up to zero on their own. Hence,
+/// the optimal solution of the instance needs at most `nodes - groups` transactions.
+/// If more groups are requested than possible, the number of groups is reduced to `nodes / 2`.
+///
+/// * `config` - Parameters of the instance which should be generated
+///
+/// Example:
+/// ```
+/// use payback::generator::{generate, GeneratorConfig};
+///
+/// let config = GeneratorConfig {
+///     nodes: 8,
+///     groups: 2,
+///     seed: Some(42),
+///     ..Default::default()
+/// };
+/// let graph = generate(&config);
+/// ```
+pub fn generate(config: &GeneratorConfig) -> Graph {
+    let mut rng = match config.seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+    let groups = config.groups.clamp(1, (config.nodes / 2).max(1));
+    let mut indices = (0..config.nodes).collect_vec();
+    indices.shuffle(&mut rng);
+    let mut weights: Vec<i64> = vec![0; config.nodes];
+    for g in 0..groups {
+        let group = indices
+            .iter()
+            .skip(g)
+            .step_by(groups)
+            .copied()
+            .collect_vec();
+        let group_weights = generate_group(group.len(), config, &mut rng);
+        for (i, w) in group.into_iter().zip(group_weights) {
+            weights[i] = w;
+        }
+    }
+    debug!(
+        "Generated weights {:?} with {} planted groups.",
+        weights, groups
+    );
+    Graph::from(weights)
+}
+
+/// Draws `size` weights, which add up to zero. Only a group of size one has a zero weight.
+fn generate_group(size: usize, config: &GeneratorConfig, rng: &mut StdRng) -> Vec<i64> {
+    if size < 2 {
+        return vec![0; size];
+    }
+    let mut weights = (0..size - 1)
+        .map(|_| {
+            let w = draw_weight(config, rng);
+            if rng.gen_bool(0.5) {
+                w
+            } else {
+                -w
+            }
+        })
+        .collect_vec();
+    if weights.iter().sum::<i64>() == 0 {
+        weights[0] += weights[0].signum();
+    }
+    weights.push(-weights.iter().sum::<i64>());
+    weights
+}
+
+fn draw_weight(config: &GeneratorConfig, rng: &mut StdRng) -> i64 {
+    let max_weight = config.max_weight.max(1);
+    match config.distribution {
+        WeightDistribution::Uniform => rng.gen_range(1..=max_weight),
+        WeightDistribution::Exponential => {
+            let mean = (max_weight as f64 / 4.0).max(1.0);
+            let sample = -(1.0 - rng.gen::<f64>()).ln() * mean;
+            (sample.ceil() as i64).clamp(1, max_weight)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::generator::{generate, GeneratorConfig, WeightDistribution};
+    use crate::probleminstance::{ProblemInstance, SolvingMethods};
+    use tracing::debug;
+    use tracing_subscriber::EnvFilter;
+
+    fn init() {
+        let _ = tracing_subscriber::fmt()
+            .with_env_filter(
+                EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("debug")),
+            )
+            .with_test_writer()
+            .try_init();
+    }
+
+    #[test]
+    fn test_generate() {
+        init();
+        debug!("Running 'test_generate'");
+        for distribution in [WeightDistribution::Uniform, WeightDistribution::Exponential] {
+            let config = GeneratorConfig {
+                nodes: 9,
+                groups: 3,
+                max_weight: 20,
+                distribution,
+                seed: Some(7),
+            };
+            let graph = generate(&config);
+            debug!("Generated graph: {}", graph);
+            assert_eq!(graph.vertices.len(), 9);
+            assert_eq!(graph.to_string(), generate(&config).to_string());
+            let instance = ProblemInstance::from(graph);
+            assert!(instance.is_solvable());
+            let sol = instance.solve_edges_with(SolvingMethods::DPGreedySatisfaction);
+            assert!(sol.is_some());
+            assert!(sol.unwrap().len() <= 9 - 3);
+        }
+    }
+
+    #[test]
+    fn test_generate_too_many_groups() {
+        init();
+        debug!("Running 'test_generate_too_many_groups'");
+        let config = GeneratorConfig {
+            nodes: 5,
+            groups: 10,
+            seed: Some(1),
+            ..Default::default()
+        };
+        let instance = ProblemInstance::from(generate(&config));
+        assert!(instance.is_solvable());
+    }
+}
@@ -0,0 +1,388 @@
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, Instant};
+
+use itertools::Itertools;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use tracing::{debug, instrument, trace, Span};
+
+use crate::approximation::greedy_satisfaction;
+use crate::bucketing::bucketed_dp;
+use crate::events::{SolverEvent, SolverObserver};
+use crate::graph::{Graph, NamedNode};
+use crate::probleminstance::{EdgeSolution, ProblemInstance};
+
+/// Number of partitionings in every generation.
+const POPULATION: usize = 32;
+/// Largest number of generations without a time budget.
+const GENERATIONS: usize = 200;
+/// Number of generations without a better partitioning, after which the evolution stops without
+/// a time budget.
+const STALL: usize = 20;
+
+/// Partitioning of the people, in which every person has the label of its group. The people
+/// are referenced by their position in the instance without people with a weight of 0.
+type Labels = Vec<usize>;
+
+/// Heuristic for large instances, which evolves a population of partitionings of the people.
+/// Children merge the zero-sum groups of two parents and are mutated by moving people, merging
+/// and splitting groups and repairing groups, which don't add up to zero. Groups, which don't
+/// add up to zero, are settled together, so a partitioning with more zero-sum groups needs
+/// fewer transactions. The evolution stops early, if every zero-sum group has a single person
+/// of one sign. The first generation contains the partitionings of 'GreedySatisfaction' and
+/// 'ApproxBucketedDP', so the solution is never worse than theirs and the approximation factor
+/// is 2.
+///
+/// * `instance` - The problem instance which should be solved
+/// * `seed` - Seed of the random number generator. Without a seed, a random one is chosen.
+/// * `time_budget` - Time spent evolving the population. Without a budget, at most
+///   [`GENERATIONS`] many generations are evolved.
+/// * `observer` - Receives the events of the solver
+///
+/// Example:
+/// ```
+/// use payback::graph::Graph;
+/// use payback::probleminstance::{ProblemInstance, SolvingMethods};
+///
+/// let instance: ProblemInstance = Graph::from(vec![-2, -1, 1, 2]).into();
+/// let solution = instance.solve_with(SolvingMethods::ApproxGenetic);
+/// assert_eq!(solution.unwrap().len(), 2);
+/// ```
+#[instrument(
+    level = "debug",
+    skip_all,
+    fields(vertices = instance.g.vertices.len(), generations)
+)]
+pub(crate) fn genetic(
+    instance: &ProblemInstance,
+    seed: Option<u64>,
+    time_budget: Option<Duration>,
+    observer: &dyn SolverObserver,
+) -> EdgeSolution {
+    if !instance.is_solvable() {
+        return None;
+    }
+    let people: Vec<&NamedNode> = instance
+        .g
+        .vertices
+        .iter()
+        .filter(|v| v.weight != 0)
+        .collect();
+    if people.is_empty() {
+        return Some(BTreeMap::new());
+    }
+    let mut rng = match seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+    let weights: Vec<i64> = people.iter().map(|v| v.weight).collect();
+
+    // The events of the approximations aren't events of the instance, so they are dropped.
+    let approximations = [
+        greedy_satisfaction(instance),
+        bucketed_dp(instance, None, &()),
+    ];
+    let mut population: Vec<(usize, Labels)> = approximations
+        .iter()
+        .map(|solution| solution_labels(instance, &people, solution))
+        .chain((approximations.len()..POPULATION).map(|_| random_labels(weights.len(), &mut rng)))
+        .map(|labels| (groups(&weights, &labels).len(), labels))
+        .collect();
+    // Every zero-sum group has a person with a positive and a negative weight.
+    let max_parts = weights
+        .iter()
+        .filter(|w| **w > 0)
+        .count()
+        .min(weights.iter().filter(|w| **w < 0).count());
+    let start = Instant::now();
+    let (mut generations, mut improved, mut best) = (0, 0, 0);
+    loop {
+        population.sort_by_key(|(parts, _)| std::cmp::Reverse(*parts));
+        if population[0].0 > best {
+            (improved, best) = (generations, population[0].0);
+        }
+        let running = match time_budget {
+            Some(budget) => start.elapsed() < budget,
+            None => generations < GENERATIONS && generations - improved < STALL,
+        };
+        if !running || best >= max_parts {
+            break;
+        }
+        generations += 1;
+        trace!(generation = generations, best, "Evolved");
+        // The best partitioning survives, so the best found is never lost.
+        let mut next = vec![population[0].to_owned()];
+        while next.len() < POPULATION {
+            let a = tournament(&population, &mut rng);
+            let b = tournament(&population, &mut rng);
+            let mut child = crossover(&weights, a, b, &mut rng);
+            mutate(&weights, &mut child, &mut rng);
+            next.push((groups(&weights, &child).len(), child));
+        }
+        population = next;
+    }
+    Span::current().record("generations", generations);
+
+    // The population is sorted by the number of groups.
+    let (_, fittest) = population.swap_remove(0);
+    let mut solution = BTreeMap::new();
+    for group in groups(&weights, &fittest) {
+        let members: Vec<&NamedNode> = group.iter().map(|i| people[*i]).collect();
+        observer.on_event(&SolverEvent::PartitionDiscovered {
+            people: members.iter().map(|v| v.name.to_owned()).collect(),
+        });
+        solution.extend(
+            greedy_satisfaction(&ProblemInstance::from(Graph::from(members)))
+                .expect("Groups add up to zero."),
+        );
+    }
+    debug!(transactions = solution.len(), "Solved genetically");
+    Some(solution)
+}
+
+/// Groups of the partitioning, which add up to zero, and the remaining people.
+fn zero_sum_groups(weights: &[i64], labels: &Labels) -> (Vec<Vec<usize>>, Vec<usize>) {
+    let mut by_label: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+    for (i, label) in labels.iter().enumerate() {
+        by_label.entry(*label).or_default().push(i);
+    }
+    let (zero_sum, rest): (Vec<Vec<usize>>, Vec<Vec<usize>>) = by_label
+        .into_values()
+        .partition(|group| group.iter().map(|i| weights[*i]).sum::<i64>() == 0);
+    (zero_sum, rest.into_iter().flatten().sorted().collect())
+}
+
+/// Groups of the partitioning, which add up to zero, and one group of the remaining people, if
+/// there are any. The groups are ordered by their first person.
+fn groups(weights: &[i64], labels: &Labels) -> Vec<Vec<usize>> {
+    let (mut groups, rest) = zero_sum_groups(weights, labels);
+    if !rest.is_empty() {
+        groups.push(rest);
+    }
+    groups.sort();
+    groups
+}
+
+/// Labels the people by the groups, which settle among themselves in the solution.
+fn solution_labels(
+    instance: &ProblemInstance,
+    people: &[&NamedNode],
+    solution: &EdgeSolution,
+) -> Labels {
+    let position: HashMap<&str, usize> = people
+        .iter()
+        .enumerate()
+        .map(|(i, v)| (v.name.as_str(), i))
+        .collect();
+    let mut labels = vec![0; people.len()];
+    for (label, group) in instance
+        .partitioning(solution)
+        .unwrap_or_default()
+        .into_iter()
+        .enumerate()
+    {
+        for name in group {
+            if let Some(i) = position.get(name.as_str()) {
+                labels[*i] = label;
+            }
+        }
+    }
+    labels
+}
+
+/// Partitioning of `n` people into a random number of groups of at least two people on average.
+fn random_labels(n: usize, rng: &mut StdRng) -> Labels {
+    let k = rng.gen_range(1..=(n / 2).max(1));
+    (0..n).map(|_| rng.gen_range(0..k)).collect()
+}
+
+/// Better of two random partitionings of the population.
+fn tournament<'a>(population: &'a [(usize, Labels)], rng: &mut StdRng) -> &'a Labels {
+    let a = &population[rng.gen_range(0..population.len())];
+    let b = &population[rng.gen_range(0..population.len())];
+    if a.0 >= b.0 {
+        &a.1
+    } else {
+        &b.1
+    }
+}
+
+/// Child with about half of the zero-sum groups of `a` and the zero-sum groups of `b`, which
+/// don't overlap them. The remaining people are grouped like in `b`.
+fn crossover(weights: &[i64], a: &Labels, b: &Labels, rng: &mut StdRng) -> Labels {
+    let n = weights.len();
+    let mut child: Vec<Option<usize>> = vec![None; n];
+    for (label, group) in zero_sum_groups(weights, a).0.into_iter().enumerate() {
+        if rng.gen_bool(0.5) {
+            group.iter().for_each(|i| child[*i] = Some(label));
+        }
+    }
+    for (label, group) in zero_sum_groups(weights, b).0.into_iter().enumerate() {
+        if group.iter().all(|i| child[*i].is_none()) {
+            group.iter().for_each(|i| child[*i] = Some(n + label));
+        }
+    }
+    let child = child
+        .into_iter()
+        .enumerate()
+        .map(|(i, label)| label.unwrap_or(2 * n + b[i]))
+        .collect();
+    normalized(child)
+}
+
+/// Changes the partitioning by one of: moving a person into another or a new group, merging two
+/// groups, splitting a group in two, splitting a zero-sum group off a group or repairing a group,
+/// which doesn't add up to zero.
+fn mutate(weights: &[i64], labels: &mut Labels, rng: &mut StdRng) {
+    let n = weights.len();
+    match rng.gen_range(0..5) {
+        0 => {
+            let person = rng.gen_range(0..n);
+            labels[person] = if rng.gen_bool(0.5) {
+                n
+            } else {
+                labels[rng.gen_range(0..n)]
+            };
+        }
+        1 => {
+            let (from, to) = (labels[rng.gen_range(0..n)], labels[rng.gen_range(0..n)]);
+            labels
+                .iter_mut()
+                .filter(|l| **l == from)
+                .for_each(|l| *l = to);
+        }
+        2 => {
+            let label = labels[rng.gen_range(0..n)];
+            labels.iter_mut().filter(|l| **l == label).for_each(|l| {
+                if rng.gen_bool(0.5) {
+                    *l = n;
+                }
+            });
+        }
+        3 => split_zero_sum(weights, labels, rng),
+        _ => repair(weights, labels, rng),
+    }
+    *labels = normalized(std::mem::take(labels));
+}
+
+/// Splits the shortest zero-sum group found in a random order of the people of a random group
+/// off it. The people, whose groups don't add up to zero, count as one group. Two prefixes of
+/// the order with the same sum enclose a zero-sum group.
+fn split_zero_sum(weights: &[i64], labels: &mut Labels, rng: &mut StdRng) {
+    let groups = groups(weights, labels);
+    for (label, group) in groups.iter().enumerate() {
+        group.iter().for_each(|i| labels[*i] = label);
+    }
+    let mut group = groups[rng.gen_range(0..groups.len())].to_owned();
+    group.shuffle(rng);
+    let mut prefixes: HashMap<i64, usize> = HashMap::from([(0, 0)]);
+    let mut shortest: Option<(usize, usize)> = None;
+    let mut sum = 0;
+    for (end, i) in group.iter().enumerate().map(|(j, i)| (j + 1, i)) {
+        sum += weights[*i];
+        if let Some(start) = prefixes.insert(sum, end) {
+            let shorter = shortest.is_none_or(|(s, e)| end - start < e - s);
+            if end - start < group.len() && shorter {
+                shortest = Some((start, end));
+            }
+        }
+    }
+    if let Some((start, end)) = shortest {
+        group[start..end]
+            .iter()
+            .for_each(|i| labels[*i] = groups.len());
+    }
+}
+
+/// Completes a random group, which doesn't add up to zero, with a group or person of the
+/// opposite sum from another group, which doesn't add up to zero.
+fn repair(weights: &[i64], labels: &mut Labels, rng: &mut StdRng) {
+    let mut sums: BTreeMap<usize, i64> = BTreeMap::new();
+    for (i, label) in labels.iter().enumerate() {
+        *sums.entry(*label).or_default() += weights[i];
+    }
+    let unbalanced: Vec<(usize, i64)> = sums.into_iter().filter(|(_, s)| *s != 0).collect();
+    let Some(&(label, sum)) = unbalanced.choose(rng) else {
+        return;
+    };
+    if let Some(&(other, _)) = unbalanced.iter().find(|(l, s)| *l != label && *s == -sum) {
+        labels
+            .iter_mut()
+            .filter(|l| **l == other)
+            .for_each(|l| *l = label);
+    } else if let Some(person) = (0..weights.len())
+        .filter(|i| labels[*i] != label && weights[*i] == -sum)
+        .find(|i| unbalanced.iter().any(|(l, _)| *l == labels[*i]))
+    {
+        labels[person] = label;
+    }
+}
+
+/// Relabels the groups with 0, 1, ... in the order of their first person.
+fn normalized(labels: Labels) -> Labels {
+    let mut relabel: HashMap<usize, usize> = HashMap::new();
+    labels
+        .into_iter()
+        .map(|label| {
+            let next = relabel.len();
+            *relabel.entry(label).or_insert(next)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::approximation::greedy_satisfaction;
+    use crate::genetic::genetic;
+    use crate::graph::Graph;
+    use crate::probleminstance::{ProblemInstance, SolvingMethods};
+    use crate::testing::is_valid_solution;
+    use tracing::debug;
+    use tracing_subscriber::EnvFilter;
+
+    fn init() {
+        let _ = tracing_subscriber::fmt()
+            .with_env_filter(
+                EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("debug")),
+            )
+            .with_test_writer()
+            .try_init();
+    }
+
+    #[test]
+    fn test_genetic() {
+        init();
+        debug!("Running 'test_genetic'");
+        let instance = ProblemInstance::from(Graph::from(vec![-5, -3, -2, 1, 4, 5, 3, -3]));
+        let sol = genetic(&instance, Some(1), None, &());
+        assert!(is_valid_solution(&instance.g, &instance.settlement(&sol)));
+        assert_eq!(
+            sol.as_ref().map(|s| s.len()),
+            instance
+                .solve_edges_with(SolvingMethods::DPGreedySatisfaction)
+                .map(|s| s.len())
+        );
+        // The same seed gives the same solution.
+        assert_eq!(genetic(&instance, Some(1), None, &()), sol);
+
+        let weights: Vec<i64> = (0..200).map(|i| (i * 37 % 101) - 50).collect();
+        let imbalance: i64 = weights.iter().sum();
+        let mut weights = weights;
+        weights.push(-imbalance);
+        let instance = ProblemInstance::from(Graph::from(weights));
+        let sol = genetic(&instance, Some(7), Some(Duration::from_millis(200)), &());
+        debug!("Transactions: {:?}", sol.as_ref().map(|s| s.len()));
+        assert!(is_valid_solution(&instance.g, &instance.settlement(&sol)));
+        assert!(sol.unwrap().len() <= greedy_satisfaction(&instance).unwrap().len());
+        assert!(genetic(
+            &ProblemInstance::from(Graph::from(vec![-1, 2])),
+            None,
+            None,
+            &()
+        )
+        .is_none());
+    }
+}
@@ -1,11 +1,13 @@
 use itertools::Itertools;
-use log::debug;
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
 use std::fmt::Display;
 use std::iter::zip;
+use tracing::{debug, trace};
 
-use crate::graph_parser::deserialize_string_to_graph;
+use crate::graph_parser::{deserialize_graph, deserialize_string_to_graph, detect_format};
 
+/// Person of a [`Graph`] with the amount they have to receive (positive weight) or pay
+/// (negative weight).
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct NamedNode {
     pub(crate) id: usize,
@@ -13,16 +15,117 @@ pub struct NamedNode {
     pub(crate) weight: i64,
 }
 
-#[derive(Clone, Debug, Hash, Eq, PartialEq)]
+impl NamedNode {
+    pub fn new(id: usize, name: impl Into<String>, weight: i64) -> Self {
+        NamedNode {
+            id,
+            name: name.into(),
+            weight,
+        }
+    }
+
+    /// Id of the person, which the edges of the graph and of solutions reference.
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn weight(&self) -> i64 {
+        self.weight
+    }
+}
+
+/// Transaction between the people with the ids `u` and `v`. In a solution, `v` pays `u` the
+/// amount of the edge or, if the amount is negative, `u` pays `v`.
+#[derive(Clone, Debug, Hash, Eq, PartialEq, Ord, PartialOrd)]
 pub struct Edge {
     pub(crate) u: usize,
     pub(crate) v: usize,
 }
 
+impl Edge {
+    pub fn new(u: usize, v: usize) -> Self {
+        Edge { u, v }
+    }
+
+    pub fn u(&self) -> usize {
+        self.u
+    }
+
+    pub fn v(&self) -> usize {
+        self.v
+    }
+
+    /// Payer, payee and the positive amount of a transaction of the solution along the edge.
+    ///
+    /// Example:
+    /// ```
+    /// use payback::Edge;
+    ///
+    /// assert_eq!(Edge::new(0, 1).payment(5), (1, 0, 5));
+    /// assert_eq!(Edge::new(0, 1).payment(-5), (0, 1, 5));
+    /// ```
+    pub fn payment(&self, amount: i64) -> (usize, usize, i64) {
+        if amount >= 0 {
+            (self.v, self.u, amount)
+        } else {
+            (self.u, self.v, -amount)
+        }
+    }
+}
+
+/// Debt of `from` to `to` in the csv edge format.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Debt {
+    pub from: String,
+    pub to: String,
+    pub amount: i64,
+}
+
+/// Extra fields of a person like an IBAN or email as key value pairs, which are passed through
+/// to the outputs.
+pub type Metadata = Vec<(String, String)>;
+
 #[derive(Clone, Debug)]
 pub struct Graph {
     pub(crate) vertices: Vec<NamedNode>,
     pub(crate) edges: Vec<Edge>,
+    pub(crate) metadata: HashMap<String, Metadata>,
+    /// Debts of graphs created from debts. The weights of the vertices are the sum of the debts.
+    pub(crate) debts: Option<Vec<Debt>>,
+    index: Index,
+}
+
+/// Positions in the vectors of a [`Graph`] for constant time lookups. Built once, since graphs
+/// aren't changed after their creation.
+#[derive(Clone, Debug, Default)]
+struct Index {
+    /// Position in `vertices` by id.
+    by_id: HashMap<usize, usize>,
+    /// Position in `vertices` by name. For duplicate names, the first vertex is used.
+    by_name: HashMap<String, usize>,
+    /// Positions in `edges` of the edges leaving a vertex by its id.
+    out: HashMap<usize, Vec<usize>>,
+    /// Positions in `edges` of the edges entering a vertex by its id.
+    into: HashMap<usize, Vec<usize>>,
+}
+
+impl Index {
+    fn new(vertices: &[NamedNode], edges: &[Edge]) -> Self {
+        let mut index = Index::default();
+        for (i, v) in vertices.iter().enumerate() {
+            index.by_id.insert(v.id, i);
+            index.by_name.entry(v.name.to_owned()).or_insert(i);
+        }
+        for (i, e) in edges.iter().enumerate() {
+            index.out.entry(e.u).or_default().push(i);
+            index.into.entry(e.v).or_default().push(i);
+        }
+        index
+    }
 }
 
 impl Ord for NamedNode {
@@ -42,11 +145,18 @@ impl PartialOrd for NamedNode {
     }
 }
 
-/// Parses a String and converts it to a graph.
+/// Parses a String and converts it to a graph. Formats other than csv are detected with
+/// [`detect_format()`].
 impl TryFrom<String> for Graph {
     type Error = &'static str;
 
     fn try_from(value: String) -> Result<Self, Self::Error> {
+        if let Some(format) = detect_format(&value) {
+            return deserialize_graph(&value, format).map_err(|e| {
+                debug!("Unable to parse string '{}' as {:?}: {}", value, format, e);
+                "Unable to parse string into graph."
+            });
+        }
         match deserialize_string_to_graph(&value) {
             Ok(graph) => Ok(graph),
             Err(err_tup) => {
@@ -83,12 +193,10 @@ impl From<Vec<(String, i64)>> for Graph {
     }
 }
 
+/// The vertices are sorted by name, so the graph doesn't depend on the iteration order of the map.
 impl From<HashMap<String, i64>> for Graph {
     fn from(value: HashMap<String, i64>) -> Self {
-        Graph::new(
-            value.keys().map(|k| k.to_owned()).collect_vec(),
-            value.values().map(|w| w.to_owned()).collect_vec(),
-        )
+        Graph::from(value.into_iter().sorted().collect_vec())
     }
 }
 
@@ -103,10 +211,7 @@ impl From<Vec<NamedNode>> for Graph {
                 Edge { u: u.id, v: v.id }
             })
             .collect();
-        Graph {
-            vertices: value,
-            edges,
-        }
+        Graph::from_parts(value, edges)
     }
 }
 
@@ -121,43 +226,99 @@ impl From<Vec<&NamedNode>> for Graph {
                 Edge { u: u.id, v: v.id }
             })
             .collect();
-        Graph {
-            vertices: value.into_iter().map(|x| x.to_owned()).collect(),
-            edges,
-        }
+        Graph::from_parts(value.into_iter().map(|x| x.to_owned()).collect(), edges)
     }
 }
 
 /// Functions to create Graphs from weighted edges.
+/// The debts are sorted, so the graph doesn't depend on the iteration order of the map.
 impl From<HashMap<(String, String), i64>> for Graph {
     fn from(value: HashMap<(String, String), i64>) -> Self {
-        let mut unique_v: HashSet<String> = HashSet::new();
-        value.keys().for_each(|(s1, s2)| {
-            unique_v.insert(s1.to_string());
-            unique_v.insert(s2.to_string());
-        });
-        let mut name_weight_tup: HashMap<String, i64> =
-            unique_v.clone().into_iter().map(|x| (x, 0_i64)).collect();
-        for uv in unique_v.into_iter().permutations(2) {
-            let u: &String = uv.first().unwrap();
-            let v: &String = uv.get(1).unwrap();
-            let weight = value.get(&(u.to_string(), v.to_string())).unwrap_or(&0);
-            if let Some(x) = name_weight_tup.get_mut(u) {
-                *x -= weight;
+        Graph::from(value.into_iter().sorted().collect_vec())
+    }
+}
+
+/// The vertices are ordered by their first appearance. Debts of a person to themself are ignored
+/// and later debts between the same people in the same order replace earlier ones. A negative
+/// debt is the reversed debt, i.e. `(("A", "B"), -5)` means that B owes A 5, which adds up with
+/// other debts of B to A. The debts are kept and can be accessed by [`Graph::debts()`].
+impl From<Vec<((String, String), i64)>> for Graph {
+    fn from(value: Vec<((String, String), i64)>) -> Self {
+        let kept = net_debts(unique_debts(&value));
+        let mut balances: Vec<(String, i64)> = vec![];
+        let mut index: HashMap<&String, usize> = HashMap::new();
+        for ((u, v), _) in &value {
+            for name in [u, v] {
+                index.entry(name).or_insert_with(|| {
+                    balances.push((name.to_owned(), 0));
+                    balances.len() - 1
+                });
             }
-            if let Some(x) = name_weight_tup.get_mut(v) {
-                *x += weight;
+        }
+        for debt in &kept {
+            balances[index[&debt.from]].1 -= debt.amount;
+            balances[index[&debt.to]].1 += debt.amount;
+        }
+        Graph::from(balances).with_debts(kept)
+    }
+}
+
+/// Reverses a debt with a negative amount, so `u` owes `v` a positive amount. Amounts of
+/// `i64::MIN` can't be reversed and are kept.
+pub(crate) fn reverse_negative_debt(debt: ((String, String), i64)) -> ((String, String), i64) {
+    match debt {
+        ((u, v), w) if w < 0 && w != i64::MIN => ((v, u), -w),
+        debt => debt,
+    }
+}
+
+/// Reverses the negative debts and adds up the debts, which are then between the same people in
+/// the same direction, at the position of the first one. Debts, whose sum overflows, are kept
+/// apart. Duplicates must be removed before, see [`unique_debts()`], so the result doesn't
+/// depend on the order of the debts.
+pub(crate) fn net_debts(debts: Vec<Debt>) -> Vec<Debt> {
+    let mut netted: Vec<Debt> = vec![];
+    let mut index: HashMap<(String, String), usize> = HashMap::new();
+    for debt in debts {
+        let ((from, to), amount) = reverse_negative_debt(((debt.from, debt.to), debt.amount));
+        let key = (from, to);
+        let merged = index
+            .get(&key)
+            .and_then(|i| Some((*i, netted[*i].amount.checked_add(amount)?)));
+        match merged {
+            Some((i, sum)) => netted[i].amount = sum,
+            None => {
+                index.insert(key.to_owned(), netted.len());
+                netted.push(Debt {
+                    from: key.0,
+                    to: key.1,
+                    amount,
+                });
             }
         }
-        Graph::from(name_weight_tup)
     }
+    netted
 }
 
-impl From<Vec<((String, String), i64)>> for Graph {
-    fn from(value: Vec<((String, String), i64)>) -> Self {
-        let map: HashMap<(String, String), i64> = value.into_iter().collect();
-        Graph::from(map)
+/// Debts without debts of people to themself. Later debts between the same people replace
+/// earlier ones, but keep the position of the first one.
+pub(crate) fn unique_debts(debts: &[((String, String), i64)]) -> Vec<Debt> {
+    let mut unique: Vec<Debt> = vec![];
+    let mut index: HashMap<&(String, String), usize> = HashMap::new();
+    for (key, amount) in debts.iter().filter(|((u, v), _)| u != v) {
+        match index.get(key) {
+            Some(i) => unique[*i].amount = *amount,
+            None => {
+                index.insert(key, unique.len());
+                unique.push(Debt {
+                    from: key.0.to_owned(),
+                    to: key.1.to_owned(),
+                    amount: *amount,
+                });
+            }
+        }
     }
+    unique
 }
 
 #[allow(clippy::manual_try_fold)]
@@ -175,7 +336,20 @@ impl Display for Graph {
 }
 
 impl Graph {
-    pub(crate) fn new(names: Vec<String>, weights: Vec<i64>) -> Self {
+    /// Creates a graph of people with their names and weights, which are given in the same order.
+    /// The ids of the people are their positions.
+    ///
+    /// Panics if there are more names than weights or the other way around.
+    ///
+    /// Example:
+    /// ```
+    /// use payback::Graph;
+    ///
+    /// let graph = Graph::new(vec!["A".to_string(), "B".to_string()], vec![-3, 3]);
+    /// assert_eq!(graph.node("B").map(|v| v.id()), Some(1));
+    /// assert_eq!(graph.edges().len(), 2);
+    /// ```
+    pub fn new(names: Vec<String>, weights: Vec<i64>) -> Self {
         assert!(
             names.len() == weights.len(),
             "The length of the names and weights must be the same."
@@ -192,32 +366,221 @@ impl Graph {
             let v: usize = *uv.get(1).unwrap();
             edges.push(Edge { u, v });
         }
-        let g = Graph { vertices, edges };
-        debug!("Created following graph:\n{}", g.to_string());
+        let g = Graph::from_parts(vertices, edges);
+        trace!(graph = %g, "Created graph");
         g
     }
 
+    fn from_parts(vertices: Vec<NamedNode>, edges: Vec<Edge>) -> Self {
+        Graph {
+            index: Index::new(&vertices, &edges),
+            vertices,
+            edges,
+            metadata: HashMap::new(),
+            debts: None,
+        }
+    }
+
+    /// People of the graph.
+    pub fn vertices(&self) -> &[NamedNode] {
+        &self.vertices
+    }
+
+    /// Edges between every ordered pair of people.
+    pub fn edges(&self) -> &[Edge] {
+        &self.edges
+    }
+
+    /// Person with the name.
+    pub fn node(&self, name: &str) -> Option<&NamedNode> {
+        self.index.by_name.get(name).map(|i| &self.vertices[*i])
+    }
+
+    /// Person with the id.
+    pub fn node_by_id(&self, id: usize) -> Option<&NamedNode> {
+        self.get_node_from_id(id)
+    }
+
     #[allow(dead_code)]
     pub(crate) fn get_node_from_name(&self, s: String) -> Option<&NamedNode> {
-        self.vertices.iter().find(|v| v.name == s)
+        self.index.by_name.get(&s).map(|i| &self.vertices[*i])
     }
 
     pub(crate) fn get_node_from_id(&self, id: usize) -> Option<&NamedNode> {
-        self.vertices.iter().find(|v| v.id == id)
+        self.index.by_id.get(&id).map(|i| &self.vertices[*i])
     }
 
     pub(crate) fn get_node_name(&self, id: usize) -> Option<String> {
-        self.vertices
-            .iter()
-            .find(|v| v.id == id)
-            .map(|v| v.name.clone())
+        self.get_node_from_id(id).map(|v| v.name.clone())
+    }
+
+    /// Edges leaving the vertex with the id.
+    #[allow(dead_code)]
+    pub(crate) fn edges_out(&self, id: usize) -> impl Iterator<Item = &Edge> {
+        self.index
+            .out
+            .get(&id)
+            .into_iter()
+            .flatten()
+            .map(|i| &self.edges[*i])
+    }
+
+    /// Edges entering the vertex with the id.
+    #[allow(dead_code)]
+    pub(crate) fn edges_into(&self, id: usize) -> impl Iterator<Item = &Edge> {
+        self.index
+            .into
+            .get(&id)
+            .into_iter()
+            .flatten()
+            .map(|i| &self.edges[*i])
     }
 
     pub(crate) fn get_node_name_or(&self, id: usize, or: String) -> String {
         self.get_node_name(id).unwrap_or(or)
     }
 
+    /// Lists every person with their weight. Positive weights have to be received and negative
+    /// weights have to be paid.
+    pub fn balances(&self) -> Vec<(String, i64)> {
+        self.vertices
+            .iter()
+            .map(|v| (v.name.to_owned(), v.weight))
+            .collect()
+    }
+
+    /// Attaches the metadata of people by their name. Metadata of people not in the graph is
+    /// ignored.
+    pub fn with_metadata(mut self, metadata: HashMap<String, Metadata>) -> Self {
+        self.metadata = metadata
+            .into_iter()
+            .filter(|(name, _)| self.index.by_name.contains_key(name))
+            .collect();
+        self
+    }
+
+    /// Metadata of the person with the name.
+    pub fn metadata(&self, name: &str) -> Option<&Metadata> {
+        self.metadata.get(name)
+    }
+
+    /// Keeps only the people for which `keep` is true. Graphs with debts drop the debts with
+    /// removed people, so they stay balanced. Graphs without debts only lose the weights of the
+    /// removed people. The metadata of kept people is kept.
+    ///
+    /// Example:
+    /// ```
+    /// use payback::graph::Graph;
+    ///
+    /// let graph: Graph = "A,B,5\nB,C,3\nD,B,1".to_string().try_into().unwrap();
+    /// let without_c = graph.restrict(|name| name != "C");
+    /// assert_eq!(without_c.balances().iter().map(|(_, w)| w).sum::<i64>(), 0);
+    /// assert_eq!(without_c.debts().unwrap().len(), 2);
+    /// ```
+    pub fn restrict(&self, keep: impl Fn(&str) -> bool) -> Graph {
+        let restricted = match &self.debts {
+            Some(debts) => Graph::from(
+                debts
+                    .iter()
+                    .filter(|d| keep(&d.from) && keep(&d.to))
+                    .map(|d| ((d.from.to_owned(), d.to.to_owned()), d.amount))
+                    .collect_vec(),
+            ),
+            None => Graph::from(
+                self.balances()
+                    .into_iter()
+                    .filter(|(name, _)| keep(name))
+                    .collect_vec(),
+            ),
+        };
+        restricted.with_metadata(self.metadata.to_owned())
+    }
+
+    pub(crate) fn with_debts(mut self, debts: Vec<Debt>) -> Self {
+        self.debts = Some(debts);
+        self
+    }
+
+    /// Debts of who owed whom for graphs created from debts, e.g. parsed from the csv edge
+    /// format. Graphs created from weights have no debts.
+    ///
+    /// Example:
+    /// ```
+    /// use payback::graph::Graph;
+    ///
+    /// let graph: Graph = "A,B,5\nB,C,3".to_string().try_into().unwrap();
+    /// assert_eq!(graph.debts().unwrap()[1].amount, 3);
+    /// let graph: Graph = "A,-5\nB,5".to_string().try_into().unwrap();
+    /// assert!(graph.debts().is_none());
+    /// ```
+    pub fn debts(&self) -> Option<&[Debt]> {
+        self.debts.as_deref()
+    }
+
     pub(crate) fn get_average_vertex_weight(&self) -> f64 {
         self.vertices.iter().map(|v| v.weight).sum::<i64>() as f64 / (self.vertices.len() as f64)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::graph::{Debt, Edge, Graph, NamedNode};
+    use tracing::debug;
+    use tracing_subscriber::EnvFilter;
+
+    fn init() {
+        let _ = tracing_subscriber::fmt()
+            .with_env_filter(
+                EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("debug")),
+            )
+            .with_test_writer()
+            .try_init();
+    }
+
+    #[test]
+    fn test_lookups() {
+        init();
+        debug!("Running 'test_lookups'");
+        let graph = Graph::from(vec![("A".to_string(), -1), ("B".to_string(), 1)]);
+        let b = graph.get_node_from_name("B".to_string()).unwrap();
+        assert_eq!(graph.get_node_from_id(b.id), Some(b));
+        assert_eq!(graph.get_node_name(b.id), Some("B".to_string()));
+        assert_eq!(graph.get_node_from_name("C".to_string()), None);
+        assert_eq!(graph.get_node_name(42), None);
+        assert_eq!(graph.node("B"), Some(b));
+        assert_eq!(graph.node_by_id(b.id()), Some(b));
+        assert_eq!((b.name(), b.weight()), ("B", 1));
+        assert_eq!(graph.vertices()[0], NamedNode::new(0, "A", -1));
+        assert_eq!(graph.edges(), [Edge::new(0, 1), Edge::new(1, 0)]);
+
+        // Subgraphs keep the ids of the vertices.
+        let sub = Graph::from(vec![b]);
+        assert_eq!(sub.get_node_from_id(b.id), Some(b));
+        assert_eq!(sub.edges_out(b.id).count(), 0);
+
+        let graph: Graph = vec![-2, 1, 1].into();
+        assert!(graph.edges_out(0).all(|e| e.u == 0));
+        assert_eq!(
+            graph.edges_into(0).collect::<Vec<_>>(),
+            [&Edge { u: 1, v: 0 }, &Edge { u: 2, v: 0 }]
+        );
+    }
+
+    #[test]
+    fn test_debts() {
+        init();
+        debug!("Running 'test_debts'");
+        let graph: Graph = "A,B,5\nB,B,2\nB,C,3\nA,B,4".to_string().try_into().unwrap();
+        let debt = |from: &str, to: &str, amount| Debt {
+            from: from.to_string(),
+            to: to.to_string(),
+            amount,
+        };
+        assert_eq!(
+            graph.debts().unwrap(),
+            [debt("A", "B", 4), debt("B", "C", 3)]
+        );
+        assert_eq!(graph.balances().iter().map(|(_, w)| w).sum::<i64>(), 0);
+        assert!(Graph::from(vec![-1, 1]).debts().is_none());
+    }
+}
@@ -1,16 +1,90 @@
-use csv::ReaderBuilder;
+use csv::{ReaderBuilder, WriterBuilder};
 use itertools::Itertools;
-use serde_derive::Deserialize;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt::Display;
+use tracing::instrument;
 
-use crate::graph::Graph;
+use crate::graph::{net_debts, unique_debts, Debt, Graph};
+use crate::graphml::{is_graphml, parse_graphml, to_graphml};
+use crate::verification::Payment;
 
-#[derive(Debug, PartialEq, Deserialize)]
+/// Limits for [`parse_instance()`] to guard against hostile inputs.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ParseOptions {
+    /// Maximal size of the input in bytes.
+    pub max_input_bytes: Option<usize>,
+    /// Maximal number of vertices of the resulting graph. It is checked before the graph is
+    /// built. The default of 128 is the most people
+    /// [`crate::probleminstance::ProblemInstance::min_transactions()`] handles.
+    pub max_vertices: Option<usize>,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        ParseOptions {
+            max_input_bytes: Some(1 << 24),
+            max_vertices: Some(128),
+        }
+    }
+}
+
+/// Reasons why [`parse_instance()`] rejected an input.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParseError {
+    /// The input is larger than [`ParseOptions::max_input_bytes`].
+    InputTooLarge { size: usize, max: usize },
+    /// The input is not valid UTF-8.
+    InvalidUtf8,
+    /// The input is neither in the csv node format nor in the csv edge format.
+    InvalidFormat {
+        node_error: String,
+        edge_error: String,
+    },
+    /// The graph has more vertices than [`ParseOptions::max_vertices`].
+    TooManyVertices { found: usize, max: usize },
+    /// The weights are too large to be summed up without an overflow.
+    WeightOverflow,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseError::InputTooLarge { size, max } => {
+                write!(
+                    f,
+                    "Input has {} bytes, but at most {} are allowed.",
+                    size, max
+                )
+            }
+            ParseError::InvalidUtf8 => write!(f, "Input is not valid UTF-8."),
+            ParseError::InvalidFormat {
+                node_error,
+                edge_error,
+            } => write!(
+                f,
+                "Unable to parse input as nodes ({}) or as edges ({}).",
+                node_error, edge_error
+            ),
+            ParseError::TooManyVertices { found, max } => write!(
+                f,
+                "Input has {} vertices, but at most {} are allowed.",
+                found, max
+            ),
+            ParseError::WeightOverflow => write!(f, "Weights are too large."),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
 struct NodeRecord {
     name: String,
     weight: i64,
 }
 
-#[derive(Debug, PartialEq, Deserialize)]
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
 struct EdgeRecord {
     from: String,
     to: String,
@@ -24,11 +98,13 @@ impl NodeRecord {
 }
 
 impl EdgeRecord {
+    /// Debt of the record. Negative weights are reversed after duplicates are removed.
     fn to_tuple(&self) -> ((String, String), i64) {
         ((self.from.to_owned(), self.to.to_owned()), self.weight)
     }
 }
 
+#[instrument(level = "debug", skip_all, fields(bytes = data.len()))]
 pub(crate) fn deserialize_string_to_graph(
     data: &String,
 ) -> Result<Graph, (csv::Error, csv::Error)> {
@@ -49,6 +125,575 @@ pub(crate) fn deserialize_string_to_graph(
     }
 }
 
+/// Problems found by [`validate_instance()`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ValidationIssue {
+    /// The input is neither in the csv node format nor in the csv edge format.
+    InvalidFormat(String),
+    /// The weights don't add up to zero. Such an instance can't be solved.
+    Unbalanced { imbalance: i64 },
+    /// A name appears in multiple node records. Each record is treated as its own person.
+    DuplicateNode(String),
+    /// A debt appears in multiple edge records. Only the last record is used.
+    DuplicateEdge { from: String, to: String },
+    /// A person owes themself something. The record is ignored.
+    SelfLoop(String),
+}
+
+impl ValidationIssue {
+    /// Errors make the instance unsolvable, while all other issues are only warnings.
+    pub fn is_error(&self) -> bool {
+        matches!(
+            self,
+            ValidationIssue::InvalidFormat(_) | ValidationIssue::Unbalanced { .. }
+        )
+    }
+}
+
+impl Display for ValidationIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let level = if self.is_error() { "Error" } else { "Warning" };
+        match self {
+            ValidationIssue::InvalidFormat(e) => write!(f, "{}: {}", level, e),
+            ValidationIssue::Unbalanced { imbalance } => write!(
+                f,
+                "{}: The weights add up to {} instead of 0.",
+                level, imbalance
+            ),
+            ValidationIssue::DuplicateNode(name) => write!(
+                f,
+                "{}: {:?} appears multiple times and is treated as different people.",
+                level, name
+            ),
+            ValidationIssue::DuplicateEdge { from, to } => write!(
+                f,
+                "{}: The debt from {:?} to {:?} appears multiple times. Only the last one is used.",
+                level, from, to
+            ),
+            ValidationIssue::SelfLoop(name) => write!(
+                f,
+                "{}: {:?} owes themself something. This debt is ignored.",
+                level, name
+            ),
+        }
+    }
+}
+
+/// Validates an instance given in one of the csv formats without solving it.
+/// Returns every issue found. If no issue is an error, the instance can be solved.
+///
+/// Example:
+/// ```
+/// use payback::graph_parser::{validate_instance, ValidationIssue};
+///
+/// let issues = validate_instance("A,-1\nB,2");
+/// assert_eq!(issues, vec![ValidationIssue::Unbalanced { imbalance: 1 }]);
+/// ```
+pub fn validate_instance(data: &str) -> Vec<ValidationIssue> {
+    let data = data.to_string();
+    let mut issues = vec![];
+    match (deserialize_to_nodes(&data), deserialize_to_edges(&data)) {
+        (Ok(nodes), _) => {
+            issues.extend(
+                nodes
+                    .iter()
+                    .map(|n| &n.name)
+                    .duplicates()
+                    .map(|name| ValidationIssue::DuplicateNode(name.to_owned())),
+            );
+        }
+        (_, Ok(edges)) => {
+            issues.extend(
+                edges
+                    .iter()
+                    .map(|e| e.to_tuple().0)
+                    .duplicates()
+                    .map(|(from, to)| ValidationIssue::DuplicateEdge { from, to }),
+            );
+            issues.extend(
+                edges
+                    .iter()
+                    .filter(|e| e.from == e.to)
+                    .map(|e| ValidationIssue::SelfLoop(e.from.to_owned())),
+            );
+        }
+        (Err(node_error), Err(edge_error)) => {
+            issues.push(ValidationIssue::InvalidFormat(
+                ParseError::InvalidFormat {
+                    node_error: node_error.to_string(),
+                    edge_error: edge_error.to_string(),
+                }
+                .to_string(),
+            ));
+            return issues;
+        }
+    }
+    match parse_instance(data.as_bytes(), ParseOptions::default()) {
+        Ok(graph) => {
+            let imbalance: i64 = graph.vertices.iter().map(|v| v.weight).sum();
+            if imbalance != 0 {
+                issues.push(ValidationIssue::Unbalanced { imbalance });
+            }
+        }
+        Err(e) => issues.push(ValidationIssue::InvalidFormat(e.to_string())),
+    }
+    issues
+}
+
+/// Parses an instance in the csv node or edge format from raw bytes.
+/// Opposed to converting a String into a [`Graph`], this function never panics regardless of the
+/// input. Instead, every malformed input is reported as a [`ParseError`]. This makes it suitable
+/// for fuzzing and untrusted inputs.
+///
+/// * `data` - The raw input
+/// * `options` - Limits the input has to satisfy
+///
+/// Example:
+/// ```
+/// use payback::graph_parser::{parse_instance, ParseError, ParseOptions};
+///
+/// let graph = parse_instance(b"A,-1\nB,1", ParseOptions::default());
+/// assert!(graph.is_ok());
+/// let graph = parse_instance(b"A,9223372036854775807\nB,1", ParseOptions::default());
+/// assert_eq!(graph.unwrap_err(), ParseError::WeightOverflow);
+/// ```
+#[instrument(level = "debug", skip_all, fields(bytes = data.len()))]
+pub fn parse_instance(data: &[u8], options: ParseOptions) -> Result<Graph, ParseError> {
+    if let Some(max) = options.max_input_bytes {
+        if data.len() > max {
+            return Err(ParseError::InputTooLarge {
+                size: data.len(),
+                max,
+            });
+        }
+    }
+    let data = String::from_utf8(data.to_vec()).map_err(|_| ParseError::InvalidUtf8)?;
+    let mut debts = None;
+    let balances: Vec<(String, i64)> = match deserialize_to_nodes(&data) {
+        Ok(nodes) => nodes.iter().map(|n| n.to_tuple()).collect_vec(),
+        Err(node_error) => match deserialize_to_edges(&data) {
+            Ok(edges) => {
+                let netted = net_debts(unique_debts(
+                    &edges.iter().map(|e| e.to_tuple()).collect_vec(),
+                ));
+                let balances = edges_to_balances(&edges, &netted)?;
+                debts = Some(netted);
+                balances
+            }
+            Err(edge_error) => {
+                return Err(ParseError::InvalidFormat {
+                    node_error: node_error.to_string(),
+                    edge_error: edge_error.to_string(),
+                })
+            }
+        },
+    };
+    if let Some(max) = options.max_vertices {
+        if balances.len() > max {
+            return Err(ParseError::TooManyVertices {
+                found: balances.len(),
+                max,
+            });
+        }
+    }
+    balances
+        .iter()
+        .try_fold(0_i64, |acc, (_, w)| {
+            w.checked_abs().and_then(|w| acc.checked_add(w))
+        })
+        .ok_or(ParseError::WeightOverflow)?;
+    let graph = Graph::from(balances);
+    Ok(match debts {
+        Some(debts) => graph.with_debts(debts),
+        None => graph,
+    })
+}
+
+/// Sums up the debts of the edges to the vertex weights with the same semantic as converting a
+/// `Vec<((String, String), i64)>` into a [`Graph`], but without overflowing.
+fn edges_to_balances(
+    edges: &[EdgeRecord],
+    debts: &[Debt],
+) -> Result<Vec<(String, i64)>, ParseError> {
+    let mut balances: Vec<(String, i64)> = vec![];
+    let mut index: HashMap<String, usize> = HashMap::new();
+    // People are added in the order of their first debt, so the graph is the same on every run.
+    for e in edges {
+        for name in [&e.from, &e.to] {
+            if !index.contains_key(name) {
+                index.insert(name.to_owned(), balances.len());
+                balances.push((name.to_owned(), 0));
+            }
+        }
+    }
+    for debt in debts {
+        let weight = debt.amount;
+        for (name, delta) in [(&debt.from, weight.checked_neg()), (&debt.to, Some(weight))] {
+            let i = index[name];
+            balances[i].1 = delta
+                .and_then(|d| balances[i].1.checked_add(d))
+                .ok_or(ParseError::WeightOverflow)?;
+        }
+    }
+    Ok(balances)
+}
+
+/// Finds the records of the csv edge format with a negative weight, which are otherwise read as
+/// the reversed debt. Records may have further columns like a currency or a date.
+///
+/// Example:
+/// ```
+/// use payback::graph_parser::negative_debts;
+///
+/// let negative = negative_debts("A,B,5\nB,C,-3,EUR\nD,-1");
+/// assert_eq!(negative.len(), 1);
+/// assert_eq!((negative[0].from.as_str(), negative[0].amount), ("B", -3));
+/// ```
+pub fn negative_debts(data: &str) -> Vec<Debt> {
+    ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(data.as_bytes())
+        .records()
+        .filter_map(|r| r.ok())
+        .filter(|r| r.len() >= 3 && r[1].trim().parse::<i64>().is_err())
+        .filter_map(|r| {
+            let amount = r[2].trim().parse::<i64>().ok().filter(|a| *a < 0)?;
+            Some(Debt {
+                from: r[0].to_owned(),
+                to: r[1].to_owned(),
+                amount,
+            })
+        })
+        .collect()
+}
+
+/// Formats in which an instance can be read or written.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum InstanceFormat {
+    /// Csv with one 'NodeName,weight' record per vertex.
+    NodesCsv,
+    /// Csv with one 'NodeNameFrom,NodeNameTo,weight' record per debt.
+    EdgesCsv,
+    /// Json object mapping every vertex name to its weight, e.g. '{"A": -1, "B": 1}'.
+    NodesJson,
+    /// Json list of debts with the keys 'from', 'to' and 'amount', e.g.
+    /// '[{"from": "A", "to": "B", "amount": 1}]'.
+    EdgesJson,
+    /// GraphML with a 'balance' per node or an 'amount' per edge.
+    Graphml,
+    /// Whitespace separated 'NodeNameFrom NodeNameTo weight' record per debt like in graph
+    /// benchmark datasets. Lines starting with '#' or '%' are comments. Records without a weight
+    /// are a debt of 1.
+    EdgeList,
+    /// Pajek '.net' file with the people under '*Vertices' and the debts under '*Arcs' or
+    /// '*Edges'. Debts without a weight are a debt of 1.
+    Pajek,
+}
+
+/// Detects the formats, which aren't csv. Returns `None` for the csv formats, which are detected
+/// while parsing.
+///
+/// Example:
+/// ```
+/// use payback::graph_parser::{detect_format, InstanceFormat};
+///
+/// assert_eq!(detect_format("# benchmark\n1 2 5\n2 3 1"), Some(InstanceFormat::EdgeList));
+/// assert_eq!(detect_format("*Vertices 2\n*Arcs\n1 2 3"), Some(InstanceFormat::Pajek));
+/// assert_eq!(detect_format("{\"A\": -1, \"B\": 1}"), Some(InstanceFormat::NodesJson));
+/// assert_eq!(detect_format("A,B,5"), None);
+/// ```
+pub fn detect_format(data: &str) -> Option<InstanceFormat> {
+    if is_graphml(data) {
+        return Some(InstanceFormat::Graphml);
+    }
+    match data.trim_start().chars().next() {
+        Some('{') => return Some(InstanceFormat::NodesJson),
+        Some('[') => return Some(InstanceFormat::EdgesJson),
+        _ => {}
+    }
+    let mut lines = content_lines(data).map(|(_, line)| line).peekable();
+    let first = lines.peek()?;
+    if first.to_lowercase().starts_with("*vertices") {
+        return Some(InstanceFormat::Pajek);
+    }
+    lines
+        .all(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            !line.contains(',')
+                && (fields.len() == 2
+                    || fields.len() == 3 && parse_integer_amount(fields[2]).is_some())
+        })
+        .then_some(InstanceFormat::EdgeList)
+}
+
+/// Parses a graph given in the specified format.
+///
+/// Example:
+/// ```
+/// use payback::graph_parser::{deserialize_graph, InstanceFormat};
+///
+/// let graph = deserialize_graph("{\"A\": -1, \"B\": 1}", InstanceFormat::NodesJson);
+/// assert!(graph.is_ok());
+/// ```
+pub fn deserialize_graph(data: &str, format: InstanceFormat) -> Result<Graph, String> {
+    match format {
+        InstanceFormat::NodesCsv => deserialize_to_nodes(&data.to_string())
+            .map(|nodes| Graph::from(nodes.iter().map(|n| n.to_tuple()).collect_vec()))
+            .map_err(|e| e.to_string()),
+        InstanceFormat::EdgesCsv => deserialize_to_edges(&data.to_string())
+            .map(|edges| Graph::from(edges.iter().map(|e| e.to_tuple()).collect_vec()))
+            .map_err(|e| e.to_string()),
+        InstanceFormat::NodesJson => {
+            serde_json::from_str::<serde_json::Map<String, serde_json::Value>>(data)
+                .map_err(|e| e.to_string())?
+                .into_iter()
+                .map(|(name, weight)| {
+                    weight
+                        .as_i64()
+                        .map(|w| (name.to_owned(), w))
+                        .ok_or(format!("Weight of '{}' is not an integer.", name))
+                })
+                .collect::<Result<Vec<(String, i64)>, String>>()
+                .map(Graph::from)
+        }
+        InstanceFormat::EdgesJson => serde_json::from_str::<Vec<Payment>>(data)
+            .map(|debts| {
+                Graph::from(
+                    debts
+                        .into_iter()
+                        .map(|d| ((d.from, d.to), d.amount))
+                        .collect_vec(),
+                )
+            })
+            .map_err(|e| e.to_string()),
+        InstanceFormat::Graphml => parse_graphml(data),
+        InstanceFormat::EdgeList => deserialize_edge_list(data),
+        InstanceFormat::Pajek => deserialize_pajek(data),
+    }
+}
+
+/// Serializes a graph into the specified format.
+/// Only graphs with debts, see [`Graph::debts()`], can be serialized into
+/// [`InstanceFormat::EdgesCsv`], [`InstanceFormat::EdgesJson`], [`InstanceFormat::EdgeList`] and
+/// [`InstanceFormat::Pajek`].
+pub fn serialize_graph(graph: &Graph, format: InstanceFormat) -> Result<String, String> {
+    let debts = || {
+        graph.debts().ok_or(
+            "Graphs can only be serialized as edges, if they were created from debts.".to_owned(),
+        )
+    };
+    match format {
+        InstanceFormat::NodesCsv => serialize_graph_to_string(graph),
+        InstanceFormat::EdgesCsv => serialize_debts_to_string(debts()?),
+        InstanceFormat::EdgeList => serialize_edge_list(debts()?),
+        InstanceFormat::Pajek => serialize_pajek(graph, debts()?),
+        InstanceFormat::NodesJson => {
+            let map: serde_json::Map<String, serde_json::Value> = graph
+                .vertices
+                .iter()
+                .map(|v| (v.name.to_owned(), v.weight.into()))
+                .collect();
+            serde_json::to_string_pretty(&map).map_err(|e| e.to_string())
+        }
+        InstanceFormat::EdgesJson => {
+            let debts: Vec<Payment> = debts()?
+                .iter()
+                .map(|d| Payment {
+                    from: d.from.to_owned(),
+                    to: d.to.to_owned(),
+                    amount: d.amount,
+                })
+                .collect();
+            serde_json::to_string_pretty(&debts).map_err(|e| e.to_string())
+        }
+        InstanceFormat::Graphml => Ok(to_graphml(graph)),
+    }
+}
+
+/// Integer amount, which may be written as a float like '5.0' by tools using doubles.
+pub(crate) fn parse_integer_amount(value: &str) -> Option<i64> {
+    value.parse::<i64>().ok().or_else(|| {
+        let float = value.parse::<f64>().ok()?;
+        (float.fract() == 0.0 && float.abs() < i64::MAX as f64).then_some(float as i64)
+    })
+}
+
+/// Trimmed lines with their index, which aren't empty or comments starting with '#' or '%'.
+fn content_lines(data: &str) -> impl Iterator<Item = (usize, &str)> {
+    data.lines()
+        .map(str::trim)
+        .enumerate()
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with(['#', '%']))
+}
+
+/// Weight of a debt in the line of a text format, which is 1 if it isn't given.
+fn line_weight(weight: Option<&str>, line: usize) -> Result<i64, String> {
+    weight.map_or(Ok(1), |w| {
+        parse_integer_amount(w).ok_or(format!(
+            "Weight '{}' in line {} is no integer.",
+            w,
+            line + 1
+        ))
+    })
+}
+
+fn deserialize_edge_list(data: &str) -> Result<Graph, String> {
+    content_lines(data)
+        .map(|(i, line)| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            match fields[..] {
+                [from, to] | [from, to, _] => Ok((
+                    (from.to_owned(), to.to_owned()),
+                    line_weight(fields.get(2).copied(), i)?,
+                )),
+                _ => Err(format!("Line {} is no 'from to weight' record.", i + 1)),
+            }
+        })
+        .collect::<Result<Vec<((String, String), i64)>, String>>()
+        .map(Graph::from)
+}
+
+/// Sections of a Pajek file, which payback reads.
+enum PajekSection {
+    Vertices,
+    /// Either '*Arcs' or '*Edges'. Edges are read like arcs, since debts have a direction.
+    Debts,
+}
+
+fn deserialize_pajek(data: &str) -> Result<Graph, String> {
+    let mut names: HashMap<&str, &str> = HashMap::new();
+    let mut debts: Vec<((String, String), i64)> = vec![];
+    let mut section = None;
+    for (i, line) in content_lines(data) {
+        if let Some(header) = line.strip_prefix('*') {
+            let name = header.split_whitespace().next().unwrap_or_default();
+            section = match name.to_lowercase().as_str() {
+                "vertices" => Some(PajekSection::Vertices),
+                "arcs" | "edges" => Some(PajekSection::Debts),
+                _ => return Err(format!("Unsupported Pajek section '*{}'.", name)),
+            };
+            continue;
+        }
+        match section {
+            Some(PajekSection::Vertices) => {
+                let (id, label) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+                let label = label.trim_start();
+                let label = match label.strip_prefix('"') {
+                    Some(quoted) => quoted.split('"').next().unwrap_or_default(),
+                    None => label.split_whitespace().next().unwrap_or(id),
+                };
+                names.insert(id, label);
+            }
+            Some(PajekSection::Debts) => {
+                let fields: Vec<&str> = line.split_whitespace().collect();
+                let [from, to, ..] = fields[..] else {
+                    return Err(format!("Line {} is no 'from to weight' record.", i + 1));
+                };
+                let name = |id: &str| names.get(id).copied().unwrap_or(id).to_owned();
+                debts.push((
+                    (name(from), name(to)),
+                    line_weight(fields.get(2).copied(), i)?,
+                ));
+            }
+            None => return Err(format!("Line {} is outside of a Pajek section.", i + 1)),
+        }
+    }
+    Ok(Graph::from(debts))
+}
+
+fn serialize_edge_list(debts: &[Debt]) -> Result<String, String> {
+    debts.iter().try_fold(String::new(), |out, d| {
+        if let Some(name) = [&d.from, &d.to]
+            .into_iter()
+            .find(|n| n.is_empty() || n.contains(char::is_whitespace))
+        {
+            return Err(format!("{:?} can't be written in an edge list.", name));
+        }
+        Ok(out + &format!("{} {} {}\n", d.from, d.to, d.amount))
+    })
+}
+
+fn serialize_pajek(graph: &Graph, debts: &[Debt]) -> Result<String, String> {
+    if let Some(v) = graph.vertices.iter().find(|v| v.name.contains('"')) {
+        return Err(format!("{:?} can't be written in Pajek.", v.name));
+    }
+    let ids: HashMap<&str, usize> = graph
+        .vertices
+        .iter()
+        .enumerate()
+        .map(|(i, v)| (v.name.as_str(), i + 1))
+        .collect();
+    let mut out = format!("*Vertices {}\n", graph.vertices.len());
+    for (i, v) in graph.vertices.iter().enumerate() {
+        out += &format!("{} \"{}\"\n", i + 1, v.name);
+    }
+    out += "*Arcs\n";
+    for d in debts {
+        out += &format!(
+            "{} {} {}\n",
+            ids[d.from.as_str()],
+            ids[d.to.as_str()],
+            d.amount
+        );
+    }
+    Ok(out)
+}
+
+/// Parses a list of payments. The payments are either given as csv with one
+/// 'NodeNameFrom,NodeNameTo,amount' record per payment or as a json list of objects with the keys
+/// 'from', 'to' and 'amount'.
+pub fn deserialize_plan(data: &str) -> Result<Vec<Payment>, String> {
+    if data.trim_start().starts_with('[') {
+        serde_json::from_str(data).map_err(|e| e.to_string())
+    } else {
+        let mut rdr = ReaderBuilder::new()
+            .has_headers(false)
+            .from_reader(data.as_bytes());
+        rdr.deserialize()
+            .collect::<Result<_, _>>()
+            .map_err(|e| e.to_string())
+    }
+}
+
+/// Serializes the vertices of a graph with their weights into the csv node format
+/// 'NodeName,weight'.
+pub(crate) fn serialize_graph_to_string(graph: &Graph) -> Result<String, String> {
+    let mut wtr = WriterBuilder::new().has_headers(false).from_writer(vec![]);
+    graph
+        .vertices
+        .iter()
+        .try_for_each(|v| {
+            wtr.serialize(NodeRecord {
+                name: v.name.to_owned(),
+                weight: v.weight,
+            })
+        })
+        .map_err(|e| e.to_string())?;
+    wtr.into_inner()
+        .map_err(|e| e.to_string())
+        .and_then(|bytes| String::from_utf8(bytes).map_err(|e| e.to_string()))
+}
+
+/// Serializes debts into the csv edge format 'NodeNameFrom,NodeNameTo,weight'.
+fn serialize_debts_to_string(debts: &[Debt]) -> Result<String, String> {
+    let mut wtr = WriterBuilder::new().has_headers(false).from_writer(vec![]);
+    debts
+        .iter()
+        .try_for_each(|d| {
+            wtr.serialize(EdgeRecord {
+                from: d.from.to_owned(),
+                to: d.to.to_owned(),
+                weight: d.amount,
+            })
+        })
+        .map_err(|e| e.to_string())?;
+    wtr.into_inner()
+        .map_err(|e| e.to_string())
+        .and_then(|bytes| String::from_utf8(bytes).map_err(|e| e.to_string()))
+}
+
 fn deserialize_to_nodes(data: &String) -> Result<Vec<NodeRecord>, csv::Error> {
     let mut rdr = ReaderBuilder::new()
         .has_headers(false)
@@ -65,14 +710,26 @@ fn deserialize_to_edges(data: &String) -> Result<Vec<EdgeRecord>, csv::Error> {
 
 #[cfg(test)]
 mod tests {
-    use env_logger::Env;
-    use log::debug;
+    use itertools::Itertools;
+    use tracing::debug;
+    use tracing_subscriber::EnvFilter;
 
-    use crate::graph_parser::{deserialize_to_edges, deserialize_to_nodes, EdgeRecord, NodeRecord};
+    use crate::graph::Graph;
+    use crate::graph_parser::{
+        deserialize_graph, deserialize_plan, deserialize_string_to_graph, deserialize_to_edges,
+        deserialize_to_nodes, detect_format, negative_debts, parse_instance, serialize_graph,
+        serialize_graph_to_string, validate_instance, EdgeRecord, InstanceFormat, NodeRecord,
+        ParseError, ParseOptions, ValidationIssue,
+    };
+    use crate::probleminstance::{ProblemInstance, SolvingMethods};
+    use proptest::prelude::*;
 
     fn init() {
-        let _ = env_logger::Builder::from_env(Env::default().default_filter_or("debug"))
-            .is_test(true)
+        let _ = tracing_subscriber::fmt()
+            .with_env_filter(
+                EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("debug")),
+            )
+            .with_test_writer()
             .try_init();
     }
 
@@ -134,4 +791,258 @@ mod tests {
         let data = "A,1";
         assert!(deserialize_to_edges(&data.to_string()).is_err());
     }
+
+    #[test]
+    fn test_serialize_graph_to_string() {
+        init();
+        debug!("Running 'test_serialize_graph_to_string'");
+        let graph: Graph = vec![("A".to_string(), -1), ("B".to_string(), 1)].into();
+        let out = serialize_graph_to_string(&graph);
+        assert_eq!(out, Ok("A,-1\nB,1\n".to_string()));
+        let parsed = deserialize_string_to_graph(&out.unwrap());
+        assert!(parsed.is_ok());
+        assert_eq!(parsed.unwrap().vertices, graph.vertices);
+    }
+
+    #[test]
+    fn test_parse_instance() {
+        init();
+        debug!("Running 'test_parse_instance'");
+        let graph = parse_instance(b"A,C,1\nA,D,1\nB,D,1\nA,A,5", ParseOptions::default());
+        assert!(graph.is_ok());
+        let mut weights = graph
+            .unwrap()
+            .vertices
+            .into_iter()
+            .map(|v| (v.name, v.weight))
+            .collect::<Vec<(String, i64)>>();
+        weights.sort();
+        assert_eq!(
+            weights,
+            vec![
+                ("A".to_string(), -2),
+                ("B".to_string(), -1),
+                ("C".to_string(), 1),
+                ("D".to_string(), 2)
+            ]
+        );
+        assert_eq!(
+            parse_instance(&[0xff, 0xfe], ParseOptions::default()).err(),
+            Some(ParseError::InvalidUtf8)
+        );
+        assert!(matches!(
+            parse_instance(b"A,B,C,D", ParseOptions::default()),
+            Err(ParseError::InvalidFormat { .. })
+        ));
+        assert_eq!(
+            parse_instance(b"A,B,-9223372036854775808", ParseOptions::default()).err(),
+            Some(ParseError::WeightOverflow)
+        );
+        let options = ParseOptions {
+            max_input_bytes: Some(8),
+            max_vertices: Some(1),
+        };
+        assert_eq!(
+            parse_instance(b"A,-1\nB,1", options).err(),
+            Some(ParseError::TooManyVertices { found: 2, max: 1 })
+        );
+        assert_eq!(
+            parse_instance(b"A,-10\nB,10", options).err(),
+            Some(ParseError::InputTooLarge { size: 10, max: 8 })
+        );
+
+        // By default, instances too large for the solvers are rejected before a graph is built.
+        let people = |n: i64| {
+            (0..n)
+                .map(|i| format!("P{},{}", i, if i == 0 { 1 - n } else { 1 }))
+                .join("\n")
+        };
+        assert!(parse_instance(people(128).as_bytes(), ParseOptions::default()).is_ok());
+        assert_eq!(
+            parse_instance(people(129).as_bytes(), ParseOptions::default()).err(),
+            Some(ParseError::TooManyVertices {
+                found: 129,
+                max: 128
+            })
+        );
+        let edges = (0..65).map(|i| format!("D{},C{},1", i, i)).join("\n");
+        assert_eq!(
+            parse_instance(edges.as_bytes(), ParseOptions::default()).err(),
+            Some(ParseError::TooManyVertices {
+                found: 130,
+                max: 128
+            })
+        );
+    }
+
+    #[test]
+    fn test_deterministic_order() {
+        init();
+        debug!("Running 'test_deterministic_order'");
+        let data = "D,A,1\nC,B,1\nA,C,2\nB,D,2".to_string();
+        let names = |g: &Graph| g.balances().into_iter().map(|(n, _)| n).collect::<Vec<_>>();
+        let graph = deserialize_string_to_graph(&data).unwrap();
+        assert_eq!(names(&graph), ["D", "A", "C", "B"]);
+        let parsed = parse_instance(data.as_bytes(), ParseOptions::default()).unwrap();
+        assert_eq!(graph.vertices, parsed.vertices);
+        let plans: Vec<String> = (0..10)
+            .map(|_| {
+                let instance: ProblemInstance = deserialize_string_to_graph(&data).unwrap().into();
+                let sol = instance.solve_with(SolvingMethods::DPStarExpand);
+                instance.solution_string(&sol).unwrap()
+            })
+            .collect();
+        debug!("Plans: {:?}", plans);
+        assert!(plans.iter().all(|p| *p == plans[0]));
+    }
+
+    #[test]
+    fn test_negative_debts() {
+        init();
+        debug!("Running 'test_negative_debts'");
+        let reversed = deserialize_string_to_graph(&"A,B,-5\nB,C,3".to_string()).unwrap();
+        let graph = deserialize_string_to_graph(&"B,A,5\nB,C,3".to_string()).unwrap();
+        assert_eq!(reversed.debts(), graph.debts());
+        let parsed = parse_instance(b"A,B,-5\nB,C,3", ParseOptions::default()).unwrap();
+        assert_eq!(parsed.debts(), graph.debts());
+        // The reversed debt adds up with the debt in the same direction regardless of the order.
+        for data in ["A,B,5\nB,A,-3", "B,A,-3\nA,B,5"] {
+            let netted = parse_instance(data.as_bytes(), ParseOptions::default()).unwrap();
+            let debts = netted.debts().unwrap();
+            assert_eq!(debts.len(), 1);
+            assert_eq!(
+                (
+                    debts[0].from.as_str(),
+                    debts[0].to.as_str(),
+                    debts[0].amount
+                ),
+                ("A", "B", 8)
+            );
+            assert_eq!(
+                netted.balances(),
+                deserialize_string_to_graph(&data.to_string())
+                    .unwrap()
+                    .balances()
+            );
+            let mut balances = netted.balances();
+            balances.sort();
+            assert_eq!(balances, [("A".to_string(), -8), ("B".to_string(), 8)]);
+        }
+        // Duplicates in the same direction are still replaced by the later one.
+        let replaced = deserialize_string_to_graph(&"A,B,5\nA,B,2".to_string()).unwrap();
+        assert_eq!(replaced.debts().unwrap()[0].amount, 2);
+        assert_eq!(negative_debts("A,B,-5\nB,C,3").len(), 1);
+        assert!(negative_debts("A,-5\nB,5").is_empty());
+    }
+
+    #[test]
+    fn test_convert_formats() {
+        init();
+        debug!("Running 'test_convert_formats'");
+        let graph = deserialize_graph("A,C,1\nA,D,1\nB,D,1", InstanceFormat::EdgesCsv).unwrap();
+        let json = serialize_graph(&graph, InstanceFormat::NodesJson).unwrap();
+        debug!("Json: {}", json);
+        let from_json = deserialize_graph(&json, InstanceFormat::NodesJson).unwrap();
+        let csv = serialize_graph(&from_json, InstanceFormat::NodesCsv).unwrap();
+        assert_eq!(csv, "A,-2\nB,-1\nC,1\nD,2\n");
+        assert_eq!(
+            serialize_graph(&graph, InstanceFormat::EdgesCsv).unwrap(),
+            "A,C,1\nA,D,1\nB,D,1\n"
+        );
+        assert!(serialize_graph(&from_json, InstanceFormat::EdgesCsv).is_err());
+        assert!(deserialize_graph("{\"A\": 1.5}", InstanceFormat::NodesJson).is_err());
+        assert!(deserialize_graph("A,1", InstanceFormat::EdgesCsv).is_err());
+        let json = serialize_graph(&graph, InstanceFormat::EdgesJson).unwrap();
+        assert_eq!(detect_format(&json), Some(InstanceFormat::EdgesJson));
+        let debts = deserialize_graph(&json, InstanceFormat::EdgesJson).unwrap();
+        assert_eq!(debts.debts(), graph.debts());
+        assert!(deserialize_graph("[{\"from\": \"A\"}]", InstanceFormat::EdgesJson).is_err());
+
+        // Edge lists and Pajek files of graph benchmarks.
+        let edge_list = "% benchmark\nA C 1\n\nA D\nB D 1.0\n";
+        assert_eq!(detect_format(edge_list), Some(InstanceFormat::EdgeList));
+        let graph = deserialize_graph(edge_list, InstanceFormat::EdgeList).unwrap();
+        assert_eq!(
+            serialize_graph(&graph, InstanceFormat::EdgesCsv).unwrap(),
+            "A,C,1\nA,D,1\nB,D,1\n"
+        );
+        let pajek = serialize_graph(&graph, InstanceFormat::Pajek).unwrap();
+        debug!("Pajek: {}", pajek);
+        assert_eq!(detect_format(&pajek), Some(InstanceFormat::Pajek));
+        let from_pajek = deserialize_graph(&pajek, InstanceFormat::Pajek).unwrap();
+        assert_eq!(
+            serialize_graph(&from_pajek, InstanceFormat::EdgeList).unwrap(),
+            "A C 1\nA D 1\nB D 1\n"
+        );
+        let labeled = "*Vertices 3\n1 \"Ann Lee\" 0.1 0.2\n2 Bob\n*Edges\n1 2 4\n2 3";
+        assert_eq!(
+            deserialize_graph(labeled, InstanceFormat::Pajek)
+                .unwrap()
+                .balances(),
+            vec![
+                ("Ann Lee".to_owned(), -4),
+                ("Bob".to_owned(), 3),
+                ("3".to_owned(), 1)
+            ]
+        );
+        assert!(deserialize_graph("*Matrix\n0 1", InstanceFormat::Pajek).is_err());
+        assert!(deserialize_graph("A B x", InstanceFormat::EdgeList).is_err());
+        assert!(serialize_graph(&from_json, InstanceFormat::EdgeList).is_err());
+    }
+
+    #[test]
+    fn test_deserialize_plan() {
+        init();
+        debug!("Running 'test_deserialize_plan'");
+        let csv = deserialize_plan("A,B,1\nB,C,2");
+        let json = deserialize_plan(
+            r#"[{"from": "A", "to": "B", "amount": 1}, {"from": "B", "to": "C", "amount": 2}]"#,
+        );
+        assert!(csv.is_ok());
+        assert_eq!(csv, json);
+        assert_eq!(csv.unwrap()[1].amount, 2);
+        assert!(deserialize_plan("A,1").is_err());
+    }
+
+    #[test]
+    fn test_validate_instance() {
+        init();
+        debug!("Running 'test_validate_instance'");
+        assert!(validate_instance("A,-1\nB,1").is_empty());
+        assert_eq!(
+            validate_instance("A,-1\nB,1\nA,0"),
+            vec![ValidationIssue::DuplicateNode("A".to_string())]
+        );
+        let issues = validate_instance("A,B,1\nA,B,2\nC,C,1");
+        assert_eq!(
+            issues,
+            vec![
+                ValidationIssue::DuplicateEdge {
+                    from: "A".to_string(),
+                    to: "B".to_string()
+                },
+                ValidationIssue::SelfLoop("C".to_string())
+            ]
+        );
+        assert!(issues.iter().all(|i| !i.is_error()));
+        let issues = validate_instance("A,B");
+        assert_eq!(issues.len(), 1);
+        assert!(issues[0].is_error());
+    }
+
+    proptest! {
+        #[test]
+        fn prop_parse_instance_never_panics(data in proptest::collection::vec(any::<u8>(), 0..64)) {
+            let _ = parse_instance(&data, ParseOptions::default());
+        }
+
+        #[test]
+        fn prop_parsed_instance_never_panics_solver(data in "([a-c],(-?[0-9]{1,19})\n){0,6}") {
+            if let Ok(graph) = parse_instance(data.as_bytes(), ParseOptions::default()) {
+                let instance = ProblemInstance::from(graph);
+                let _ = instance.solve_edges_with(SolvingMethods::ApproxGreedySatisfaction);
+                let _ = instance.solve_edges_with(SolvingMethods::DPStarExpand);
+            }
+        }
+    }
 }
@@ -0,0 +1,453 @@
+use std::collections::HashMap;
+use tracing::{debug, instrument};
+
+use crate::graph::Graph;
+use crate::graph_parser::parse_integer_amount;
+
+/// Names of the attributes of nodes, which hold the balance of a person.
+const BALANCE_ATTRIBUTES: [&str; 2] = ["balance", "weight"];
+/// Names of the attributes of edges, which hold the amount the source owes the target.
+const AMOUNT_ATTRIBUTES: [&str; 2] = ["amount", "weight"];
+/// Names of the attributes of nodes, which are used as the name of a person instead of the id.
+const NAME_ATTRIBUTES: [&str; 2] = ["label", "name"];
+
+/// Part of an xml document, which is relevant for GraphML.
+#[derive(Debug, PartialEq, Eq)]
+enum Token {
+    /// Opening tag with its attributes. Empty elements like '<key/>' are followed by their
+    /// closing tag.
+    Start {
+        name: String,
+        attributes: HashMap<String, String>,
+    },
+    End(String),
+    Text(String),
+}
+
+/// Node or edge of the document with the values of its data elements by attribute name.
+#[derive(Debug, Default)]
+struct Element {
+    attributes: HashMap<String, String>,
+    data: HashMap<String, String>,
+}
+
+impl Element {
+    /// Value of the first of the attributes, which the element has data for.
+    fn value(&self, names: &[&str]) -> Option<&str> {
+        names
+            .iter()
+            .find_map(|n| self.data.get(*n))
+            .map(|v| v.trim())
+    }
+
+    fn attribute(&self, name: &str, element: &str) -> Result<&str, String> {
+        self.attributes.get(name).map(|a| a.as_str()).ok_or(format!(
+            "GraphML {} without a '{}' attribute.",
+            element, name
+        ))
+    }
+}
+
+/// Checks if the data looks like xml and not like one of the csv formats.
+pub fn is_graphml(data: &str) -> bool {
+    data.trim_start().starts_with('<')
+}
+
+/// Parses a graph in GraphML. The instance is either given by edges, whose 'amount' or 'weight'
+/// attribute is the debt of the source to the target, or by nodes with a 'balance' or 'weight'
+/// attribute. If edges have amounts, the balances of the nodes are ignored. People are named by
+/// their 'label' or 'name' attribute and otherwise by their id.
+///
+/// Example:
+/// ```
+/// use payback::graphml::parse_graphml;
+///
+/// let graph = parse_graphml(
+///     r#"<graphml>
+///       <key id="d0" for="edge" attr.name="amount" attr.type="long"/>
+///       <graph edgedefault="directed">
+///         <node id="A"/>
+///         <node id="B"/>
+///         <edge source="A" target="B"><data key="d0">5</data></edge>
+///       </graph>
+///     </graphml>"#,
+/// )
+/// .unwrap();
+/// assert_eq!(graph.debts().unwrap()[0].amount, 5);
+/// ```
+#[instrument(level = "debug", skip_all, fields(bytes = data.len()))]
+pub fn parse_graphml(data: &str) -> Result<Graph, String> {
+    let mut keys: HashMap<String, String> = HashMap::new();
+    let mut nodes: Vec<Element> = vec![];
+    let mut edges: Vec<Element> = vec![];
+    let mut current: Option<(String, Element)> = None;
+    let mut data_key: Option<String> = None;
+    for token in tokenize(data)? {
+        match token {
+            Token::Start { name, attributes } => match name.as_str() {
+                "key" => {
+                    if let Some(id) = attributes.get("id") {
+                        let attr_name = attributes.get("attr.name").unwrap_or(id);
+                        keys.insert(id.to_owned(), attr_name.to_lowercase());
+                    }
+                }
+                "node" | "edge" if current.is_none() => {
+                    current = Some((
+                        name,
+                        Element {
+                            attributes,
+                            ..Default::default()
+                        },
+                    ))
+                }
+                "data" if current.is_some() => {
+                    let key = attributes
+                        .get("key")
+                        .ok_or("GraphML data without a 'key' attribute.")?;
+                    let attr_name = keys.get(key).cloned().unwrap_or(key.to_lowercase());
+                    if let Some((_, element)) = &mut current {
+                        element.data.insert(attr_name.to_owned(), String::new());
+                    }
+                    data_key = Some(attr_name);
+                }
+                _ => {}
+            },
+            Token::Text(text) => {
+                if let (Some(key), Some((_, element))) = (&data_key, &mut current) {
+                    if let Some(value) = element.data.get_mut(key) {
+                        *value += &text;
+                    }
+                }
+            }
+            Token::End(name) => match name.as_str() {
+                "data" => data_key = None,
+                "node" | "edge" if current.as_ref().is_some_and(|(n, _)| *n == name) => {
+                    let (_, element) = current.take().expect("Checked above.");
+                    match name.as_str() {
+                        "node" => nodes.push(element),
+                        _ => edges.push(element),
+                    }
+                }
+                _ => {}
+            },
+        }
+    }
+    debug!(nodes = nodes.len(), edges = edges.len(), "Read GraphML");
+
+    let mut names: HashMap<&str, String> = HashMap::new();
+    for node in &nodes {
+        let id = node.attribute("id", "node")?;
+        let name = node.value(&NAME_ATTRIBUTES).unwrap_or(id);
+        names.insert(id, name.to_owned());
+    }
+    if edges.iter().any(|e| e.value(&AMOUNT_ATTRIBUTES).is_some()) {
+        let mut debts: Vec<((String, String), i64)> = vec![];
+        for edge in &edges {
+            let Some(amount) = edge.value(&AMOUNT_ATTRIBUTES) else {
+                continue;
+            };
+            let person = |attribute: &str| -> Result<String, String> {
+                let id = edge.attribute(attribute, "edge")?;
+                names
+                    .get(id)
+                    .cloned()
+                    .ok_or(format!("GraphML edge references unknown node '{}'.", id))
+            };
+            let (from, to) = (person("source")?, person("target")?);
+            let amount = parse_integer_amount(amount).ok_or(format!(
+                "Amount '{}' of {:?} to {:?} is no integer.",
+                amount, from, to
+            ))?;
+            debts.push(((from, to), amount));
+        }
+        return Ok(Graph::from(debts));
+    }
+    if nodes.iter().any(|n| n.value(&BALANCE_ATTRIBUTES).is_some()) {
+        return nodes
+            .iter()
+            .map(|node| {
+                let name = names[node.attribute("id", "node")?].to_owned();
+                let balance = match node.value(&BALANCE_ATTRIBUTES) {
+                    Some(b) => parse_integer_amount(b)
+                        .ok_or(format!("Balance '{}' of {:?} is no integer.", b, name))?,
+                    None => 0,
+                };
+                Ok((name, balance))
+            })
+            .collect::<Result<Vec<(String, i64)>, String>>()
+            .map(Graph::from);
+    }
+    Err("GraphML has neither nodes with a balance nor edges with an amount.".to_owned())
+}
+
+/// Serializes a graph into GraphML. Every node has its balance. Graphs with debts, see
+/// [`Graph::debts()`], additionally have an edge with the amount for every debt.
+///
+/// Example:
+/// ```
+/// use payback::graph::Graph;
+/// use payback::graphml::{parse_graphml, to_graphml};
+///
+/// let graph = Graph::from(vec![("A".to_owned(), -1), ("B".to_owned(), 1)]);
+/// let parsed = parse_graphml(&to_graphml(&graph)).unwrap();
+/// assert_eq!(parsed.balances(), graph.balances());
+/// ```
+pub fn to_graphml(graph: &Graph) -> String {
+    let mut xml = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+        <graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n  \
+        <key id=\"balance\" for=\"node\" attr.name=\"balance\" attr.type=\"long\"/>\n  \
+        <key id=\"amount\" for=\"edge\" attr.name=\"amount\" attr.type=\"long\"/>\n  \
+        <graph edgedefault=\"directed\">\n"
+        .to_owned();
+    for v in &graph.vertices {
+        xml += &format!(
+            "    <node id=\"{}\"><data key=\"balance\">{}</data></node>\n",
+            escape(&v.name),
+            v.weight
+        );
+    }
+    for debt in graph.debts().unwrap_or_default() {
+        xml += &format!(
+            "    <edge source=\"{}\" target=\"{}\"><data key=\"amount\">{}</data></edge>\n",
+            escape(&debt.from),
+            escape(&debt.to),
+            debt.amount
+        );
+    }
+    xml + "  </graph>\n</graphml>\n"
+}
+
+/// Splits the xml into tags and text. The prolog, comments, processing instructions and the
+/// doctype are skipped. Namespace prefixes of the tags are removed.
+fn tokenize(data: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = vec![];
+    let mut rest = data;
+    while !rest.is_empty() {
+        let Some(start) = rest.find('<') else {
+            tokens.push(Token::Text(unescape(rest)));
+            break;
+        };
+        if start > 0 {
+            tokens.push(Token::Text(unescape(&rest[..start])));
+        }
+        rest = &rest[start..];
+        let skip = |rest: &str, close: &str| -> Result<usize, String> {
+            rest.find(close)
+                .map(|i| i + close.len())
+                .ok_or("Unterminated xml element.".to_owned())
+        };
+        if let Some(cdata) = rest.strip_prefix("<![CDATA[") {
+            let end = skip(cdata, "]]>")?;
+            tokens.push(Token::Text(cdata[..end - 3].to_owned()));
+            rest = &cdata[end..];
+        } else if rest.starts_with("<!--") {
+            rest = &rest[skip(rest, "-->")?..];
+        } else if rest.starts_with("<?") {
+            rest = &rest[skip(rest, "?>")?..];
+        } else if rest.starts_with("<!") {
+            rest = &rest[skip(rest, ">")?..];
+        } else {
+            let end = tag_end(rest).ok_or("Unterminated xml tag.")?;
+            let tag = &rest[1..end];
+            rest = &rest[end + 1..];
+            if let Some(name) = tag.strip_prefix('/') {
+                tokens.push(Token::End(local_name(name.trim()).to_owned()));
+                continue;
+            }
+            let (tag, empty) = match tag.strip_suffix('/') {
+                Some(tag) => (tag, true),
+                None => (tag, false),
+            };
+            let name_end = tag.find(char::is_whitespace).unwrap_or(tag.len());
+            let name = local_name(&tag[..name_end]).to_owned();
+            let attributes = parse_attributes(&tag[name_end..])?;
+            tokens.push(Token::Start {
+                name: name.to_owned(),
+                attributes,
+            });
+            if empty {
+                tokens.push(Token::End(name));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+/// Position of the '>' closing the tag at the start of `rest`, which isn't inside a quoted
+/// attribute value.
+fn tag_end(rest: &str) -> Option<usize> {
+    let mut quote = None;
+    for (i, c) in rest.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), c) if q == c => quote = None,
+            (None, '>') => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+fn parse_attributes(mut rest: &str) -> Result<HashMap<String, String>, String> {
+    let mut attributes = HashMap::new();
+    loop {
+        rest = rest.trim_start();
+        if rest.is_empty() {
+            return Ok(attributes);
+        }
+        let (name, value) = rest
+            .split_once('=')
+            .ok_or(format!("Invalid xml attributes '{}'.", rest))?;
+        let value = value.trim_start();
+        let quote = value
+            .chars()
+            .next()
+            .filter(|c| *c == '"' || *c == '\'')
+            .ok_or(format!(
+                "Unquoted value of xml attribute '{}'.",
+                name.trim()
+            ))?;
+        let end = value[1..].find(quote).ok_or(format!(
+            "Unterminated value of xml attribute '{}'.",
+            name.trim()
+        ))?;
+        attributes.insert(
+            local_name(name.trim()).to_owned(),
+            unescape(&value[1..end + 1]),
+        );
+        rest = &value[end + 2..];
+    }
+}
+
+/// Name without its namespace prefix. Attributes like 'attr.name' keep their dots.
+fn local_name(name: &str) -> &str {
+    name.rsplit(':').next().unwrap_or(name)
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+fn unescape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        out += &rest[..start];
+        rest = &rest[start..];
+        let entity = rest.find(';').map(|end| (&rest[1..end], end));
+        let decoded = entity.and_then(|(e, _)| match e {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => e
+                .strip_prefix("#x")
+                .map(|hex| u32::from_str_radix(hex, 16))
+                .or_else(|| e.strip_prefix('#').map(|dec| dec.parse::<u32>()))?
+                .ok()
+                .and_then(char::from_u32),
+        });
+        match (decoded, entity) {
+            (Some(c), Some((_, end))) => {
+                out.push(c);
+                rest = &rest[end + 1..];
+            }
+            _ => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out + rest
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::graph::Graph;
+    use crate::graph_parser::{serialize_graph, InstanceFormat};
+    use crate::graphml::{is_graphml, parse_graphml, to_graphml};
+    use tracing::debug;
+    use tracing_subscriber::EnvFilter;
+
+    fn init() {
+        let _ = tracing_subscriber::fmt()
+            .with_env_filter(
+                EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("debug")),
+            )
+            .with_test_writer()
+            .try_init();
+    }
+
+    #[test]
+    fn test_parse_graphml() {
+        init();
+        debug!("Running 'test_parse_graphml'");
+        // Nodes with balances and labels like tools for network analysis write them.
+        let nodes = r#"<?xml version="1.0" encoding="UTF-8"?>
+            <!-- Written by a tool -->
+            <graphml xmlns="http://graphml.graphdrawing.org/xmlns">
+              <key attr.name="label" attr.type="string" for="node" id="label"/>
+              <key attr.name="balance" attr.type="double" for="node" id="d1"/>
+              <graph edgedefault="undirected">
+                <node id="n0"><data key="label">Tom &amp; Jerry</data><data key="d1">-3.0</data></node>
+                <node id="n1"><data key="d1">3</data></node>
+                <node id="n2"/>
+                <edge source="n0" target="n1"/>
+              </graph>
+            </graphml>"#;
+        assert!(is_graphml(nodes));
+        let graph = parse_graphml(nodes).unwrap();
+        debug!("Graph: {:?}", graph);
+        assert_eq!(
+            graph.balances(),
+            vec![
+                ("Tom & Jerry".to_owned(), -3),
+                ("n1".to_owned(), 3),
+                ("n2".to_owned(), 0)
+            ]
+        );
+        assert!(graph.debts().is_none());
+        assert_eq!(
+            serialize_graph(&graph, InstanceFormat::NodesCsv).unwrap(),
+            "Tom & Jerry,-3\nn1,3\nn2,0\n"
+        );
+
+        // Edges with amounts are debts and take precedence over the balances.
+        let edges = r#"<graphml><key id="w" for="edge" attr.name="weight"/><graph>
+              <node id="A"><data key="balance">9</data></node><node id="B"/><node id="C"/>
+              <edge source="A" target="B"><data key="w">5</data></edge>
+              <edge source="B" target="C"><data key="w"><![CDATA[2]]></data></edge>
+            </graph></graphml>"#;
+        let graph = parse_graphml(edges).unwrap();
+        assert_eq!(graph.debts().unwrap().len(), 2);
+        assert_eq!(
+            serialize_graph(&graph, InstanceFormat::EdgesCsv).unwrap(),
+            "A,B,5\nB,C,2\n"
+        );
+
+        // Written GraphML is read as the same graph.
+        let parsed = parse_graphml(&to_graphml(&graph)).unwrap();
+        assert_eq!(parsed.debts(), graph.debts());
+        assert_eq!(parsed.balances(), graph.balances());
+
+        for invalid in [
+            "<graphml><graph><node id=\"A\"/></graph></graphml>",
+            "<graphml><graph><node id=\"A\"><data key=\"balance\">1.5</data></node></graph></graphml>",
+            "<graphml><graph><edge source=\"A\" target=\"B\"><data key=\"amount\">1</data></edge></graph></graphml>",
+            "<graphml><graph><node id=\"A",
+        ] {
+            let error = parse_graphml(invalid).unwrap_err();
+            debug!("Error: {}", error);
+        }
+        assert!(!is_graphml("A,B,5"));
+        assert_eq!(
+            Graph::try_from(nodes.to_owned()).map(|g| g.balances().len()),
+            Ok(3)
+        );
+    }
+}
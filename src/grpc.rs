@@ -0,0 +1,392 @@
+//! gRPC interface to solve, verify and summarize instances. Large instances are solved as
+//! jobs of a [`JobQueue`].
+//! Only available with the `grpc` feature.
+// The service trait requires `tonic::Status` as error type.
+#![allow(clippy::result_large_err)]
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tonic::{Request, Response, Status};
+
+use crate::asynchronous::solve_async;
+use crate::graph::Graph;
+use crate::jobs::{JobQueue, JobState};
+use crate::probleminstance::{ProblemInstance, SolvingMethods};
+use crate::settlement::Settlement;
+use crate::verification::{verify_plan, Payment};
+
+/// Messages and service definitions generated from 'proto/payback.proto'.
+pub mod proto {
+    tonic::include_proto!("payback.v1");
+}
+
+use proto::payback_server::{Payback, PaybackServer};
+use proto::{
+    CancelJobRequest, CancelJobResponse, GetJobRequest, GetJobResponse, Instance, Person,
+    SolveRequest, SolveResponse, StatsRequest, StatsResponse, SubmitJobRequest, SubmitJobResponse,
+    Transaction, VerifyRequest, VerifyResponse,
+};
+
+impl From<Instance> for Graph {
+    fn from(value: Instance) -> Self {
+        Graph::from(
+            value
+                .people
+                .into_iter()
+                .map(|p| (p.name, p.weight))
+                .collect::<Vec<(String, i64)>>(),
+        )
+    }
+}
+
+fn instance_to_graph(instance: Option<Instance>) -> Result<Graph, Status> {
+    instance
+        .map(Graph::from)
+        .ok_or(Status::invalid_argument("No instance was given."))
+}
+
+/// Method of a request. Defaults to 'approx-star-expand'.
+fn request_method(method: &str) -> Result<SolvingMethods, Status> {
+    if method.is_empty() {
+        Ok(SolvingMethods::ApproxStarExpand)
+    } else {
+        method
+            .parse::<SolvingMethods>()
+            .map_err(Status::invalid_argument)
+    }
+}
+
+fn transactions(solution: &Option<Settlement>) -> Option<Vec<Transaction>> {
+    Some(
+        solution
+            .to_owned()?
+            .into_iter()
+            .map(|t| Transaction {
+                from: t.payer,
+                to: t.payee,
+                amount: t.amount,
+            })
+            .collect(),
+    )
+}
+
+/// Implementation of the payback gRPC service.
+#[derive(Debug, Default)]
+pub struct PaybackService {
+    jobs: JobQueue,
+}
+
+impl PaybackService {
+    /// Service solving the jobs of the queue.
+    pub fn new(jobs: JobQueue) -> Self {
+        PaybackService { jobs }
+    }
+}
+
+#[tonic::async_trait]
+impl Payback for PaybackService {
+    async fn solve(
+        &self,
+        request: Request<SolveRequest>,
+    ) -> Result<Response<SolveResponse>, Status> {
+        let request = request.into_inner();
+        let method = request_method(&request.method)?;
+        let instance = Arc::new(ProblemInstance::from(instance_to_graph(request.instance)?));
+        if !instance.is_solvable() {
+            return Err(Status::failed_precondition(format!(
+                "The weights add up to {} instead of 0.",
+                instance.imbalance()
+            )));
+        }
+        let solution = solve_async(instance.clone(), method)
+            .await
+            .map_err(|e| Status::internal(e.to_string()))?;
+        Ok(Response::new(SolveResponse {
+            transactions: transactions(&solution)
+                .ok_or(Status::failed_precondition("No result was found."))?,
+        }))
+    }
+
+    async fn verify(
+        &self,
+        request: Request<VerifyRequest>,
+    ) -> Result<Response<VerifyResponse>, Status> {
+        let request = request.into_inner();
+        let graph = instance_to_graph(request.instance)?;
+        let plan = request
+            .plan
+            .into_iter()
+            .map(|t| Payment {
+                from: t.from,
+                to: t.to,
+                amount: t.amount,
+            })
+            .collect::<Vec<Payment>>();
+        let report = verify_plan(&graph, &plan);
+        Ok(Response::new(VerifyResponse {
+            settled: report.is_settled(),
+            transactions: report.transactions as u64,
+            lower_bound: report.lower_bound as u64,
+            unsettled: report
+                .unsettled
+                .into_iter()
+                .map(|(name, weight)| Person { name, weight })
+                .collect(),
+        }))
+    }
+
+    async fn stats(
+        &self,
+        request: Request<StatsRequest>,
+    ) -> Result<Response<StatsResponse>, Status> {
+        let graph = instance_to_graph(request.into_inner().instance)?;
+        let total_debt = graph
+            .vertices
+            .iter()
+            .filter(|v| v.weight < 0)
+            .map(|v| -v.weight)
+            .sum();
+        let instance = ProblemInstance::from(graph);
+        Ok(Response::new(StatsResponse {
+            people: instance.vertex_count() as u64,
+            solvable: instance.is_solvable(),
+            total_debt,
+            lower_bound: instance.transaction_lower_bound() as u64,
+        }))
+    }
+
+    async fn submit_job(
+        &self,
+        request: Request<SubmitJobRequest>,
+    ) -> Result<Response<SubmitJobResponse>, Status> {
+        let request = request.into_inner();
+        let method = request_method(&request.method)?;
+        let instance = ProblemInstance::from(instance_to_graph(request.instance)?);
+        Ok(Response::new(SubmitJobResponse {
+            id: self.jobs.submit(instance, method),
+        }))
+    }
+
+    async fn get_job(
+        &self,
+        request: Request<GetJobRequest>,
+    ) -> Result<Response<GetJobResponse>, Status> {
+        let id = request.into_inner().id;
+        let state = self
+            .jobs
+            .state(id)
+            .ok_or(Status::not_found(format!("There is no job {}.", id)))?;
+        let mut response = GetJobResponse::default();
+        match state {
+            JobState::Queued => response.set_state(proto::JobState::Queued),
+            JobState::Running => response.set_state(proto::JobState::Running),
+            JobState::Done {
+                solution,
+                approximate,
+            } => match transactions(&solution) {
+                Some(transactions) => {
+                    response.set_state(proto::JobState::Done);
+                    response.transactions = transactions;
+                    response.approximate = approximate;
+                }
+                None => {
+                    response.set_state(proto::JobState::Failed);
+                    response.error = "No result was found.".to_owned();
+                }
+            },
+            JobState::Failed(e) => {
+                response.set_state(proto::JobState::Failed);
+                response.error = e;
+            }
+            JobState::Cancelled => response.set_state(proto::JobState::Cancelled),
+        }
+        Ok(Response::new(response))
+    }
+
+    async fn cancel_job(
+        &self,
+        request: Request<CancelJobRequest>,
+    ) -> Result<Response<CancelJobResponse>, Status> {
+        let id = request.into_inner().id;
+        if self.jobs.state(id).is_none() {
+            return Err(Status::not_found(format!("There is no job {}.", id)));
+        }
+        Ok(Response::new(CancelJobResponse {
+            cancelled: self.jobs.cancel(id),
+        }))
+    }
+}
+
+/// Serves the payback gRPC service on the given address until the process is stopped. Jobs are
+/// solved by the queue.
+pub async fn serve(addr: SocketAddr, jobs: JobQueue) -> Result<(), tonic::transport::Error> {
+    tonic::transport::Server::builder()
+        .add_service(PaybackServer::new(PaybackService::new(jobs)))
+        .serve(addr)
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::grpc::proto::payback_client::PaybackClient;
+    use crate::grpc::proto::payback_server::Payback;
+    use crate::grpc::proto::payback_server::PaybackServer;
+    use crate::grpc::proto::{
+        CancelJobRequest, GetJobRequest, Instance, JobState, Person, SolveRequest, StatsRequest,
+        SubmitJobRequest, Transaction, VerifyRequest,
+    };
+    use crate::grpc::PaybackService;
+    use tonic::transport::server::TcpIncoming;
+    use tonic::transport::Server;
+    use tonic::{Code, Request};
+    use tracing::debug;
+    use tracing_subscriber::EnvFilter;
+
+    fn init() {
+        let _ = tracing_subscriber::fmt()
+            .with_env_filter(
+                EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("debug")),
+            )
+            .with_test_writer()
+            .try_init();
+    }
+
+    fn instance() -> Instance {
+        Instance {
+            people: vec![
+                Person {
+                    name: "A".to_string(),
+                    weight: -2,
+                },
+                Person {
+                    name: "B".to_string(),
+                    weight: 2,
+                },
+            ],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_grpc_service() {
+        init();
+        debug!("Running 'test_grpc_service'");
+        let service = PaybackService::default();
+        let solved = service
+            .solve(Request::new(SolveRequest {
+                instance: Some(instance()),
+                method: "dp-greedy-satisfaction".to_string(),
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(
+            solved.transactions,
+            vec![Transaction {
+                from: "A".to_string(),
+                to: "B".to_string(),
+                amount: 2
+            }]
+        );
+        let verified = service
+            .verify(Request::new(VerifyRequest {
+                instance: Some(instance()),
+                plan: solved.transactions,
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert!(verified.settled);
+        let stats = service
+            .stats(Request::new(StatsRequest {
+                instance: Some(instance()),
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(stats.total_debt, 2);
+        assert!(service
+            .solve(Request::new(SolveRequest {
+                instance: Some(instance()),
+                method: "unknown".to_string(),
+            }))
+            .await
+            .is_err());
+
+        let id = service
+            .submit_job(Request::new(SubmitJobRequest {
+                instance: Some(instance()),
+                method: "dp-greedy-satisfaction".to_string(),
+            }))
+            .await
+            .unwrap()
+            .into_inner()
+            .id;
+        service.jobs.wait(id).await;
+        let job = service
+            .get_job(Request::new(GetJobRequest { id }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(job.state(), JobState::Done);
+        assert_eq!(job.transactions.len(), 1);
+        assert!(!job.approximate);
+        let cancelled = service
+            .cancel_job(Request::new(CancelJobRequest { id }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert!(!cancelled.cancelled);
+        assert!(service
+            .get_job(Request::new(GetJobRequest { id: id + 1 }))
+            .await
+            .is_err());
+    }
+    #[tokio::test]
+    async fn test_grpc_requests() {
+        init();
+        debug!("Running 'test_grpc_requests'");
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let incoming = TcpIncoming::from_listener(listener, true, None).unwrap();
+        let server = tokio::spawn(
+            Server::builder()
+                .add_service(PaybackServer::new(PaybackService::default()))
+                .serve_with_incoming(incoming),
+        );
+        let mut client = PaybackClient::connect(format!("http://{}", addr))
+            .await
+            .unwrap();
+
+        let solved = client
+            .solve(SolveRequest {
+                instance: Some(instance()),
+                method: "dp-greedy-satisfaction".to_string(),
+            })
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(
+            solved.transactions,
+            vec![Transaction {
+                from: "A".to_string(),
+                to: "B".to_string(),
+                amount: 2
+            }]
+        );
+
+        // The weights of an unsolvable instance don't add up to zero.
+        let mut unsolvable = instance();
+        unsolvable.people[1].weight = 1;
+        let status = client
+            .solve(SolveRequest {
+                instance: Some(unsolvable),
+                method: "dp-greedy-satisfaction".to_string(),
+            })
+            .await
+            .unwrap_err();
+        debug!("Status: {:?}", status);
+        assert_eq!(status.code(), Code::FailedPrecondition);
+        assert_eq!(status.message(), "The weights add up to -1 instead of 0.");
+        server.abort();
+    }
+}
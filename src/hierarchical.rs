@@ -0,0 +1,251 @@
+use std::collections::{BTreeMap, HashMap};
+
+use itertools::Itertools;
+use tracing::{debug, instrument, trace, Span};
+
+use crate::approximation::greedy_satisfaction;
+use crate::dynamic_program::patcas_dp;
+use crate::events::{SolverEvent, SolverObserver};
+use crate::graph::{Edge, Graph, NamedNode};
+use crate::probleminstance::{Cancel, EdgeSolution, ProblemInstance};
+
+/// Largest number of people in a cluster, which is solved by a single run of the dynamic
+/// program.
+const CLUSTER_SIZE: usize = 12;
+/// Id of the person balancing a cluster, which isn't used by the vertices of a graph.
+const PLACEHOLDER: usize = usize::MAX;
+
+/// Algorithm approximating the payback problem for large instances by divide and conquer.
+/// The people are split into clusters of at most [`CLUSTER_SIZE`] people, which are solved
+/// exactly by the dynamic program of 'DPGreedySatisfaction'. A cluster, whose weights don't add
+/// up to zero, settles the groups it can and passes its residual to a representative. The
+/// representatives are clustered and solved the same way until they fit into a single cluster.
+/// Has a approximation factor of 2.
+///
+/// * `instance` - The problem instance which should be solved
+/// * `cluster_by` - Key of the metadata, by which the people are clustered first. People with
+///   the same value are clustered together. Without a key or tag, people are clustered by the
+///   sign and magnitude of their weights.
+/// * `observer` - Receives the events of the solver
+///
+/// Example:
+/// ```
+/// use payback::graph::Graph;
+/// use payback::probleminstance::{ProblemInstance, SolvingMethods};
+///
+/// let instance: ProblemInstance = Graph::from(vec![-2, -1, 1, 2]).into();
+/// let solution = instance.solve_with(SolvingMethods::ApproxHierarchical);
+/// ```
+#[instrument(
+    level = "debug",
+    skip_all,
+    fields(vertices = instance.g.vertices.len(), levels)
+)]
+pub(crate) fn hierarchical(
+    instance: &ProblemInstance,
+    cluster_by: Option<&str>,
+    observer: &dyn SolverObserver,
+) -> EdgeSolution {
+    if !instance.is_solvable() {
+        return None;
+    }
+    let mut tagged: BTreeMap<Option<&str>, Vec<NamedNode>> = BTreeMap::new();
+    for v in instance.g.vertices.iter().filter(|v| v.weight != 0) {
+        let tag = cluster_by.and_then(|key| {
+            instance
+                .g
+                .metadata(&v.name)?
+                .iter()
+                .find(|(k, _)| k == key)
+                .map(|(_, value)| value.as_str())
+        });
+        tagged.entry(tag).or_default().push(v.to_owned());
+    }
+    let mut clusters: Vec<Vec<NamedNode>> = tagged.into_values().flat_map(by_magnitude).collect();
+
+    let mut solution: BTreeMap<Edge, i64> = BTreeMap::new();
+    let mut levels = 0;
+    loop {
+        levels += 1;
+        let representatives: Vec<NamedNode> = clusters
+            .into_iter()
+            .filter_map(|c| settle_cluster(c, &mut solution, observer))
+            .collect();
+        trace!(level = levels, ?representatives, "Settled level");
+        if representatives.is_empty() {
+            break;
+        }
+        clusters = by_magnitude(representatives);
+    }
+    Span::current().record("levels", levels);
+    debug!(transactions = solution.len(), "Solved hierarchically");
+    Some(solution)
+}
+
+/// Splits the people into clusters of at most [`CLUSTER_SIZE`] people with similar magnitudes.
+/// The signs alternate, so clusters contain people, who can settle among themselves.
+fn by_magnitude(people: Vec<NamedNode>) -> Vec<Vec<NamedNode>> {
+    let (positive, negative): (Vec<NamedNode>, Vec<NamedNode>) =
+        people.into_iter().partition(|v| v.weight > 0);
+    let sorted = |vs: Vec<NamedNode>| vs.into_iter().sorted_by_key(|v| v.weight.abs());
+    sorted(positive)
+        .interleave(sorted(negative))
+        .chunks(CLUSTER_SIZE)
+        .into_iter()
+        .map(|c| c.collect())
+        .collect()
+}
+
+/// Settles the groups of the cluster, which add up to zero, and returns the representative of
+/// the people left. The people left settle with their representative, which takes over their
+/// weights.
+fn settle_cluster(
+    cluster: Vec<NamedNode>,
+    solution: &mut BTreeMap<Edge, i64>,
+    observer: &dyn SolverObserver,
+) -> Option<NamedNode> {
+    let residual: i64 = cluster.iter().map(|v| v.weight).sum();
+    let by_id: HashMap<usize, &NamedNode> = cluster.iter().map(|v| (v.id, v)).collect();
+    let mut people: Vec<NamedNode> = cluster
+        .iter()
+        .map(|v| NamedNode {
+            name: v.id.to_string(),
+            ..v.to_owned()
+        })
+        .collect();
+    if residual != 0 {
+        people.push(NamedNode {
+            id: PLACEHOLDER,
+            name: PLACEHOLDER.to_string(),
+            weight: -residual,
+        });
+    }
+    let coarse = ProblemInstance::from(Graph::from(people));
+    // The bounds of a cluster aren't bounds of the instance, so its events are dropped.
+    let sol = patcas_dp(&coarse, &greedy_satisfaction, &(), Cancel::default());
+    let mut left: Vec<&NamedNode> = vec![];
+    for group in coarse.partitioning(&sol).unwrap_or_default() {
+        let members: Vec<&NamedNode> = group
+            .iter()
+            .filter_map(|id| by_id.get(&id.parse().ok()?).copied())
+            .collect();
+        if members.len() < group.len() {
+            left = members;
+            continue;
+        }
+        observer.on_event(&SolverEvent::PartitionDiscovered {
+            people: members.iter().map(|v| v.name.to_owned()).collect(),
+        });
+        let edges = greedy_satisfaction(&ProblemInstance::from(Graph::from(members)))
+            .expect("Groups of the dynamic program add up to zero.");
+        add_edges(solution, edges);
+    }
+    // The person with the largest weight of the sign of the residual represents the others.
+    let representative = *left
+        .iter()
+        .filter(|v| v.weight.signum() == residual.signum())
+        .max_by_key(|v| v.weight.abs())?;
+    let edges = left
+        .iter()
+        .filter(|v| v.id != representative.id)
+        .map(|v| {
+            if v.weight > 0 {
+                (
+                    Edge {
+                        u: v.id,
+                        v: representative.id,
+                    },
+                    v.weight,
+                )
+            } else {
+                (
+                    Edge {
+                        u: representative.id,
+                        v: v.id,
+                    },
+                    -v.weight,
+                )
+            }
+        })
+        .collect();
+    add_edges(solution, edges);
+    Some(NamedNode {
+        weight: residual,
+        ..representative.to_owned()
+    })
+}
+
+/// Adds the edges to the solution. Amounts of edges, which are already in the solution, are
+/// added up.
+fn add_edges(solution: &mut BTreeMap<Edge, i64>, edges: BTreeMap<Edge, i64>) {
+    for (e, w) in edges {
+        *solution.entry(e).or_default() += w;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::graph::Graph;
+    use crate::hierarchical::hierarchical;
+    use crate::probleminstance::ProblemInstance;
+    use crate::testing::is_valid_solution;
+    use tracing::debug;
+    use tracing_subscriber::EnvFilter;
+
+    fn init() {
+        let _ = tracing_subscriber::fmt()
+            .with_env_filter(
+                EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("debug")),
+            )
+            .with_test_writer()
+            .try_init();
+    }
+
+    #[test]
+    fn test_hierarchical() {
+        init();
+        debug!("Running 'test_hierarchical'");
+        // Pairs of opposite weights are found in every cluster.
+        let weights: Vec<i64> = (1..=100).flat_map(|w| [w, -w]).collect();
+        let instance = ProblemInstance::from(Graph::from(weights));
+        let sol = hierarchical(&instance, None, &());
+        assert!(is_valid_solution(&instance.g, &instance.settlement(&sol)));
+        assert_eq!(sol.unwrap().len(), 100);
+
+        let weights: Vec<i64> = (0..250).map(|i| (i * 37 % 101) - 50).collect();
+        let imbalance: i64 = weights.iter().sum();
+        let mut weights = weights;
+        weights.push(-imbalance);
+        let instance = ProblemInstance::from(Graph::from(weights.to_owned()));
+        let sol = hierarchical(&instance, None, &());
+        debug!("Transactions: {:?}", sol.as_ref().map(|s| s.len()));
+        assert!(is_valid_solution(&instance.g, &instance.settlement(&sol)));
+        assert!(sol.unwrap().len() < weights.iter().filter(|w| **w != 0).count() - 1);
+
+        // People with the same tag settle among themselves.
+        let graph = Graph::from(vec![
+            ("A".to_owned(), -2),
+            ("B".to_owned(), 2),
+            ("C".to_owned(), -2),
+            ("D".to_owned(), 2),
+        ])
+        .with_metadata(HashMap::from([
+            ("A".to_owned(), vec![("team".to_owned(), "x".to_owned())]),
+            ("D".to_owned(), vec![("team".to_owned(), "x".to_owned())]),
+        ]));
+        let instance = ProblemInstance::from(graph);
+        let sol = hierarchical(&instance, Some("team"), &());
+        assert!(is_valid_solution(&instance.g, &instance.settlement(&sol)));
+        let named = instance.named_solution(&sol).unwrap();
+        debug!("Solution: {:?}", named);
+        assert!(named
+            .transactions()
+            .iter()
+            .any(|t| t.payer == "A" && t.payee == "D"));
+        assert!(
+            hierarchical(&ProblemInstance::from(Graph::from(vec![-1, 2])), None, &()).is_none()
+        );
+    }
+}
@@ -0,0 +1,138 @@
+use std::env;
+
+use crate::decimals::format_minor;
+
+/// Languages of the human-readable output.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum Lang {
+    #[default]
+    En,
+    De,
+}
+
+/// Fixed texts of the human-readable output, which are translated by [`Lang::text()`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Text {
+    NoResult,
+    Summary,
+    Total,
+    Transactions,
+    CumulativeBalances,
+    /// Follows the name of a payee without an IBAN.
+    NoIban,
+    /// Follows the name of a person excluded from the instance and precedes their balance.
+    Excluded,
+    /// Precedes the number of a round of a schedule.
+    Round,
+    /// Precedes the debts, on which interest or late fees accrued.
+    Accrued,
+    /// Precedes the balances converted into the base currency.
+    Conversions,
+    /// Precedes the rounding deltas of people.
+    RoundingAudit,
+    /// Replaces the rounding deltas, if no amount was rounded.
+    NothingRounded,
+}
+
+impl Lang {
+    /// Language of a locale like 'de_DE.UTF-8'. Returns `None` for unsupported languages.
+    pub fn from_locale(locale: &str) -> Option<Lang> {
+        match locale.get(..2)?.to_ascii_lowercase().as_str() {
+            "en" => Some(Lang::En),
+            "de" => Some(Lang::De),
+            _ => None,
+        }
+    }
+
+    /// Detects the language from the first set locale variable of 'LC_ALL', 'LC_MESSAGES' and
+    /// 'LANG'. Falls back to English.
+    pub fn detect() -> Lang {
+        ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .iter()
+            .filter_map(|var| env::var(var).ok())
+            .find(|locale| !locale.is_empty())
+            .and_then(|locale| Lang::from_locale(&locale))
+            .unwrap_or_default()
+    }
+
+    pub fn text(&self, text: Text) -> &'static str {
+        match (self, text) {
+            (Lang::En, Text::NoResult) => "No result was found.",
+            (Lang::En, Text::Summary) => "Summary",
+            (Lang::En, Text::Total) => "Total",
+            (Lang::En, Text::Transactions) => "transactions",
+            (Lang::En, Text::CumulativeBalances) => "Cumulative balances",
+            (Lang::En, Text::NoIban) => "has no IBAN, so no QR code is created.",
+            (Lang::En, Text::Excluded) => "is left out with a balance of",
+            (Lang::En, Text::Round) => "Round",
+            (Lang::En, Text::Accrued) => "Accrued interest and fees",
+            (Lang::En, Text::Conversions) => "Converted balances",
+            (Lang::En, Text::RoundingAudit) => "Rounding audit",
+            (Lang::En, Text::NothingRounded) => "No amounts were rounded.",
+            (Lang::De, Text::NoResult) => "Es wurde kein Ergebnis gefunden.",
+            (Lang::De, Text::Summary) => "Zusammenfassung",
+            (Lang::De, Text::Total) => "Gesamt",
+            (Lang::De, Text::Transactions) => "Überweisungen",
+            (Lang::De, Text::CumulativeBalances) => "Kumulierte Salden",
+            (Lang::De, Text::NoIban) => "hat keine IBAN, daher wird kein QR-Code erstellt.",
+            (Lang::De, Text::Excluded) => "wird ausgelassen, Saldo:",
+            (Lang::De, Text::Round) => "Runde",
+            (Lang::De, Text::Accrued) => "Aufgelaufene Zinsen und Gebühren",
+            (Lang::De, Text::Conversions) => "Umgerechnete Salden",
+            (Lang::De, Text::RoundingAudit) => "Rundungsprüfung",
+            (Lang::De, Text::NothingRounded) => "Es wurden keine Beträge gerundet.",
+        }
+    }
+
+    /// Formats an amount in minor units with `decimals` many decimal places and the decimal
+    /// separator of the language.
+    pub fn amount(&self, amount: i64, decimals: u32) -> String {
+        let separator = match self {
+            Lang::En => '.',
+            Lang::De => ',',
+        };
+        format_minor(amount, decimals, separator)
+    }
+
+    /// Describes a transaction, e.g. '"A" to "B": 5'.
+    pub fn transaction(&self, from: &str, to: &str, amount: i64, decimals: u32) -> String {
+        let amount = self.amount(amount, decimals);
+        match self {
+            Lang::En => format!("{:?} to {:?}: {}", from, to, amount),
+            Lang::De => format!("{:?} an {:?}: {}", from, to, amount),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::i18n::{Lang, Text};
+    use tracing::debug;
+    use tracing_subscriber::EnvFilter;
+
+    fn init() {
+        let _ = tracing_subscriber::fmt()
+            .with_env_filter(
+                EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("debug")),
+            )
+            .with_test_writer()
+            .try_init();
+    }
+
+    #[test]
+    fn test_languages() {
+        init();
+        debug!("Running 'test_languages'");
+        assert_eq!(Lang::from_locale("de_DE.UTF-8"), Some(Lang::De));
+        assert_eq!(Lang::from_locale("EN_us"), Some(Lang::En));
+        assert_eq!(Lang::from_locale("C"), None);
+        assert_eq!(Lang::En.transaction("A", "B", 15, 0), "\"A\" to \"B\": 15");
+        assert_eq!(Lang::De.transaction("A", "B", 15, 0), "\"A\" an \"B\": 15");
+        assert_eq!(
+            Lang::De.transaction("A", "B", 1505, 2),
+            "\"A\" an \"B\": 15,05"
+        );
+        assert_eq!(Lang::En.text(Text::NoResult), "No result was found.");
+    }
+}
@@ -0,0 +1,168 @@
+use chrono::NaiveDate;
+use csv::WriterBuilder;
+use tracing::{debug, instrument, trace};
+
+use crate::dates::{parse_dated_records, DATE_FORMAT};
+use crate::payments::PAYMENT_MARKER;
+
+/// Terms, by which dated debts grow until they are settled.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct AccrualTerms {
+    /// Simple interest in percent per year of 365 days, e.g. 5.0.
+    pub interest: f64,
+    /// Fee added once to every debt, which is older than the grace period.
+    pub late_fee: i64,
+    /// Days after the date of a debt, in which neither interest nor a fee accrues.
+    pub grace_days: i64,
+}
+
+impl AccrualTerms {
+    /// Amount accrued on the principal between the date of the debt and the settlement. The
+    /// interest is rounded to the nearest whole amount and has the sign of the principal.
+    pub fn accrued(&self, principal: i64, date: NaiveDate, settlement: NaiveDate) -> i64 {
+        let days = (settlement - date).num_days() - self.grace_days;
+        if days <= 0 {
+            return 0;
+        }
+        let interest =
+            (principal.abs() as f64 * self.interest / 100.0 * days as f64 / 365.0).round() as i64;
+        principal.signum() * (interest + self.late_fee)
+    }
+}
+
+/// Debt of `debtor` to `creditor`, which grew by `accrued` on top of its `principal`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AccruedDebt {
+    pub debtor: String,
+    pub creditor: String,
+    pub date: NaiveDate,
+    pub principal: i64,
+    pub accrued: i64,
+}
+
+/// Raises every dated debt in the csv edge format by the interest and late fee accrued until the
+/// settlement date. Payments and records without a date don't accrue anything. The records keep
+/// their dates, so they can still be filtered or grouped afterwards.
+///
+/// Returns the records with the raised amounts and the debts, on which something accrued.
+///
+/// Example:
+/// ```
+/// use payback::dates::parse_date;
+/// use payback::interest::{accrue, AccrualTerms};
+///
+/// let terms = AccrualTerms { interest: 10.0, late_fee: 2, grace_days: 0 };
+/// let settlement = parse_date("2025-01-01").unwrap();
+/// let (data, debts) = accrue("A,B,730,2024-01-02", settlement, &terms).unwrap();
+/// assert_eq!(data, "A,B,805,2024-01-02\n");
+/// assert_eq!(debts[0].accrued, 75);
+/// ```
+#[instrument(level = "debug", skip(data))]
+pub fn accrue(
+    data: &str,
+    settlement: NaiveDate,
+    terms: &AccrualTerms,
+) -> Result<(String, Vec<AccruedDebt>), String> {
+    let records = parse_dated_records(data)?
+        .ok_or("Interest and late fees need records with a date column.")?;
+    let mut debts = vec![];
+    let mut wtr = WriterBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_writer(vec![]);
+    for mut record in records {
+        let is_debt = record.fields.len() >= 3
+            && record.fields[0].trim() != PAYMENT_MARKER
+            && record.fields[1].trim().parse::<i64>().is_err();
+        let principal = record
+            .fields
+            .get(2)
+            .and_then(|f| f.trim().parse::<i64>().ok());
+        if let (true, Some(principal), Some(date)) = (is_debt, principal, record.date) {
+            let accrued = terms.accrued(principal, date, settlement);
+            if accrued != 0 {
+                record.fields[2] = (principal + accrued).to_string();
+                let (debtor, creditor) = if principal > 0 { (0, 1) } else { (1, 0) };
+                debts.push(AccruedDebt {
+                    debtor: record.fields[debtor].trim().to_owned(),
+                    creditor: record.fields[creditor].trim().to_owned(),
+                    date,
+                    principal: principal.abs(),
+                    accrued: accrued.abs(),
+                });
+            }
+        }
+        if let Some(date) = record.date {
+            record.fields.push(date.format(DATE_FORMAT).to_string());
+        }
+        wtr.write_record(&record.fields)
+            .map_err(|e| e.to_string())?;
+    }
+    trace!(?debts, "Accrued debts");
+    debug!(
+        debts = debts.len(),
+        accrued = debts.iter().map(|d| d.accrued).sum::<i64>(),
+        "Accrued interest and fees until {}",
+        settlement
+    );
+    let data = wtr
+        .into_inner()
+        .map_err(|e| e.to_string())
+        .and_then(|bytes| String::from_utf8(bytes).map_err(|e| e.to_string()))?;
+    Ok((data, debts))
+}
+
+/// Latest date of the records, which is the default settlement date.
+pub fn latest_date(data: &str) -> Result<Option<NaiveDate>, String> {
+    Ok(parse_dated_records(data)?
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|r| r.date)
+        .max())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dates::parse_date;
+    use crate::interest::{accrue, latest_date, AccrualTerms};
+    use tracing::debug;
+    use tracing_subscriber::EnvFilter;
+
+    fn init() {
+        let _ = tracing_subscriber::fmt()
+            .with_env_filter(
+                EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("debug")),
+            )
+            .with_test_writer()
+            .try_init();
+    }
+
+    #[test]
+    fn test_accrue() {
+        init();
+        debug!("Running 'test_accrue'");
+        let data = "A,B,1000,2024-01-01\nB,C,-730,EUR,2024-03-01\nC,A,50\npaid,A,B,500,2024-02-01\nD,E,10,2024-06-25\n";
+        let settlement = latest_date(data).unwrap().unwrap();
+        assert_eq!(settlement, parse_date("2024-06-25").unwrap());
+        let terms = AccrualTerms {
+            interest: 10.0,
+            late_fee: 5,
+            grace_days: 30,
+        };
+        let (accrued, debts) = accrue(data, settlement, &terms).unwrap();
+        debug!("Accrued records:\n{}", accrued);
+        // 146 days after the grace period: 1000 * 0.1 * 146 / 365 = 40.
+        // 86 days after the grace period: 730 * 0.1 * 86 / 365 = 17.2.
+        assert_eq!(
+            accrued,
+            "A,B,1045,2024-01-01\nB,C,-752,EUR,2024-03-01\nC,A,50\npaid,A,B,500,2024-02-01\nD,E,10,2024-06-25\n"
+        );
+        assert_eq!(debts.len(), 2);
+        assert_eq!(
+            (debts[1].debtor.as_str(), debts[1].creditor.as_str()),
+            ("C", "B")
+        );
+        assert_eq!((debts[1].principal, debts[1].accrued), (730, 22));
+        assert!(accrue("A,B,1", settlement, &terms).is_err());
+    }
+}
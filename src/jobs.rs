@@ -0,0 +1,372 @@
+//! Queue of solving jobs, which are run by a pool of workers in the background. Clients submit
+//! large instances and poll for the result instead of waiting for it.
+//! Only available with the `tokio` feature.
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{oneshot, Semaphore};
+use tracing::{debug, info};
+
+use crate::asynchronous::AsyncSolveError;
+use crate::probleminstance::{Cancel, MethodOptions, ProblemInstance, SolvingMethods};
+use crate::settlement::Settlement;
+
+pub type JobId = u64;
+
+/// State of a job of a [`JobQueue`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum JobState {
+    /// The job waits for a free worker.
+    Queued,
+    Running,
+    /// The job finished. If the method took longer than the timeout of the queue, the
+    /// approximation of the method is used instead and `approximate` is set.
+    Done {
+        solution: Option<Settlement>,
+        approximate: bool,
+    },
+    Failed(String),
+    Cancelled,
+}
+
+impl JobState {
+    /// Whether the job won't change anymore.
+    pub fn is_finished(&self) -> bool {
+        !matches!(self, JobState::Queued | JobState::Running)
+    }
+}
+
+struct Job {
+    instance: Arc<ProblemInstance>,
+    state: JobState,
+    /// Stops the job, while it isn't finished.
+    cancel: Option<oneshot::Sender<()>>,
+    /// Stops the exact method of the job, once it is set.
+    stop: Arc<AtomicBool>,
+    /// When the job finished.
+    finished: Option<Instant>,
+}
+
+impl std::fmt::Debug for Job {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Job")
+            .field("state", &self.state)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Runs solving jobs on at most `workers` many workers at once. A worker is busy, until its
+/// solver returns, even if the job was cancelled or timed out before. Finished jobs are removed
+/// on the next submit after they have been kept for the time to live of the queue.
+///
+/// Example:
+/// ```
+/// use payback::graph::Graph;
+/// use payback::jobs::{JobQueue, JobState};
+/// use payback::probleminstance::{ProblemInstance, SolvingMethods};
+/// use std::time::Duration;
+///
+/// # tokio::runtime::Runtime::new().unwrap().block_on(async {
+/// let queue = JobQueue::new(2, Duration::from_secs(10));
+/// let instance = ProblemInstance::from(Graph::from(vec![-2, 2]));
+/// let id = queue.submit(instance, SolvingMethods::DPGreedySatisfaction);
+/// let state = queue.wait(id).await;
+/// assert!(matches!(state, Some(JobState::Done { approximate: false, .. })));
+/// # });
+/// ```
+#[derive(Debug)]
+pub struct JobQueue {
+    jobs: Arc<Mutex<HashMap<JobId, Job>>>,
+    workers: Arc<Semaphore>,
+    next_id: AtomicU64,
+    /// Longest time a method runs before its job falls back to its approximation.
+    timeout: Duration,
+    /// Time finished jobs are kept.
+    ttl: Duration,
+}
+
+impl Default for JobQueue {
+    /// One worker per available core, a timeout of one minute and a time to live of one hour.
+    fn default() -> Self {
+        JobQueue::new(default_workers(), Duration::from_secs(60))
+    }
+}
+
+/// One worker per available core.
+pub fn default_workers() -> usize {
+    std::thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+impl JobQueue {
+    pub fn new(workers: usize, timeout: Duration) -> Self {
+        JobQueue {
+            jobs: Arc::new(Mutex::new(HashMap::new())),
+            workers: Arc::new(Semaphore::new(workers.max(1))),
+            next_id: AtomicU64::new(1),
+            timeout,
+            ttl: Duration::from_secs(60 * 60),
+        }
+    }
+
+    /// Keeps finished jobs for `ttl` instead of one hour.
+    pub fn with_ttl(self, ttl: Duration) -> Self {
+        JobQueue { ttl, ..self }
+    }
+
+    /// Queues solving the instance with the method and returns the id of the job at once.
+    /// Has to be called within a tokio runtime.
+    pub fn submit(&self, instance: ProblemInstance, method: SolvingMethods) -> JobId {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (cancel, mut cancelled) = oneshot::channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let instance = Arc::new(instance);
+        {
+            let mut jobs = self.jobs.lock().expect("Job queue lock is poisoned.");
+            jobs.retain(|_, job| job.finished.is_none_or(|t| t.elapsed() < self.ttl));
+            jobs.insert(
+                id,
+                Job {
+                    instance: instance.clone(),
+                    state: JobState::Queued,
+                    cancel: Some(cancel),
+                    stop: stop.clone(),
+                    finished: None,
+                },
+            );
+        }
+        info!(id, method = method.name(), "Queued job");
+        let jobs = self.jobs.clone();
+        let workers = self.workers.clone();
+        let timeout = self.timeout;
+        tokio::spawn(async move {
+            let permit = tokio::select! {
+                permit = workers.acquire_owned() => permit.expect("Workers are never closed."),
+                _ = &mut cancelled => return,
+            };
+            set_state(&jobs, id, JobState::Running);
+            // The permit is released, when the solver returns, so cancelled and timed out
+            // solvers still count as busy workers.
+            let solver = tokio::task::spawn_blocking({
+                let (instance, stop) = (instance.clone(), stop.clone());
+                move || {
+                    let _permit = permit;
+                    let options = MethodOptions {
+                        cancel: Cancel::new(Some(&stop)),
+                        ..Default::default()
+                    };
+                    instance.settlement(&instance.solve_with_options(method, &(), &options))
+                }
+            });
+            let state = tokio::select! {
+                solved = tokio::time::timeout(timeout, solver) => {
+                    match solved {
+                        Ok(Ok(solution)) => JobState::Done { solution, approximate: false },
+                        Ok(Err(e)) => {
+                            JobState::Failed(AsyncSolveError::Panicked(e.to_string()).to_string())
+                        }
+                        Err(_) => {
+                            debug!(id, ?timeout, "Job timed out, approximating instead");
+                            stop.store(true, Ordering::Relaxed);
+                            let approximation = tokio::task::spawn_blocking(move || {
+                                instance.settlement(&instance.fallback_solution(method))
+                            });
+                            match approximation.await {
+                                Ok(solution) => JobState::Done { solution, approximate: true },
+                                Err(e) => JobState::Failed(
+                                    AsyncSolveError::Panicked(e.to_string()).to_string(),
+                                ),
+                            }
+                        }
+                    }
+                }
+                _ = &mut cancelled => return,
+            };
+            info!(id, "Finished job");
+            set_state(&jobs, id, state);
+        });
+        id
+    }
+
+    /// State of the job or `None`, if there is no job with the id.
+    pub fn state(&self, id: JobId) -> Option<JobState> {
+        self.jobs
+            .lock()
+            .expect("Job queue lock is poisoned.")
+            .get(&id)
+            .map(|job| job.state.to_owned())
+    }
+
+    /// Instance of the job.
+    pub fn instance(&self, id: JobId) -> Option<Arc<ProblemInstance>> {
+        self.jobs
+            .lock()
+            .expect("Job queue lock is poisoned.")
+            .get(&id)
+            .map(|job| job.instance.clone())
+    }
+
+    /// Cancels the job, if it isn't finished yet. Returns whether the job was cancelled.
+    /// A running exact method is stopped. The approximations can't be interrupted, so they finish
+    /// in the background, but their result is discarded.
+    pub fn cancel(&self, id: JobId) -> bool {
+        let mut jobs = self.jobs.lock().expect("Job queue lock is poisoned.");
+        let Some(job) = jobs.get_mut(&id) else {
+            return false;
+        };
+        if job.state.is_finished() {
+            return false;
+        }
+        if let Some(cancel) = job.cancel.take() {
+            let _ = cancel.send(());
+        }
+        job.stop.store(true, Ordering::Relaxed);
+        job.state = JobState::Cancelled;
+        job.finished = Some(Instant::now());
+        info!(id, "Cancelled job");
+        true
+    }
+
+    /// Waits until the job is finished and returns its state. Returns `None`, if there is no job
+    /// with the id.
+    pub async fn wait(&self, id: JobId) -> Option<JobState> {
+        loop {
+            match self.state(id) {
+                Some(state) if !state.is_finished() => {
+                    tokio::time::sleep(Duration::from_millis(10)).await
+                }
+                state => return state,
+            }
+        }
+    }
+}
+
+fn set_state(jobs: &Mutex<HashMap<JobId, Job>>, id: JobId, state: JobState) {
+    let mut jobs = jobs.lock().expect("Job queue lock is poisoned.");
+    if let Some(job) = jobs.get_mut(&id).filter(|job| !job.state.is_finished()) {
+        if state.is_finished() {
+            job.cancel = None;
+            job.finished = Some(Instant::now());
+        }
+        job.state = state;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::generator::{generate, GeneratorConfig};
+    use crate::graph::Graph;
+    use crate::jobs::{JobQueue, JobState};
+    use crate::probleminstance::{ProblemInstance, SolvingMethods};
+    use std::time::Duration;
+    use tracing::debug;
+    use tracing_subscriber::EnvFilter;
+
+    fn init() {
+        let _ = tracing_subscriber::fmt()
+            .with_env_filter(
+                EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("debug")),
+            )
+            .with_test_writer()
+            .try_init();
+    }
+
+    #[tokio::test]
+    async fn test_job_queue() {
+        init();
+        debug!("Running 'test_job_queue'");
+        let queue = JobQueue::new(1, Duration::from_millis(1));
+        let large = || {
+            ProblemInstance::from(generate(&GeneratorConfig {
+                nodes: 9,
+                seed: Some(1),
+                ..Default::default()
+            }))
+        };
+        let first = queue.submit(large(), SolvingMethods::PartitioningGreedySatisfaction);
+        // The second job waits for the only worker, so it is cancelled before it runs.
+        let second = queue.submit(large(), SolvingMethods::PartitioningGreedySatisfaction);
+        assert_eq!(queue.state(second), Some(JobState::Queued));
+        assert!(queue.cancel(second));
+        assert!(!queue.cancel(second));
+        assert_eq!(queue.wait(second).await, Some(JobState::Cancelled));
+
+        // The first job times out and falls back to an approximation.
+        match queue.wait(first).await {
+            Some(JobState::Done {
+                solution,
+                approximate,
+            }) => {
+                assert!(approximate);
+                assert!(solution.is_some());
+            }
+            state => panic!("Unexpected state {:?}", state),
+        }
+        assert!(!queue.cancel(first));
+        assert_eq!(queue.state(42), None);
+
+        // Finished jobs are removed on the next submit after their time to live.
+        let queue = JobQueue::new(1, Duration::from_secs(10)).with_ttl(Duration::ZERO);
+        let id = queue.submit(large(), SolvingMethods::ApproxStarExpand);
+        assert!(queue.wait(id).await.is_some());
+        let next = queue.submit(large(), SolvingMethods::ApproxStarExpand);
+        assert_eq!(queue.state(id), None);
+        assert!(queue.wait(next).await.is_some());
+
+        let queue = JobQueue::default();
+        let id = queue.submit(
+            ProblemInstance::from(Graph::from(vec![-2, 2])),
+            SolvingMethods::DPGreedySatisfaction,
+        );
+        assert!(matches!(
+            queue.wait(id).await,
+            Some(JobState::Done {
+                approximate: false,
+                ..
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_job_timeout_fallback() {
+        init();
+        debug!("Running 'test_job_timeout_fallback'");
+        let queue = JobQueue::new(1, Duration::from_millis(50));
+        let instance = || {
+            ProblemInstance::from(generate(&GeneratorConfig {
+                nodes: 14,
+                seed: Some(1),
+                ..Default::default()
+            }))
+        };
+        let method = SolvingMethods::PartitioningStarExpand;
+        let id = queue.submit(instance(), method);
+        assert!(!queue.state(id).unwrap().is_finished());
+
+        // The job is reported as done with the approximation replacing the method.
+        let state = queue.wait(id).await;
+        debug!("State: {:?}", state);
+        assert_eq!(
+            state,
+            Some(JobState::Done {
+                solution: instance().settlement(&instance().fallback_solution(method)),
+                approximate: true,
+            })
+        );
+        assert_eq!(queue.state(id), state);
+        assert!(!queue.cancel(id));
+
+        // The timed out method is stopped, so the worker takes the next job.
+        let next = queue.submit(
+            ProblemInstance::from(Graph::from(vec![-2, 2])),
+            SolvingMethods::DPGreedySatisfaction,
+        );
+        assert!(matches!(
+            tokio::time::timeout(Duration::from_secs(10), queue.wait(next)).await,
+            Ok(Some(JobState::Done {
+                approximate: false,
+                ..
+            }))
+        ));
+    }
+}
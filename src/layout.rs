@@ -0,0 +1,197 @@
+use serde_json::json;
+use std::collections::HashMap;
+use std::f64::consts::PI;
+use tracing::debug;
+
+use crate::probleminstance::ProblemInstance;
+use crate::settlement::Settlement;
+
+/// Number of iterations of the force-directed layout.
+const ITERATIONS: usize = 200;
+
+/// Position of a person in the layout.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NodePosition {
+    pub name: String,
+    pub weight: i64,
+    pub x: f64,
+    pub y: f64,
+}
+
+/// Transaction of `from` paying `amount` to `to`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LayoutEdge {
+    pub from: String,
+    pub to: String,
+    pub amount: i64,
+}
+
+/// Positions of the people in the unit square and the transactions between them, so front-ends
+/// can draw the solution without a layout engine.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Layout {
+    pub nodes: Vec<NodePosition>,
+    pub edges: Vec<LayoutEdge>,
+}
+
+impl Layout {
+    /// Json object with a list of 'nodes' with the keys 'name', 'weight', 'x' and 'y' and a list
+    /// of 'edges' with the keys 'from', 'to' and 'amount'.
+    pub fn to_json(&self) -> serde_json::Value {
+        json!({
+            "nodes": self.nodes.iter().map(|n| json!({
+                "name": n.name,
+                "weight": n.weight,
+                "x": n.x,
+                "y": n.y,
+            })).collect::<Vec<_>>(),
+            "edges": self.edges.iter().map(|e| json!({
+                "from": e.from,
+                "to": e.to,
+                "amount": e.amount,
+            })).collect::<Vec<_>>(),
+        })
+    }
+}
+
+/// Lays out the people of the instance with the force-directed algorithm of Fruchterman and
+/// Reingold, where the transactions of the solution pull people together. The layout is
+/// deterministic, so the same solution is always drawn the same.
+///
+/// Example:
+/// ```
+/// use payback::graph::Graph;
+/// use payback::layout::layout;
+/// use payback::probleminstance::{ProblemInstance, SolvingMethods};
+///
+/// let instance: ProblemInstance = Graph::from(vec![-2, -1, 1, 2]).into();
+/// let solution = instance.solve_with(SolvingMethods::DPStarExpand);
+/// let layout = layout(&instance, &solution).unwrap();
+/// assert_eq!(layout.nodes.len(), 4);
+/// assert_eq!(layout.to_json()["edges"].as_array().unwrap().len(), 2);
+/// ```
+pub fn layout(instance: &ProblemInstance, solution: &Option<Settlement>) -> Result<Layout, String> {
+    let transactions: Vec<(String, String, i64)> = solution
+        .to_owned()
+        .ok_or("No result was found.")?
+        .into_iter()
+        .map(Into::into)
+        .collect();
+    let vertices = &instance.g.vertices;
+    let n = vertices.len();
+    let index: HashMap<&str, usize> = vertices
+        .iter()
+        .enumerate()
+        .map(|(i, v)| (v.name.as_str(), i))
+        .collect();
+    let springs: Vec<(usize, usize)> = transactions
+        .iter()
+        .filter_map(|(from, to, _)| Some((*index.get(from.as_str())?, *index.get(to.as_str())?)))
+        .collect();
+
+    // Start on a circle, so the layout doesn't depend on a random number generator.
+    let mut positions: Vec<(f64, f64)> = (0..n)
+        .map(|i| {
+            let angle = 2.0 * PI * i as f64 / n as f64;
+            (0.5 + 0.4 * angle.cos(), 0.5 + 0.4 * angle.sin())
+        })
+        .collect();
+    if n > 1 {
+        // Optimal distance between people in the unit square.
+        let k = (1.0 / n as f64).sqrt();
+        let mut temperature = 0.1;
+        for _ in 0..ITERATIONS {
+            let mut displacements = vec![(0.0, 0.0); n];
+            for i in 0..n {
+                for j in (i + 1)..n {
+                    let (dx, dy, dist) = difference(positions[i], positions[j]);
+                    let force = k * k / dist;
+                    displacements[i].0 += dx / dist * force;
+                    displacements[i].1 += dy / dist * force;
+                    displacements[j].0 -= dx / dist * force;
+                    displacements[j].1 -= dy / dist * force;
+                }
+            }
+            for (u, v) in &springs {
+                let (dx, dy, dist) = difference(positions[*u], positions[*v]);
+                let force = dist * dist / k;
+                displacements[*u].0 -= dx / dist * force;
+                displacements[*u].1 -= dy / dist * force;
+                displacements[*v].0 += dx / dist * force;
+                displacements[*v].1 += dy / dist * force;
+            }
+            for (p, (dx, dy)) in positions.iter_mut().zip(displacements) {
+                let length = (dx * dx + dy * dy).sqrt().max(f64::EPSILON);
+                let step = length.min(temperature);
+                p.0 = (p.0 + dx / length * step).clamp(0.0, 1.0);
+                p.1 = (p.1 + dy / length * step).clamp(0.0, 1.0);
+            }
+            temperature *= 0.97;
+        }
+    } else if n == 1 {
+        positions[0] = (0.5, 0.5);
+    }
+    debug!("Laid out {} people and {} transactions", n, springs.len());
+    Ok(Layout {
+        nodes: vertices
+            .iter()
+            .zip(positions)
+            .map(|(v, (x, y))| NodePosition {
+                name: v.name.to_owned(),
+                weight: v.weight,
+                x,
+                y,
+            })
+            .collect(),
+        edges: transactions
+            .into_iter()
+            .map(|(from, to, amount)| LayoutEdge { from, to, amount })
+            .collect(),
+    })
+}
+
+/// Difference of the positions and their distance, which is never 0.
+fn difference(a: (f64, f64), b: (f64, f64)) -> (f64, f64, f64) {
+    let (dx, dy) = (a.0 - b.0, a.1 - b.1);
+    (dx, dy, (dx * dx + dy * dy).sqrt().max(0.001))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::graph::Graph;
+    use crate::layout::layout;
+    use crate::probleminstance::{ProblemInstance, SolvingMethods};
+    use tracing::debug;
+    use tracing_subscriber::EnvFilter;
+
+    fn init() {
+        let _ = tracing_subscriber::fmt()
+            .with_env_filter(
+                EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("debug")),
+            )
+            .with_test_writer()
+            .try_init();
+    }
+
+    #[test]
+    fn test_layout() {
+        init();
+        debug!("Running 'test_layout'");
+        let instance: ProblemInstance = Graph::from(vec![-3, -2, -1, 1, 2, 3]).into();
+        let solution = instance.solve_with(SolvingMethods::DPGreedySatisfaction);
+        let first = layout(&instance, &solution).unwrap();
+        debug!("Layout: {}", first.to_json());
+        assert_eq!(first, layout(&instance, &solution).unwrap());
+        assert!(first
+            .nodes
+            .iter()
+            .all(|n| (0.0..=1.0).contains(&n.x) && (0.0..=1.0).contains(&n.y)));
+        // People paying each other are closer than people in different partitions.
+        let distance = |a: usize, b: usize| {
+            let (a, b) = (&first.nodes[a], &first.nodes[b]);
+            ((a.x - b.x).powi(2) + (a.y - b.y).powi(2)).sqrt()
+        };
+        assert!(distance(0, 5) < distance(0, 4));
+        assert!(layout(&instance, &None).is_err());
+    }
+}
@@ -0,0 +1,411 @@
+use serde_derive::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::debug;
+
+use crate::graph::Graph;
+use crate::verification::Payment;
+
+/// Change of the balances recorded in a [`Ledger`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LedgerEvent {
+    /// `payer` paid `amount` for all `participants`, which share it equally.
+    /// If the amount can't be split equally, the first participants pay one more.
+    ExpenseAdded {
+        payer: String,
+        amount: i64,
+        participants: Vec<String>,
+    },
+    /// A single payment outside of a settlement.
+    PaymentRecorded(Payment),
+    /// All payments of a settlement plan were executed.
+    SettlementExecuted { payments: Vec<Payment> },
+    /// A settlement plan was proposed. Its payments only change the balances once they are
+    /// marked as paid. A new plan replaces all open payments of the previous one.
+    PlanProposed { payments: Vec<PlannedPayment> },
+    /// The planned payment with the id was executed.
+    TransactionPaid { id: String },
+    /// Balances replacing all previous events after a compaction with the number of plans
+    /// proposed so far, which keeps the ids of later plans unique.
+    Compacted {
+        balances: Vec<(String, i64)>,
+        #[serde(default)]
+        plans: usize,
+    },
+}
+
+/// Payment of a proposed plan with an id, which is stable across invocations.
+/// The id has the form '<plan>-<index>', where plan counts the proposed plans starting at 1.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlannedPayment {
+    pub id: String,
+    #[serde(flatten)]
+    pub payment: Payment,
+}
+
+/// Event of a [`Ledger`] with the time it was recorded in seconds since the unix epoch.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LedgerEntry {
+    pub time: u64,
+    #[serde(flatten)]
+    pub event: LedgerEvent,
+}
+
+impl From<LedgerEvent> for LedgerEntry {
+    fn from(event: LedgerEvent) -> Self {
+        LedgerEntry {
+            time: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs()),
+            event,
+        }
+    }
+}
+
+/// Append-only log of events from which the current balances are derived.
+/// The log is stored as json lines with one [`LedgerEntry`] per line.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Ledger {
+    pub entries: Vec<LedgerEntry>,
+}
+
+/// Splits the amount into `n` integer shares, which differ by at most one.
+/// The first shares are larger if the amount can't be split equally.
+pub(crate) fn split_equally(amount: i64, n: usize) -> impl Iterator<Item = i64> {
+    let n = n as i64;
+    (0..n).map(move |i| amount.div_euclid(n) + i64::from(i < amount.rem_euclid(n)))
+}
+
+/// Balances in the order in which people first appear with the state of the latest plan.
+#[derive(Default)]
+struct Balances {
+    index: HashMap<String, usize>,
+    balances: Vec<(String, i64)>,
+    plans: usize,
+    open: Vec<PlannedPayment>,
+    paid: HashSet<String>,
+}
+
+impl Balances {
+    fn add(&mut self, name: &str, amount: i64) {
+        let i = *self.index.entry(name.to_owned()).or_insert_with(|| {
+            self.balances.push((name.to_owned(), 0));
+            self.balances.len() - 1
+        });
+        self.balances[i].1 += amount;
+    }
+
+    fn apply(&mut self, event: &LedgerEvent) {
+        match event {
+            LedgerEvent::ExpenseAdded {
+                payer,
+                amount,
+                participants,
+            } => {
+                self.add(payer, *amount);
+                for (participant, share) in participants
+                    .iter()
+                    .zip(split_equally(*amount, participants.len()))
+                {
+                    self.add(participant, -share);
+                }
+            }
+            LedgerEvent::PaymentRecorded(payment) => self.pay(payment),
+            LedgerEvent::SettlementExecuted { payments } => {
+                payments.iter().for_each(|p| self.pay(p))
+            }
+            LedgerEvent::PlanProposed { payments } => {
+                self.plans += 1;
+                self.open = payments.to_owned();
+            }
+            LedgerEvent::TransactionPaid { id } => {
+                if let Some(i) = self.open.iter().position(|p| &p.id == id) {
+                    let planned = self.open.remove(i);
+                    self.pay(&planned.payment);
+                    self.paid.insert(planned.id);
+                }
+            }
+            LedgerEvent::Compacted { balances, plans } => {
+                *self = Balances {
+                    plans: *plans,
+                    ..Default::default()
+                };
+                balances.iter().for_each(|(name, w)| self.add(name, *w));
+            }
+        }
+    }
+
+    fn pay(&mut self, payment: &Payment) {
+        self.add(&payment.from, payment.amount);
+        self.add(&payment.to, -payment.amount);
+    }
+}
+
+impl Ledger {
+    /// Reads the ledger from a json lines file. A missing file is an empty ledger.
+    pub fn load(path: &Path) -> Result<Ledger, String> {
+        if !path.exists() {
+            return Ok(Ledger::default());
+        }
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("Unable to read ledger {:?}: {}", path, e))?;
+        let entries = content
+            .lines()
+            .enumerate()
+            .filter(|(_, l)| !l.trim().is_empty())
+            .map(|(i, l)| {
+                serde_json::from_str(l)
+                    .map_err(|e| format!("Invalid event in line {} of {:?}: {}", i + 1, path, e))
+            })
+            .collect::<Result<Vec<LedgerEntry>, String>>()?;
+        debug!("Loaded {} events from {:?}", entries.len(), path);
+        Ok(Ledger { entries })
+    }
+
+    /// Appends the event to the ledger and to the file at `path` without rewriting it.
+    pub fn append(&mut self, path: &Path, event: LedgerEvent) -> Result<&LedgerEntry, String> {
+        if let LedgerEvent::ExpenseAdded { participants, .. } = &event {
+            if participants.is_empty() {
+                return Err("An expense needs at least one participant.".to_string());
+            }
+        }
+        let entry = LedgerEntry::from(event);
+        let line = serde_json::to_string(&entry).map_err(|e| e.to_string())?;
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .and_then(|mut f| writeln!(f, "{}", line))
+            .map_err(|e| format!("Unable to write ledger {:?}: {}", path, e))?;
+        self.entries.push(entry);
+        Ok(self.entries.last().expect("Entry was just pushed."))
+    }
+
+    fn replay_state(&self, n: usize) -> Balances {
+        let mut balances = Balances::default();
+        self.entries
+            .iter()
+            .take(n)
+            .for_each(|e| balances.apply(&e.event));
+        balances
+    }
+
+    /// Derives the balances after the first `n` events.
+    pub fn replay(&self, n: usize) -> Graph {
+        Graph::from(self.replay_state(n).balances)
+    }
+
+    /// Payments of the latest proposed plan, which aren't marked as paid yet.
+    pub fn open_payments(&self) -> Vec<PlannedPayment> {
+        self.replay_state(self.entries.len()).open
+    }
+
+    /// Proposes a new settlement plan and assigns an id to every payment. The plan replaces the
+    /// open payments of the previous plan, so re-planning only covers the remaining balances.
+    pub fn propose_plan(
+        &mut self,
+        path: &Path,
+        payments: Vec<Payment>,
+    ) -> Result<Vec<PlannedPayment>, String> {
+        let plan = self.replay_state(self.entries.len()).plans + 1;
+        let payments: Vec<PlannedPayment> = payments
+            .into_iter()
+            .enumerate()
+            .map(|(i, payment)| PlannedPayment {
+                id: format!("{}-{}", plan, i + 1),
+                payment,
+            })
+            .collect();
+        self.append(
+            path,
+            LedgerEvent::PlanProposed {
+                payments: payments.to_owned(),
+            },
+        )?;
+        Ok(payments)
+    }
+
+    /// Marks the open payment with the id as paid, which settles its amount.
+    pub fn mark_paid(&mut self, path: &Path, id: &str) -> Result<PlannedPayment, String> {
+        let state = self.replay_state(self.entries.len());
+        let planned = match state.open.into_iter().find(|p| p.id == id) {
+            Some(p) => p,
+            None if state.paid.contains(id) => {
+                return Err(format!("Payment '{}' is already marked as paid.", id))
+            }
+            None => return Err(format!("There is no open payment with id '{}'.", id)),
+        };
+        self.append(path, LedgerEvent::TransactionPaid { id: id.to_owned() })?;
+        Ok(planned)
+    }
+
+    /// Derives the current balances from all events.
+    pub fn balances(&self) -> Graph {
+        self.replay(self.entries.len())
+    }
+
+    /// Replaces all events with a single [`LedgerEvent::Compacted`] event holding the current
+    /// balances. People with a settled balance and open payments of a plan are dropped.
+    pub fn compact(&self) -> Ledger {
+        let state = self.replay_state(self.entries.len());
+        let balances = state
+            .balances
+            .into_iter()
+            .filter(|(_, w)| *w != 0)
+            .collect();
+        Ledger {
+            entries: vec![LedgerEntry::from(LedgerEvent::Compacted {
+                balances,
+                plans: state.plans,
+            })],
+        }
+    }
+
+    /// Overwrites the file at `path` with the ledger. Only needed after a compaction.
+    pub fn write(&self, path: &Path) -> Result<(), String> {
+        let mut content = String::new();
+        for entry in &self.entries {
+            content += &serde_json::to_string(entry).map_err(|e| e.to_string())?;
+            content += "\n";
+        }
+        std::fs::write(path, content)
+            .map_err(|e| format!("Unable to write ledger {:?}: {}", path, e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ledger::{Ledger, LedgerEvent};
+    use crate::verification::Payment;
+    use tracing::debug;
+    use tracing_subscriber::EnvFilter;
+
+    fn init() {
+        let _ = tracing_subscriber::fmt()
+            .with_env_filter(
+                EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("debug")),
+            )
+            .with_test_writer()
+            .try_init();
+    }
+
+    #[test]
+    fn test_ledger() {
+        init();
+        debug!("Running 'test_ledger'");
+        let path =
+            std::env::temp_dir().join(format!("payback_ledger_{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut ledger = Ledger::load(&path).unwrap();
+        ledger
+            .append(
+                &path,
+                LedgerEvent::ExpenseAdded {
+                    payer: "A".to_string(),
+                    amount: 10,
+                    participants: vec!["A".to_string(), "B".to_string(), "C".to_string()],
+                },
+            )
+            .unwrap();
+        ledger
+            .append(
+                &path,
+                LedgerEvent::PaymentRecorded(Payment {
+                    from: "B".to_string(),
+                    to: "A".to_string(),
+                    amount: 3,
+                }),
+            )
+            .unwrap();
+        assert!(ledger
+            .append(
+                &path,
+                LedgerEvent::ExpenseAdded {
+                    payer: "A".to_string(),
+                    amount: 1,
+                    participants: vec![],
+                },
+            )
+            .is_err());
+
+        let ledger = Ledger::load(&path).unwrap();
+        assert_eq!(ledger.entries.len(), 2);
+        assert_eq!(
+            ledger.replay(1).balances(),
+            vec![
+                ("A".to_string(), 6),
+                ("B".to_string(), -3),
+                ("C".to_string(), -3)
+            ]
+        );
+        let balances = vec![
+            ("A".to_string(), 3),
+            ("B".to_string(), 0),
+            ("C".to_string(), -3),
+        ];
+        assert_eq!(ledger.balances().balances(), balances);
+
+        let compacted = ledger.compact();
+        compacted.write(&path).unwrap();
+        let compacted = Ledger::load(&path).unwrap();
+        assert_eq!(compacted.entries.len(), 1);
+        assert_eq!(
+            compacted.balances().balances(),
+            vec![("A".to_string(), 3), ("C".to_string(), -3)]
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_ledger_plan() {
+        init();
+        debug!("Running 'test_ledger_plan'");
+        let path = std::env::temp_dir().join(format!("payback_plan_{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut ledger = Ledger::default();
+        let payment = |from: &str, to: &str, amount: i64| Payment {
+            from: from.to_string(),
+            to: to.to_string(),
+            amount,
+        };
+        ledger
+            .append(
+                &path,
+                LedgerEvent::Compacted {
+                    balances: vec![
+                        ("A".to_string(), 3),
+                        ("B".to_string(), -1),
+                        ("C".to_string(), -2),
+                    ],
+                    plans: 0,
+                },
+            )
+            .unwrap();
+        let plan = ledger
+            .propose_plan(&path, vec![payment("B", "A", 1), payment("C", "A", 2)])
+            .unwrap();
+        assert_eq!(plan[0].id, "1-1");
+        assert_eq!(plan[1].id, "1-2");
+        assert_eq!(ledger.balances().balances()[0], ("A".to_string(), 3));
+
+        let mut ledger = Ledger::load(&path).unwrap();
+        assert_eq!(ledger.mark_paid(&path, "1-2").unwrap(), plan[1]);
+        assert!(ledger.mark_paid(&path, "1-2").is_err());
+        assert!(ledger.mark_paid(&path, "2-1").is_err());
+        assert_eq!(ledger.open_payments(), vec![plan[0].to_owned()]);
+        assert_eq!(ledger.balances().balances()[0], ("A".to_string(), 1));
+
+        // Compaction keeps later ids unique.
+        let mut ledger = ledger.compact();
+        assert!(ledger.open_payments().is_empty());
+        let plan = ledger
+            .propose_plan(&path, vec![payment("B", "A", 1)])
+            .unwrap();
+        assert_eq!(plan[0].id, "2-1");
+        std::fs::remove_file(&path).unwrap();
+    }
+}
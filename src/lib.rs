@@ -1,7 +1,85 @@
+//! Resolves debt networks with as few transactions as possible.
+//!
+//! The types to embed the solvers are exported at the root of the crate: build a [`Graph`] of
+//! people with the amounts they have to pay or receive, turn it into a [`ProblemInstance`] and
+//! solve it with one of the [`SolvingMethods`].
+//!
+//! Example:
+//! ```
+//! use payback::{Graph, ProblemInstance, Settlement, SolvingMethods, Transaction};
+//!
+//! let instance = ProblemInstance::from(Graph::from(vec![
+//!     ("Anna".to_string(), -3),
+//!     ("Ben".to_string(), 3),
+//! ]));
+//! let settlement: Settlement = instance
+//!     .solve_named(SolvingMethods::DPGreedySatisfaction)
+//!     .unwrap();
+//! let anna_to_ben = Transaction {
+//!     payer: "Anna".to_string(),
+//!     payee: "Ben".to_string(),
+//!     amount: 3,
+//! };
+//! assert_eq!(settlement.transactions(), [anna_to_ben]);
+//! ```
+pub mod anonymize;
 mod approximation;
+#[cfg(feature = "tokio")]
+pub mod asynchronous;
+pub mod audit;
+pub mod baseline;
+pub mod batch;
+mod bucketing;
+pub mod cache;
+pub mod canonical;
+pub mod comparison;
+pub mod compression;
+pub mod constraints;
+pub mod corpus;
+pub mod currency;
+pub mod dates;
+pub mod decimals;
+pub mod diff;
 mod dynamic_program;
+pub mod events;
 mod exact_partitioning;
+pub mod generator;
+mod genetic;
 pub mod graph;
-mod graph_parser;
+pub mod graph_parser;
+pub mod graphml;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+mod hierarchical;
+pub mod i18n;
+pub mod interest;
+#[cfg(feature = "tokio")]
+pub mod jobs;
+pub mod layout;
+pub mod ledger;
+pub mod metadata;
+pub mod payments;
+pub mod pipeline;
 pub mod probleminstance;
+pub mod provenance;
+#[cfg(feature = "qr")]
+pub mod qr;
+pub mod recommend;
+pub mod recurring;
+pub mod rounds;
+pub mod session;
+pub mod settlement;
+mod single_payment;
+pub mod solver_config;
+pub mod streaming;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+pub mod trace;
+pub mod transfer_costs;
 mod tree_bases;
+pub mod units;
+pub mod verification;
+
+pub use graph::{Debt, Edge, Graph, Metadata, NamedNode};
+pub use probleminstance::{NamedSolution, ProblemInstance, SolvingMethods};
+pub use settlement::{Settlement, Transaction};
@@ -1,46 +1,954 @@
-use clap::{Parser, ValueEnum};
-use clap_stdin::FileOrStdin;
-use env_logger::Env;
-use graph::Graph;
-use probleminstance::{ProblemInstance, SolvingMethods};
+use chrono::NaiveDate;
+use clap::{
+    builder::PossibleValue, Args as ClapArgs, CommandFactory, FromArgMatches, Parser, Subcommand,
+    ValueEnum,
+};
+use clap_complete::Shell;
+use constraints::Constraints;
+use corpus::Corpus;
+use currency::Rates;
+use dates::Period;
+use generator::{GeneratorConfig, WeightDistribution};
+use graph_parser::{InstanceFormat, ValidationIssue};
+use i18n::Lang;
+#[cfg(feature = "qr")]
+use i18n::Text;
+use pipeline::{Postprocessing, Preprocessing};
+use probleminstance::SolvingMethods;
+#[cfg(feature = "qr")]
+use probleminstance::{EdgeSolution, ProblemInstance};
+use solver_config::Objective;
+use std::path::PathBuf;
+use std::sync::{LazyLock, Mutex};
+use std::time::Duration;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::EnvFilter;
+use transfer_costs::TransferCosts;
+use units::Scale;
 
+pub mod anonymize;
 pub mod approximation;
+#[cfg(feature = "tokio")]
+pub mod asynchronous;
+pub mod audit;
+pub mod baseline;
+pub mod batch;
+pub mod bucketing;
+pub mod cache;
+pub mod canonical;
+mod cli;
+pub mod comparison;
+pub mod compression;
+pub mod config;
+pub mod constraints;
+pub mod corpus;
+pub mod currency;
+pub mod dates;
+pub mod decimals;
+pub mod diff;
 pub mod dynamic_program;
+pub mod events;
 pub mod exact_partitioning;
+pub mod generator;
+pub mod genetic;
 pub mod graph;
 pub mod graph_parser;
+pub mod graphml;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod hierarchical;
+pub mod i18n;
+pub mod interest;
+#[cfg(feature = "tokio")]
+pub mod jobs;
+pub mod layout;
+pub mod ledger;
+pub mod metadata;
+pub mod payments;
+pub mod pipeline;
 pub mod probleminstance;
+pub mod provenance;
+#[cfg(feature = "qr")]
+pub mod qr;
+pub mod recommend;
+pub mod recurring;
+pub mod rounds;
+pub mod session;
+pub mod settlement;
+pub mod single_payment;
+pub mod solver_config;
+pub mod streaming;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+pub mod trace;
+pub mod transfer_costs;
 pub mod tree_bases;
+pub mod units;
+pub mod verification;
+#[cfg(feature = "webhook")]
+pub mod webhook;
 
 /// Calculate to resolve debt networks with as few transactions as possible.
 ///
 /// If you have a network of people, which own each other money, paying off debts can lead to many transactions.
 /// With this crate the amount of transactions can be minimized.
 #[derive(Parser, Debug)]
-#[command(author, version, about, long_about)]
+#[command(
+    author,
+    version,
+    about,
+    long_about,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Commands>,
+
     /// Path to the input file. Use '-' instead to use the stdin.
     /// The data must be in a csv format.
     /// Either specify edge with 'NodeNameFrom,NodeNameTo,weight' or nodes with 'NodeName,weight'.
-    /// You can't mix these to formats.
-    #[arg(group = "input")]
-    file: FileOrStdin,
+    /// You can't mix these to formats. Amounts of the csv formats can have decimal places like
+    /// '12.34'.
+    #[arg(group = "input", required = true)]
+    file: Option<Input>,
+
+    /// Format of the input file. If not given, it is detected automatically. Only the csv
+    /// formats can have dates, currencies and metadata columns.
+    #[arg(long, value_enum, value_name = "FORMAT", env = "PAYBACK_INPUT_FORMAT")]
+    input_format: Option<InstanceFormat>,
 
     /// Turns on verbose output.
-    #[arg(short = 'v', long)]
+    #[arg(short = 'v', long, global = true, env = "PAYBACK_VERBOSE")]
     verbose: bool,
 
     /// Turn on debug output.
-    #[arg(short = 'd', long)]
+    #[arg(short = 'd', long, global = true, env = "PAYBACK_DEBUG")]
     debug: bool,
 
+    /// Write the log to this file instead of stderr, so it doesn't mix with the output. Logs at
+    /// the level of '--verbose' if neither '--verbose' nor '--debug' is given. The log of
+    /// 'serve' is rotated daily by appending the date to the file name.
+    #[arg(long, global = true, env = "PAYBACK_LOG_FILE")]
+    log_file: Option<PathBuf>,
+
+    /// Path to a toml config file with defaults for options not given on the command line.
+    /// Defaults to 'payback/config.toml' in the config directory of the user.
+    #[arg(long, global = true, env = "PAYBACK_CONFIG")]
+    config: Option<PathBuf>,
+
+    /// Print nothing but the result. Errors are only reported by the exit code.
+    #[arg(
+        short = 'q',
+        long,
+        global = true,
+        conflicts_with_all = ["verbose", "debug"],
+        env = "PAYBACK_QUIET"
+    )]
+    quiet: bool,
+
+    /// Print errors as json object to stderr instead of text to stdout,
+    /// e.g. '{"error": {"kind": "unbalanced", "imbalance": 3, "message": "..."}}'.
+    #[arg(long, global = true, env = "PAYBACK_JSON_ERRORS")]
+    json_errors: bool,
+
+    /// Only parse and validate the input without solving it.
+    /// Exits with 0 if the instance is valid, 1 if it can't be parsed and 2 if it is unbalanced.
+    #[arg(long)]
+    check: bool,
+
     /// Specify the format of the output to stdout.
-    #[arg(value_enum, default_value_t = OutputFormat::Transactions)]
+    #[arg(value_enum, default_value_t = OutputFormat::Transactions, env = "PAYBACK_OUTPUT")]
     output: OutputFormat,
 
     /// Tell payback with solving method should be used.
-    #[arg(value_enum, default_value_t = SolvingMethods::ApproxStarExpand)]
+    #[arg(
+        value_enum,
+        default_value_t = MethodChoice::Method(SolvingMethods::ApproxStarExpand),
+        env = "PAYBACK_METHOD"
+    )]
+    method: MethodChoice,
+
+    /// Only use records dated on or after this day ('YYYY-MM-DD'). The date is given as
+    /// additional last column of every record.
+    #[arg(long, value_parser = dates::parse_date)]
+    from: Option<NaiveDate>,
+
+    /// Only use records dated on or before this day ('YYYY-MM-DD').
+    #[arg(long, value_parser = dates::parse_date)]
+    to: Option<NaiveDate>,
+
+    /// Solve the records of every period on their own and print a plan per period with a
+    /// cumulative summary. Every record needs a date.
+    #[arg(long, value_enum)]
+    group_by: Option<Period>,
+
+    /// Render the original debts as dashed gray edges beneath the transactions of the dot
+    /// output. Only instances in the csv edge format have debts.
+    #[arg(long)]
+    show_debts: bool,
+
+    /// Multiply all amounts by this factor, e.g. 100 to convert euros to cents or 0.01 to
+    /// convert cents to euros. Fails if an amount doesn't stay a whole number.
+    #[arg(long, value_name = "FACTOR", env = "PAYBACK_SCALE")]
+    scale: Option<Scale>,
+
+    /// Unit of the amounts, e.g. 'hours' or 'points', which is appended to every amount instead
+    /// of a currency. People's metadata 'unit=<LABEL>' gives it too, but all units must match.
+    #[arg(long, value_name = "LABEL", env = "PAYBACK_UNIT")]
+    unit: Option<String>,
+
+    /// Most decimal places of the amounts of the input. The amounts are solved in minor units,
+    /// e.g. cents for 2 places.
+    #[arg(skip)]
+    decimals: u32,
+
+    /// Only settle between these people. Can be given multiple times. Debts with other people
+    /// are left out.
+    #[arg(long, value_name = "NAME")]
+    include: Vec<String>,
+
+    /// Settle without these people. Can be given multiple times. Debts with them are left out.
+    #[arg(long, value_name = "NAME")]
+    exclude: Vec<String>,
+
+    /// Reject debts with a negative amount instead of reading 'A,B,-5' as B owing A 5.
+    #[arg(long, env = "PAYBACK_STRICT")]
+    strict: bool,
+
+    /// Solve again instead of using the plan stored for an unchanged instance and options in
+    /// the cache directory, e.g. '~/.cache/payback'.
+    #[arg(long, env = "PAYBACK_NO_CACHE")]
+    no_cache: bool,
+
+    /// Print statistics of the solver to stderr after solving, like the number of incumbents
+    /// and the approximate memory of its largest data structure next to the estimate for the
+    /// instance. Implies --no-cache.
+    #[arg(long, env = "PAYBACK_STATS")]
+    stats: bool,
+
+    /// Only print the lower and upper bound on the number of transactions, which take
+    /// milliseconds to compute, instead of solving. If the bounds are equal, an approximation is
+    /// optimal and an exact method isn't worth it.
+    #[arg(long, conflicts_with_all = ["trace", "stats"])]
+    bound_only: bool,
+
+    /// Write the search of the exact method to this file: the table of the dynamic programs, the
+    /// tree of the branching methods or the partitionings tried. Written as dot for the
+    /// extensions '.dot' and '.gv' and as json otherwise. Solves the instance a second time.
+    #[arg(long, value_name = "PATH")]
+    trace: Option<PathBuf>,
+
+    #[command(flatten)]
+    solver: SolverArgs,
+
+    /// Spread the transactions over this many rounds, e.g. paydays, and print a schedule per
+    /// round.
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+    rounds: Option<u64>,
+
+    /// Largest amount a person can pay per round. A person's metadata 'cap=<AMOUNT>' replaces
+    /// it. Requires --rounds.
+    #[arg(long, value_name = "AMOUNT", requires = "rounds", value_parser = clap::value_parser!(i64).range(1..))]
+    round_cap: Option<i64>,
+
+    /// Abort solving after this many seconds with exit code 3.
+    #[arg(long, value_name = "SECONDS", value_parser = parse_timeout)]
+    timeout: Option<Duration>,
+
+    /// Path to a csv file with monthly recurring expenses 'payer,amount,day,participant,...'.
+    /// They are added as dated records between --from and --to or, if not given, between the
+    /// first and last dated record.
+    #[arg(long)]
+    recurring: Option<PathBuf>,
+
+    /// Accrue simple interest in percent per year on every dated debt until the settlement
+    /// date. Defaults to 'interest' of the config file.
+    #[arg(long, value_name = "PERCENT")]
+    interest: Option<f64>,
+
+    /// Add a fee to every dated debt, which is older than the grace period at the settlement
+    /// date. Defaults to 'late_fee' of the config file.
+    #[arg(long, value_name = "AMOUNT", value_parser = clap::value_parser!(i64).range(0..))]
+    late_fee: Option<i64>,
+
+    /// Days after the date of a debt, in which neither interest nor a late fee accrues.
+    /// Defaults to 'grace_days' of the config file or 0.
+    #[arg(long, value_name = "DAYS", value_parser = clap::value_parser!(i64).range(0..))]
+    grace_days: Option<i64>,
+
+    /// End the transactions output with the amounts every person gained or lost by splitting
+    /// recurring expenses or converting currencies into whole amounts and their total, which is
+    /// zero. Scaling never rounds, since it fails for amounts, which don't stay whole.
+    #[arg(long, env = "PAYBACK_AUDIT_ROUNDING")]
+    audit_rounding: bool,
+
+    /// Write the rounding audit as json to this file, e.g. to keep it with the books. It is
+    /// written even without --audit-rounding.
+    #[arg(long, value_name = "PATH")]
+    audit_file: Option<PathBuf>,
+
+    /// Day ('YYYY-MM-DD'), until which interest and late fees accrue. Defaults to --to or the
+    /// date of the latest record.
+    #[arg(long, value_parser = dates::parse_date)]
+    settle_on: Option<NaiveDate>,
+
+    /// Template for every transaction of the transactions output, e.g.
+    /// '{from} -> {to}, {amount}, IBAN {to.iban}'. Metadata of people is given as trailing
+    /// 'key=value' columns of their records and inserted with '{from.key}' and '{to.key}'.
+    #[arg(long, env = "PAYBACK_TEMPLATE")]
+    template: Option<String>,
+
+    /// Start the output with a comment line naming the version of payback, the method, the
+    /// objective and a hash of the input, so the plan can be traced back to how it was produced.
+    /// The json posted by '--post-result' becomes an object with the keys 'provenance' and
+    /// 'transactions'.
+    #[arg(long, env = "PAYBACK_PROVENANCE")]
+    provenance: bool,
+
+    #[command(flatten)]
+    currency: CurrencyArgs,
+
+    /// Language of the human-readable output. Detected from the locale if not given.
+    #[arg(long, value_enum, global = true, env = "PAYBACK_LANG")]
+    lang: Option<Lang>,
+
+    #[cfg(feature = "qr")]
+    #[command(flatten)]
+    qr: QrArgs,
+
+    #[cfg(feature = "webhook")]
+    #[command(flatten)]
+    webhook: WebhookArgs,
+}
+
+impl Args {
+    fn lang(&self) -> Lang {
+        self.lang.unwrap_or_else(Lang::detect)
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum Commands {
+    /// Generate a random balanced instance and print it in the csv node format to stdout.
+    Generate(GenerateArgs),
+    /// Run several solving methods on the same instance and compare their runtime and results.
+    Bench(BenchArgs),
+    /// Solve every instance of a directory or glob on its own and write a json plan per
+    /// instance and a summary of all instances to the output directory.
+    Batch(Box<BatchArgs>),
+    /// Convert an instance from one format into another without solving it.
+    Convert(ConvertArgs),
+    /// Check if a proposed plan of payments settles all balances of an instance.
+    Verify(VerifyArgs),
+    /// Replace all names by stable pseudonyms and optionally perturb the weights, so the
+    /// instance can be shared in bug reports. Prints the instance in the csv node format.
+    Anonymize(AnonymizeArgs),
+    /// Compare two instances and print the balance changes and new, removed or changed debts.
+    Diff {
+        /// Path to the old instance in one of the csv formats.
+        old: Input,
+        /// Path to the new instance in one of the csv formats.
+        new: Input,
+    },
+    /// Record expenses and payments in an append-only ledger and settle its balances.
+    Ledger(LedgerArgs),
+    /// Settle a huge instance in the csv node format in a single pass with bounded memory and
+    /// print every transaction as soon as it is known. Uses at most n - 1 transactions.
+    Stream {
+        /// Path to the input file. Use '-' instead to use the stdin.
+        input: PathBuf,
+        /// Maximal number of balances kept open at the same time.
+        #[arg(long, default_value_t = 1024)]
+        capacity: usize,
+    },
+    /// List all available solving methods with their runtime.
+    Methods,
+    /// Print a completion script for the given shell to stdout.
+    Completions {
+        /// Shell for which the completions are generated.
+        #[arg(value_enum)]
+        shell: Shell,
+    },
+    /// Serve solving, verification and statistics over gRPC until the process is stopped.
+    #[cfg(feature = "grpc")]
+    Serve {
+        /// Address the server listens on.
+        #[arg(long, default_value = "127.0.0.1:50051", env = "PAYBACK_ADDR")]
+        addr: std::net::SocketAddr,
+
+        /// Number of jobs solved at once. Defaults to the number of cores.
+        #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+        workers: Option<u64>,
+
+        /// Solve jobs, whose method takes longer than this many seconds, with the approximation
+        /// of the method instead.
+        #[arg(long, value_name = "SECONDS", default_value = "60", value_parser = parse_timeout)]
+        job_timeout: Duration,
+    },
+}
+
+#[derive(ClapArgs, Debug)]
+struct GenerateArgs {
+    /// Number of people in the generated instance.
+    #[arg(short = 'n', long, default_value_t = 10)]
+    nodes: usize,
+
+    /// Number of planted groups, whose debts add up to zero on their own.
+    #[arg(short = 'g', long, default_value_t = 1)]
+    groups: usize,
+
+    /// Maximal absolute weight of a person.
+    #[arg(short = 'w', long, default_value_t = 100)]
+    max_weight: i64,
+
+    /// Distribution of the absolute weights.
+    #[arg(long, value_enum, default_value_t = WeightDistribution::Uniform)]
+    distribution: WeightDistribution,
+
+    /// Seed for the random number generator to make the output reproducible.
+    #[arg(short = 's', long, env = "PAYBACK_SEED")]
+    seed: Option<u64>,
+}
+
+impl From<&GenerateArgs> for GeneratorConfig {
+    fn from(args: &GenerateArgs) -> Self {
+        GeneratorConfig {
+            nodes: args.nodes,
+            groups: args.groups,
+            max_weight: args.max_weight,
+            distribution: args.distribution,
+            seed: args.seed,
+        }
+    }
+}
+
+#[derive(ClapArgs, Debug)]
+struct BenchArgs {
+    /// Path to the input file. Use '-' instead to use the stdin.
+    /// Without a file, a random instance is generated with the generator options.
+    file: Option<Input>,
+
+    /// Run the benchmark on every instance of a bundled corpus instead.
+    #[arg(long, value_enum, conflicts_with = "file")]
+    corpus: Option<Corpus>,
+
+    /// Comma separated list of solving methods to compare.
+    /// Defaults to all methods, which are feasible for the size of the instance.
+    #[arg(short = 'm', long, value_enum, value_delimiter = ',')]
+    methods: Vec<SolvingMethods>,
+
+    /// Stop waiting for a method after this many seconds and report it as timed out.
+    #[arg(long, value_name = "SECONDS", value_parser = parse_timeout)]
+    timeout: Option<Duration>,
+
+    /// Don't add the results to the bench history, from which the method 'recommend' predicts
+    /// the best method.
+    #[arg(long)]
+    no_history: bool,
+
+    /// Compare the results with the baseline in this json file and fail on regressions. If the
+    /// file doesn't exist yet, the results are written to it as the baseline.
+    #[arg(long, value_name = "PATH")]
+    baseline: Option<PathBuf>,
+
+    /// Overwrite the baseline with the results of this run after comparing them.
+    #[arg(long, requires = "baseline")]
+    update_baseline: bool,
+
+    /// Relative slowdown compared to the baseline, from which a method is a regression, e.g.
+    /// 0.2 for 20%. Any additional transaction is always a regression.
+    #[arg(
+        long,
+        value_name = "FRACTION",
+        default_value_t = 0.2,
+        requires = "baseline"
+    )]
+    regression_threshold: f64,
+
+    #[command(flatten)]
+    generate: GenerateArgs,
+}
+
+#[derive(ClapArgs, Debug)]
+struct BatchArgs {
+    /// Directory with the instances as '.csv' files or a glob like 'groups/*.csv'.
+    input: PathBuf,
+
+    /// Directory to which '<name>.json' is written for every instance and 'summary.json' for
+    /// the batch. It is created if it doesn't exist.
+    #[arg(short = 'o', long, default_value = "results")]
+    out_dir: PathBuf,
+
+    /// Solving method used for every instance.
+    #[arg(short = 'm', long, value_enum, default_value_t = SolvingMethods::ApproxStarExpand)]
     method: SolvingMethods,
+
+    /// Abort solving an instance after this many seconds. The instance is then reported as
+    /// failed, while the batch goes on, unless --fallback is given.
+    #[arg(long, value_name = "SECONDS", value_parser = parse_timeout)]
+    timeout: Option<Duration>,
+
+    /// Number of instances solved at the same time. Defaults to the number of cpus.
+    #[arg(short = 'j', long, env = "PAYBACK_JOBS")]
+    jobs: Option<usize>,
+    #[command(flatten)]
+    solver: SolverArgs,
+}
+
+#[derive(ClapArgs, Debug)]
+struct ConvertArgs {
+    /// Format of the input. If not given, it is detected automatically.
+    #[arg(short = 'f', long, value_enum)]
+    from: Option<InstanceFormat>,
+
+    /// Format of the output.
+    #[arg(short = 't', long, value_enum)]
+    to: InstanceFormat,
+
+    /// Path to the input file. Use '-' instead to use the stdin.
+    input: Input,
+
+    /// Path to the output file. If not given, the output is written to stdout.
+    output: Option<std::path::PathBuf>,
+}
+
+#[derive(ClapArgs, Debug)]
+struct AnonymizeArgs {
+    /// Path to the instance in one of the csv formats. Use '-' instead to use the stdin.
+    input: Input,
+
+    /// Salt mixed into the pseudonyms, so they can't be guessed from common names.
+    #[arg(long, default_value = "")]
+    salt: String,
+
+    /// Change every weight by up to twice this amount, while keeping the instance solvable.
+    #[arg(short = 'p', long)]
+    perturb: Option<i64>,
+
+    /// Seed for perturbing the weights to make the output reproducible. Ignored without
+    /// --perturb, so a PAYBACK_SEED exported for the solver doesn't break anonymizing.
+    #[arg(short = 's', long, env = "PAYBACK_SEED")]
+    seed: Option<u64>,
+
+    /// Write every name with its pseudonym as csv into this file to map the results back.
+    #[arg(long)]
+    mapping: Option<PathBuf>,
+}
+
+#[derive(ClapArgs, Debug)]
+struct VerifyArgs {
+    /// Path to the instance in one of the csv formats. Use '-' instead to use the stdin.
+    instance: Input,
+
+    /// Path to the plan. Either csv with 'NodeNameFrom,NodeNameTo,amount' records or a json list
+    /// of objects with the keys 'from', 'to' and 'amount'.
+    plan: Input,
+}
+
+#[derive(ClapArgs, Debug)]
+struct SolverArgs {
+    /// Granularity to which 'approx-bucketed-dp' rounds the weights. Rounding makes the
+    /// solver faster, but can lead to more transactions. Defaults to 1.
+    #[arg(long, value_name = "SIZE", value_parser = clap::value_parser!(i64).range(1..))]
+    bucket_size: Option<i64>,
+
+    /// Metadata key, by which 'approx-hierarchical' clusters the people, like 'team' for the
+    /// column 'team=berlin'. People without the key are clustered by their weights.
+    #[arg(long, value_name = "KEY")]
+    cluster_by: Option<String>,
+
+    /// Seed of 'approx-genetic' to make the output reproducible. Without a seed, a random one
+    /// is chosen.
+    #[arg(long, env = "PAYBACK_SEED")]
+    seed: Option<u64>,
+
+    /// Seconds 'approx-genetic' spends improving the plan. Without a budget, it evolves a fixed
+    /// number of generations.
+    #[arg(long, value_name = "SECONDS", value_parser = parse_timeout)]
+    time_budget: Option<Duration>,
+
+    /// Rearrange the plan, so every debtor makes exactly one payment. Keeps the number of
+    /// transactions, but can move more money.
+    #[arg(long, env = "PAYBACK_SINGLE_PAYMENT")]
+    single_payment: bool,
+
+    /// Path to a toml file with constraints on the plan: forbidden pairs, caps on the number
+    /// of transactions of people and on amounts, fixed payments, a hub and groups settling
+    /// among themselves.
+    #[arg(long, value_name = "PATH")]
+    constraints: Option<PathBuf>,
+
+    /// Largest number of transactions any person pays or receives, e.g. for people, who don't
+    /// want to make more than two transfers. Takes precedence over 'max_transactions_per_person'
+    /// of the constraints file.
+    #[arg(
+        long,
+        value_name = "COUNT",
+        env = "PAYBACK_MAX_TRANSACTIONS_PER_PERSON"
+    )]
+    max_transactions_per_person: Option<usize>,
+
+    /// Pair of people 'A,B', who can't pay each other in either direction, e.g. because they
+    /// share no payment channel. Can be given multiple times and adds to the forbidden pairs of
+    /// the constraints file.
+    #[arg(long, value_name = "A,B", value_parser = parse_pair)]
+    forbid: Vec<(String, String)>,
+
+    /// Pair of people 'A,B', who would rather pay each other, e.g. roommates sharing a payment
+    /// app. Among plans with the fewest transactions, the one with more payments between
+    /// preferred pairs wins. Can be given multiple times.
+    #[arg(long, value_name = "A,B", value_parser = parse_pair)]
+    prefer: Vec<(String, String)>,
+
+    /// Largest amount of a single transfer, e.g. because of limits of banks or apps. Larger
+    /// transactions are split into several transfers between the same people. Takes precedence
+    /// over 'max_amount' of the constraints file.
+    #[arg(
+        long,
+        value_name = "AMOUNT",
+        value_parser = clap::value_parser!(i64).range(1..),
+        env = "PAYBACK_MAX_AMOUNT"
+    )]
+    max_amount: Option<i64>,
+
+    /// Path to a csv matrix of the costs of transactions between people for the
+    /// 'min-transfer-cost' objective. The first row holds the payees and the first column the
+    /// payers. Missing pairs cost 1.
+    #[arg(long, value_name = "PATH")]
+    transfer_costs: Option<PathBuf>,
+
+    /// Steps simplifying the instance before solving, applied in the given order.
+    #[arg(long, value_enum, value_name = "STEP", value_delimiter = ',')]
+    preprocess: Vec<Preprocessing>,
+
+    /// Steps improving the plan after solving, applied in the given order.
+    #[arg(long, value_enum, value_name = "STEP", value_delimiter = ',')]
+    postprocess: Vec<Postprocessing>,
+
+    /// Objective optimized by the solver. 'weighted-sum' minimizes
+    /// 'alpha * transactions + beta * total amount'. 'min-transactions-then-amount' minimizes the
+    /// total amount among the plans with the fewest transactions.
+    #[arg(long, value_enum, default_value_t = Objective::MinTransactions)]
+    objective: Objective,
+
+    /// Cost of a transaction in the 'weighted-sum' objective.
+    #[arg(long, default_value_t = 1.0, value_parser = parse_objective_weight)]
+    alpha: f64,
+
+    /// Cost of moving one unit of money in the 'weighted-sum' objective.
+    #[arg(long, default_value_t = 0.0, value_parser = parse_objective_weight)]
+    beta: f64,
+
+    /// Use the approximation of the method instead of failing, if solving takes longer than
+    /// --timeout.
+    #[arg(long, requires = "timeout")]
+    fallback: bool,
+
+    /// Number of threads, on which the branching methods explore their branches. Defaults to
+    /// the number of cpus.
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+    threads: Option<u64>,
+}
+
+#[derive(ClapArgs, Debug)]
+struct CurrencyArgs {
+    /// Currency into which all amounts are converted before solving, if the records have a
+    /// currency as last column, e.g. 'NodeName,weight,currency'.
+    #[arg(long, env = "PAYBACK_BASE_CURRENCY")]
+    base_currency: Option<String>,
+
+    /// Path to a csv file with exchange rates 'currency,rate', where rate is the value of one unit
+    /// of the currency in the base currency.
+    #[arg(long, requires = "base_currency")]
+    rates: Option<PathBuf>,
+
+    /// Exchange rate as 'currency=rate'. Can be given multiple times and takes precedence over
+    /// the rates file.
+    #[arg(long, value_parser = parse_rate, requires = "base_currency")]
+    rate: Vec<(String, f64)>,
+
+    /// Print the balance of every person in each currency with its value in the base currency
+    /// before the transactions.
+    #[arg(long, requires = "base_currency")]
+    show_conversions: bool,
+}
+
+fn parse_timeout(s: &str) -> Result<Duration, String> {
+    s.parse::<f64>()
+        .ok()
+        .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+        .filter(|d| !d.is_zero())
+        .ok_or(format!(
+            "Expected a positive number of seconds, got '{}'.",
+            s
+        ))
+}
+
+fn parse_objective_weight(s: &str) -> Result<f64, String> {
+    s.parse::<f64>()
+        .ok()
+        .filter(|w| w.is_finite() && *w >= 0.0)
+        .ok_or(format!("Expected a non-negative number, got '{}'.", s))
+}
+
+fn parse_rate(s: &str) -> Result<(String, f64), String> {
+    let (currency, rate) = s
+        .split_once('=')
+        .ok_or(format!("Expected 'currency=rate', got '{}'.", s))?;
+    rate.parse()
+        .map(|r| (currency.to_owned(), r))
+        .map_err(|e| format!("Invalid rate '{}': {}", rate, e))
+}
+
+fn parse_pair(s: &str) -> Result<(String, String), String> {
+    s.split_once(',')
+        .map(|(a, b)| (a.trim().to_owned(), b.trim().to_owned()))
+        .filter(|(a, b)| !a.is_empty() && !b.is_empty())
+        .ok_or(format!("Expected a pair of names 'A,B', got '{}'.", s))
+}
+
+impl CurrencyArgs {
+    fn rates(&self) -> Result<Option<Rates>, String> {
+        let Some(base) = &self.base_currency else {
+            return Ok(None);
+        };
+        let mut rates = Rates::new(base.to_owned());
+        if let Some(path) = &self.rates {
+            rates.insert_csv(
+                &std::fs::read_to_string(path)
+                    .map_err(|e| format!("Unable to read rates {:?}: {}", path, e))?,
+            )?;
+        }
+        for (currency, rate) in &self.rate {
+            rates.insert(currency.to_owned(), *rate)?;
+        }
+        Ok(Some(rates))
+    }
+}
+
+impl SolverArgs {
+    /// Constraints of the constraints file, which are empty without a file.
+    fn constraints(&self) -> Result<Constraints, String> {
+        self.constraints
+            .as_deref()
+            .map_or(Ok(Constraints::default()), constraints::load_constraints)
+    }
+
+    /// Costs of the transfer costs file, if it is given.
+    fn transfer_costs(&self) -> Result<Option<TransferCosts>, String> {
+        self.transfer_costs
+            .as_deref()
+            .map(TransferCosts::load)
+            .transpose()
+    }
+}
+
+#[cfg(feature = "qr")]
+#[derive(ClapArgs, Debug)]
+struct QrArgs {
+    /// Render an EPC QR code for every transaction to a payee with an IBAN in the metadata.
+    /// Unicode codes are printed below the transactions, while the others are written to files.
+    #[arg(long, value_enum)]
+    qr: Option<qr::QrFormat>,
+
+    /// Directory the QR code files are written to.
+    #[arg(long, default_value = ".", requires = "qr")]
+    qr_dir: PathBuf,
+}
+
+#[cfg(feature = "qr")]
+impl QrArgs {
+    /// Renders the QR codes of the transactions and returns what should be printed.
+    fn render(
+        &self,
+        instance: &ProblemInstance,
+        solution: &EdgeSolution,
+        lang: Lang,
+    ) -> Result<String, String> {
+        let Some(format) = self.qr else {
+            return Ok(String::new());
+        };
+        let mut out = String::new();
+        let transactions = instance
+            .solution_transactions(solution)
+            .ok_or(lang.text(Text::NoResult))?;
+        for (i, (from, to, amount)) in transactions.iter().enumerate() {
+            let Some(payee) = instance
+                .metadata(to)
+                .filter(|m| m.iter().any(|(k, _)| k.eq_ignore_ascii_case("iban")))
+            else {
+                out += &format!("{:?} {}\n", to, lang.text(Text::NoIban));
+                continue;
+            };
+            let payload = qr::epc_payload(
+                to,
+                payee,
+                *amount,
+                instance.decimals(),
+                &format!("Payback from {}", from),
+            )?;
+            let code = qr::render_qr(&payload, format)?;
+            if format == qr::QrFormat::Unicode {
+                out += &format!(
+                    "{}\n{}\n",
+                    lang.transaction(from, to, *amount, instance.decimals()),
+                    String::from_utf8_lossy(&code)
+                );
+            } else {
+                let path =
+                    self.qr_dir
+                        .join(format!("{}-{}-{}.{}", i + 1, from, to, format.extension()));
+                std::fs::write(&path, code)
+                    .map_err(|e| format!("Unable to write QR code {:?}: {}", path, e))?;
+                out += &format!("QR code of {:?} to {:?} written to {:?}.\n", from, to, path);
+            }
+        }
+        Ok(out)
+    }
+}
+
+#[cfg(feature = "webhook")]
+#[derive(ClapArgs, Debug)]
+struct WebhookArgs {
+    /// After solving, post the transactions as json list to this url.
+    #[arg(long, value_name = "URL", env = "PAYBACK_POST_RESULT")]
+    post_result: Option<String>,
+
+    /// Sign the posted json with HMAC-SHA256 using this secret.
+    /// The signature is sent in the 'X-Payback-Signature' header.
+    #[arg(long, requires = "post_result", env = "PAYBACK_POST_SECRET")]
+    post_secret: Option<String>,
+
+    /// Number of retries if posting the result fails.
+    #[arg(long, default_value_t = 3, requires = "post_result")]
+    post_retries: u32,
+}
+
+#[cfg(feature = "webhook")]
+impl WebhookArgs {
+    fn webhook(&self) -> Option<webhook::Webhook> {
+        self.post_result.as_ref().map(|url| webhook::Webhook {
+            url: url.to_owned(),
+            secret: self.post_secret.to_owned(),
+            retries: self.post_retries,
+        })
+    }
+}
+
+#[derive(ClapArgs, Debug)]
+struct LedgerArgs {
+    /// Path to the ledger file with one json event per line. It is created if it doesn't exist.
+    ledger: PathBuf,
+
+    #[command(subcommand)]
+    action: LedgerAction,
+}
+
+#[derive(Subcommand, Debug)]
+enum LedgerAction {
+    /// Record that the payer paid an amount, which all participants share equally.
+    Expense {
+        payer: String,
+        amount: i64,
+        /// People sharing the expense. Include the payer if they take part.
+        #[arg(required = true)]
+        participants: Vec<String>,
+    },
+    /// Record a single payment from one person to another.
+    Pay {
+        from: String,
+        to: String,
+        amount: i64,
+    },
+    /// Print the balances derived from the ledger in the csv node format.
+    Balances {
+        /// Only replay the first n events.
+        #[arg(long)]
+        at: Option<usize>,
+    },
+    /// Solve the current balances, record the plan as executed settlement and print it.
+    Settle {
+        /// Solving method used for the plan.
+        #[arg(short = 'm', long, value_enum, default_value_t = SolvingMethods::ApproxStarExpand)]
+        method: SolvingMethods,
+        /// Only print the plan without recording it.
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Solve the current balances and record the plan with an id for every payment.
+    /// Proposing a new plan replaces the open payments of the previous one.
+    Plan {
+        /// Solving method used for the plan.
+        #[arg(short = 'm', long, value_enum, default_value_t = SolvingMethods::ApproxStarExpand)]
+        method: SolvingMethods,
+    },
+    /// Mark payments of the latest plan as paid by their ids.
+    Paid {
+        #[arg(required = true)]
+        ids: Vec<String>,
+    },
+    /// Print the payments of the latest plan, which aren't marked as paid yet.
+    Open,
+    /// Print all events of the ledger.
+    Log,
+    /// Replace all events with a single event holding the current balances.
+    Compact,
+}
+
+/// Path of an input file or '-' for the stdin. The file is only read by the command, so an
+/// unreadable file fails like any other I/O error instead of as an invalid argument.
+#[derive(Clone, Debug)]
+struct Input(std::path::PathBuf);
+
+impl std::str::FromStr for Input {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Input(s.into()))
+    }
+}
+
+impl Input {
+    /// Reads the content of the input. Inputs compressed with gzip are decompressed.
+    fn read(&self) -> Result<String, String> {
+        let data = compression::read_input(&self.0)?;
+        Ok(match self.0.to_str() {
+            Some("-") => data.trim_end().to_owned(),
+            _ => data,
+        })
+    }
+}
+
+/// Solving method of the command line, which can also be recommended from the bench history.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum MethodChoice {
+    Method(SolvingMethods),
+    Recommend,
+}
+
+static METHOD_CHOICES: LazyLock<Vec<MethodChoice>> = LazyLock::new(|| {
+    SolvingMethods::value_variants()
+        .iter()
+        .map(|m| MethodChoice::Method(*m))
+        .chain([MethodChoice::Recommend])
+        .collect()
+});
+
+impl ValueEnum for MethodChoice {
+    fn value_variants<'a>() -> &'a [Self] {
+        &METHOD_CHOICES
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        match self {
+            MethodChoice::Method(method) => method.to_possible_value(),
+            MethodChoice::Recommend => Some(PossibleValue::new("recommend").help(
+                "Predict the best method for the instance from the results of 'bench', which \
+                fits into --timeout or else 1 second",
+            )),
+        }
+    }
 }
 
 #[derive(Clone, Debug, ValueEnum)]
@@ -49,31 +957,216 @@ enum OutputFormat {
     Dot,
     /// Print result to stdout by listing the needed transactions
     Transactions,
+    /// Csv records 'from,to,amount' like the csv edge format, so the plan can be read again
+    Csv,
 }
 
-fn main() -> Result<(), String> {
-    let args = Args::parse();
-    let log_level = match (args.verbose, args.debug) {
-        (_, true) => "debug",
-        (true, _) => "info",
-        (_, _) => "off",
-    };
-    env_logger::Builder::from_env(Env::default().default_filter_or(log_level)).init();
-    let graph: Graph = args.file.to_string().try_into()?;
-    let instance = ProblemInstance::from(graph);
-    let sol = instance.solve_with(args.method);
-    let out = match args.output {
-        OutputFormat::Dot => instance.solution_to_dot_string(&sol),
-        OutputFormat::Transactions => instance.solution_string(&sol),
-    };
-    match out {
-        Ok(s) => {
-            println!("{}", s);
-            Ok(())
+/// Failure of a command, whose kind can be reported to wrappers with `--json-errors`.
+#[derive(Debug, PartialEq)]
+enum CliError {
+    /// The input can't be parsed.
+    InvalidInput(String),
+    /// The weights don't add up to zero.
+    Unbalanced {
+        imbalance: i64,
+        currency: Option<String>,
+    },
+    /// The solver found no solution.
+    NoResult(String),
+    /// The solver didn't finish within the timeout.
+    TimedOut(Duration),
+    /// `--check` found errors in the input. The issues include its warnings.
+    Check(Vec<ValidationIssue>),
+    Other(String),
+}
+
+/// Exit codes of the binary, so failures can be told apart in scripts.
+mod exit_code {
+    /// The input can't be parsed.
+    pub const PARSE_ERROR: i32 = 1;
+    /// The instance is unbalanced and can't be solved.
+    pub const UNSOLVABLE: i32 = 2;
+    /// The solver didn't finish within `--timeout`.
+    pub const TIMEOUT: i32 = 3;
+    /// The solver found no solution.
+    pub const NO_RESULT: i32 = 4;
+    /// Any other failure like an unreadable file or an internal error.
+    pub const OTHER: i32 = 5;
+    /// The command line arguments are invalid.
+    pub const USAGE: i32 = 64;
+}
+
+impl From<String> for CliError {
+    fn from(value: String) -> Self {
+        CliError::Other(value)
+    }
+}
+
+impl From<&str> for CliError {
+    fn from(value: &str) -> Self {
+        CliError::Other(value.to_owned())
+    }
+}
+
+impl std::fmt::Display for CliError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CliError::InvalidInput(s) | CliError::NoResult(s) | CliError::Other(s) => {
+                write!(f, "{}", s)
+            }
+            CliError::TimedOut(timeout) => {
+                write!(f, "Solving took longer than {:?}.", timeout)
+            }
+            CliError::Unbalanced {
+                imbalance,
+                currency: None,
+            } => write!(f, "The weights add up to {} instead of 0.", imbalance),
+            CliError::Unbalanced {
+                imbalance,
+                currency: Some(currency),
+            } => write!(
+                f,
+                "The balances in {} add up to {} instead of 0.",
+                currency, imbalance
+            ),
+            CliError::Check(issues) => {
+                write!(f, "The instance is invalid:")?;
+                issues.iter().try_for_each(|i| write!(f, "\n  {}", i))
+            }
+        }
+    }
+}
+
+impl CliError {
+    fn exit_code(&self) -> i32 {
+        match self {
+            CliError::InvalidInput(_) => exit_code::PARSE_ERROR,
+            CliError::Unbalanced { .. } => exit_code::UNSOLVABLE,
+            CliError::TimedOut(_) => exit_code::TIMEOUT,
+            CliError::NoResult(_) => exit_code::NO_RESULT,
+            CliError::Check(issues) => match issues.iter().find(|i| i.is_error()) {
+                Some(ValidationIssue::InvalidFormat(_)) => exit_code::PARSE_ERROR,
+                _ => exit_code::UNSOLVABLE,
+            },
+            CliError::Other(_) => exit_code::OTHER,
+        }
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        let mut error = serde_json::Map::new();
+        let kind = match self {
+            CliError::InvalidInput(_) => "invalid_input",
+            CliError::Unbalanced {
+                imbalance,
+                currency,
+            } => {
+                error.insert("imbalance".to_owned(), (*imbalance).into());
+                if let Some(currency) = currency {
+                    error.insert("currency".to_owned(), currency.to_owned().into());
+                }
+                "unbalanced"
+            }
+            CliError::NoResult(_) => "no_result",
+            CliError::TimedOut(timeout) => {
+                error.insert("timeout".to_owned(), timeout.as_secs_f64().into());
+                "timeout"
+            }
+            CliError::Check(issues) => {
+                let issues: Vec<String> = issues.iter().map(|i| i.to_string()).collect();
+                error.insert("issues".to_owned(), issues.into());
+                match self.exit_code() {
+                    exit_code::PARSE_ERROR => "invalid_input",
+                    _ => "unbalanced",
+                }
+            }
+            CliError::Other(_) => "other",
+        };
+        error.insert("kind".to_owned(), kind.into());
+        error.insert("message".to_owned(), self.to_string().into());
+        serde_json::json!({ "error": error })
+    }
+
+    /// Report of the error, which is its json object with `--json-errors` and nothing with
+    /// `--quiet`, so the exit code is the only report.
+    fn report(&self, json_errors: bool, quiet: bool) -> Option<String> {
+        match (json_errors, quiet) {
+            (_, true) => None,
+            (true, false) => Some(self.to_json().to_string()),
+            (false, false) => Some(format!("Error: {}", self)),
         }
-        Err(s) => {
-            println!("Error: {}", s);
-            Err(s)
+    }
+}
+
+fn main() {
+    let matches = Args::command()
+        .try_get_matches()
+        .and_then(|matches| Args::from_arg_matches(&matches).map(|args| (matches, args)));
+    let (matches, args) = matches.unwrap_or_else(|e| {
+        // Clap exits with 2 on invalid arguments, which is already used for unsolvable instances.
+        let _ = e.print();
+        std::process::exit(if e.use_stderr() { exit_code::USAGE } else { 0 })
+    });
+    let (json_errors, quiet) = (args.json_errors, args.quiet);
+    let out = init_logging(&args)
+        .and_then(|_| config::load_config(args.config.as_deref()))
+        .map_err(CliError::Other)
+        .and_then(|config| cli::run_command(args, &matches, &config));
+    match out {
+        Ok(s) => println!("{}", s),
+        Err(e) => {
+            match e.report(json_errors, quiet) {
+                Some(report) if json_errors => eprintln!("{}", report),
+                Some(report) => println!("{}", report),
+                None => {}
+            }
+            std::process::exit(e.exit_code())
         }
     }
 }
+
+fn init_logging(args: &Args) -> Result<(), String> {
+    let log_level = match (args.verbose, args.debug, &args.log_file) {
+        (_, true, _) => "debug",
+        (true, _, _) | (_, _, Some(_)) => "info",
+        (_, _, _) => "off",
+    };
+    let subscriber = tracing_subscriber::fmt().with_env_filter(
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(log_level)),
+    );
+    match &args.log_file {
+        None => subscriber.with_writer(std::io::stderr).init(),
+        Some(path) => subscriber
+            .with_ansi(false)
+            .with_writer(log_file_writer(path, args)?)
+            .init(),
+    }
+    Ok(())
+}
+
+fn log_file_writer(path: &std::path::Path, args: &Args) -> Result<BoxMakeWriter, String> {
+    #[cfg(feature = "grpc")]
+    if matches!(args.command, Some(Commands::Serve { .. })) {
+        // The server runs until it is stopped, so its log is split into one file per day.
+        let prefix = path
+            .file_name()
+            .ok_or(format!("Log file {:?} has no file name.", path))?;
+        let dir = path
+            .parent()
+            .filter(|d| !d.as_os_str().is_empty())
+            .unwrap_or(std::path::Path::new("."));
+        let appender = tracing_appender::rolling::RollingFileAppender::builder()
+            .rotation(tracing_appender::rolling::Rotation::DAILY)
+            .filename_prefix(prefix.to_string_lossy())
+            .build(dir)
+            .map_err(|e| format!("Unable to open log file {:?}: {}", path, e))?;
+        return Ok(BoxMakeWriter::new(appender));
+    }
+    #[cfg(not(feature = "grpc"))]
+    let _ = args;
+    let file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| format!("Unable to open log file {:?}: {}", path, e))?;
+    Ok(BoxMakeWriter::new(Mutex::new(file)))
+}
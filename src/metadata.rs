@@ -0,0 +1,238 @@
+use csv::{ReaderBuilder, StringRecord, WriterBuilder};
+use std::collections::HashMap;
+use tracing::{debug, instrument};
+
+use crate::decimals::format_money;
+use crate::graph::Metadata;
+
+/// Splits off the metadata of people from the records. Metadata is given as additional trailing
+/// 'key=value' columns and belongs to the person in the first column, e.g.
+/// 'Alice,-42,iban=DE02120300000000202051,email=alice@example.com'. Records with only a name and
+/// metadata are removed, so metadata can also be given separate from the weights and debts.
+///
+/// Returns the records without metadata and the metadata of every person. Records without
+/// metadata are returned unchanged.
+///
+/// Example:
+/// ```
+/// use payback::metadata::split_metadata;
+///
+/// let (data, metadata) = split_metadata("A,B,1,iban=DE02\nB,email=b@example.com").unwrap();
+/// assert_eq!(data, "A,B,1\n");
+/// assert_eq!(metadata["B"], vec![("email".to_string(), "b@example.com".to_string())]);
+/// ```
+#[instrument(level = "debug", skip_all, fields(bytes = data.len()))]
+pub fn split_metadata(data: &str) -> Result<(String, HashMap<String, Metadata>), String> {
+    let records = ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(data.as_bytes())
+        .records()
+        .collect::<Result<Vec<StringRecord>, csv::Error>>()
+        .map_err(|e| e.to_string())?;
+    let mut metadata: HashMap<String, Metadata> = HashMap::new();
+    let mut stripped: Vec<Vec<&str>> = vec![];
+    for record in &records {
+        let fields: Vec<&str> = record.iter().collect();
+        let Some((name, rest)) = fields.split_first() else {
+            continue;
+        };
+        let n = rest.iter().rev().take_while(|f| f.contains('=')).count();
+        let (kept, pairs) = rest.split_at(rest.len() - n);
+        if !pairs.is_empty() {
+            let entry = metadata.entry(name.to_string()).or_default();
+            for pair in pairs {
+                let (key, value) = pair
+                    .split_once('=')
+                    .expect("Only fields with '=' are split.");
+                entry.push((key.trim().to_owned(), value.trim().to_owned()));
+            }
+        }
+        if !kept.is_empty() || pairs.is_empty() {
+            stripped.push(fields[..=kept.len()].to_vec());
+        }
+    }
+    if metadata.is_empty() {
+        return Ok((data.to_owned(), metadata));
+    }
+    debug!(people = metadata.len(), "Found metadata");
+    let mut wtr = WriterBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_writer(vec![]);
+    stripped
+        .iter()
+        .try_for_each(|fields| wtr.write_record(fields))
+        .map_err(|e| e.to_string())?;
+    let data = wtr
+        .into_inner()
+        .map_err(|e| e.to_string())
+        .and_then(|bytes| String::from_utf8(bytes).map_err(|e| e.to_string()))?;
+    Ok((data, metadata))
+}
+
+/// Metadata keys of payment handles. 'pay' is a link template and 'paypal' a PayPal.me handle.
+pub const PAYMENT_KEYS: [&str; 2] = ["pay", "paypal"];
+
+/// Percent-encodes everything except unreserved characters, so the text can be used in a link.
+fn percent_encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// Link to pay `amount` to the payee with the amount pre-filled. The link is given in the
+/// metadata of the payee either as template with the key 'pay', e.g.
+/// 'pay=https://paypal.me/bob/{amount}EUR', or as PayPal.me handle with the key 'paypal'.
+/// The templates may also contain '{from}' and '{to}', which are percent-encoded. The amount
+/// is given in minor units with `decimals` many decimal places.
+///
+/// Example:
+/// ```
+/// use payback::metadata::payment_link;
+///
+/// let bob = vec![("paypal".to_string(), "bob".to_string())];
+/// let link = payment_link(Some(&bob), "Alice", "Bob", 42, 0);
+/// assert_eq!(link.unwrap(), "https://paypal.me/bob/42.00");
+/// let link = payment_link(Some(&bob), "Alice", "Bob", 4250, 2);
+/// assert_eq!(link.unwrap(), "https://paypal.me/bob/42.50");
+/// ```
+pub fn payment_link(
+    payee: Option<&Metadata>,
+    from: &str,
+    to: &str,
+    amount: i64,
+    decimals: u32,
+) -> Option<String> {
+    let template = payee?.iter().find_map(|(k, v)| match k.as_str() {
+        "pay" => Some(v.to_owned()),
+        "paypal" => Some(format!(
+            "https://paypal.me/{}/{{amount}}",
+            percent_encode(v)
+        )),
+        _ => None,
+    })?;
+    Some(render_transaction(
+        &template,
+        (&percent_encode(from), None),
+        (&percent_encode(to), None),
+        amount,
+        decimals,
+    ))
+}
+
+/// Renders a transaction with the template. The placeholders '{from}', '{to}' and '{amount}' are
+/// replaced by the payer, payee and amount. Metadata of the payer and payee is inserted with
+/// '{from.key}' and '{to.key}' and the [`payment_link()`] of the payee with '{link}'. Missing
+/// metadata is replaced by an empty string. The amount is given in minor units with `decimals`
+/// many decimal places.
+///
+/// Example:
+/// ```
+/// use payback::metadata::render_transaction;
+///
+/// let to = vec![("iban".to_string(), "DE02".to_string())];
+/// let template = "{from} -> {to}, {amount}, IBAN {to.iban}";
+/// let line = render_transaction(template, ("A", None), ("B", Some(&to)), 42, 0);
+/// assert_eq!(line, "A -> B, 42.00, IBAN DE02");
+/// ```
+pub fn render_transaction(
+    template: &str,
+    from: (&str, Option<&Metadata>),
+    to: (&str, Option<&Metadata>),
+    amount: i64,
+    decimals: u32,
+) -> String {
+    let mut out = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        out += &rest[..start];
+        let Some(end) = rest[start..].find('}') else {
+            break;
+        };
+        let placeholder = &rest[start + 1..start + end];
+        let lookup = |(name, metadata): (&str, Option<&Metadata>), key: Option<&str>| match key {
+            None => name.to_owned(),
+            Some(key) => metadata
+                .and_then(|m| m.iter().find(|(k, _)| k == key))
+                .map(|(_, v)| v.to_owned())
+                .unwrap_or_default(),
+        };
+        let (person, key) = match placeholder.split_once('.') {
+            Some((person, key)) => (person, Some(key)),
+            None => (placeholder, None),
+        };
+        out += &match (person, key) {
+            ("from", key) => lookup(from, key),
+            ("to", key) => lookup(to, key),
+            // Links and bank transfers expect amounts with cents.
+            ("amount", None) => format_money(amount, decimals),
+            ("link", None) => {
+                payment_link(to.1, from.0, to.0, amount, decimals).unwrap_or_default()
+            }
+            _ => rest[start..=start + end].to_owned(),
+        };
+        rest = &rest[start + end + 1..];
+    }
+    out + rest
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::metadata::{payment_link, render_transaction, split_metadata};
+    use tracing::debug;
+    use tracing_subscriber::EnvFilter;
+
+    fn init() {
+        let _ = tracing_subscriber::fmt()
+            .with_env_filter(
+                EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("debug")),
+            )
+            .with_test_writer()
+            .try_init();
+    }
+
+    #[test]
+    fn test_metadata() {
+        init();
+        debug!("Running 'test_metadata'");
+        let data = "A,-1,2024-01-01,iban=DE02, email = a@example.com\nB,1\nB,phone=123";
+        let (stripped, metadata) = split_metadata(data).unwrap();
+        assert_eq!(stripped, "A,-1,2024-01-01\nB,1\n");
+        assert_eq!(
+            metadata["A"],
+            vec![
+                ("iban".to_string(), "DE02".to_string()),
+                ("email".to_string(), "a@example.com".to_string())
+            ]
+        );
+        assert_eq!(metadata.len(), 2);
+        assert_eq!(split_metadata("A,B,1").unwrap().0, "A,B,1");
+
+        let line = render_transaction(
+            "{from} pays {amount} to {to} ({to.email}{to.phone}) {unknown}",
+            ("B", metadata.get("B")),
+            ("A", metadata.get("A")),
+            1,
+            0,
+        );
+        assert_eq!(line, "B pays 1.00 to A (a@example.com) {unknown}");
+
+        let (_, metadata) =
+            split_metadata("Bob Ross,pay=https://bank.example/pay?from={from}&amount={amount}")
+                .unwrap();
+        let link = payment_link(metadata.get("Bob Ross"), "Anna & Co", "Bob Ross", 5, 0);
+        assert_eq!(
+            link.unwrap(),
+            "https://bank.example/pay?from=Anna%20%26%20Co&amount=5.00"
+        );
+        assert_eq!(payment_link(None, "A", "B", 1, 0), None);
+        let line = render_transaction("{to}: {link}", ("A", None), ("B", None), 1, 0);
+        assert_eq!(line, "B: ");
+    }
+}
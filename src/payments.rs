@@ -0,0 +1,197 @@
+use csv::{ReaderBuilder, StringRecord, WriterBuilder};
+use itertools::Itertools;
+use std::collections::HashMap;
+use tracing::{debug, instrument};
+
+/// First column of a payment record 'paid,NodeNameFrom,NodeNameTo,amount', which states that
+/// `from` paid `amount` to `to`.
+pub const PAYMENT_MARKER: &str = "paid";
+
+/// Debt or payment of `people.0` to `people.1` with the further columns of the record.
+struct Record {
+    people: (String, String),
+    amount: i64,
+    rest: Vec<String>,
+    payment: bool,
+}
+
+/// Debts or payments by the people and further columns.
+type Amounts<'a> = HashMap<(&'a (String, String), &'a Vec<String>), i64>;
+
+fn is_payment(record: &StringRecord) -> bool {
+    record.len() >= 4
+        && record[0].trim() == PAYMENT_MARKER
+        && record[3].trim().parse::<i64>().is_ok()
+}
+
+/// Nets payment records against the debts of an instance in the csv edge format, so a
+/// chronological file of debts and payments gives the correct balances. Payments between the
+/// same people add up, while later debts still replace earlier ones. Further columns like a
+/// currency are kept and only records with the same further columns are netted.
+///
+/// The debts and payments between two people are replaced by a single debt of what is left to
+/// pay. Inputs without payment records are returned unchanged.
+///
+/// Example:
+/// ```
+/// use payback::payments::net_payments;
+///
+/// let data = "A,B,10\npaid,A,B,4\npaid,A,B,1";
+/// assert_eq!(net_payments(data).unwrap(), "A,B,5\n");
+/// ```
+#[instrument(level = "debug", skip_all, fields(bytes = data.len()))]
+pub fn net_payments(data: &str) -> Result<String, String> {
+    let records = ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(data.as_bytes())
+        .records()
+        .collect::<Result<Vec<StringRecord>, csv::Error>>()
+        .map_err(|e| e.to_string())?;
+    if !records.iter().any(is_payment) {
+        return Ok(data.to_owned());
+    }
+    let mut parsed: Vec<Record> = vec![];
+    for r in &records {
+        let payment = is_payment(r);
+        let fields: Vec<String> = r
+            .iter()
+            .skip(payment as usize)
+            .map(|f| f.to_owned())
+            .collect();
+        let amount = fields
+            .get(2)
+            .and_then(|a| a.trim().parse::<i64>().ok())
+            .filter(|_| fields[1].trim().parse::<i64>().is_err())
+            .ok_or(format!(
+                "Payments can only be combined with debts 'NodeNameFrom,NodeNameTo,weight', but got '{}'.",
+                r.iter().join(",")
+            ))?;
+        parsed.push(Record {
+            people: (fields[0].to_owned(), fields[1].to_owned()),
+            amount,
+            rest: fields[3..].to_vec(),
+            payment,
+        });
+    }
+
+    // Debts and payments are netted regardless of their direction.
+    let pair = |(u, v): &(String, String), rest: &Vec<String>| {
+        let key = if u < v { (u, v) } else { (v, u) };
+        (key.0.to_owned(), key.1.to_owned(), rest.to_owned())
+    };
+    let paid_pairs: Vec<(String, String, Vec<String>)> = parsed
+        .iter()
+        .filter(|r| r.payment)
+        .map(|r| pair(&r.people, &r.rest))
+        .collect();
+    let mut debts: Amounts = HashMap::new();
+    let mut payments: Amounts = HashMap::new();
+    for r in &parsed {
+        if r.payment {
+            let sum = payments.entry((&r.people, &r.rest)).or_default();
+            *sum = sum
+                .checked_add(r.amount)
+                .ok_or("The payments are too large to be summed up.")?;
+        } else {
+            debts.insert((&r.people, &r.rest), r.amount);
+        }
+    }
+
+    let mut netted: Vec<Vec<String>> = vec![];
+    let mut written: Vec<(String, String, Vec<String>)> = vec![];
+    for Record {
+        people: uv,
+        amount,
+        rest,
+        ..
+    } in &parsed
+    {
+        let key = pair(uv, rest);
+        if !paid_pairs.contains(&key) {
+            netted.push(
+                [uv.0.to_owned(), uv.1.to_owned(), amount.to_string()]
+                    .into_iter()
+                    .chain(rest.to_owned())
+                    .collect(),
+            );
+            continue;
+        }
+        if written.contains(&key) {
+            continue;
+        }
+        let vu = (uv.1.to_owned(), uv.0.to_owned());
+        let get =
+            |map: &Amounts, k: &(String, String)| map.get(&(k, rest)).copied().unwrap_or_default();
+        // What u still owes v.
+        let open = i64::try_from(
+            get(&debts, uv) as i128 + get(&payments, &vu) as i128
+                - get(&debts, &vu) as i128
+                - get(&payments, uv) as i128,
+        )
+        .map_err(|_| "The debts and payments are too large to be netted.")?;
+        netted.push(
+            [uv.0.to_owned(), uv.1.to_owned(), open.to_string()]
+                .into_iter()
+                .chain(rest.to_owned())
+                .collect(),
+        );
+        written.push(key);
+    }
+    debug!("Netted payments of {} pairs", written.len());
+
+    let mut wtr = WriterBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_writer(vec![]);
+    netted
+        .iter()
+        .try_for_each(|r| wtr.write_record(r))
+        .map_err(|e| e.to_string())?;
+    wtr.into_inner()
+        .map_err(|e| e.to_string())
+        .and_then(|bytes| String::from_utf8(bytes).map_err(|e| e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::graph::Graph;
+    use crate::payments::net_payments;
+    use tracing::debug;
+    use tracing_subscriber::EnvFilter;
+
+    fn init() {
+        let _ = tracing_subscriber::fmt()
+            .with_env_filter(
+                EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("debug")),
+            )
+            .with_test_writer()
+            .try_init();
+    }
+
+    #[test]
+    fn test_net_payments() {
+        init();
+        debug!("Running 'test_net_payments'");
+        assert_eq!(net_payments("A,B,1\nA,B,2").unwrap(), "A,B,1\nA,B,2");
+
+        let data = "A,B,10\nC,B,3\npaid,B,A,2\nA,B,12\npaid,A,B,5";
+        let netted = net_payments(data).unwrap();
+        debug!("Netted: {:?}", netted);
+        assert_eq!(netted, "A,B,9\nC,B,3\n");
+        let graph: Graph = netted.try_into().unwrap();
+        assert_eq!(
+            graph.balances(),
+            [
+                ("A".to_string(), -9),
+                ("B".to_string(), 12),
+                ("C".to_string(), -3)
+            ]
+        );
+
+        // Overpaying reverses the debt and payments in other currencies are kept apart.
+        let netted = net_payments("A,B,3,EUR\npaid,A,B,5,EUR\npaid,A,B,1,USD").unwrap();
+        assert_eq!(netted, "A,B,-2,EUR\nA,B,-1,USD\n");
+        assert!(net_payments("A,1\npaid,A,B,1").is_err());
+    }
+}
@@ -0,0 +1,640 @@
+use itertools::Itertools;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Debug;
+use std::sync::Arc;
+use tracing::{debug, instrument};
+
+use crate::approximation::greedy_satisfaction;
+use crate::constraints::star;
+use crate::graph::{Edge, Graph, NamedNode};
+use crate::probleminstance::{EdgeSolution, ProblemInstance};
+use crate::single_payment::single_payment;
+use crate::solver_config::SolveError;
+use crate::transfer_costs::TransferCosts;
+
+/// Step before solving, which simplifies the instance.
+pub trait Preprocessor: Debug + Send + Sync {
+    /// Splits the instance into parts, which are solved on their own, and the transactions,
+    /// which settle the rest of the instance.
+    fn preprocess(&self, instance: &ProblemInstance)
+        -> (Vec<ProblemInstance>, BTreeMap<Edge, i64>);
+}
+
+/// Step after solving, which improves the solution.
+pub trait Postprocessor: Debug + Send + Sync {
+    fn postprocess(&self, instance: &ProblemInstance, solution: EdgeSolution) -> EdgeSolution;
+}
+
+/// Preprocessing steps of payback.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum Preprocessing {
+    /// Settles people with opposite balances directly with each other. Doesn't increase the
+    /// minimal number of transactions.
+    OppositePairs,
+    /// Solves every connected component of the debts on its own. Only instances in the csv
+    /// edge format have debts, and the parts of other steps don't, so it should be the first step.
+    Components,
+}
+
+/// Postprocessing steps of payback.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum Postprocessing {
+    /// Settles every group of people, who pay each other, with 'GreedySatisfaction', which moves
+    /// the minimal amount with at most as many transactions.
+    Resettle,
+    /// Rearranges the solution, so every debtor makes exactly one payment.
+    SinglePayment,
+}
+
+/// Stages run around the solver. The preprocessing steps are applied in order to every part of
+/// the instance left by the step before, and the postprocessing steps are applied in order to
+/// the solution of the whole instance.
+///
+/// Example:
+/// ```
+/// use payback::graph::Graph;
+/// use payback::pipeline::{Pipeline, Preprocessing};
+/// use payback::probleminstance::{ProblemInstance, SolvingMethods};
+/// use payback::solver_config::SolverConfig;
+/// use std::sync::Arc;
+///
+/// let instance: ProblemInstance = Graph::from(vec![-3, -2, 1, 2, 2]).into();
+/// let config = SolverConfig {
+///     method: SolvingMethods::ApproxStarExpand,
+///     pipeline: Pipeline {
+///         pre: vec![Arc::new(Preprocessing::OppositePairs)],
+///         ..Default::default()
+///     },
+///     ..Default::default()
+/// };
+/// let solution = instance.solve_with_config(&config).unwrap();
+/// assert_eq!(solution.unwrap().len(), 3);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Pipeline {
+    pub pre: Vec<Arc<dyn Preprocessor>>,
+    pub post: Vec<Arc<dyn Postprocessor>>,
+}
+
+impl Pipeline {
+    pub fn is_empty(&self) -> bool {
+        self.pre.is_empty() && self.post.is_empty()
+    }
+
+    /// Runs the stages around `solve`, which solves every part left by the preprocessing.
+    #[instrument(level = "debug", skip_all, fields(pre = self.pre.len(), post = self.post.len()))]
+    pub(crate) fn run(
+        &self,
+        instance: &ProblemInstance,
+        solve: impl Fn(&ProblemInstance) -> Result<EdgeSolution, SolveError>,
+    ) -> Result<EdgeSolution, SolveError> {
+        let mut solution: BTreeMap<Edge, i64> = BTreeMap::new();
+        let mut parts = vec![ProblemInstance::from(instance.g.to_owned())];
+        for stage in &self.pre {
+            let mut next = vec![];
+            for part in &parts {
+                let (split, edges) = stage.preprocess(part);
+                add_edges(&mut solution, edges);
+                next.extend(split);
+            }
+            parts = next;
+            debug!(?stage, parts = parts.len(), "Preprocessed");
+        }
+        for part in parts
+            .iter()
+            .filter(|p| p.g.vertices.iter().any(|v| v.weight != 0))
+        {
+            let Some(edges) = solve(part)? else {
+                return Ok(None);
+            };
+            add_edges(&mut solution, edges);
+        }
+        let mut solution = Some(solution);
+        for stage in &self.post {
+            solution = stage.postprocess(instance, solution);
+            debug!(?stage, "Postprocessed");
+        }
+        Ok(solution)
+    }
+}
+
+impl Preprocessor for Preprocessing {
+    fn preprocess(
+        &self,
+        instance: &ProblemInstance,
+    ) -> (Vec<ProblemInstance>, BTreeMap<Edge, i64>) {
+        match self {
+            Preprocessing::OppositePairs => opposite_pairs(instance),
+            Preprocessing::Components => (components(instance), BTreeMap::new()),
+        }
+    }
+}
+
+impl Postprocessor for Postprocessing {
+    fn postprocess(&self, instance: &ProblemInstance, solution: EdgeSolution) -> EdgeSolution {
+        match self {
+            Postprocessing::Resettle => resettle_groups(instance, &solution),
+            Postprocessing::SinglePayment => single_payment(instance, &solution),
+        }
+    }
+}
+
+/// Instance of the people, which keeps their ids and the metadata of the instance.
+fn sub_instance(instance: &ProblemInstance, people: Vec<&NamedNode>) -> ProblemInstance {
+    ProblemInstance::from(Graph::from(people).with_metadata(instance.g.metadata.to_owned()))
+}
+
+fn add_edges(solution: &mut BTreeMap<Edge, i64>, edges: BTreeMap<Edge, i64>) {
+    for (e, w) in edges {
+        *solution.entry(e).or_default() += w;
+    }
+}
+
+fn opposite_pairs(instance: &ProblemInstance) -> (Vec<ProblemInstance>, BTreeMap<Edge, i64>) {
+    let mut open: HashMap<i64, Vec<&NamedNode>> = HashMap::new();
+    let mut edges = BTreeMap::new();
+    for v in instance.g.vertices.iter().filter(|v| v.weight != 0) {
+        match open.get_mut(&-v.weight).and_then(|vs| vs.pop()) {
+            Some(w) => {
+                let (creditor, debtor) = if v.weight > 0 { (v, w) } else { (w, v) };
+                edges.insert(
+                    Edge {
+                        u: creditor.id,
+                        v: debtor.id,
+                    },
+                    creditor.weight,
+                );
+            }
+            None => open.entry(v.weight).or_default().push(v),
+        }
+    }
+    let mut rest: Vec<&NamedNode> = open.into_values().flatten().collect();
+    rest.sort_by_key(|v| v.id);
+    (vec![sub_instance(instance, rest)], edges)
+}
+
+fn components(instance: &ProblemInstance) -> Vec<ProblemInstance> {
+    let Some(debts) = instance.g.debts() else {
+        return vec![ProblemInstance::from(instance.g.to_owned())];
+    };
+    let by_name: HashMap<&str, &NamedNode> = instance
+        .g
+        .vertices
+        .iter()
+        .map(|v| (v.name.as_str(), v))
+        .collect();
+    // The debts connect the people like the transactions of a solution.
+    let connections: BTreeMap<Edge, i64> = debts
+        .iter()
+        .filter_map(|d| {
+            Some((
+                Edge {
+                    u: by_name.get(d.to.as_str())?.id,
+                    v: by_name.get(d.from.as_str())?.id,
+                },
+                d.amount,
+            ))
+        })
+        .collect();
+    instance
+        .partitioning(&Some(connections))
+        .unwrap_or_default()
+        .into_iter()
+        .map(|names| {
+            sub_instance(
+                instance,
+                names.iter().map(|n| by_name[n.as_str()]).collect(),
+            )
+        })
+        .collect()
+}
+
+/// Settles every group of people, who pay each other in the solution, with 'GreedySatisfaction'.
+/// Every group then moves the minimal amount with at most as many transactions as before.
+#[instrument(level = "debug", skip_all)]
+pub(crate) fn resettle_groups(instance: &ProblemInstance, solution: &EdgeSolution) -> EdgeSolution {
+    let by_name: HashMap<&str, &NamedNode> = instance
+        .g
+        .vertices
+        .iter()
+        .map(|v| (v.name.as_str(), v))
+        .collect();
+    let mut resettled = BTreeMap::new();
+    for group in instance.partitioning(solution)? {
+        let people: Vec<&NamedNode> = group.iter().map(|name| by_name[name.as_str()]).collect();
+        resettled.extend(greedy_satisfaction(&ProblemInstance::from(Graph::from(
+            people,
+        )))?);
+    }
+    Some(resettled)
+}
+
+/// Settles the solution with the plan, whose payers have the least total effort, among the plans
+/// with the minimal number of transactions. See [`cheapest_groups()`] for the limits.
+pub(crate) fn min_effort_groups(
+    instance: &ProblemInstance,
+    solution: &EdgeSolution,
+) -> EdgeSolution {
+    let efforts: HashMap<usize, f64> = instance
+        .g
+        .vertices
+        .iter()
+        .map(|v| (v.id, instance.effort(&v.name)))
+        .collect();
+    cheapest_groups(instance, solution, |payer, _| efforts[&payer])
+}
+
+/// Settles every group of people, who pay each other in the solution, with the arrangement,
+/// whose transactions have the least total transfer cost. See [`cheapest_groups()`].
+pub(crate) fn min_transfer_cost_groups(
+    instance: &ProblemInstance,
+    solution: &EdgeSolution,
+    costs: &TransferCosts,
+) -> EdgeSolution {
+    let names: HashMap<usize, &str> = instance
+        .g
+        .vertices
+        .iter()
+        .map(|v| (v.id, v.name.as_str()))
+        .collect();
+    cheapest_groups(instance, solution, |payer, payee| {
+        costs.cost(names[&payer], names[&payee])
+    })
+}
+
+/// Settles every group of people, who pay each other in the solution, with the arrangement,
+/// which has the most transactions between preferred pairs. See [`cheapest_groups()`].
+/// Preferred pairs may be in different groups, so paying between preferred pairs first among
+/// all people is tried too. Arrangements with transactions between forbidden pairs aren't used.
+pub(crate) fn preferred_pairs_groups(
+    instance: &ProblemInstance,
+    solution: &EdgeSolution,
+    preferred: &[(String, String)],
+    forbidden: &[(String, String)],
+) -> EdgeSolution {
+    let names: HashMap<usize, &str> = instance
+        .g
+        .vertices
+        .iter()
+        .map(|v| (v.id, v.name.as_str()))
+        .collect();
+    let cost = |payer: usize, payee: usize| {
+        let (payer, payee) = (names[&payer], names[&payee]);
+        let pays =
+            |(a, b): &(String, String)| (a == payer && b == payee) || (a == payee && b == payer);
+        // Arrangements with a forbidden transaction never cost less than any other.
+        if forbidden.iter().any(pays) {
+            f64::INFINITY
+        } else if preferred.iter().any(pays) {
+            0.0
+        } else {
+            1.0
+        }
+    };
+    let whole = cheapest_pairs_first(&instance.g.vertices, &cost);
+    let solution = match solution {
+        Some(sol)
+            if whole.len() <= sol.len() && total_cost(&whole, &cost) < total_cost(sol, &cost) =>
+        {
+            Some(whole)
+        }
+        _ => solution.to_owned(),
+    };
+    cheapest_groups(instance, &solution, cost)
+}
+
+/// Sum of the costs of the transactions given by the costs of payer and payee.
+fn total_cost(edges: &BTreeMap<Edge, i64>, cost: &impl Fn(usize, usize) -> f64) -> f64 {
+    edges
+        .iter()
+        .map(|(e, w)| {
+            let (payer, payee, _) = e.payment(*w);
+            cost(payer, payee)
+        })
+        .sum()
+}
+
+/// Settles the people like 'GreedySatisfaction', but pays between the cheapest pairs first.
+fn cheapest_pairs_first(
+    people: &[NamedNode],
+    cost: &impl Fn(usize, usize) -> f64,
+) -> BTreeMap<Edge, i64> {
+    let mut weights: HashMap<usize, i64> = people.iter().map(|v| (v.id, v.weight)).collect();
+    let pairs = people
+        .iter()
+        .filter(|d| d.weight < 0)
+        .flat_map(|d| people.iter().filter(|c| c.weight > 0).map(|c| (d.id, c.id)))
+        .sorted_by(|a, b| cost(a.0, a.1).total_cmp(&cost(b.0, b.1)));
+    let mut edges = BTreeMap::new();
+    for (debtor, creditor) in pairs {
+        let amount = (-weights[&debtor]).min(weights[&creditor]);
+        if amount > 0 {
+            *weights.get_mut(&debtor).expect("Debtors have a weight.") += amount;
+            *weights
+                .get_mut(&creditor)
+                .expect("Creditors have a weight.") -= amount;
+            edges.insert(
+                Edge {
+                    u: creditor,
+                    v: debtor,
+                },
+                amount,
+            );
+        }
+    }
+    edges
+}
+
+/// Most people with a balance, whose cheapest plan is searched exactly. The search takes
+/// O(3^n * n) steps for n people.
+pub const MAX_CHEAPEST_PEOPLE: usize = 12;
+
+/// Plan of the least total cost among all plans with the minimal number of transactions. Returns
+/// `None` for more than [`MAX_CHEAPEST_PEOPLE`] people with a balance or if every such plan has
+/// an infinite cost.
+///
+/// A plan with the minimal number of transactions settles every group of a partition into the
+/// most zero-sum groups by a spanning tree of the group, in which every transaction carries the
+/// balance of the people on one side of it. The cheapest tree of every set of people with the
+/// transaction to a person outside is found by a dynamic program over the subsets.
+fn cheapest_settlement(
+    people: &[NamedNode],
+    cost: &impl Fn(usize, usize) -> f64,
+) -> Option<BTreeMap<Edge, i64>> {
+    let people: Vec<&NamedNode> = people.iter().filter(|v| v.weight != 0).collect();
+    let n = people.len();
+    if n > MAX_CHEAPEST_PEOPLE {
+        return None;
+    }
+    let full = (1_usize << n) - 1;
+    let sums: Vec<i64> = (0..=full)
+        .map(|set| {
+            (0..n)
+                .filter(|i| set >> i & 1 == 1)
+                .map(|i| people[i].weight)
+                .sum()
+        })
+        .collect();
+    if sums[full] != 0 {
+        return None;
+    }
+    // Transaction between `child`, whose subtree has the balance `sum`, and its parent.
+    let transaction = |child: usize, parent: usize, sum: i64| {
+        let (child, parent) = (people[child].id, people[parent].id);
+        match sum > 0 {
+            true => (
+                Edge {
+                    u: child,
+                    v: parent,
+                },
+                sum,
+            ),
+            false => (
+                Edge {
+                    u: parent,
+                    v: child,
+                },
+                -sum,
+            ),
+        }
+    };
+    let pay = |child: usize, parent: usize, sum: i64| {
+        let (e, w) = transaction(child, parent, sum);
+        let (payer, payee, _) = e.payment(w);
+        cost(payer, payee)
+    };
+    // Cheapest tree of the set rooted at the person and the split off subtree with its root.
+    let mut tree = vec![(f64::INFINITY, 0, 0); (full + 1) * n];
+    // Cheapest tree of the set with the transaction to the person outside and its root.
+    let mut attached = vec![(f64::INFINITY, 0); (full + 1) * n];
+    for set in 1..=full {
+        for root in (0..n).filter(|r| set >> r & 1 == 1) {
+            let rest = set & !(1 << root);
+            if rest == 0 {
+                tree[set * n + root].0 = 0.0;
+                continue;
+            }
+            let lowest = rest & rest.wrapping_neg();
+            let mut subtree = rest;
+            while subtree != 0 {
+                // Subtrees without a balance wouldn't need their transaction.
+                if subtree & lowest != 0 && sums[subtree] != 0 {
+                    let cost = attached[subtree * n + root].0 + tree[(set ^ subtree) * n + root].0;
+                    if cost < tree[set * n + root].0 {
+                        tree[set * n + root] = (cost, subtree, attached[subtree * n + root].1);
+                    }
+                }
+                subtree = (subtree - 1) & rest;
+            }
+        }
+        for parent in (0..n).filter(|p| set >> p & 1 == 0) {
+            for child in (0..n).filter(|c| set >> c & 1 == 1) {
+                let cost = tree[set * n + child].0 + pay(child, parent, sums[set]);
+                if cost < attached[set * n + parent].0 {
+                    attached[set * n + parent] = (cost, child);
+                }
+            }
+        }
+    }
+    // Most groups and then the least cost of every zero-sum set with the first group.
+    let mut best: Vec<Option<(usize, f64, usize)>> = vec![None; full + 1];
+    best[0] = Some((0, 0.0, 0));
+    for set in (1..=full).filter(|s| sums[*s] == 0) {
+        let lowest = set & set.wrapping_neg();
+        let mut group = set;
+        while group != 0 {
+            if group & lowest != 0 && sums[group] == 0 {
+                if let Some((groups, cost, _)) = best[set ^ group] {
+                    let root = group.trailing_zeros() as usize;
+                    let candidate = (groups + 1, cost + tree[group * n + root].0, group);
+                    let better = match best[set] {
+                        None => true,
+                        Some((g, c, _)) => candidate.0 > g || (candidate.0 == g && candidate.1 < c),
+                    };
+                    if better {
+                        best[set] = Some(candidate);
+                    }
+                }
+            }
+            group = (group - 1) & set;
+        }
+    }
+    if !best[full].is_some_and(|(_, cost, _)| cost.is_finite()) {
+        return None;
+    }
+    let mut edges = BTreeMap::new();
+    let mut set = full;
+    while let Some((_, _, group)) = best[set].filter(|_| set != 0) {
+        let mut trees = vec![(group, group.trailing_zeros() as usize)];
+        while let Some((tree_set, root)) = trees.pop() {
+            if tree_set == 1 << root {
+                continue;
+            }
+            let (_, subtree, child) = tree[tree_set * n + root];
+            let (e, w) = transaction(child, root, sums[subtree]);
+            edges.insert(e, w);
+            trees.extend([(subtree, child), (tree_set ^ subtree, root)]);
+        }
+        set ^= group;
+    }
+    Some(edges)
+}
+
+/// Settles the people of the solution with the plan of the least total cost of its transactions
+/// among all plans with the minimal number of transactions, if there are at most
+/// [`MAX_CHEAPEST_PEOPLE`] people with a balance. Otherwise, every group of people, who pay each
+/// other in the solution, is settled on its own, either by its cheapest plan, if it is small
+/// enough, or by the cheapest arrangement among 'GreedySatisfaction', paying between the cheapest
+/// pairs first, a star around every person of the group and the transactions of the solution.
+/// Arrangements with more transactions than the solution aren't used, so large groups aren't
+/// guaranteed to be settled optimally.
+#[instrument(level = "debug", skip_all)]
+fn cheapest_groups(
+    instance: &ProblemInstance,
+    solution: &EdgeSolution,
+    cost: impl Fn(usize, usize) -> f64,
+) -> EdgeSolution {
+    solution.as_ref()?;
+    if let Some(cheapest) = cheapest_settlement(&instance.g.vertices, &cost) {
+        return Some(cheapest);
+    }
+    let by_name: HashMap<&str, &NamedNode> = instance
+        .g
+        .vertices
+        .iter()
+        .map(|v| (v.name.as_str(), v))
+        .collect();
+    let total = |edges: &BTreeMap<Edge, i64>| total_cost(edges, &cost);
+    let mut arranged = BTreeMap::new();
+    for group in instance.partitioning(solution)? {
+        let people: Vec<NamedNode> = group
+            .iter()
+            .map(|name| by_name[name.as_str()].to_owned())
+            .collect();
+        if let Some(cheapest) = cheapest_settlement(&people, &cost) {
+            arranged.extend(cheapest);
+            continue;
+        }
+        let ids: Vec<usize> = people.iter().map(|v| v.id).collect();
+        let current: BTreeMap<Edge, i64> = solution
+            .as_ref()?
+            .iter()
+            .filter(|(e, _)| ids.contains(&e.u))
+            .map(|(e, w)| (e.to_owned(), *w))
+            .collect();
+        let candidates = greedy_satisfaction(&ProblemInstance::from(Graph::from(people.clone())))
+            .into_iter()
+            .chain([cheapest_pairs_first(&people, &cost)])
+            .chain(ids.iter().map(|hub| star(*hub, &people)));
+        let limit = current.len();
+        let best = candidates
+            .filter(|c| c.len() <= limit)
+            .fold(
+                current,
+                |best, c| {
+                    if total(&c) < total(&best) {
+                        c
+                    } else {
+                        best
+                    }
+                },
+            );
+        arranged.extend(best);
+    }
+    Some(arranged)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::graph::{Edge, Graph};
+    use crate::pipeline::{preferred_pairs_groups, Pipeline, Postprocessing, Preprocessing};
+    use crate::probleminstance::{ProblemInstance, SolvingMethods};
+    use crate::solver_config::SolverConfig;
+    use crate::verification::{verify_plan, Payment};
+    use tracing::debug;
+    use tracing_subscriber::EnvFilter;
+
+    fn init() {
+        let _ = tracing_subscriber::fmt()
+            .with_env_filter(
+                EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("debug")),
+            )
+            .with_test_writer()
+            .try_init();
+    }
+
+    #[test]
+    fn test_pipeline() {
+        init();
+        debug!("Running 'test_pipeline'");
+        let graph = Graph::from(vec![
+            (("A".to_owned(), "B".to_owned()), 5),
+            (("B".to_owned(), "C".to_owned()), 3),
+            (("D".to_owned(), "E".to_owned()), 4),
+            (("F".to_owned(), "E".to_owned()), 2),
+            (("G".to_owned(), "H".to_owned()), 7),
+        ]);
+        let instance = ProblemInstance::from(graph);
+        let config = SolverConfig {
+            method: SolvingMethods::DPGreedySatisfaction,
+            pipeline: Pipeline {
+                pre: vec![
+                    Arc::new(Preprocessing::Components),
+                    Arc::new(Preprocessing::OppositePairs),
+                ],
+                post: vec![
+                    Arc::new(Postprocessing::Resettle),
+                    Arc::new(Postprocessing::SinglePayment),
+                ],
+            },
+            ..Default::default()
+        };
+        debug!("Config: {:?}", config);
+        let sol = instance.solve_edges_with_config(&config).unwrap();
+        let plan: Vec<Payment> = instance
+            .solution_transactions(&sol)
+            .unwrap()
+            .into_iter()
+            .map(|(from, to, amount)| Payment { from, to, amount })
+            .collect();
+        debug!("Plan: {:?}", plan);
+        assert!(verify_plan(&instance.g, &plan).is_settled());
+        assert_eq!(sol.map(|s| s.len()), instance.min_transactions());
+    }
+
+    #[test]
+    fn test_preferred_pairs_groups() {
+        init();
+        debug!("Running 'test_preferred_pairs_groups'");
+        let instance = ProblemInstance::from(Graph::from(vec![
+            ("A".to_owned(), -2),
+            ("B".to_owned(), -2),
+            ("C".to_owned(), 2),
+            ("D".to_owned(), 2),
+        ]));
+        // A pays D and B pays C.
+        let sol = Some(
+            [(Edge { u: 3, v: 0 }, 2), (Edge { u: 2, v: 1 }, 2)]
+                .into_iter()
+                .collect(),
+        );
+        let pair = |a: &str, b: &str| vec![(a.to_owned(), b.to_owned())];
+        let has = |sol: &Option<_>, a: &str, b: &str| {
+            instance
+                .solution_transactions(sol)
+                .unwrap()
+                .iter()
+                .any(|(from, to, _)| (from == a && to == b) || (from == b && to == a))
+        };
+        let preferred = preferred_pairs_groups(&instance, &sol, &pair("B", "D"), &[]);
+        assert!(has(&preferred, "B", "D"));
+        // Paying between B and D forces A to pay C, which is forbidden.
+        let kept = preferred_pairs_groups(&instance, &sol, &pair("B", "D"), &pair("A", "C"));
+        assert_eq!(kept, sol);
+        assert!(!has(&kept, "A", "C"));
+    }
+}
@@ -1,11 +1,27 @@
-use log::debug;
-use petgraph::{dot::Dot, graph::DiGraph, graph::NodeIndex};
-use std::collections::HashMap;
+use petgraph::dot::Dot;
+use petgraph::graph::{DiGraph, EdgeReference, NodeIndex};
+use petgraph::visit::EdgeRef;
+use serde_derive::Serialize;
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Display;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tracing::{debug, instrument, warn};
 
 use crate::approximation::{greedy_satisfaction, star_expand};
-use crate::dynamic_program::patcas_dp;
+use crate::bucketing::bucketed_dp;
+use crate::decimals::format_minor;
+use crate::dynamic_program::{dp_min_transactions, patcas_dp, TABLE_ENTRY_BYTES};
+use crate::events::{Progress, Recorder, SolverEvent, SolverObserver};
 use crate::exact_partitioning::naive_all_partitioning;
-use crate::graph::{Edge, Graph, NamedNode};
+use crate::genetic::genetic;
+use crate::graph::{Edge, Graph, Metadata, NamedNode};
+use crate::hierarchical::hierarchical;
+use crate::metadata::{payment_link, render_transaction};
+use crate::settlement::{Settlement, Transaction};
+use crate::solver_config::SolverConfig;
 use crate::tree_bases::best_partition;
 
 #[cfg(windows)]
@@ -13,15 +29,100 @@ const LINE_ENDING: &str = "\r\n";
 #[cfg(not(windows))]
 const LINE_ENDING: &str = "\n";
 
-pub type Solution = Option<HashMap<Edge, f64>>;
+/// Transactions of a solution. The transactions are sorted, so outputs are the same on every run.
+/// The edges reference the internal ids of the vertices, so only the solvers use them. Use
+/// [`ProblemInstance::settlement()`] to use a solution without the instance.
+pub(crate) type EdgeSolution = Option<BTreeMap<Edge, i64>>;
 
-#[derive(Copy, Clone, Debug, clap::ValueEnum)]
+/// Options of the methods, which have some, given to [`ProblemInstance::solve_with_options()`].
+#[derive(Clone, Debug, Default)]
+pub(crate) struct MethodOptions<'a> {
+    pub(crate) bucket_size: Option<i64>,
+    pub(crate) cluster_by: Option<&'a str>,
+    pub(crate) seed: Option<u64>,
+    pub(crate) time_budget: Option<Duration>,
+    /// Stops the exact methods, once any of its flags is set.
+    pub(crate) cancel: Cancel<'a>,
+    /// Use the approximation of the exact methods, if they find no solution without being
+    /// cancelled. Without it, they return `None` like on their own.
+    pub(crate) fallback: bool,
+}
+
+/// Flags, which stop the exact methods, once any of them is set, e.g. the Ctrl-C flag of the
+/// caller and the flag of a timeout. The methods only read the flags.
+#[derive(Copy, Clone, Debug, Default)]
+pub(crate) struct Cancel<'a>([Option<&'a AtomicBool>; 2]);
+
+impl<'a> Cancel<'a> {
+    pub(crate) fn new(flag: Option<&'a AtomicBool>) -> Self {
+        Cancel([flag, None])
+    }
+
+    /// Stops the methods as well, once `flag` is set.
+    pub(crate) fn or(self, flag: Option<&'a AtomicBool>) -> Self {
+        Cancel([self.0[0], flag])
+    }
+}
+
+/// Whether the solve was cancelled by setting one of the flags.
+pub(crate) fn is_cancelled(cancel: Cancel) -> bool {
+    cancel.0.iter().flatten().any(|c| c.load(Ordering::Relaxed))
+}
+
+/// Transactions of a solution with the names of the people, which are independent of the
+/// instance. Created by [`ProblemInstance::named_solution()`].
+pub type NamedSolution = Option<Settlement>;
+
+/// Options of [`ProblemInstance::solution_to_dot_string_with()`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DotOptions {
+    /// Render the original debts as dashed gray edges beneath the transactions of the solution.
+    /// Graphs without debts, see [`Graph::debts()`], are rendered without them.
+    pub show_debts: bool,
+}
+
+/// Edge of the dot output, which is either a transaction or an original debt.
+struct DotEdge {
+    amount: i64,
+    decimals: u32,
+    debt: bool,
+}
+
+impl std::fmt::Display for DotEdge {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", format_minor(self.amount, self.decimals, '.'))
+    }
+}
+
+/// Settlement together with the groups of people, who settle among themselves.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PartitionedSolution {
+    pub settlement: Settlement,
+    /// Names of the people of every zero-sum group. The groups are ordered like their first
+    /// person in the instance. People with a weight of 0 belong to no group.
+    pub partitions: Vec<Vec<String>>,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
 pub enum SolvingMethods {
     /// 2-Approximation schema with one high responsibility node.
     /// Doesn't necessarily return minimal total transaction amount possible.
+    #[default]
     ApproxStarExpand,
     /// 2-Approximation schema with minimal edge weight sum.
     ApproxGreedySatisfaction,
+    /// 2-Approximation schema, which rounds the weights into buckets and partitions the rounded
+    /// instance with a dynamic program. Near-optimal for instances too large for exact methods.
+    ApproxBucketedDP,
+    /// 2-Approximation schema, which splits the people into clusters, solves them exactly and
+    /// settles the residuals of the clusters the same way. Meant for instances with hundreds of
+    /// people.
+    ApproxHierarchical,
+    /// 2-Approximation schema, which evolves a population of partitionings of the people
+    /// starting from the one of 'GreedySatisfaction'. Meant for instances too large for exact
+    /// methods, on which it returns the best solution found within a time budget.
+    ApproxGenetic,
     /// Excat partitioning based solving algorithmus, which solves partitions with 'StarExpand'.
     /// Doesn't necessarily return minimal total transaction amount possible.
     PartitioningStarExpand,
@@ -40,8 +141,249 @@ pub enum SolvingMethods {
     DPGreedySatisfaction,
 }
 
+impl SolvingMethods {
+    /// All solving methods in the order of their declaration.
+    pub const ALL: [SolvingMethods; 11] = [
+        SolvingMethods::ApproxStarExpand,
+        SolvingMethods::ApproxGreedySatisfaction,
+        SolvingMethods::ApproxBucketedDP,
+        SolvingMethods::ApproxHierarchical,
+        SolvingMethods::ApproxGenetic,
+        SolvingMethods::PartitioningStarExpand,
+        SolvingMethods::PartitioningGreedySatisfaction,
+        SolvingMethods::BranchingPartitionStarExpand,
+        SolvingMethods::BranchingPartitionGreedySatisfaction,
+        SolvingMethods::DPStarExpand,
+        SolvingMethods::DPGreedySatisfaction,
+    ];
+
+    /// Name of the method on the command line, e.g. 'dp-greedy-satisfaction'.
+    ///
+    /// Example:
+    /// ```
+    /// use payback::probleminstance::SolvingMethods;
+    ///
+    /// assert_eq!(SolvingMethods::ApproxBucketedDP.name(), "approx-bucketed-dp");
+    /// ```
+    pub fn name(&self) -> &'static str {
+        match self {
+            SolvingMethods::ApproxStarExpand => "approx-star-expand",
+            SolvingMethods::ApproxGreedySatisfaction => "approx-greedy-satisfaction",
+            SolvingMethods::ApproxBucketedDP => "approx-bucketed-dp",
+            SolvingMethods::ApproxHierarchical => "approx-hierarchical",
+            SolvingMethods::ApproxGenetic => "approx-genetic",
+            SolvingMethods::PartitioningStarExpand => "partitioning-star-expand",
+            SolvingMethods::PartitioningGreedySatisfaction => "partitioning-greedy-satisfaction",
+            SolvingMethods::BranchingPartitionStarExpand => "branching-partition-star-expand",
+            SolvingMethods::BranchingPartitionGreedySatisfaction => {
+                "branching-partition-greedy-satisfaction"
+            }
+            SolvingMethods::DPStarExpand => "dp-star-expand",
+            SolvingMethods::DPGreedySatisfaction => "dp-greedy-satisfaction",
+        }
+    }
+
+    /// Largest amount of vertices for which the method is expected to finish in reasonable time.
+    /// Approximations have no such limit and return `None`.
+    pub fn max_feasible_vertices(&self) -> Option<usize> {
+        match self {
+            SolvingMethods::ApproxStarExpand
+            | SolvingMethods::ApproxGreedySatisfaction
+            | SolvingMethods::ApproxBucketedDP
+            | SolvingMethods::ApproxHierarchical
+            | SolvingMethods::ApproxGenetic => None,
+            SolvingMethods::PartitioningStarExpand
+            | SolvingMethods::PartitioningGreedySatisfaction => Some(10),
+            SolvingMethods::BranchingPartitionStarExpand
+            | SolvingMethods::BranchingPartitionGreedySatisfaction
+            | SolvingMethods::DPStarExpand
+            | SolvingMethods::DPGreedySatisfaction => Some(20),
+        }
+    }
+
+    /// Checks if the method always returns a solution with the minimal number of transactions.
+    pub fn is_exact(&self) -> bool {
+        !matches!(
+            self,
+            SolvingMethods::ApproxStarExpand
+                | SolvingMethods::ApproxGreedySatisfaction
+                | SolvingMethods::ApproxBucketedDP
+                | SolvingMethods::ApproxHierarchical
+                | SolvingMethods::ApproxGenetic
+        )
+    }
+
+    /// Checks if the solution of the method depends on a random seed.
+    pub fn is_randomized(&self) -> bool {
+        matches!(self, SolvingMethods::ApproxGenetic)
+    }
+
+    /// Runtime of the method in terms of the number of vertices n.
+    pub fn complexity(&self) -> &'static str {
+        match self {
+            SolvingMethods::ApproxStarExpand | SolvingMethods::ApproxGreedySatisfaction => "O(n)",
+            SolvingMethods::ApproxBucketedDP => "O(n^2)",
+            SolvingMethods::ApproxHierarchical => "O(n log n)",
+            SolvingMethods::ApproxGenetic => "O(n log n) per generation",
+            SolvingMethods::PartitioningStarExpand
+            | SolvingMethods::PartitioningGreedySatisfaction => "O*(n^n / (ln n)^n)",
+            SolvingMethods::BranchingPartitionStarExpand
+            | SolvingMethods::BranchingPartitionGreedySatisfaction
+            | SolvingMethods::DPStarExpand
+            | SolvingMethods::DPGreedySatisfaction => "O*(3^n)",
+        }
+    }
+
+    /// Checks if the method is feasible for an instance with `vertices` many vertices.
+    pub fn is_feasible_for(&self, vertices: usize) -> bool {
+        self.max_feasible_vertices()
+            .is_none_or(|max| vertices <= max)
+    }
+
+    /// Method chosen for an instance with `vertices` many vertices with non zero weight without
+    /// further knowledge. This is the exact 'DPGreedySatisfaction' for instances small enough for
+    /// it and 'ApproxBucketedDP' otherwise.
+    ///
+    /// Example:
+    /// ```
+    /// use payback::probleminstance::SolvingMethods;
+    ///
+    /// assert_eq!(SolvingMethods::auto(8), SolvingMethods::DPGreedySatisfaction);
+    /// assert_eq!(SolvingMethods::auto(100), SolvingMethods::ApproxBucketedDP);
+    /// ```
+    pub fn auto(vertices: usize) -> SolvingMethods {
+        if SolvingMethods::DPGreedySatisfaction.is_feasible_for(vertices) {
+            SolvingMethods::DPGreedySatisfaction
+        } else {
+            SolvingMethods::ApproxBucketedDP
+        }
+    }
+
+    /// Rough upper bound on the bytes of the largest data structure of the method for an
+    /// instance with `vertices` many vertices with non zero weight. The bound saturates at
+    /// `usize::MAX`. Approximations only need memory linear in the vertices and return `None`.
+    ///
+    /// Example:
+    /// ```
+    /// use payback::probleminstance::SolvingMethods;
+    ///
+    /// let small = SolvingMethods::DPGreedySatisfaction.estimated_memory(10).unwrap();
+    /// let large = SolvingMethods::DPGreedySatisfaction.estimated_memory(28).unwrap();
+    /// assert!(small < 1 << 20 && large > 1 << 30);
+    /// assert_eq!(SolvingMethods::ApproxStarExpand.estimated_memory(28), None);
+    /// ```
+    pub fn estimated_memory(&self, vertices: usize) -> Option<usize> {
+        let n = vertices as f64;
+        let vec = std::mem::size_of::<Vec<()>>() as f64;
+        let bytes = match self {
+            SolvingMethods::ApproxStarExpand
+            | SolvingMethods::ApproxGreedySatisfaction
+            | SolvingMethods::ApproxBucketedDP
+            | SolvingMethods::ApproxHierarchical
+            | SolvingMethods::ApproxGenetic => return None,
+            // Every pair of subsets of the positive and negative vertices can be a table entry.
+            // The hash map keeps an eighth of its capacity free.
+            SolvingMethods::DPStarExpand | SolvingMethods::DPGreedySatisfaction => {
+                n.exp2() * 8.0 / 7.0 * TABLE_ENTRY_BYTES as f64
+            }
+            // Half of the subsets with half of the vertices each.
+            SolvingMethods::BranchingPartitionStarExpand
+            | SolvingMethods::BranchingPartitionGreedySatisfaction => {
+                (n - 1.0).exp2() * (vec + n / 2.0 * std::mem::size_of::<NamedNode>() as f64)
+            }
+            // Every partitioning has at most n partitions referencing n vertices.
+            SolvingMethods::PartitioningStarExpand
+            | SolvingMethods::PartitioningGreedySatisfaction => {
+                bell_number(vertices) * (vec + n * vec + n * std::mem::size_of::<&()>() as f64)
+            }
+        };
+        // Casting saturates.
+        Some(bytes as usize)
+    }
+}
+
+impl Display for SolvingMethods {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// Parses the name of a method, see [`SolvingMethods::name()`], ignoring the case.
+///
+/// Example:
+/// ```
+/// use payback::probleminstance::SolvingMethods;
+///
+/// let method: SolvingMethods = "DP-Greedy-Satisfaction".parse().unwrap();
+/// assert_eq!(method, SolvingMethods::DPGreedySatisfaction);
+/// assert_eq!(method.to_string(), "dp-greedy-satisfaction");
+/// assert!("greedy".parse::<SolvingMethods>().is_err());
+/// ```
+impl FromStr for SolvingMethods {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        SolvingMethods::ALL
+            .into_iter()
+            .find(|m| m.name().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| {
+                format!(
+                    "Unknown solving method {:?}, expected one of: {}.",
+                    s,
+                    SolvingMethods::ALL.map(|m| m.name()).join(", ")
+                )
+            })
+    }
+}
+
+/// Number of partitionings of a set with `n` elements computed with the Bell triangle.
+fn bell_number(n: usize) -> f64 {
+    let mut row = vec![1.0];
+    for _ in 1..n {
+        let mut next = vec![*row.last().expect("Rows aren't empty.")];
+        for x in &row {
+            next.push(next.last().expect("Rows aren't empty.") + x);
+        }
+        row = next;
+    }
+    *row.last().expect("Rows aren't empty.")
+}
+
+/// Bounds on the minimal number of transactions given by
+/// [`ProblemInstance::transaction_bounds()`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TransactionBounds {
+    pub lower: usize,
+    pub upper: usize,
+}
+
+impl TransactionBounds {
+    /// Whether the approximation is optimal, so an exact method can't find fewer transactions.
+    pub fn is_tight(&self) -> bool {
+        self.lower == self.upper
+    }
+}
+
+impl Display for TransactionBounds {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Lower bound: {}", self.lower)?;
+        write!(
+            f,
+            "Upper bound: {} (gap: {})",
+            self.upper,
+            self.upper - self.lower
+        )
+    }
+}
+
+#[derive(Clone)]
 pub struct ProblemInstance {
     pub(crate) g: Graph,
+    /// Decimal places of the amounts, which are given in minor units. See [`crate::decimals`].
+    decimals: u32,
+    /// Largest amount of a single transfer. Larger transactions are split into several transfers
+    /// by [`ProblemInstance::settlement()`].
+    max_amount: Option<i64>,
 }
 
 impl From<Graph> for ProblemInstance {
@@ -53,37 +395,337 @@ impl From<Graph> for ProblemInstance {
 #[allow(dead_code)]
 impl ProblemInstance {
     fn new(graph: Graph) -> Self {
-        ProblemInstance { g: graph }
+        ProblemInstance {
+            g: graph,
+            decimals: 0,
+            max_amount: None,
+        }
+    }
+
+    /// Formats the amounts of the instance with `decimals` many decimal places, e.g. for amounts
+    /// in cents read by [`crate::decimals::to_minor_units()`].
+    ///
+    /// Example:
+    /// ```
+    /// use payback::graph::Graph;
+    /// use payback::probleminstance::{ProblemInstance, SolvingMethods};
+    ///
+    /// let instance = ProblemInstance::from(Graph::from(vec![-250, 250])).with_decimals(2);
+    /// let solution = instance.solve_with(SolvingMethods::DPGreedySatisfaction);
+    /// assert_eq!(instance.solution_string(&solution).unwrap().trim(), "\"0\" to \"1\": 2.50");
+    /// ```
+    pub fn with_decimals(self, decimals: u32) -> Self {
+        ProblemInstance { decimals, ..self }
+    }
+
+    pub fn decimals(&self) -> u32 {
+        self.decimals
+    }
+
+    /// Splits transactions larger than `max_amount` into several transfers, e.g. because of
+    /// limits of banks. The solution keeps one transaction per pair of people, so only its
+    /// [`ProblemInstance::settlement()`] and everything built on it is split. See
+    /// [`Settlement::split()`].
+    ///
+    /// Example:
+    /// ```
+    /// use payback::graph::Graph;
+    /// use payback::probleminstance::{ProblemInstance, SolvingMethods};
+    ///
+    /// let instance = ProblemInstance::from(Graph::from(vec![-5, 5])).with_max_amount(Some(2));
+    /// let settlement = instance.solve_with(SolvingMethods::DPGreedySatisfaction).unwrap();
+    /// assert_eq!(
+    ///     settlement.transactions().iter().map(|t| t.amount).collect::<Vec<_>>(),
+    ///     [2, 2, 1]
+    /// );
+    /// ```
+    pub fn with_max_amount(self, max_amount: Option<i64>) -> Self {
+        ProblemInstance { max_amount, ..self }
+    }
+
+    pub fn max_amount(&self) -> Option<i64> {
+        self.max_amount
     }
 
     pub fn is_solvable(&self) -> bool {
         let avg = self.g.get_average_vertex_weight();
         if avg != 0_f64 {
-            debug!(
-                "Graph {:?} has not the average weight {:?}. Should be 0",
-                self.g.to_string(),
-                avg
-            );
+            debug!(average = avg, "Graph has not the average weight 0");
             false
         } else {
             true
         }
     }
 
-    pub fn solve_with(&self, method: SolvingMethods) -> Solution {
-        match method {
+    /// Solves the instance with the method of [`SolvingMethods::auto()`] for its size.
+    ///
+    /// Example:
+    /// ```
+    /// use payback::graph::Graph;
+    /// use payback::probleminstance::ProblemInstance;
+    ///
+    /// let instance: ProblemInstance = Graph::from(vec![-3, -2, 1, 2, 2]).into();
+    /// assert_eq!(instance.solve().unwrap().len(), 3);
+    /// ```
+    pub fn solve(&self) -> Option<Settlement> {
+        let people = self.g.vertices.iter().filter(|v| v.weight != 0).count();
+        self.solve_with(SolvingMethods::auto(people))
+    }
+
+    /// Solves the instance with the method. `None` if no solution was found.
+    pub fn solve_with(&self, method: SolvingMethods) -> Option<Settlement> {
+        self.settlement(&self.solve_edges_with(method))
+    }
+
+    /// Solves the instance like [`ProblemInstance::solve_with()`], but keeps the edges of the
+    /// solution.
+    pub(crate) fn solve_edges_with(&self, method: SolvingMethods) -> EdgeSolution {
+        self.solve_with_options(method, &(), &MethodOptions::default())
+    }
+
+    /// Solves the instance like [`ProblemInstance::solve_with()`], but uses the approximation of
+    /// the method instead, if the method takes longer than the `timeout`. See
+    /// [`SolverConfig::fallback_on_timeout`].
+    ///
+    /// Example:
+    /// ```
+    /// use payback::graph::Graph;
+    /// use payback::probleminstance::{ProblemInstance, SolvingMethods};
+    /// use std::time::Duration;
+    ///
+    /// let instance: ProblemInstance = Graph::from(vec![-3, -2, 1, 2, 2]).into();
+    /// let sol = instance.solve_with_timeout(SolvingMethods::DPStarExpand, Duration::from_secs(10));
+    /// assert_eq!(sol.unwrap().len(), 3);
+    /// ```
+    pub fn solve_with_timeout(
+        &self,
+        method: SolvingMethods,
+        timeout: Duration,
+    ) -> Option<Settlement> {
+        let config = SolverConfig {
+            method,
+            timeout: Some(timeout),
+            fallback_on_timeout: true,
+            ..Default::default()
+        };
+        let solution = self
+            .solve_edges_with_config(&config)
+            .unwrap_or_else(|_| self.fallback_solution(method));
+        self.settlement(&solution)
+    }
+
+    /// Solves the instance like [`ProblemInstance::solve_with()`] and calls the `callback` with
+    /// the best solution so far, whenever it, the lower bound or the estimated progress changes.
+    /// Exact methods start with the solution of their approximation, so there is a solution to
+    /// show while they run.
+    ///
+    /// Example:
+    /// ```
+    /// use payback::events::Progress;
+    /// use payback::graph::Graph;
+    /// use payback::probleminstance::{ProblemInstance, SolvingMethods};
+    /// use std::cell::Cell;
+    ///
+    /// let instance: ProblemInstance = Graph::from(vec![-3, -2, 1, 2, 2]).into();
+    /// let fraction = Cell::new(0.0);
+    /// let sol = instance.solve_with_progress(SolvingMethods::DPStarExpand, &|p: &Progress| {
+    ///     if let Some(incumbent) = p.incumbent {
+    ///         println!("{} transactions, {:.0}% done", incumbent.len(), 100.0 * p.fraction);
+    ///     }
+    ///     fraction.set(p.fraction);
+    /// });
+    /// assert_eq!(sol.unwrap().len(), 3);
+    /// assert_eq!(fraction.get(), 1.0);
+    /// ```
+    pub fn solve_with_progress(
+        &self,
+        method: SolvingMethods,
+        callback: &dyn Fn(&Progress),
+    ) -> Option<Settlement> {
+        let incumbent = RefCell::new(None);
+        let lower_bound = Cell::new(0);
+        let fraction = Cell::new(0.0);
+        let observer = |event: &SolverEvent| {
+            match event {
+                SolverEvent::IncumbentSolution { solution } => {
+                    incumbent.replace(Some(solution.to_owned()));
+                }
+                SolverEvent::BoundImproved { lower_bound: bound } if *bound > lower_bound.get() => {
+                    lower_bound.set(*bound)
+                }
+                SolverEvent::Progress { fraction: f } => fraction.set(*f),
+                _ => return,
+            }
+            callback(&Progress {
+                incumbent: incumbent.borrow().as_ref(),
+                lower_bound: lower_bound.get(),
+                fraction: fraction.get(),
+            })
+        };
+        // Exact methods show the solution of their approximation, until they finish.
+        if method.is_exact() && self.is_solvable() {
+            if let Some(solution) = self.settlement(&self.fallback_solution(method)) {
+                observer(&SolverEvent::IncumbentSolution { solution });
+            }
+        }
+        self.solve_observed(method, &observer)
+    }
+
+    /// Solves the instance like [`ProblemInstance::solve_with()`] and reports the progress to
+    /// the `observer`. See [`SolverEvent`] for the reported events.
+    #[instrument(level = "debug", skip(self, observer), fields(vertices = self.g.vertices.len()))]
+    pub fn solve_observed(
+        &self,
+        method: SolvingMethods,
+        observer: &dyn SolverObserver,
+    ) -> Option<Settlement> {
+        self.settlement(&self.solve_with_options(method, observer, &MethodOptions::default()))
+    }
+
+    /// Solves the instance like [`ProblemInstance::solve_observed()`] with the options of
+    /// methods, which have some.
+    pub(crate) fn solve_with_options(
+        &self,
+        method: SolvingMethods,
+        observer: &dyn SolverObserver,
+        options: &MethodOptions,
+    ) -> EdgeSolution {
+        let cancel = options.cancel;
+        let recorder = Recorder::new(observer);
+        recorder.on_event(&SolverEvent::BoundImproved {
+            lower_bound: self.transaction_lower_bound(),
+        });
+        let mut solution = match method {
             SolvingMethods::ApproxStarExpand => star_expand(self),
             SolvingMethods::ApproxGreedySatisfaction => greedy_satisfaction(self),
-            SolvingMethods::PartitioningStarExpand => naive_all_partitioning(self, &star_expand),
+            SolvingMethods::ApproxBucketedDP => bucketed_dp(self, options.bucket_size, &recorder),
+            SolvingMethods::ApproxHierarchical => hierarchical(self, options.cluster_by, &recorder),
+            SolvingMethods::ApproxGenetic => {
+                genetic(self, options.seed, options.time_budget, &recorder)
+            }
+            SolvingMethods::PartitioningStarExpand => {
+                naive_all_partitioning(self, &star_expand, &recorder, cancel)
+            }
             SolvingMethods::PartitioningGreedySatisfaction => {
-                naive_all_partitioning(self, &greedy_satisfaction)
+                naive_all_partitioning(self, &greedy_satisfaction, &recorder, cancel)
+            }
+            SolvingMethods::BranchingPartitionStarExpand => {
+                best_partition(self, &star_expand, &recorder, cancel)
             }
-            SolvingMethods::BranchingPartitionStarExpand => best_partition(self, &star_expand),
             SolvingMethods::BranchingPartitionGreedySatisfaction => {
-                best_partition(self, &greedy_satisfaction)
+                best_partition(self, &greedy_satisfaction, &recorder, cancel)
+            }
+            SolvingMethods::DPStarExpand => patcas_dp(self, &star_expand, &recorder, cancel),
+            SolvingMethods::DPGreedySatisfaction => {
+                patcas_dp(self, &greedy_satisfaction, &recorder, cancel)
+            }
+        };
+        // Exact solvers only fail on instances, which are too large for them, or when they are
+        // cancelled. A cancelled solver may return its best solution so far, which is only kept,
+        // if it beats the approximation. The approximation is only computed, if it may be used.
+        if method.is_exact()
+            && self.is_solvable()
+            && (is_cancelled(cancel) || (solution.is_none() && options.fallback))
+        {
+            let approximation = self.fallback_solution(method);
+            let fallback = match (&solution, &approximation) {
+                (None, _) => true,
+                (Some(sol), Some(approx)) => approx.len() < sol.len(),
+                (Some(_), None) => false,
+            };
+            if fallback {
+                let reason = match is_cancelled(cancel) {
+                    true => format!("{:?} was cancelled.", method),
+                    false => format!("{:?} found no solution.", method),
+                };
+                recorder.on_event(&SolverEvent::FallbackTriggered { reason });
+                solution = approximation;
+            }
+        }
+        if !method.is_exact() || recorder.fallback() {
+            if let Some(sol) = &solution {
+                recorder.on_event(&SolverEvent::NewIncumbent {
+                    transactions: sol.len(),
+                });
             }
-            SolvingMethods::DPStarExpand => patcas_dp(self, &star_expand),
-            SolvingMethods::DPGreedySatisfaction => patcas_dp(self, &greedy_satisfaction),
+        }
+        if let Some(sol) = &solution {
+            recorder.on_event(&SolverEvent::IncumbentSolution {
+                solution: self.settlement(&Some(sol.to_owned())).unwrap_or_default(),
+            });
+        }
+        recorder.on_event(&SolverEvent::Progress { fraction: 1.0 });
+        recorder.finish(solution.as_ref().map(|s| s.len()));
+        solution
+    }
+
+    /// Sum of all weights, which is 0 for solvable instances.
+    pub fn imbalance(&self) -> i64 {
+        self.g.vertices.iter().map(|v| v.weight).sum()
+    }
+
+    /// Number of vertices in the instance.
+    pub fn vertex_count(&self) -> usize {
+        self.g.vertices.len()
+    }
+
+    /// Lower bound on the number of transactions needed to settle the instance.
+    /// Every person with a positive weight has to receive and every person with a negative
+    /// weight has to make at least one transaction.
+    pub fn transaction_lower_bound(&self) -> usize {
+        let positive = self.g.vertices.iter().filter(|v| v.weight > 0).count();
+        let negative = self.g.vertices.iter().filter(|v| v.weight < 0).count();
+        positive.max(negative)
+    }
+
+    /// Bounds on the minimal number of transactions, which are computed in milliseconds even
+    /// for large instances. The upper bound is the number of transactions of the better of the
+    /// approximations 'StarExpand' and 'GreedySatisfaction'. Returns `None` for unsolvable
+    /// instances.
+    ///
+    /// Example:
+    /// ```
+    /// use payback::graph::Graph;
+    /// use payback::probleminstance::ProblemInstance;
+    ///
+    /// let instance: ProblemInstance = Graph::from(vec![-3, -2, 1, 4]).into();
+    /// let bounds = instance.transaction_bounds().unwrap();
+    /// assert_eq!((bounds.lower, bounds.upper), (2, 3));
+    /// ```
+    pub fn transaction_bounds(&self) -> Option<TransactionBounds> {
+        let upper = [star_expand(self)?.len(), greedy_satisfaction(self)?.len()]
+            .into_iter()
+            .min()?;
+        Some(TransactionBounds {
+            lower: self.transaction_lower_bound(),
+            upper,
+        })
+    }
+
+    /// Minimal number of transactions needed to settle the instance. Opposed to solving the
+    /// instance, only the number is computed, which is faster. Returns `None` for unsolvable
+    /// instances and instances with more than 128 people with a non zero weight.
+    ///
+    /// Example:
+    /// ```
+    /// use payback::graph::Graph;
+    /// use payback::probleminstance::ProblemInstance;
+    ///
+    /// let instance: ProblemInstance = Graph::from(vec![-2, -1, 1, 2]).into();
+    /// assert_eq!(instance.min_transactions(), Some(2));
+    /// ```
+    pub fn min_transactions(&self) -> Option<usize> {
+        dp_min_transactions(self)
+    }
+
+    /// Solution of the approximation, which replaces the exact `method`, if it fails or takes
+    /// too long.
+    pub(crate) fn fallback_solution(&self, method: SolvingMethods) -> EdgeSolution {
+        match method {
+            SolvingMethods::PartitioningStarExpand
+            | SolvingMethods::BranchingPartitionStarExpand
+            | SolvingMethods::DPStarExpand => star_expand(self),
+            _ => greedy_satisfaction(self),
         }
     }
 
@@ -91,66 +733,343 @@ impl ProblemInstance {
         self.g.vertices.iter().map(|v| v.weight.abs()).sum::<i64>() / 2
     }
 
-    pub fn solution_string(&self, solution: &Solution) -> Result<String, String> {
+    /// Solves the instance like [`ProblemInstance::solve_with()`] and groups the people, who
+    /// settle among themselves.
+    ///
+    /// Example:
+    /// ```
+    /// use payback::graph::Graph;
+    /// use payback::probleminstance::{ProblemInstance, SolvingMethods};
+    ///
+    /// let instance: ProblemInstance = Graph::from(vec![
+    ///     ("A".to_string(), -2),
+    ///     ("B".to_string(), -1),
+    ///     ("C".to_string(), 1),
+    ///     ("D".to_string(), 2),
+    /// ])
+    /// .into();
+    /// let solution = instance.solve_partitioned(SolvingMethods::DPStarExpand).unwrap();
+    /// assert_eq!(solution.partitions, [vec!["A", "D"], vec!["B", "C"]]);
+    /// ```
+    pub fn solve_partitioned(&self, method: SolvingMethods) -> Option<PartitionedSolution> {
+        let solution = self.solve_edges_with(method);
+        Some(PartitionedSolution {
+            partitions: self.partitioning(&solution)?,
+            settlement: self.settlement(&solution)?,
+        })
+    }
+
+    /// Groups of people, who only pay each other in the solution. Since the solvers solve every
+    /// partition with a tree, these are the partitions found by the exact solvers.
+    pub(crate) fn partitioning(&self, solution: &EdgeSolution) -> Option<Vec<Vec<String>>> {
+        let solution = solution.as_ref()?;
+        // Union find over the positions of the vertices.
+        let position: HashMap<usize, usize> = self
+            .g
+            .vertices
+            .iter()
+            .enumerate()
+            .map(|(i, v)| (v.id, i))
+            .collect();
+        let mut parent: Vec<usize> = (0..self.g.vertices.len()).collect();
+        fn root(parent: &mut [usize], mut i: usize) -> usize {
+            while parent[i] != i {
+                parent[i] = parent[parent[i]];
+                i = parent[i];
+            }
+            i
+        }
+        for edge in solution.keys() {
+            if let (Some(u), Some(v)) = (position.get(&edge.u), position.get(&edge.v)) {
+                let (u, v) = (root(&mut parent, *u), root(&mut parent, *v));
+                parent[u.max(v)] = u.min(v);
+            }
+        }
+        let mut groups: BTreeMap<usize, Vec<String>> = BTreeMap::new();
+        for (i, v) in self.g.vertices.iter().enumerate() {
+            if v.weight != 0 {
+                let r = root(&mut parent, i);
+                groups.entry(r).or_default().push(v.name.to_owned());
+            }
+        }
+        Some(groups.into_values().collect())
+    }
+
+    /// Solves the instance like [`ProblemInstance::solve_with()`] with one transaction per payer
+    /// and payee sorted by their names. So it doesn't depend on the ids or the order of the
+    /// people.
+    ///
+    /// Example:
+    /// ```
+    /// use payback::graph::Graph;
+    /// use payback::probleminstance::{ProblemInstance, SolvingMethods};
+    /// use payback::settlement::Transaction;
+    ///
+    /// let instance = ProblemInstance::from(Graph::from(vec![
+    ///     ("Alice".to_string(), -2),
+    ///     ("Bob".to_string(), 2),
+    /// ]));
+    /// let named = instance.solve_named(SolvingMethods::ApproxStarExpand).unwrap();
+    /// let alice_to_bob = Transaction {
+    ///     payer: "Alice".to_string(),
+    ///     payee: "Bob".to_string(),
+    ///     amount: 2,
+    /// };
+    /// assert_eq!(named.transactions(), [alice_to_bob]);
+    /// ```
+    pub fn solve_named(&self, method: SolvingMethods) -> NamedSolution {
+        self.named_solution(&self.solve_edges_with(method))
+    }
+
+    /// Settlement of the solution like [`ProblemInstance::settlement()`], but with one
+    /// transaction per payer and payee sorted by their names. So it doesn't depend on the ids or
+    /// the order of the people.
+    pub(crate) fn named_solution(&self, solution: &EdgeSolution) -> NamedSolution {
+        let mut named: BTreeMap<(String, String), i64> = BTreeMap::new();
+        for t in self.settlement(solution)? {
+            *named.entry((t.payer, t.payee)).or_default() += t.amount;
+        }
+        Some(Settlement::new(
+            named
+                .into_iter()
+                .map(|((payer, payee), amount)| Transaction {
+                    payer,
+                    payee,
+                    amount,
+                })
+                .collect(),
+        ))
+    }
+
+    /// Transactions of a solution with the names of the people. `None` if no solution was found.
+    /// Transactions larger than [`ProblemInstance::max_amount()`] are split.
+    pub(crate) fn settlement(&self, solution: &EdgeSolution) -> Option<Settlement> {
+        solution.as_ref().map(|map| {
+            let settlement = Settlement::new(
+                map.iter()
+                    .map(|(edge, amount)| Transaction::from_edge(&self.g, edge, *amount))
+                    .collect(),
+            );
+            match self.max_amount {
+                Some(max) => settlement.split(max),
+                None => settlement,
+            }
+        })
+    }
+
+    /// Lists the transactions of a solution as tuples of payer, payee and amount.
+    pub(crate) fn solution_transactions(
+        &self,
+        solution: &EdgeSolution,
+    ) -> Option<Vec<(String, String, i64)>> {
+        self.settlement(solution)
+            .map(|settlement| settlement.into_iter().map(Into::into).collect())
+    }
+
+    pub fn solution_string(&self, solution: &Option<Settlement>) -> Result<String, String> {
         match solution {
             None => Err("No result was found.".to_string()),
-            Some(map) => {
-                let mut res: String = "".to_string();
-                for (edge, weight) in map {
-                    let u = self.g.get_node_name_or(edge.u, edge.u.to_string());
-                    let v = self.g.get_node_name_or(edge.v, edge.v.to_string());
-                    if *weight >= 0.0 {
-                        res += &format!("{:?} to {:?}: {:?}", v, u, weight);
-                    } else {
-                        res += &format!("{:?} to {:?}: {:?}", u, v, -weight);
-                    }
-                    res += LINE_ENDING;
-                }
-                Ok(res)
-            }
+            Some(settlement) => Ok(settlement
+                .transactions()
+                .iter()
+                .map(|t| {
+                    format!(
+                        "{:?} to {:?}: {}{}",
+                        t.payer,
+                        t.payee,
+                        format_minor(t.amount, self.decimals, '.'),
+                        LINE_ENDING
+                    )
+                })
+                .collect()),
         }
     }
 
-    pub fn solution_to_dot_string(&self, solution: &Solution) -> Result<String, String> {
+    /// Renders every transaction of a solution in its own line with the template. See
+    /// [`render_transaction()`] for the placeholders.
+    pub fn solution_template_string(
+        &self,
+        solution: &Option<Settlement>,
+        template: &str,
+    ) -> Result<String, String> {
+        let settlement = solution.as_ref().ok_or("No result was found.")?;
+        Ok(settlement
+            .transactions()
+            .iter()
+            .map(|t| {
+                render_transaction(
+                    template,
+                    (&t.payer, self.g.metadata(&t.payer)),
+                    (&t.payee, self.g.metadata(&t.payee)),
+                    t.amount,
+                    self.decimals,
+                ) + LINE_ENDING
+            })
+            .collect())
+    }
+
+    /// Metadata of the person with the name.
+    pub fn metadata(&self, name: &str) -> Option<&Metadata> {
+        self.g.metadata(name)
+    }
+
+    /// Effort of the person to make a transaction given by the metadata 'effort=<WEIGHT>'.
+    /// Defaults to 1 for people without or with an invalid effort.
+    pub fn effort(&self, name: &str) -> f64 {
+        self.metadata(name)
+            .and_then(|m| m.iter().find(|(k, _)| k == "effort"))
+            .and_then(|(_, effort)| {
+                effort
+                    .parse::<f64>()
+                    .inspect_err(|_| {
+                        warn!("Ignoring the invalid effort {:?} of {:?}.", effort, name)
+                    })
+                    .ok()
+            })
+            .unwrap_or(1.0)
+    }
+
+    /// Sum of the efforts of the payers of all transactions. See [`ProblemInstance::effort()`].
+    ///
+    /// Example:
+    /// ```
+    /// use payback::graph::Graph;
+    /// use payback::probleminstance::{ProblemInstance, SolvingMethods};
+    ///
+    /// let instance: ProblemInstance = Graph::from(vec![-2, -1, 3]).into();
+    /// let solution = instance.solve_with(SolvingMethods::ApproxStarExpand);
+    /// assert_eq!(instance.solution_effort(&solution), Some(2.0));
+    /// ```
+    pub fn solution_effort(&self, solution: &Option<Settlement>) -> Option<f64> {
+        Some(
+            solution
+                .as_ref()?
+                .transactions()
+                .iter()
+                .map(|t| self.effort(&t.payer))
+                .sum(),
+        )
+    }
+
+    /// Link for `from` to pay `amount` to `to` given by the metadata of `to`. See
+    /// [`payment_link()`].
+    pub fn payment_link(&self, from: &str, to: &str, amount: i64) -> Option<String> {
+        payment_link(self.g.metadata(to), from, to, amount, self.decimals)
+    }
+
+    pub fn solution_to_dot_string(&self, solution: &Option<Settlement>) -> Result<String, String> {
+        self.solution_to_dot_string_with(solution, &DotOptions::default())
+    }
+
+    /// Renders the solution in the dot format like [`ProblemInstance::solution_to_dot_string()`]
+    /// with the options.
+    ///
+    /// Example:
+    /// ```
+    /// use payback::graph::Graph;
+    /// use payback::probleminstance::{DotOptions, ProblemInstance, SolvingMethods};
+    ///
+    /// let graph: Graph = "A,B,1\nB,C,1".to_string().try_into().unwrap();
+    /// let instance = ProblemInstance::from(graph);
+    /// let solution = instance.solve_with(SolvingMethods::DPGreedySatisfaction);
+    /// let options = DotOptions { show_debts: true };
+    /// let dot = instance.solution_to_dot_string_with(&solution, &options).unwrap();
+    /// assert_eq!(dot.matches("style = dashed").count(), 2);
+    /// ```
+    pub fn solution_to_dot_string_with(
+        &self,
+        solution: &Option<Settlement>,
+        options: &DotOptions,
+    ) -> Result<String, String> {
         match solution {
-            None => {
-                println!("No result was found.");
-                Err("No result was found.".to_owned())
-            }
+            None => Err("No result was found.".to_owned()),
             Some(sol) => {
                 let mut pet_graph =
-                    DiGraph::<String, f64>::with_capacity(self.g.vertices.len(), sol.len());
+                    DiGraph::<String, DotEdge>::with_capacity(self.g.vertices.len(), sol.len());
                 let node_map: HashMap<NamedNode, NodeIndex> = self
                     .g
                     .vertices
                     .iter()
                     .map(|v| (v.to_owned(), pet_graph.add_node(v.name.to_owned())))
                     .collect();
-                sol.iter().try_for_each(|(e, w)| -> Result<(), String> {
-                    let u = self
-                        .g
-                        .get_node_from_id(e.u)
-                        .ok_or(format!("Can't find vertex with index {:?}", e.u))
-                        .and_then(|u_node| {
-                            node_map.get(u_node).ok_or(format!(
-                                "Can't find node '{:?}' in the pet graph.",
-                                u_node.name
-                            ))
-                        })?;
-                    let v = self
-                        .g
-                        .get_node_from_id(e.v)
-                        .ok_or(format!("Can't find vertex with index {:?}", e.v))
-                        .and_then(|v_node| {
-                            node_map.get(v_node).ok_or(format!(
+                let node_index = |id: usize| -> Result<NodeIndex, String> {
+                    self.g
+                        .get_node_from_id(id)
+                        .ok_or(format!("Can't find vertex with index {:?}", id))
+                        .and_then(|node| {
+                            node_map.get(node).copied().ok_or(format!(
                                 "Can't find node '{:?}' in the pet graph.",
-                                v_node.name
+                                node.name
                             ))
-                        })?;
-                    pet_graph.update_edge(v.to_owned(), u.to_owned(), *w);
-                    Ok(())
-                })?;
-                Ok(Dot::new(&pet_graph).to_string())
+                        })
+                };
+                if options.show_debts {
+                    // Edges added first are drawn beneath later ones.
+                    for debt in self.g.debts().unwrap_or_default() {
+                        let from = self
+                            .g
+                            .get_node_from_name(debt.from.to_owned())
+                            .ok_or(format!("Can't find vertex {:?}", debt.from))?;
+                        let to = self
+                            .g
+                            .get_node_from_name(debt.to.to_owned())
+                            .ok_or(format!("Can't find vertex {:?}", debt.to))?;
+                        pet_graph.add_edge(
+                            node_index(from.id)?,
+                            node_index(to.id)?,
+                            DotEdge {
+                                amount: debt.amount,
+                                decimals: self.decimals,
+                                debt: true,
+                            },
+                        );
+                    }
+                }
+                let debts = pet_graph.edge_count();
+                let node = |name: &str| -> Result<NodeIndex, String> {
+                    self.g
+                        .get_node_from_name(name.to_owned())
+                        .ok_or(format!("Can't find vertex {:?}", name))
+                        .and_then(|node| node_index(node.id))
+                };
+                sol.transactions()
+                    .iter()
+                    .try_for_each(|t| -> Result<(), String> {
+                        let (v, u) = (node(&t.payer)?, node(&t.payee)?);
+                        // Transactions split by the maximal amount are drawn as one edge.
+                        match pet_graph
+                            .edges_connecting(v, u)
+                            .map(|edge| edge.id())
+                            .find(|id| id.index() >= debts)
+                        {
+                            Some(id) => pet_graph[id].amount += t.amount,
+                            None => {
+                                pet_graph.add_edge(
+                                    v,
+                                    u,
+                                    DotEdge {
+                                        amount: t.amount,
+                                        decimals: self.decimals,
+                                        debt: false,
+                                    },
+                                );
+                            }
+                        }
+                        Ok(())
+                    })?;
+                let edge_attributes = |_, edge: EdgeReference<DotEdge>| {
+                    if edge.weight().debt {
+                        "style = dashed color = gray ".to_owned()
+                    } else {
+                        String::new()
+                    }
+                };
+                Ok(
+                    Dot::with_attr_getters(&pet_graph, &[], &edge_attributes, &|_, _| {
+                        String::new()
+                    })
+                    .to_string(),
+                )
             }
         }
     }
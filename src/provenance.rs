@@ -0,0 +1,113 @@
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+use crate::probleminstance::SolvingMethods;
+
+/// Objective optimized by the solving methods.
+pub const OBJECTIVE: &str = "min-transactions";
+
+/// Describes how a plan was produced, so a published plan can be traced back to its input and
+/// the used settings.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Provenance {
+    /// Version of payback.
+    pub version: String,
+    pub method: String,
+    /// Seed of randomized methods.
+    pub seed: Option<u64>,
+    pub objective: String,
+    /// Hash of the input in the form 'sha256:<hex>'.
+    pub input_hash: String,
+}
+
+/// Sha256 hash of the input in the form 'sha256:<hex>'.
+pub fn input_hash(input: &[u8]) -> String {
+    format!("sha256:{}", hex::encode(Sha256::digest(input)))
+}
+
+impl Provenance {
+    /// Provenance of a plan solved by the method from the input.
+    ///
+    /// Example:
+    /// ```
+    /// use payback::probleminstance::SolvingMethods;
+    /// use payback::provenance::Provenance;
+    ///
+    /// let provenance = Provenance::new(SolvingMethods::DPStarExpand, b"A,-1\nB,1");
+    /// assert_eq!(provenance.method, "dp-star-expand");
+    /// assert!(provenance.comment("#").starts_with("# payback "));
+    /// ```
+    pub fn new(method: SolvingMethods, input: &[u8]) -> Self {
+        Provenance {
+            version: env!("CARGO_PKG_VERSION").to_owned(),
+            method: method.name().to_owned(),
+            seed: None,
+            objective: OBJECTIVE.to_owned(),
+            input_hash: input_hash(input),
+        }
+    }
+
+    /// Json object with the keys 'version', 'method', 'seed', 'objective' and 'input_hash'.
+    pub fn to_json(&self) -> serde_json::Value {
+        json!({
+            "version": self.version,
+            "method": self.method,
+            "seed": self.seed,
+            "objective": self.objective,
+            "input_hash": self.input_hash,
+        })
+    }
+
+    /// One line summary starting with the comment marker of the output format, e.g.
+    /// '# payback 0.6.3 method=dp-star-expand objective=min-transactions input=sha256:...'.
+    pub fn comment(&self, marker: &str) -> String {
+        let seed = self
+            .seed
+            .map(|s| format!(" seed={}", s))
+            .unwrap_or_default();
+        format!(
+            "{} payback {} method={}{} objective={} input={}",
+            marker, self.version, self.method, seed, self.objective, self.input_hash
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::probleminstance::SolvingMethods;
+    use crate::provenance::{input_hash, Provenance};
+    use tracing::debug;
+    use tracing_subscriber::EnvFilter;
+
+    fn init() {
+        let _ = tracing_subscriber::fmt()
+            .with_env_filter(
+                EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("debug")),
+            )
+            .with_test_writer()
+            .try_init();
+    }
+
+    #[test]
+    fn test_provenance() {
+        init();
+        debug!("Running 'test_provenance'");
+        assert_eq!(
+            input_hash(b""),
+            "sha256:e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        let mut provenance = Provenance::new(SolvingMethods::ApproxStarExpand, b"A,-1\nB,1");
+        assert_ne!(
+            provenance,
+            Provenance::new(SolvingMethods::ApproxStarExpand, b"A,1\nB,-1")
+        );
+        provenance.seed = Some(7);
+        let comment = provenance.comment("//");
+        debug!("Comment: {}", comment);
+        assert!(comment.starts_with(&format!("// payback {}", env!("CARGO_PKG_VERSION"))));
+        assert!(comment.contains(" method=approx-star-expand seed=7 objective=min-transactions "));
+        let json = provenance.to_json();
+        assert_eq!(json["seed"], 7);
+        assert_eq!(json["input_hash"], provenance.input_hash);
+    }
+}
@@ -0,0 +1,177 @@
+use image::{ImageFormat, Luma};
+use qrcode::render::{svg, unicode};
+use qrcode::{EcLevel, QrCode};
+use std::io::Cursor;
+
+use crate::decimals::format_minor;
+use crate::graph::Metadata;
+
+/// Formats in which QR codes are rendered.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum QrFormat {
+    /// Unicode blocks, which can be scanned from the terminal.
+    Unicode,
+    Svg,
+    Png,
+}
+
+impl QrFormat {
+    /// File extension of the format.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            QrFormat::Unicode => "txt",
+            QrFormat::Svg => "svg",
+            QrFormat::Png => "png",
+        }
+    }
+}
+
+fn field<'a>(payee: &'a Metadata, key: &str) -> Option<&'a str> {
+    payee
+        .iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case(key))
+        .map(|(_, v)| v.as_str())
+}
+
+/// Builds the payload of an EPC QR code, which banking apps read as SEPA credit transfer.
+/// The IBAN of the payee is taken from the metadata key 'iban'. The optional keys 'bic' and
+/// 'name' set the BIC and the account holder, who defaults to the name of the payee.
+/// Amounts are always in euro, since EPC QR codes don't support other currencies.
+///
+/// Example:
+/// ```
+/// use payback::qr::epc_payload;
+///
+/// let bob = vec![("iban".to_string(), "DE02 1203 0000 0000 2020 51".to_string())];
+/// let payload = epc_payload("Bob", &bob, 4200, 2, "Payback").unwrap();
+/// assert_eq!(payload, "BCD\n002\n1\nSCT\n\nBob\nDE02120300000000202051\nEUR42.00\n\n\nPayback");
+/// ```
+pub fn epc_payload(
+    name: &str,
+    payee: &Metadata,
+    amount: i64,
+    decimals: u32,
+    text: &str,
+) -> Result<String, String> {
+    let iban: String = field(payee, "iban")
+        .ok_or(format!("{:?} has no IBAN.", name))?
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>()
+        .to_uppercase();
+    let holder = field(payee, "name").unwrap_or(name);
+    if holder.chars().count() > 70 {
+        return Err(format!(
+            "The name {:?} is longer than 70 characters.",
+            holder
+        ));
+    }
+    // EPC QR codes hold euros with cents from 0.01 to 999999999.99.
+    let cents = match 2_u32.checked_sub(decimals) {
+        Some(missing) => amount.checked_mul(10_i64.pow(missing)),
+        None => Some(amount / 10_i64.pow(decimals - 2))
+            .filter(|_| amount % 10_i64.pow(decimals - 2) == 0),
+    };
+    let Some(cents) = cents.filter(|c| (1..=99_999_999_999).contains(c)) else {
+        return Err(format!(
+            "The amount {} can't be paid by an EPC QR code.",
+            format_minor(amount, decimals, '.')
+        ));
+    };
+    Ok([
+        "BCD",
+        "002",
+        // UTF-8
+        "1",
+        "SCT",
+        field(payee, "bic").unwrap_or_default(),
+        holder,
+        &iban,
+        &format!("EUR{}", format_minor(cents, 2, '.')),
+        // Purpose and structured reference are left empty.
+        "",
+        "",
+        &text.chars().take(140).collect::<String>(),
+    ]
+    .join("\n"))
+}
+
+/// Renders the payload as QR code with the error correction level 'M' required by EPC QR codes.
+pub fn render_qr(payload: &str, format: QrFormat) -> Result<Vec<u8>, String> {
+    let code =
+        QrCode::with_error_correction_level(payload, EcLevel::M).map_err(|e| e.to_string())?;
+    match format {
+        QrFormat::Unicode => Ok(code
+            .render::<unicode::Dense1x2>()
+            .dark_color(unicode::Dense1x2::Light)
+            .light_color(unicode::Dense1x2::Dark)
+            .build()
+            .into_bytes()),
+        QrFormat::Svg => Ok(code
+            .render::<svg::Color>()
+            .min_dimensions(200, 200)
+            .build()
+            .into_bytes()),
+        QrFormat::Png => {
+            let mut png = Cursor::new(vec![]);
+            code.render::<Luma<u8>>()
+                .min_dimensions(200, 200)
+                .build()
+                .write_to(&mut png, ImageFormat::Png)
+                .map_err(|e| e.to_string())?;
+            Ok(png.into_inner())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::qr::{epc_payload, render_qr, QrFormat};
+    use tracing::debug;
+    use tracing_subscriber::EnvFilter;
+
+    fn init() {
+        let _ = tracing_subscriber::fmt()
+            .with_env_filter(
+                EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("debug")),
+            )
+            .with_test_writer()
+            .try_init();
+    }
+
+    #[test]
+    fn test_qr_codes() {
+        init();
+        debug!("Running 'test_qr_codes'");
+        let bob = vec![
+            ("iban".to_string(), "de02120300000000202051".to_string()),
+            ("bic".to_string(), "BYLADEM1001".to_string()),
+            ("name".to_string(), "Bob Ross".to_string()),
+        ];
+        let payload = epc_payload("Bob", &bob, 15, 0, "Alice to Bob").unwrap();
+        assert_eq!(
+            payload.lines().collect::<Vec<_>>()[4..8],
+            [
+                "BYLADEM1001",
+                "Bob Ross",
+                "DE02120300000000202051",
+                "EUR15.00"
+            ]
+        );
+        assert!(epc_payload("Bob", &vec![], 15, 0, "").is_err());
+        assert!(epc_payload("Bob", &bob, 0, 0, "").is_err());
+        assert!(epc_payload("Bob", &bob, 1000, 3, "")
+            .unwrap()
+            .contains("\nEUR1.00\n"));
+        assert!(epc_payload("Bob", &bob, 1001, 3, "").is_err());
+
+        let text = String::from_utf8(render_qr(&payload, QrFormat::Unicode).unwrap()).unwrap();
+        debug!("QR code:\n{}", text);
+        assert!(text.contains('█'));
+        let svg = String::from_utf8(render_qr(&payload, QrFormat::Svg).unwrap()).unwrap();
+        assert!(svg.contains("<svg"));
+        let png = render_qr(&payload, QrFormat::Png).unwrap();
+        assert_eq!(&png[1..4], b"PNG");
+    }
+}
@@ -0,0 +1,292 @@
+use serde_derive::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tracing::{debug, instrument};
+
+use crate::comparison::MethodResult;
+use crate::probleminstance::{ProblemInstance, SolvingMethods};
+
+/// Number of the most similar bench results of a method, from which its runtime and quality are
+/// predicted.
+const NEIGHBORS: usize = 3;
+
+/// Features of an instance, by which instances are compared.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InstanceFeatures {
+    /// Number of people with a balance other than zero.
+    pub people: usize,
+    /// Largest absolute balance.
+    pub max_amount: i64,
+    /// Number of distinct absolute balances. Few distinct balances lead to many groups.
+    pub distinct_amounts: usize,
+}
+
+impl InstanceFeatures {
+    pub fn of(instance: &ProblemInstance) -> Self {
+        let amounts: Vec<i64> = instance
+            .g
+            .vertices
+            .iter()
+            .filter(|v| v.weight != 0)
+            .map(|v| v.weight.abs())
+            .collect();
+        InstanceFeatures {
+            people: amounts.len(),
+            max_amount: amounts.iter().copied().max().unwrap_or_default(),
+            distinct_amounts: amounts.iter().collect::<HashSet<_>>().len(),
+        }
+    }
+
+    /// Distance of the features on a logarithmic scale, so the number of people dominates.
+    fn distance(&self, other: &InstanceFeatures) -> f64 {
+        let log = |x: f64| (1.0 + x).ln();
+        let d = |a: f64, b: f64, weight: f64| weight * (log(a) - log(b)).powi(2);
+        (d(self.people as f64, other.people as f64, 4.0)
+            + d(self.max_amount as f64, other.max_amount as f64, 1.0)
+            + d(
+                self.distinct_amounts as f64,
+                other.distinct_amounts as f64,
+                1.0,
+            ))
+        .sqrt()
+    }
+}
+
+/// Result of a single method in a bench run.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BenchRecord {
+    pub features: InstanceFeatures,
+    /// Name of the method, e.g. 'dp-greedy-satisfaction'.
+    pub method: String,
+    pub elapsed_ms: f64,
+    /// Number of transactions or `None`, if the method timed out.
+    pub transactions: Option<usize>,
+    /// Fewest transactions found by any method of the run.
+    pub best_transactions: Option<usize>,
+}
+
+impl BenchRecord {
+    /// Relative number of additional transactions compared to the best method of the run.
+    /// Methods, which timed out, have an infinite gap.
+    fn gap(&self) -> f64 {
+        match (self.transactions, self.best_transactions) {
+            (Some(t), Some(best)) if best > 0 => (t as f64 - best as f64) / best as f64,
+            (Some(_), _) => 0.0,
+            (None, _) => f64::INFINITY,
+        }
+    }
+}
+
+/// Bench results, from which methods are recommended. Stored as json lines.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct History {
+    pub records: Vec<BenchRecord>,
+}
+
+impl History {
+    /// Default location of the history, which is 'payback/bench_history.jsonl' in the data
+    /// directory of the user.
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::data_dir().map(|d| d.join("payback").join("bench_history.jsonl"))
+    }
+
+    /// Reads the history from the file. A missing file is an empty history.
+    pub fn load(path: &Path) -> Result<History, String> {
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(History::default()),
+            Err(e) => return Err(format!("Unable to read bench history {:?}: {}", path, e)),
+        };
+        let records = content
+            .lines()
+            .filter(|l| !l.trim().is_empty())
+            .map(serde_json::from_str)
+            .collect::<Result<Vec<BenchRecord>, _>>()
+            .map_err(|e| format!("Invalid bench history {:?}: {}", path, e))?;
+        Ok(History { records })
+    }
+
+    /// Appends the records to the file and creates it if necessary.
+    pub fn append(path: &Path, records: &[BenchRecord]) -> Result<(), String> {
+        let error = |e: std::io::Error| format!("Unable to write bench history {:?}: {}", path, e);
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(error)?;
+        }
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(error)?;
+        for record in records {
+            writeln!(
+                file,
+                "{}",
+                serde_json::to_string(record).expect("Records are serializable.")
+            )
+            .map_err(error)?;
+        }
+        Ok(())
+    }
+}
+
+/// Records of the results of a bench run on the instance.
+pub fn bench_records(
+    instance: &ProblemInstance,
+    results: &BTreeMap<SolvingMethods, MethodResult>,
+) -> Vec<BenchRecord> {
+    let features = InstanceFeatures::of(instance);
+    let best = results
+        .values()
+        .filter_map(|r| r.metrics.transactions)
+        .min();
+    results
+        .iter()
+        .map(|(method, result)| BenchRecord {
+            features,
+            method: method.name().to_owned(),
+            elapsed_ms: result.metrics.elapsed.as_secs_f64() * 1000.0,
+            transactions: result.metrics.transactions,
+            best_transactions: best,
+        })
+        .collect()
+}
+
+/// Predicts the best method for the instance from the bench results of similar instances. The
+/// best method is the one with the fewest predicted transactions among the methods, whose
+/// predicted runtime fits into the budget. Ties are broken by the runtime.
+/// Without history, the method of [`SolvingMethods::auto()`] is chosen.
+///
+/// Example:
+/// ```
+/// use payback::graph::Graph;
+/// use payback::probleminstance::{ProblemInstance, SolvingMethods};
+/// use payback::recommend::{recommend, History};
+/// use std::time::Duration;
+///
+/// let instance: ProblemInstance = Graph::from(vec![-2, -1, 1, 2]).into();
+/// let method = recommend(&History::default(), &instance, Duration::from_secs(1));
+/// assert_eq!(method, SolvingMethods::DPGreedySatisfaction);
+/// ```
+#[instrument(level = "debug", skip_all, fields(records = history.records.len()))]
+pub fn recommend(
+    history: &History,
+    instance: &ProblemInstance,
+    budget: Duration,
+) -> SolvingMethods {
+    let features = InstanceFeatures::of(instance);
+    let budget_ms = budget.as_secs_f64() * 1000.0;
+    let mut predictions: Vec<(f64, f64, SolvingMethods)> = vec![];
+    for method in SolvingMethods::ALL {
+        let mut records: Vec<&BenchRecord> = history
+            .records
+            .iter()
+            .filter(|r| r.method == method.name())
+            .collect();
+        if records.is_empty() {
+            continue;
+        }
+        records.sort_by(|a, b| {
+            features
+                .distance(&a.features)
+                .total_cmp(&features.distance(&b.features))
+        });
+        let nearest = &records[..records.len().min(NEIGHBORS)];
+        let mean = |f: &dyn Fn(&BenchRecord) -> f64| {
+            nearest.iter().map(|r| f(r)).sum::<f64>() / nearest.len() as f64
+        };
+        let (gap, runtime) = (mean(&BenchRecord::gap), mean(&|r| r.elapsed_ms));
+        debug!(?method, gap, runtime, "Predicted method");
+        if gap.is_finite() && runtime <= budget_ms {
+            predictions.push((gap, runtime, method));
+        }
+    }
+    predictions
+        .into_iter()
+        .min_by(|a, b| a.0.total_cmp(&b.0).then(a.1.total_cmp(&b.1)))
+        .map(|(_, _, method)| method)
+        .unwrap_or_else(|| SolvingMethods::auto(features.people))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::comparison::solve_methods;
+    use crate::graph::Graph;
+    use crate::probleminstance::{ProblemInstance, SolvingMethods};
+    use crate::recommend::{bench_records, recommend, BenchRecord, History, InstanceFeatures};
+    use crate::solver_config::SolverConfig;
+    use tracing::debug;
+    use tracing_subscriber::EnvFilter;
+
+    fn init() {
+        let _ = tracing_subscriber::fmt()
+            .with_env_filter(
+                EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("debug")),
+            )
+            .with_test_writer()
+            .try_init();
+    }
+
+    #[test]
+    fn test_recommend() {
+        init();
+        debug!("Running 'test_recommend'");
+        let instance = ProblemInstance::from(Graph::from(vec![-5, -3, -2, 1, 4, 5]));
+        let results = solve_methods(
+            &instance,
+            &[
+                SolvingMethods::ApproxStarExpand,
+                SolvingMethods::DPGreedySatisfaction,
+            ],
+            &SolverConfig::default(),
+        );
+        let records = bench_records(&instance, &results);
+        debug!("Records: {:?}", records);
+        assert_eq!(records.len(), 2);
+        assert!(records.iter().all(|r| r.best_transactions == Some(4)));
+
+        let path = std::env::temp_dir().join(format!("payback-history-{}", std::process::id()));
+        History::append(&path, &records).unwrap();
+        let history = History::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        // Json may change the last digit of the runtimes.
+        let summary = |rs: &[BenchRecord]| {
+            rs.iter()
+                .map(|r| (r.features, r.method.to_owned(), r.transactions))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(summary(&history.records), summary(&records));
+        assert_eq!(
+            recommend(&history, &instance, Duration::from_secs(10)),
+            SolvingMethods::DPGreedySatisfaction
+        );
+
+        // Slow methods are only recommended within the budget.
+        let features = InstanceFeatures::of(&instance);
+        let record = |method: &str, elapsed_ms, transactions| BenchRecord {
+            features,
+            method: method.to_owned(),
+            elapsed_ms,
+            transactions: Some(transactions),
+            best_transactions: Some(4),
+        };
+        let history = History {
+            records: vec![
+                record("dp-greedy-satisfaction", 5000.0, 4),
+                record("approx-greedy-satisfaction", 1.0, 5),
+                record("approx-star-expand", 1.0, 5),
+            ],
+        };
+        assert_eq!(
+            recommend(&history, &instance, Duration::from_secs(1)),
+            SolvingMethods::ApproxStarExpand
+        );
+        assert_eq!(
+            recommend(&history, &instance, Duration::from_secs(10)),
+            SolvingMethods::DPGreedySatisfaction
+        );
+    }
+}
@@ -0,0 +1,266 @@
+use chrono::{Datelike, Months, NaiveDate};
+use csv::{ReaderBuilder, StringRecord, WriterBuilder};
+use tracing::{instrument, trace};
+
+use crate::audit::RoundingAudit;
+use crate::dates::{parse_dated_records, DATE_FORMAT};
+use crate::ledger::split_equally;
+
+/// Expense repeated every month on the same day, e.g. rent shared by a flat.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RecurringExpense {
+    pub payer: String,
+    pub amount: i64,
+    /// Day of the month. In shorter months the expense is due on the last day instead.
+    pub day: u32,
+    /// People sharing the amount equally. The payer is only included if listed.
+    pub participants: Vec<String>,
+}
+
+impl TryFrom<&StringRecord> for RecurringExpense {
+    type Error = String;
+
+    fn try_from(record: &StringRecord) -> Result<Self, Self::Error> {
+        let invalid = |reason: &str| {
+            format!(
+                "Invalid recurring expense '{}': {}",
+                record.iter().collect::<Vec<_>>().join(","),
+                reason
+            )
+        };
+        if record.len() < 4 {
+            return Err(invalid(
+                "Expected 'payer,amount,day,participant,...' with at least one participant.",
+            ));
+        }
+        let amount = record[1]
+            .trim()
+            .parse()
+            .map_err(|e| invalid(&format!("{}", e)))?;
+        let day = record[2]
+            .trim()
+            .parse()
+            .ok()
+            .filter(|d| (1..=31).contains(d))
+            .ok_or(invalid("The day must be between 1 and 31."))?;
+        Ok(RecurringExpense {
+            payer: record[0].to_owned(),
+            amount,
+            day,
+            participants: record.iter().skip(3).map(|p| p.to_owned()).collect(),
+        })
+    }
+}
+
+impl RecurringExpense {
+    /// Days between `from` and `to`, both inclusive, on which the expense is due.
+    pub fn dates(&self, from: NaiveDate, to: NaiveDate) -> Vec<NaiveDate> {
+        let mut month = from.with_day(1).expect("Every month has a first day.");
+        let mut dates = vec![];
+        while month <= to {
+            let next = month + Months::new(1);
+            let last = next.pred_opt().expect("There is a day before every month.");
+            let date = month.with_day(self.day).unwrap_or(last);
+            if from <= date && date <= to {
+                dates.push(date);
+            }
+            month = next;
+        }
+        dates
+    }
+
+    /// Adds the rounding deltas of splitting the expense once to the audit. Participants with a
+    /// larger share owe more than the exact share, which the payer receives.
+    fn audit_split(&self, audit: &mut RoundingAudit) {
+        let exact = self.amount as f64 / self.participants.len() as f64;
+        for (p, share) in self
+            .participants
+            .iter()
+            .zip(split_equally(self.amount, self.participants.len()))
+            .filter(|(p, _)| **p != self.payer)
+        {
+            audit.add(p, exact - share as f64);
+            audit.add(&self.payer, share as f64 - exact);
+        }
+    }
+
+    /// Debts of the participants to the payer in the csv edge format 'from,to,weight' for every
+    /// due date.
+    fn records(&self, from: NaiveDate, to: NaiveDate) -> Vec<(Vec<String>, NaiveDate)> {
+        self.dates(from, to)
+            .into_iter()
+            .flat_map(|date| {
+                self.participants
+                    .iter()
+                    .zip(split_equally(self.amount, self.participants.len()))
+                    .filter(|(p, share)| **p != self.payer && *share != 0)
+                    .map(move |(p, share)| {
+                        (
+                            vec![p.to_owned(), self.payer.to_owned(), share.to_string()],
+                            date,
+                        )
+                    })
+            })
+            .collect()
+    }
+}
+
+/// Parses recurring expenses from csv records 'payer,amount,day,participant,...', e.g.
+/// 'A,1200,1,A,B,C,D' for rent of 1200 paid by A on the 1st and split 4 ways.
+pub fn parse_recurring(data: &str) -> Result<Vec<RecurringExpense>, String> {
+    ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(data.as_bytes())
+        .records()
+        .map(|r| {
+            r.map_err(|e| e.to_string())
+                .and_then(|r| RecurringExpense::try_from(&r))
+        })
+        .collect()
+}
+
+/// Expands the recurring expenses into dated records in the csv edge format and appends them to
+/// the records. Without `from` or `to`, the range of the dates in the records is used.
+///
+/// Example:
+/// ```
+/// use payback::recurring::{add_recurring, parse_recurring};
+///
+/// let rent = parse_recurring("A,1200,1,A,B,C,D").unwrap();
+/// let data = add_recurring("B,C,5,2024-01-15\nC,A,3,2024-02-10", &rent, None, None).unwrap();
+/// // Only the rent on February 1st is in the range of the records.
+/// assert_eq!(data.lines().count(), 2 + 3);
+/// ```
+pub fn add_recurring(
+    data: &str,
+    expenses: &[RecurringExpense],
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+) -> Result<String, String> {
+    add_recurring_audited(data, expenses, from, to, &mut RoundingAudit::default())
+}
+
+/// Expands the recurring expenses like [`add_recurring()`] and adds the rounding deltas of
+/// splitting them to the audit.
+#[instrument(
+    level = "debug",
+    skip(data, expenses, audit),
+    fields(expenses = expenses.len())
+)]
+pub fn add_recurring_audited(
+    data: &str,
+    expenses: &[RecurringExpense],
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+    audit: &mut RoundingAudit,
+) -> Result<String, String> {
+    let dates: Vec<NaiveDate> = parse_dated_records(data)?
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|r| r.date)
+        .collect();
+    let (Some(from), Some(to)) = (
+        from.or(dates.iter().min().copied()),
+        to.or(dates.iter().max().copied()),
+    ) else {
+        return Err(
+            "Recurring expenses need dated records or a date range given by --from and --to."
+                .to_string(),
+        );
+    };
+    let mut wtr = WriterBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_writer(vec![]);
+    for expense in expenses {
+        for _ in expense.dates(from, to) {
+            expense.audit_split(audit);
+        }
+        for (mut fields, date) in expense.records(from, to) {
+            fields.push(date.format(DATE_FORMAT).to_string());
+            wtr.write_record(&fields).map_err(|e| e.to_string())?;
+        }
+    }
+    let expanded = wtr
+        .into_inner()
+        .map_err(|e| e.to_string())
+        .and_then(|bytes| String::from_utf8(bytes).map_err(|e| e.to_string()))?;
+    trace!(
+        "Expanded recurring expenses between {} and {}:\n{}",
+        from,
+        to,
+        expanded
+    );
+    let mut data = data.to_owned();
+    if !data.is_empty() && !data.ends_with('\n') {
+        data.push('\n');
+    }
+    Ok(data + &expanded)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::audit::RoundingAudit;
+    use crate::dates::parse_date;
+    use crate::recurring::{add_recurring, add_recurring_audited, parse_recurring};
+    use tracing::debug;
+    use tracing_subscriber::EnvFilter;
+
+    fn init() {
+        let _ = tracing_subscriber::fmt()
+            .with_env_filter(
+                EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("debug")),
+            )
+            .with_test_writer()
+            .try_init();
+    }
+
+    #[test]
+    fn test_recurring_expenses() {
+        init();
+        debug!("Running 'test_recurring_expenses'");
+        assert!(parse_recurring("A,1200,1").is_err());
+        assert!(parse_recurring("A,1200,32,A,B").is_err());
+        let expenses = parse_recurring("A,10,31,A,B,C\nB,4,15,B,C").unwrap();
+        let dates = expenses[0].dates(
+            parse_date("2024-01-15").unwrap(),
+            parse_date("2024-03-30").unwrap(),
+        );
+        assert_eq!(
+            dates,
+            vec![
+                parse_date("2024-01-31").unwrap(),
+                parse_date("2024-02-29").unwrap()
+            ]
+        );
+
+        let data = add_recurring(
+            "A,B,1,2024-01-01",
+            &expenses,
+            None,
+            parse_date("2024-02-14").ok(),
+        )
+        .unwrap();
+        assert_eq!(
+            data,
+            "A,B,1,2024-01-01\nB,A,3,2024-01-31\nC,A,3,2024-01-31\nC,B,2,2024-01-15\n"
+        );
+        assert!(add_recurring("A,B,1", &expenses, None, None).is_err());
+
+        // Splitting 10 three ways leaves B and C a third below their exact shares.
+        let mut audit = RoundingAudit::default();
+        add_recurring_audited(
+            "A,B,1,2024-01-01",
+            &expenses,
+            None,
+            parse_date("2024-02-14").ok(),
+            &mut audit,
+        )
+        .unwrap();
+        debug!("Rounding audit: {:?}", audit);
+        assert!(audit.is_balanced());
+        assert!((audit.deltas["A"] + 2.0 / 3.0).abs() < 1e-9);
+        assert!((audit.deltas["C"] - 1.0 / 3.0).abs() < 1e-9);
+    }
+}
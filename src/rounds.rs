@@ -0,0 +1,174 @@
+use std::collections::HashMap;
+use tracing::{debug, instrument};
+
+use crate::verification::Payment;
+
+/// Limits of a settlement, which is spread over several rounds, e.g. paydays.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RoundLimits {
+    /// Number of rounds. At least one round is planned.
+    pub rounds: usize,
+    /// Largest amount a person can pay per round. Without a cap, people can pay any amount.
+    pub cap: Option<i64>,
+    /// Caps of single people, which replace `cap`.
+    pub caps: HashMap<String, i64>,
+}
+
+impl RoundLimits {
+    fn cap_of(&self, name: &str) -> i64 {
+        self.caps
+            .get(name)
+            .copied()
+            .or(self.cap)
+            .unwrap_or(i64::MAX)
+    }
+}
+
+/// Spreads the transactions of a plan over the rounds, such that nobody pays more than their cap
+/// in a round. Transactions are kept whole if possible and are otherwise split over the rounds
+/// with the most capacity left, so few extra transactions are needed. Every payer fills the
+/// earliest rounds first.
+/// Money received in a round isn't available for payments of the same person, so plans, in which
+/// people pass money on, should be solved with a method like 'GreedySatisfaction'.
+///
+/// Returns the payments of every round or an error naming a person, who can't pay their debts
+/// within the rounds.
+///
+/// Example:
+/// ```
+/// use payback::rounds::{schedule_rounds, RoundLimits};
+///
+/// let transactions = vec![
+///     ("A".to_string(), "B".to_string(), 60),
+///     ("A".to_string(), "C".to_string(), 40),
+/// ];
+/// let limits = RoundLimits { rounds: 2, cap: Some(60), ..Default::default() };
+/// let schedule = schedule_rounds(&transactions, &limits).unwrap();
+/// assert_eq!(schedule.iter().map(|round| round.len()).collect::<Vec<_>>(), [1, 1]);
+/// ```
+#[instrument(
+    level = "debug",
+    skip_all,
+    fields(transactions = transactions.len(), rounds = limits.rounds)
+)]
+pub fn schedule_rounds(
+    transactions: &[(String, String, i64)],
+    limits: &RoundLimits,
+) -> Result<Vec<Vec<Payment>>, String> {
+    let rounds = limits.rounds.max(1);
+    let mut schedule: Vec<Vec<Payment>> = vec![vec![]; rounds];
+    let mut by_payer: Vec<(&str, Vec<(&str, i64)>)> = vec![];
+    for (from, to, amount) in transactions {
+        match by_payer.iter_mut().find(|(payer, _)| payer == from) {
+            Some((_, ts)) => ts.push((to, *amount)),
+            None => by_payer.push((from, vec![(to, *amount)])),
+        }
+    }
+    for (payer, mut payments) in by_payer {
+        let cap = limits.cap_of(payer);
+        let total: i128 = payments.iter().map(|(_, a)| *a as i128).sum();
+        if total > cap as i128 * rounds as i128 {
+            return Err(format!(
+                "{:?} has to pay {}, but can pay at most {} in {} rounds.",
+                payer, total, cap, rounds
+            ));
+        }
+        let mut left = vec![cap; rounds];
+        // Large payments first, so small ones fill the gaps.
+        payments.sort_by_key(|(_, a)| -a);
+        for (payee, amount) in payments {
+            let payment = |round: &mut Vec<Payment>, amount: i64| {
+                round.push(Payment {
+                    from: payer.to_owned(),
+                    to: payee.to_owned(),
+                    amount,
+                })
+            };
+            if let Some(r) = (0..rounds).find(|r| left[*r] >= amount) {
+                left[r] -= amount;
+                payment(&mut schedule[r], amount);
+                continue;
+            }
+            let mut remaining = amount;
+            while remaining > 0 {
+                let r = (0..rounds)
+                    .max_by_key(|r| (left[*r], std::cmp::Reverse(*r)))
+                    .expect("There is at least one round.");
+                let part = remaining.min(left[r]);
+                left[r] -= part;
+                remaining -= part;
+                payment(&mut schedule[r], part);
+            }
+        }
+    }
+    debug!(
+        payments = schedule.iter().map(|round| round.len()).sum::<usize>(),
+        "Scheduled rounds"
+    );
+    Ok(schedule)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::rounds::{schedule_rounds, RoundLimits};
+    use tracing::debug;
+    use tracing_subscriber::EnvFilter;
+
+    fn init() {
+        let _ = tracing_subscriber::fmt()
+            .with_env_filter(
+                EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("debug")),
+            )
+            .with_test_writer()
+            .try_init();
+    }
+
+    #[test]
+    fn test_schedule_rounds() {
+        init();
+        debug!("Running 'test_schedule_rounds'");
+        let transactions = vec![
+            ("A".to_string(), "B".to_string(), 70),
+            ("A".to_string(), "C".to_string(), 30),
+            ("A".to_string(), "D".to_string(), 20),
+            ("E".to_string(), "B".to_string(), 90),
+        ];
+        let limits = RoundLimits {
+            rounds: 3,
+            cap: Some(50),
+            caps: HashMap::from([("E".to_string(), 90)]),
+        };
+        let schedule = schedule_rounds(&transactions, &limits).unwrap();
+        debug!("Schedule: {:?}", schedule);
+        for round in &schedule {
+            for payer in ["A", "E"] {
+                let paid: i64 = round
+                    .iter()
+                    .filter(|p| p.from == payer)
+                    .map(|p| p.amount)
+                    .sum();
+                assert!(paid <= limits.cap_of(payer));
+            }
+        }
+        let payments: Vec<_> = schedule.iter().flatten().collect();
+        assert_eq!(payments.iter().map(|p| p.amount).sum::<i64>(), 210);
+        // Only the payment of 70 is split.
+        assert_eq!(payments.len(), 5);
+        assert_eq!(schedule[0].iter().filter(|p| p.from == "E").count(), 1);
+
+        let limits = RoundLimits {
+            rounds: 2,
+            cap: Some(50),
+            ..Default::default()
+        };
+        assert_eq!(
+            schedule_rounds(&transactions, &limits),
+            Err("\"A\" has to pay 120, but can pay at most 50 in 2 rounds.".to_string())
+        );
+        let unlimited = schedule_rounds(&transactions, &RoundLimits::default()).unwrap();
+        assert_eq!(unlimited.len(), 1);
+        assert_eq!(unlimited[0].len(), transactions.len());
+    }
+}
@@ -0,0 +1,265 @@
+use tracing::debug;
+
+use crate::graph::Graph;
+use crate::ledger::split_equally;
+
+/// Mutation of the balances in a [`Session`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Operation {
+    /// `payer` paid `amount` for all `participants`, which share it equally.
+    AddExpense {
+        payer: String,
+        amount: i64,
+        participants: Vec<String>,
+    },
+    /// `debtor` owes `creditor` the `amount`.
+    AddDebt {
+        debtor: String,
+        creditor: String,
+        amount: i64,
+    },
+    /// Overwrites the balance of a person.
+    SetBalance { name: String, weight: i64 },
+    /// Removes a person with their balance.
+    RemovePerson { name: String },
+    /// Renames a person. If the new name already exists, both people are merged.
+    RenamePerson { from: String, to: String },
+    /// Restores the balances of a [`Snapshot`].
+    Restore(Snapshot),
+}
+
+/// Balances of a [`Session`] at some point in time.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Snapshot {
+    balances: Vec<(String, i64)>,
+}
+
+/// Editable instance with a history of operations, which can be undone and redone.
+///
+/// Example:
+/// ```
+/// use payback::session::{Operation, Session};
+///
+/// let mut session = Session::default();
+/// session.apply(Operation::AddDebt {
+///     debtor: "A".to_string(),
+///     creditor: "B".to_string(),
+///     amount: 5,
+/// }).unwrap();
+/// assert!(session.undo());
+/// assert!(session.graph().balances().is_empty());
+/// assert!(session.redo());
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Session {
+    balances: Vec<(String, i64)>,
+    undo: Vec<(Operation, Snapshot)>,
+    redo: Vec<Operation>,
+}
+
+impl From<Graph> for Session {
+    fn from(value: Graph) -> Self {
+        Session {
+            balances: value.balances(),
+            ..Default::default()
+        }
+    }
+}
+
+impl Session {
+    fn add(&mut self, name: &str, amount: i64) {
+        match self.balances.iter_mut().find(|(n, _)| n == name) {
+            Some((_, w)) => *w += amount,
+            None => self.balances.push((name.to_owned(), amount)),
+        }
+    }
+
+    fn position(&self, name: &str) -> Result<usize, String> {
+        self.balances
+            .iter()
+            .position(|(n, _)| n == name)
+            .ok_or(format!("There is no person named '{}'.", name))
+    }
+
+    fn execute(&mut self, op: &Operation) -> Result<(), String> {
+        match op {
+            Operation::AddExpense {
+                payer,
+                amount,
+                participants,
+            } => {
+                if participants.is_empty() {
+                    return Err("An expense needs at least one participant.".to_string());
+                }
+                self.add(payer, *amount);
+                for (participant, share) in participants
+                    .iter()
+                    .zip(split_equally(*amount, participants.len()))
+                {
+                    self.add(participant, -share);
+                }
+            }
+            Operation::AddDebt {
+                debtor,
+                creditor,
+                amount,
+            } => {
+                self.add(debtor, -amount);
+                self.add(creditor, *amount);
+            }
+            Operation::SetBalance { name, weight } => {
+                match self.balances.iter_mut().find(|(n, _)| n == name) {
+                    Some((_, w)) => *w = *weight,
+                    None => self.balances.push((name.to_owned(), *weight)),
+                }
+            }
+            Operation::RemovePerson { name } => {
+                let i = self.position(name)?;
+                self.balances.remove(i);
+            }
+            Operation::RenamePerson { from, to } => {
+                let i = self.position(from)?;
+                let (_, weight) = self.balances.remove(i);
+                match self.balances.iter_mut().find(|(n, _)| n == to) {
+                    Some((_, w)) => *w += weight,
+                    None => self.balances.insert(i, (to.to_owned(), weight)),
+                }
+            }
+            Operation::Restore(snapshot) => self.balances = snapshot.balances.to_owned(),
+        }
+        Ok(())
+    }
+
+    /// Applies the operation and adds it to the history. Operations, which were undone, can't be
+    /// redone afterwards. A failing operation leaves the session unchanged.
+    pub fn apply(&mut self, op: Operation) -> Result<(), String> {
+        let before = self.snapshot();
+        self.execute(&op)?;
+        debug!("Applied {:?}", op);
+        self.undo.push((op, before));
+        self.redo.clear();
+        Ok(())
+    }
+
+    /// Reverts the last applied operation. Returns `false` if there is nothing to undo.
+    pub fn undo(&mut self) -> bool {
+        match self.undo.pop() {
+            Some((op, before)) => {
+                self.balances = before.balances;
+                self.redo.push(op);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Applies the last undone operation again. Returns `false` if there is nothing to redo.
+    pub fn redo(&mut self) -> bool {
+        match self.redo.pop() {
+            Some(op) => {
+                let before = self.snapshot();
+                self.execute(&op)
+                    .expect("Operations succeed when applied to the same balances again.");
+                self.undo.push((op, before));
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    /// Operations in the order they were applied without the undone ones.
+    pub fn history(&self) -> impl Iterator<Item = &Operation> {
+        self.undo.iter().map(|(op, _)| op)
+    }
+
+    /// Captures the current balances. Restoring it with [`Operation::Restore`] can be undone.
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            balances: self.balances.to_owned(),
+        }
+    }
+
+    /// Current balances as graph, which can be solved with a
+    /// [`ProblemInstance`](crate::probleminstance::ProblemInstance).
+    pub fn graph(&self) -> Graph {
+        Graph::from(self.balances.to_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::session::{Operation, Session};
+    use tracing::debug;
+    use tracing_subscriber::EnvFilter;
+
+    fn init() {
+        let _ = tracing_subscriber::fmt()
+            .with_env_filter(
+                EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("debug")),
+            )
+            .with_test_writer()
+            .try_init();
+    }
+
+    #[test]
+    fn test_session() {
+        init();
+        debug!("Running 'test_session'");
+        let mut session = Session::default();
+        session
+            .apply(Operation::AddExpense {
+                payer: "A".to_string(),
+                amount: 9,
+                participants: vec!["A".to_string(), "B".to_string(), "C".to_string()],
+            })
+            .unwrap();
+        let snapshot = session.snapshot();
+        session
+            .apply(Operation::RenamePerson {
+                from: "C".to_string(),
+                to: "B".to_string(),
+            })
+            .unwrap();
+        assert_eq!(
+            session.graph().balances(),
+            vec![("A".to_string(), 6), ("B".to_string(), -6)]
+        );
+        assert!(session
+            .apply(Operation::RemovePerson {
+                name: "C".to_string()
+            })
+            .is_err());
+        assert_eq!(session.history().count(), 2);
+
+        assert!(session.undo());
+        assert_eq!(session.snapshot(), snapshot);
+        assert!(session.redo());
+        assert!(!session.redo());
+        session
+            .apply(Operation::Restore(snapshot.to_owned()))
+            .unwrap();
+        assert_eq!(session.snapshot(), snapshot);
+        assert!(session.undo());
+        assert_eq!(session.graph().balances().len(), 2);
+
+        assert!(session.undo());
+        assert!(session.undo());
+        assert!(!session.can_undo());
+        assert!(session.graph().balances().is_empty());
+        session
+            .apply(Operation::SetBalance {
+                name: "A".to_string(),
+                weight: 1,
+            })
+            .unwrap();
+        assert!(!session.can_redo());
+    }
+}
@@ -0,0 +1,256 @@
+use std::collections::BTreeSet;
+use std::fmt::Display;
+use tracing::warn;
+
+use crate::graph::{Edge, Graph};
+use crate::verification::Payment;
+
+/// Most transfers [`Settlement::split()`] creates, so a tiny largest amount doesn't exhaust the
+/// memory on large amounts.
+pub const MAX_TRANSFERS: u64 = 1_000_000;
+
+/// Number of transfers, into which [`Settlement::split()`] splits a transaction of the amount.
+pub(crate) fn transfers(amount: i64, max_amount: i64) -> u64 {
+    amount
+        .unsigned_abs()
+        .div_ceil(max_amount.max(1) as u64)
+        .max(1)
+}
+
+/// Payment of `amount` from `payer` to `payee`, who are referenced by their names. The amount is
+/// never negative.
+#[derive(Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Transaction {
+    pub payer: String,
+    pub payee: String,
+    pub amount: i64,
+}
+
+impl Transaction {
+    /// Transaction of the edge of a solution with its amount. People, who aren't in the graph,
+    /// are named by their id.
+    pub(crate) fn from_edge(graph: &Graph, edge: &Edge, amount: i64) -> Self {
+        let (payer, payee, amount) = edge.payment(amount);
+        let name = |id: usize| graph.get_node_name_or(id, id.to_string());
+        Transaction {
+            payer: name(payer),
+            payee: name(payee),
+            amount,
+        }
+    }
+}
+
+/// Formats the transaction like '"A" to "B": 5'.
+impl Display for Transaction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?} to {:?}: {}", self.payer, self.payee, self.amount)
+    }
+}
+
+impl From<Transaction> for (String, String, i64) {
+    fn from(value: Transaction) -> Self {
+        (value.payer, value.payee, value.amount)
+    }
+}
+
+impl From<Transaction> for Payment {
+    fn from(value: Transaction) -> Self {
+        Payment {
+            from: value.payer,
+            to: value.payee,
+            amount: value.amount,
+        }
+    }
+}
+
+/// Transactions of a solution with the names of the people, which can be used without the
+/// instance. Returned by the solvers, e.g. [`crate::ProblemInstance::solve_with()`].
+///
+/// Example:
+/// ```
+/// use payback::{Graph, ProblemInstance, SolvingMethods};
+///
+/// let instance = ProblemInstance::from(Graph::from(vec![
+///     ("Anna".to_string(), -3),
+///     ("Ben".to_string(), -1),
+///     ("Carl".to_string(), 4),
+/// ]));
+/// let settlement = instance
+///     .solve_with(SolvingMethods::DPGreedySatisfaction)
+///     .unwrap();
+/// assert_eq!(settlement.len(), 2);
+/// assert_eq!(settlement.total(), 4);
+/// assert_eq!(settlement.transactions()[0].to_string(), "\"Anna\" to \"Carl\": 3");
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Settlement {
+    transactions: Vec<Transaction>,
+}
+
+impl Settlement {
+    pub fn new(transactions: Vec<Transaction>) -> Self {
+        Settlement { transactions }
+    }
+
+    /// Transactions in the order of the solution.
+    pub fn transactions(&self) -> &[Transaction] {
+        &self.transactions
+    }
+
+    /// Number of transactions.
+    pub fn len(&self) -> usize {
+        self.transactions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.transactions.is_empty()
+    }
+
+    /// Sum of the amounts of all transactions.
+    pub fn total(&self) -> i64 {
+        self.transactions.iter().map(|t| t.amount).sum()
+    }
+
+    /// Names of the people, who pay or receive a transaction.
+    pub fn people(&self) -> BTreeSet<&str> {
+        self.transactions
+            .iter()
+            .flat_map(|t| [t.payer.as_str(), t.payee.as_str()])
+            .collect()
+    }
+
+    /// Number of transfers after [`Settlement::split()`].
+    pub fn transfers(&self, max_amount: i64) -> u64 {
+        self.transactions
+            .iter()
+            .map(|t| transfers(t.amount, max_amount))
+            .fold(0, u64::saturating_add)
+    }
+
+    /// Splits every transaction larger than `max_amount` into transfers of `max_amount` and one of
+    /// the rest, which follow each other. Amounts below 1 are treated as 1. Settlements, which
+    /// would take more than [`MAX_TRANSFERS`] transfers, are returned unsplit.
+    pub fn split(self, max_amount: i64) -> Settlement {
+        let max = max_amount.max(1);
+        if self.transfers(max) > MAX_TRANSFERS {
+            warn!(
+                "Splitting by {} takes more than {} transfers, so the plan isn't split.",
+                max, MAX_TRANSFERS
+            );
+            return self;
+        }
+        Settlement::new(
+            self.transactions
+                .into_iter()
+                .flat_map(|t| {
+                    let (full, rest) = (t.amount / max, t.amount % max);
+                    std::iter::repeat_n(max, full as usize)
+                        .chain((rest > 0).then_some(rest))
+                        .map(move |amount| Transaction {
+                            amount,
+                            ..t.to_owned()
+                        })
+                })
+                .collect(),
+        )
+    }
+
+    /// Net amount the person receives through the transactions. It is negative, if they pay.
+    pub fn net(&self, name: &str) -> i64 {
+        self.transactions
+            .iter()
+            .map(|t| {
+                if t.payee == name {
+                    t.amount
+                } else if t.payer == name {
+                    -t.amount
+                } else {
+                    0
+                }
+            })
+            .sum()
+    }
+}
+
+impl IntoIterator for Settlement {
+    type Item = Transaction;
+    type IntoIter = std::vec::IntoIter<Transaction>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.transactions.into_iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::graph::{Edge, Graph};
+    use crate::settlement::{Settlement, Transaction};
+    use tracing::debug;
+    use tracing_subscriber::EnvFilter;
+
+    fn init() {
+        let _ = tracing_subscriber::fmt()
+            .with_env_filter(
+                EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("debug")),
+            )
+            .with_test_writer()
+            .try_init();
+    }
+
+    #[test]
+    fn test_settlement() {
+        init();
+        debug!("Running 'test_settlement'");
+        let graph = Graph::from(vec![
+            ("A".to_string(), -2),
+            ("B".to_string(), -1),
+            ("C".to_string(), 3),
+        ]);
+        // Negative amounts are paid against the direction of the edge.
+        let settlement = Settlement::new(vec![
+            Transaction::from_edge(&graph, &Edge::new(2, 0), 2),
+            Transaction::from_edge(&graph, &Edge::new(1, 2), -1),
+        ]);
+        assert_eq!(
+            settlement.transactions()[1],
+            Transaction {
+                payer: "B".to_string(),
+                payee: "C".to_string(),
+                amount: 1
+            }
+        );
+        assert_eq!(settlement.len(), 2);
+        assert_eq!(settlement.total(), 3);
+        assert_eq!(
+            settlement.people().into_iter().collect::<Vec<_>>(),
+            ["A", "B", "C"]
+        );
+        assert_eq!(
+            graph
+                .balances()
+                .iter()
+                .map(|(name, _)| settlement.net(name))
+                .collect::<Vec<_>>(),
+            [-2, -1, 3]
+        );
+        assert_eq!(
+            Transaction::from_edge(&graph, &Edge::new(7, 0), 1).payee,
+            "7".to_string()
+        );
+        let split = settlement.clone().split(1);
+        assert_eq!(split.len(), 3);
+        assert_eq!(split.total(), settlement.total());
+        assert_eq!(split.transactions()[1], split.transactions()[0]);
+        assert_eq!(settlement.clone().split(5), settlement);
+        assert_eq!(settlement.transfers(1), 3);
+
+        // Splitting a large amount into tiny transfers isn't done.
+        let large = Settlement::new(vec![Transaction::from_edge(
+            &graph,
+            &Edge::new(2, 0),
+            1_000_000_000_000,
+        )]);
+        assert_eq!(large.transfers(1), 1_000_000_000_000);
+        assert_eq!(large.clone().split(1), large);
+    }
+}
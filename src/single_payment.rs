@@ -0,0 +1,156 @@
+use std::collections::{BTreeMap, HashMap};
+use tracing::{debug, instrument, trace};
+
+use crate::graph::{Edge, NamedNode};
+use crate::probleminstance::{EdgeSolution, ProblemInstance};
+
+/// Reshapes a solution, so every person makes at most one payment and especially every debtor
+/// pays exactly one person. The people of every group, who settle among themselves in the
+/// solution, are arranged in trees. Every creditor is paid by debtors, who owe at least as much
+/// as the creditor is owed, and passes the surplus on. The creditor with the largest credit of
+/// a group receives the rest.
+///
+/// A group of k people needs at most k - 1 payments, so the solution keeps its number of
+/// transactions, while surpluses being passed on can increase the amount of money moved.
+#[instrument(level = "debug", skip_all, fields(vertices = instance.g.vertices.len()))]
+pub(crate) fn single_payment(instance: &ProblemInstance, solution: &EdgeSolution) -> EdgeSolution {
+    let by_name: HashMap<&str, &NamedNode> = instance
+        .g
+        .vertices
+        .iter()
+        .map(|v| (v.name.as_str(), v))
+        .collect();
+    let mut reshaped: BTreeMap<Edge, i64> = BTreeMap::new();
+    for group in instance.partitioning(solution)? {
+        let people: Vec<&NamedNode> = group.iter().map(|name| by_name[name.as_str()]).collect();
+        reshaped.extend(settle_group(&people));
+    }
+    debug!(
+        transactions = reshaped.len(),
+        before = solution.as_ref().map(|s| s.len()),
+        "Reshaped solution"
+    );
+    Some(reshaped)
+}
+
+/// Arranges a group, whose weights add up to zero, in trees, in which every person pays at most
+/// once.
+fn settle_group(people: &[&NamedNode]) -> BTreeMap<Edge, i64> {
+    let mut edges = BTreeMap::new();
+    let Some(root) = people.iter().filter(|v| v.weight > 0).max() else {
+        return edges;
+    };
+    // Roots of the trees, which still owe money, by the amount they owe.
+    let mut open: BTreeMap<i64, Vec<usize>> = BTreeMap::new();
+    for v in people.iter().filter(|v| v.weight < 0) {
+        open.entry(-v.weight).or_default().push(v.id);
+    }
+    let mut creditors: Vec<&&NamedNode> = people
+        .iter()
+        .filter(|v| v.weight > 0 && v.id != root.id)
+        .collect();
+    creditors.sort_by_key(|v| v.weight);
+    for creditor in creditors {
+        let mut received = 0;
+        while received < creditor.weight {
+            let missing = creditor.weight - received;
+            // The smallest debt covering the rest, or else the largest debt.
+            let amount = match open.range(missing..).next() {
+                Some((amount, _)) => *amount,
+                None => {
+                    *open
+                        .last_key_value()
+                        .expect("The debts of a group cover its credits.")
+                        .0
+                }
+            };
+            let payer = take(&mut open, amount);
+            edges.insert(
+                Edge {
+                    u: creditor.id,
+                    v: payer,
+                },
+                amount,
+            );
+            received += amount;
+        }
+        if received > creditor.weight {
+            open.entry(received - creditor.weight)
+                .or_default()
+                .push(creditor.id);
+        }
+    }
+    for (amount, payers) in open {
+        for payer in payers {
+            edges.insert(
+                Edge {
+                    u: root.id,
+                    v: payer,
+                },
+                amount,
+            );
+        }
+    }
+    trace!(?edges, "Settled group");
+    edges
+}
+
+fn take(open: &mut BTreeMap<i64, Vec<usize>>, amount: i64) -> usize {
+    let ids = open.get_mut(&amount).expect("Amount is open.");
+    let id = ids.pop().expect("Buckets aren't empty.");
+    if ids.is_empty() {
+        open.remove(&amount);
+    }
+    id
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::graph::Graph;
+    use crate::probleminstance::{ProblemInstance, SolvingMethods};
+    use crate::single_payment::single_payment;
+    use crate::verification::{verify_plan, Payment};
+    use tracing::debug;
+    use tracing_subscriber::EnvFilter;
+
+    fn init() {
+        let _ = tracing_subscriber::fmt()
+            .with_env_filter(
+                EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("debug")),
+            )
+            .with_test_writer()
+            .try_init();
+    }
+
+    #[test]
+    fn test_single_payment() {
+        init();
+        debug!("Running 'test_single_payment'");
+        let instance = ProblemInstance::from(Graph::from(vec![-7, -5, -3, -2, -1, 1, 4, 6, 7]));
+        for method in [
+            SolvingMethods::ApproxStarExpand,
+            SolvingMethods::ApproxGreedySatisfaction,
+            SolvingMethods::DPGreedySatisfaction,
+        ] {
+            let solution = instance.solve_edges_with(method);
+            let reshaped = single_payment(&instance, &solution);
+            debug!("Reshaped solution of {:?}: {:?}", method, reshaped);
+            let plan: Vec<Payment> = instance
+                .solution_transactions(&reshaped)
+                .unwrap()
+                .into_iter()
+                .map(|(from, to, amount)| Payment { from, to, amount })
+                .collect();
+            assert!(verify_plan(&instance.g, &plan).is_settled());
+            assert!(reshaped.as_ref().unwrap().len() <= solution.unwrap().len());
+            let mut payments: HashMap<&str, usize> = HashMap::new();
+            for p in &plan {
+                *payments.entry(&p.from).or_default() += 1;
+            }
+            assert!(payments.values().all(|count| *count == 1));
+        }
+        assert_eq!(single_payment(&instance, &None), None);
+    }
+}
@@ -0,0 +1,922 @@
+use std::fmt::Display;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::time::Duration;
+
+use serde_derive::Serialize;
+use tracing::{warn, Span};
+
+use crate::canonical::SolutionMemo;
+use crate::constraints::Constraints;
+use crate::events::{SolverEvent, SolverObserver};
+use crate::pipeline::{
+    min_effort_groups, min_transfer_cost_groups, preferred_pairs_groups, resettle_groups, Pipeline,
+};
+use crate::probleminstance::{
+    Cancel, EdgeSolution, MethodOptions, NamedSolution, ProblemInstance, SolvingMethods,
+};
+use crate::settlement::Settlement;
+use crate::single_payment::single_payment;
+use crate::transfer_costs::TransferCosts;
+
+/// Objective optimized by the solving methods.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum Objective {
+    /// Minimal number of transactions. Ties are broken by the preferred pairs of the constraints
+    /// and else by the approximation of the method.
+    #[default]
+    MinTransactions,
+    /// Minimal 'alpha * transactions + beta * total amount' with the objective weights. The
+    /// solution of the method is improved by settling every group of people, who pay each other,
+    /// with the minimal amount. Every plan moves at least the minimal amount, which the plans
+    /// with the fewest transactions move as well. So with exact methods, the result is optimal
+    /// for all weights and has the fewest transactions like 'MinTransactionsThenAmount'.
+    WeightedSum,
+    /// Minimal number of transactions, whose ties are broken by the total effort of the
+    /// payers. The effort of a person, e.g. without online banking, is given by the metadata
+    /// 'effort=<WEIGHT>' and defaults to 1. Instances of at most 12 people with a balance get
+    /// the plan with the least effort among all plans with the minimal number of transactions.
+    /// In larger instances, every group of people, who pay each other, is settled by its plan
+    /// with the least effort if it has at most 12 people and otherwise by the best of
+    /// 'GreedySatisfaction', a star around any person of the group and the solution of the
+    /// method, which may not be optimal.
+    MinEffort,
+    /// Minimal number of transactions, whose ties are broken by the total transfer cost between
+    /// the people. Plans are searched like for 'min-effort', so large groups may not be optimal.
+    MinTransferCost,
+    /// Minimal number of transactions and then minimal total amount transferred. Every group of
+    /// people, who pay each other, is settled with 'GreedySatisfaction', which moves the minimal
+    /// amount without more transactions. The result is optimal for both, if the method is exact.
+    MinTransactionsThenAmount,
+}
+
+impl Objective {
+    /// Cost of the solution under the objective. Lower is better.
+    pub(crate) fn cost(&self, weights: &ObjectiveWeights, solution: &EdgeSolution) -> Option<f64> {
+        let solution = solution.as_ref()?;
+        let transactions = solution.len() as f64;
+        Some(match self {
+            Objective::MinTransactions
+            | Objective::MinEffort
+            | Objective::MinTransferCost
+            | Objective::MinTransactionsThenAmount => transactions,
+            Objective::WeightedSum => {
+                weights.alpha * transactions
+                    + weights.beta * solution.values().map(|w| w.abs()).sum::<i64>() as f64
+            }
+        })
+    }
+}
+
+/// Coefficients of [`Objective::WeightedSum`]. Both must not be negative, since plans could
+/// otherwise lower their cost without end by passing money around in circles.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize)]
+pub struct ObjectiveWeights {
+    /// Cost of a single transaction.
+    pub alpha: f64,
+    /// Cost of moving one unit of money.
+    pub beta: f64,
+}
+
+impl Default for ObjectiveWeights {
+    fn default() -> Self {
+        ObjectiveWeights {
+            alpha: 1.0,
+            beta: 0.0,
+        }
+    }
+}
+
+/// Options of [`ProblemInstance::solve_with_config()`]. Options, which don't apply to a method,
+/// are ignored.
+///
+/// Example:
+/// ```
+/// use payback::probleminstance::SolvingMethods;
+/// use payback::solver_config::SolverConfig;
+/// use std::time::Duration;
+///
+/// let config = SolverConfig {
+///     method: SolvingMethods::DPGreedySatisfaction,
+///     timeout: Some(Duration::from_secs(10)),
+///     ..Default::default()
+/// };
+/// ```
+#[derive(Clone, Default)]
+pub struct SolverConfig {
+    pub method: SolvingMethods,
+    pub objective: Objective,
+    /// Coefficients of [`Objective::WeightedSum`].
+    pub objective_weights: ObjectiveWeights,
+    /// Abort solving after the timeout. The exact methods are then stopped by a flag of the
+    /// solve, so [`SolverConfig::cancel`] is left untouched. The approximations can't be
+    /// interrupted, so they keep running in the background until they finish, but their result
+    /// is discarded.
+    pub timeout: Option<Duration>,
+    /// After the timeout, return the approximation of the method instead of
+    /// [`SolveError::TimedOut`]. The approximation is returned as well, if an exact method finds
+    /// no solution, e.g. because the instance is too large for it.
+    pub fallback_on_timeout: bool,
+    /// Maximal number of threads used by the solver. Without a limit, the branching methods use a
+    /// thread per cpu.
+    pub threads: Option<usize>,
+    /// Seed of randomized methods. Without a seed, a random one is chosen.
+    pub seed: Option<u64>,
+    /// Time 'ApproxGenetic' spends improving its solution. Without a budget, it evolves a fixed
+    /// number of generations, so its solution only depends on the seed.
+    pub time_budget: Option<Duration>,
+    /// Granularity of the rounded weights of 'ApproxBucketedDP'. Without a size, the weights
+    /// aren't rounded.
+    pub bucket_size: Option<i64>,
+    /// Metadata key, by which 'ApproxHierarchical' clusters the people. Without a key, the
+    /// people are clustered by their weights.
+    pub cluster_by: Option<String>,
+    /// Rearrange the solution, so every debtor makes exactly one payment. See
+    /// [`single_payment()`].
+    pub single_payment: bool,
+    /// Requirements on the solution. See [`Constraints`].
+    pub constraints: Constraints,
+    /// Costs of transactions between pairs of people of [`Objective::MinTransferCost`].
+    pub transfer_costs: TransferCosts,
+    /// Stages run around the method. See [`Pipeline`].
+    pub pipeline: Pipeline,
+    /// Receives the events of the solver.
+    pub observer: Option<Arc<dyn SolverObserver + Send + Sync>>,
+    /// Stops the exact methods, once it is set, e.g. by a Ctrl-C handler. They then return the
+    /// best solution found so far, which is at least as good as the approximation of the method.
+    /// The solver only reads the flag, so it can be shared by many solves.
+    pub cancel: Option<Arc<AtomicBool>>,
+}
+
+impl std::fmt::Debug for SolverConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SolverConfig")
+            .field("method", &self.method)
+            .field("objective", &self.objective)
+            .field("objective_weights", &self.objective_weights)
+            .field("timeout", &self.timeout)
+            .field("fallback_on_timeout", &self.fallback_on_timeout)
+            .field("threads", &self.threads)
+            .field("seed", &self.seed)
+            .field("time_budget", &self.time_budget)
+            .field("bucket_size", &self.bucket_size)
+            .field("cluster_by", &self.cluster_by)
+            .field("single_payment", &self.single_payment)
+            .field("constraints", &self.constraints)
+            .field("transfer_costs", &self.transfer_costs)
+            .field("pipeline", &self.pipeline)
+            .field("observer", &self.observer.is_some())
+            .field("cancel", &self.cancel)
+            .finish()
+    }
+}
+
+/// Reasons why [`ProblemInstance::solve_with_config()`] didn't return a solution.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SolveError {
+    /// The solve took longer than [`SolverConfig::timeout`].
+    TimedOut(Duration),
+    /// The solver panicked.
+    Panicked,
+    /// The constraints of [`SolverConfig::constraints`] can't be met.
+    Infeasible(String),
+    /// The search for a plan within [`SolverConfig::constraints`] stopped after the number of
+    /// steps without finding one. A plan may still exist.
+    SearchLimit(usize),
+}
+
+impl Display for SolveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SolveError::TimedOut(timeout) => {
+                write!(f, "Solving took longer than {:?}.", timeout)
+            }
+            SolveError::Panicked => write!(f, "The solver panicked."),
+            SolveError::Infeasible(reason) => {
+                write!(f, "The constraints can't be met: {}", reason)
+            }
+            SolveError::SearchLimit(steps) => write!(
+                f,
+                "No plan within the constraints was found within the search budget of {} steps.",
+                steps
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SolveError {}
+
+impl ProblemInstance {
+    /// Solves the instance with the options of the config.
+    ///
+    /// Example:
+    /// ```
+    /// use payback::graph::Graph;
+    /// use payback::probleminstance::{ProblemInstance, SolvingMethods};
+    /// use payback::solver_config::SolverConfig;
+    ///
+    /// let instance: ProblemInstance = Graph::from(vec![-2, -1, 1, 2]).into();
+    /// let config = SolverConfig {
+    ///     method: SolvingMethods::DPStarExpand,
+    ///     ..Default::default()
+    /// };
+    /// let solution = instance.solve_with_config(&config).unwrap();
+    /// assert_eq!(solution.unwrap().len(), 2);
+    /// ```
+    pub fn solve_with_config(
+        &self,
+        config: &SolverConfig,
+    ) -> Result<Option<Settlement>, SolveError> {
+        Ok(self.settlement(&self.solve_edges_with_config(config)?))
+    }
+
+    /// Solves the instance like [`ProblemInstance::solve_with_config()`], but keeps the edges of
+    /// the solution.
+    pub(crate) fn solve_edges_with_config(
+        &self,
+        config: &SolverConfig,
+    ) -> Result<EdgeSolution, SolveError> {
+        let memo = SolutionMemo::default();
+        let solve =
+            |part: &ProblemInstance| memo.solve(part, |part| part.solve_with_method(config));
+        let rewrite = |part: &ProblemInstance, solution| part.rewrite(config, solution);
+        let solution = match (config.constraints.is_empty(), config.pipeline.is_empty()) {
+            (true, true) => rewrite(self, self.solve_with_method(config)?),
+            (true, false) => rewrite(self, config.pipeline.run(self, solve)?),
+            (false, _) => config.constraints.solve(
+                self,
+                |group| config.pipeline.run(group, solve),
+                rewrite,
+            )?,
+        };
+        config
+            .constraints
+            .check(self, &solution)
+            .map_err(SolveError::Infeasible)?;
+        Ok(solution)
+    }
+
+    /// Solves the instance like [`ProblemInstance::solve_with_config()`] and references the
+    /// people of the solution by their names.
+    ///
+    /// Example:
+    /// ```
+    /// use payback::graph::Graph;
+    /// use payback::probleminstance::{ProblemInstance, SolvingMethods};
+    /// use payback::solver_config::SolverConfig;
+    ///
+    /// let instance: ProblemInstance = Graph::from(vec![-2, -1, 1, 2]).into();
+    /// let config = SolverConfig {
+    ///     method: SolvingMethods::DPStarExpand,
+    ///     ..Default::default()
+    /// };
+    /// let settlement = instance.solve_named_with_config(&config).unwrap().unwrap();
+    /// assert_eq!(settlement.len(), 2);
+    /// ```
+    pub fn solve_named_with_config(
+        &self,
+        config: &SolverConfig,
+    ) -> Result<NamedSolution, SolveError> {
+        Ok(self.named_solution(&self.solve_edges_with_config(config)?))
+    }
+
+    /// Rearranges the solution for the objective and the single payments of the config.
+    fn rewrite(&self, config: &SolverConfig, solution: EdgeSolution) -> EdgeSolution {
+        let solution = match config.objective {
+            Objective::MinTransactions if config.constraints.preferred.is_empty() => solution,
+            Objective::MinTransactions => preferred_pairs_groups(
+                self,
+                &solution,
+                &config.constraints.preferred,
+                &config.constraints.forbidden,
+            ),
+            Objective::WeightedSum => {
+                let resettled = resettle_groups(self, &solution);
+                let cost = |sol| config.objective.cost(&config.objective_weights, sol);
+                if cost(&resettled) < cost(&solution) {
+                    resettled
+                } else {
+                    solution
+                }
+            }
+            Objective::MinEffort => min_effort_groups(self, &solution),
+            Objective::MinTransferCost => {
+                min_transfer_cost_groups(self, &solution, &config.transfer_costs)
+            }
+            Objective::MinTransactionsThenAmount => resettle_groups(self, &solution),
+        };
+        if config.single_payment {
+            single_payment(self, &solution)
+        } else {
+            solution
+        }
+    }
+
+    /// Solves the instance with the method of the config within its timeout on at most
+    /// [`SolverConfig::threads`] many threads.
+    fn solve_with_method(&self, config: &SolverConfig) -> Result<EdgeSolution, SolveError> {
+        let (method, bucket_size) = (config.method, config.bucket_size);
+        let (seed, time_budget) = (config.seed, config.time_budget);
+        let fallback_on_timeout = config.fallback_on_timeout;
+        let cluster_by = config.cluster_by.to_owned();
+        let observer = config.observer.to_owned();
+        // Every solve has its own timeout flag, so a timeout doesn't cancel later solves sharing
+        // the flag of the caller.
+        let cancel = config.cancel.to_owned();
+        let timed_out = config.timeout.map(|_| Arc::new(AtomicBool::new(false)));
+        let stop = timed_out.to_owned();
+        // Without a limit, the global thread pool of rayon with a thread per cpu is used.
+        let pool = config.threads.and_then(|threads| {
+            rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .inspect_err(|e| warn!("Unable to limit the threads: {}", e))
+                .ok()
+        });
+        let solve = move |instance: &ProblemInstance| {
+            let solve = || {
+                let observer: &dyn SolverObserver = match &observer {
+                    Some(observer) => observer.as_ref(),
+                    None => &(),
+                };
+                let options = MethodOptions {
+                    bucket_size,
+                    cluster_by: cluster_by.as_deref(),
+                    seed,
+                    time_budget,
+                    cancel: Cancel::new(cancel.as_deref()).or(timed_out.as_deref()),
+                    fallback: fallback_on_timeout,
+                };
+                instance.solve_with_options(method, observer, &options)
+            };
+            match &pool {
+                Some(pool) => pool.install(solve),
+                None => solve(),
+            }
+        };
+        Ok(match config.timeout {
+            None => solve(self),
+            Some(timeout) => {
+                // The solver gets the whole instance, so its decimals and largest amount are the
+                // same as without a timeout.
+                let instance = self.to_owned();
+                let (sender, receiver) = mpsc::channel();
+                // The solver spans are nested in the span of the caller.
+                let span = Span::current();
+                std::thread::spawn(move || sender.send(span.in_scope(|| solve(&instance))));
+                let solution = receiver.recv_timeout(timeout);
+                if let (Err(mpsc::RecvTimeoutError::Timeout), Some(stop)) = (&solution, &stop) {
+                    stop.store(true, Ordering::Relaxed);
+                }
+                match solution {
+                    Ok(solution) => solution,
+                    Err(mpsc::RecvTimeoutError::Timeout) if config.fallback_on_timeout => {
+                        if let Some(observer) = &config.observer {
+                            observer.on_event(&SolverEvent::FallbackTriggered {
+                                reason: format!("{:?} took longer than {:?}.", method, timeout),
+                            });
+                        }
+                        self.fallback_solution(method)
+                    }
+                    Err(mpsc::RecvTimeoutError::Timeout) => Err(SolveError::TimedOut(timeout))?,
+                    Err(mpsc::RecvTimeoutError::Disconnected) => Err(SolveError::Panicked)?,
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use crate::constraints::Constraints;
+    use crate::events::SolverEvent;
+    use crate::generator::{generate, GeneratorConfig};
+    use crate::graph::Graph;
+    use crate::probleminstance::{EdgeSolution, ProblemInstance, SolvingMethods};
+    use crate::solver_config::{Objective, ObjectiveWeights, SolveError, SolverConfig};
+    use crate::transfer_costs::TransferCosts;
+    use tracing::debug;
+    use tracing_subscriber::EnvFilter;
+
+    fn init() {
+        let _ = tracing_subscriber::fmt()
+            .with_env_filter(
+                EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("debug")),
+            )
+            .with_test_writer()
+            .try_init();
+    }
+
+    #[test]
+    fn test_solve_with_config() {
+        init();
+        debug!("Running 'test_solve_with_config'");
+        let instance: ProblemInstance = Graph::from(vec![-3, -2, -1, 1, 2, 3]).into();
+        let events = Arc::new(AtomicUsize::new(0));
+        let counter = events.clone();
+        let config = SolverConfig {
+            method: SolvingMethods::DPGreedySatisfaction,
+            timeout: Some(Duration::from_secs(60)),
+            observer: Some(Arc::new(move |_: &SolverEvent| {
+                counter.fetch_add(1, Ordering::Relaxed);
+            })),
+            ..Default::default()
+        };
+        debug!("Config: {:?}", config);
+        let sol = instance.solve_edges_with_config(&config).unwrap();
+        assert_eq!(sol, instance.solve_edges_with(config.method));
+        assert!(events.load(Ordering::Relaxed) > 0);
+
+        // The partitioning methods take minutes for twelve people, so they always time out.
+        let instance = ProblemInstance::from(generate(&GeneratorConfig {
+            nodes: 12,
+            seed: Some(1),
+            ..Default::default()
+        }));
+        let cancel = Arc::new(AtomicBool::new(false));
+        let config = SolverConfig {
+            method: SolvingMethods::PartitioningGreedySatisfaction,
+            timeout: Some(Duration::from_millis(1)),
+            cancel: Some(cancel.clone()),
+            ..Default::default()
+        };
+        assert_eq!(
+            instance.solve_edges_with_config(&config),
+            Err(SolveError::TimedOut(Duration::from_millis(1)))
+        );
+        // The timeout stops the solver in the background without cancelling later solves.
+        assert!(!cancel.load(Ordering::Relaxed));
+        let config = SolverConfig {
+            cancel: None,
+            ..config
+        };
+        let config = SolverConfig {
+            fallback_on_timeout: true,
+            ..config
+        };
+        assert_eq!(
+            instance.solve_edges_with_config(&config),
+            Ok(instance.solve_edges_with(SolvingMethods::ApproxGreedySatisfaction))
+        );
+        assert_eq!(
+            instance.solve_with_timeout(config.method, Duration::from_millis(1)),
+            instance.solve_with(SolvingMethods::ApproxGreedySatisfaction)
+        );
+    }
+
+    #[test]
+    fn test_timeout_keeps_instance() {
+        init();
+        debug!("Running 'test_timeout_keeps_instance'");
+        // Amounts in cents, which are split into transfers of at most 2.00.
+        let instance = ProblemInstance::from(Graph::from(vec![-500, -150, 250, 400]))
+            .with_decimals(2)
+            .with_max_amount(Some(200));
+        let config = SolverConfig {
+            method: SolvingMethods::DPGreedySatisfaction,
+            ..Default::default()
+        };
+        let untimed = instance.solve_with_config(&config).unwrap();
+        let config = SolverConfig {
+            timeout: Some(Duration::from_secs(60)),
+            ..config
+        };
+        let timed = instance.solve_with_config(&config).unwrap();
+        assert_eq!(timed, untimed);
+        assert_eq!(
+            instance.solution_string(&timed),
+            instance.solution_string(&untimed)
+        );
+    }
+
+    #[test]
+    fn test_timeout_keeps_shared_cancel() {
+        init();
+        debug!("Running 'test_timeout_keeps_shared_cancel'");
+        let large = ProblemInstance::from(generate(&GeneratorConfig {
+            nodes: 12,
+            seed: Some(1),
+            ..Default::default()
+        }));
+        let small: ProblemInstance = Graph::from(vec![-2, -1, 1, 2]).into();
+        let cancel = Arc::new(AtomicBool::new(false));
+        let config = SolverConfig {
+            method: SolvingMethods::PartitioningStarExpand,
+            timeout: Some(Duration::from_millis(100)),
+            fallback_on_timeout: true,
+            cancel: Some(cancel.clone()),
+            ..Default::default()
+        };
+        // The first solve times out and falls back to the star.
+        assert_eq!(
+            large.solve_edges_with_config(&config),
+            Ok(large.fallback_solution(config.method))
+        );
+        assert!(!cancel.load(Ordering::Relaxed));
+        // The next solve with the same flag isn't cancelled, so it finds the optimum instead of
+        // the star of three transactions. It has no timeout, so it can't time out on a loaded
+        // machine either.
+        let config = SolverConfig {
+            timeout: None,
+            ..config
+        };
+        let sol = small.solve_edges_with_config(&config).unwrap().unwrap();
+        assert_eq!(sol.len(), 2);
+
+        // The fallback after the timeout is split and formatted like the plan of the method.
+        let large = ProblemInstance::from(generate(&GeneratorConfig {
+            nodes: 12,
+            seed: Some(1),
+            ..Default::default()
+        }))
+        .with_decimals(2)
+        .with_max_amount(Some(3));
+        let config = SolverConfig {
+            timeout: Some(Duration::from_millis(1)),
+            ..config
+        };
+        let sol = large.solve_edges_with_config(&config).unwrap();
+        assert_eq!(sol, large.fallback_solution(config.method));
+        let settlement = large.settlement(&sol).unwrap();
+        assert!(settlement.len() > sol.as_ref().unwrap().len());
+        assert!(settlement.transactions().iter().all(|t| t.amount <= 3));
+        let text = large.solution_string(&large.settlement(&sol)).unwrap();
+        assert_eq!(text.lines().count(), settlement.len());
+        assert!(text
+            .lines()
+            .all(|l| l.ends_with(".01") || l.ends_with(".02") || l.ends_with(".03")));
+    }
+
+    #[test]
+    fn test_cancel() {
+        init();
+        debug!("Running 'test_cancel'");
+        let instance: ProblemInstance = Graph::from(vec![-5, -3, -2, -1, 1, 2, 3, 5]).into();
+        let cancel = Arc::new(AtomicBool::new(false));
+        for method in SolvingMethods::ALL.into_iter().filter(|m| m.is_exact()) {
+            let config = SolverConfig {
+                method,
+                cancel: Some(cancel.clone()),
+                ..Default::default()
+            };
+            let optimum = instance.solve_edges_with_config(&config).unwrap().unwrap();
+            assert_eq!(optimum.len(), 4);
+            cancel.store(true, Ordering::Relaxed);
+            let sol = instance.solve_edges_with_config(&config).unwrap();
+            assert_eq!(sol, instance.fallback_solution(method));
+            cancel.store(false, Ordering::Relaxed);
+        }
+
+        // Cancelled once the branching starts, the pair and the partitions found so far beat
+        // the star of everybody.
+        let instance: ProblemInstance = Graph::from(vec![-7, 7, -3, -2, 5, -6, 1, 5]).into();
+        let stop = cancel.clone();
+        let config = SolverConfig {
+            method: SolvingMethods::BranchingPartitionStarExpand,
+            cancel: Some(cancel.clone()),
+            observer: Some(Arc::new(move |event: &SolverEvent| {
+                if matches!(event, SolverEvent::MemoryUsed { .. }) {
+                    stop.store(true, Ordering::Relaxed);
+                }
+            })),
+            ..Default::default()
+        };
+        let sol = instance.solve_edges_with_config(&config).unwrap();
+        assert!(cancel.load(Ordering::Relaxed));
+        let settlement = instance.settlement(&sol).unwrap();
+        assert!(instance
+            .g
+            .vertices
+            .iter()
+            .all(|v| settlement.net(&v.name) == v.weight));
+        let star = instance.fallback_solution(config.method).unwrap();
+        assert!(sol.unwrap().len() < star.len());
+    }
+
+    #[test]
+    fn test_weighted_sum_objective() {
+        init();
+        debug!("Running 'test_weighted_sum_objective'");
+        let instance: ProblemInstance = Graph::from(vec![-5, -3, -2, 1, 4, 5]).into();
+        let config = SolverConfig {
+            method: SolvingMethods::DPStarExpand,
+            objective: Objective::WeightedSum,
+            objective_weights: ObjectiveWeights {
+                alpha: 1.0,
+                beta: 0.5,
+            },
+            ..Default::default()
+        };
+        let star = instance.solve_edges_with(config.method);
+        let sol = instance.solve_edges_with_config(&config).unwrap();
+        debug!("Solution: {:?}", sol);
+        let cost = |sol| config.objective.cost(&config.objective_weights, sol);
+        assert_eq!(
+            sol.as_ref().map(|s| s.len()),
+            star.as_ref().map(|s| s.len())
+        );
+        assert!(cost(&sol) < cost(&star));
+        // The minimal amount is half of the sum of all absolute weights.
+        assert_eq!(cost(&sol), Some(4.0 + 0.5 * 10.0));
+        assert_eq!(
+            Objective::MinTransactions.cost(&config.objective_weights, &sol),
+            Some(4.0)
+        );
+    }
+
+    #[test]
+    fn test_weighted_sum_weights() {
+        init();
+        debug!("Running 'test_weighted_sum_weights'");
+        // Every plan moves at least the minimal amount and the plans with the fewest
+        // transactions move it as well, so no weights make a plan with more transactions
+        // cheaper. Settling the groups of an approximation never makes it more expensive.
+        for seed in 0..10 {
+            let instance = ProblemInstance::from(generate(&GeneratorConfig {
+                nodes: 8,
+                seed: Some(seed),
+                ..Default::default()
+            }));
+            let transactions = instance.min_transactions().unwrap() as f64;
+            let amount = instance.optimal_transaction_amount() as f64;
+            for (alpha, beta) in [(1.0, 0.0), (1.0, 0.5), (0.01, 1.0), (0.0, 1.0)] {
+                for method in [
+                    SolvingMethods::DPStarExpand,
+                    SolvingMethods::BranchingPartitionStarExpand,
+                    SolvingMethods::ApproxStarExpand,
+                ] {
+                    let config = SolverConfig {
+                        method,
+                        objective: Objective::WeightedSum,
+                        objective_weights: ObjectiveWeights { alpha, beta },
+                        ..Default::default()
+                    };
+                    let cost =
+                        |sol: &EdgeSolution| config.objective.cost(&config.objective_weights, sol);
+                    let sol = instance.solve_edges_with_config(&config).unwrap();
+                    assert!(cost(&sol) <= cost(&instance.solve_edges_with(method)));
+                    if method.is_exact() {
+                        let optimum = alpha * transactions + beta * amount;
+                        assert!((cost(&sol).unwrap() - optimum).abs() < 1e-9);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_min_transactions_then_amount_objective() {
+        init();
+        debug!("Running 'test_min_transactions_then_amount_objective'");
+        let instance: ProblemInstance = Graph::from(vec![-5, -3, -2, 1, 4, 5]).into();
+        let config = SolverConfig {
+            method: SolvingMethods::DPStarExpand,
+            objective: Objective::MinTransactionsThenAmount,
+            ..Default::default()
+        };
+        let star = instance.solve_edges_with(config.method);
+        let sol = instance.solve_edges_with_config(&config).unwrap();
+        debug!("Solution: {:?}", sol);
+        let amount = |sol: &EdgeSolution| {
+            sol.as_ref()
+                .map(|s| s.values().map(|w| w.abs()).sum::<i64>())
+        };
+        assert_eq!(
+            sol.as_ref().map(|s| s.len()),
+            star.as_ref().map(|s| s.len())
+        );
+        assert!(amount(&sol) < amount(&star));
+        // The minimal amount is half of the sum of all absolute weights.
+        assert_eq!(amount(&sol), Some(10));
+    }
+
+    #[test]
+    fn test_min_transactions_then_amount_is_lexicographic() {
+        init();
+        debug!("Running 'test_min_transactions_then_amount_is_lexicographic'");
+        // Every plan moves at least the minimal amount, so every exact method has to find it
+        // with the fewest transactions, even if it settles its partitions with a star.
+        for seed in 0..10 {
+            let instance = ProblemInstance::from(generate(&GeneratorConfig {
+                nodes: 7,
+                seed: Some(seed),
+                ..Default::default()
+            }));
+            let transactions = instance.min_transactions();
+            let optimal_amount = Some(instance.optimal_transaction_amount());
+            let count = |sol: &EdgeSolution| sol.as_ref().map(|s| s.len());
+            let amount = |sol: &EdgeSolution| {
+                sol.as_ref()
+                    .map(|s| s.values().map(|w| w.abs()).sum::<i64>())
+            };
+            for method in SolvingMethods::ALL.into_iter().filter(|m| m.is_exact()) {
+                let config = SolverConfig {
+                    method,
+                    objective: Objective::MinTransactionsThenAmount,
+                    ..Default::default()
+                };
+                let sol = instance.solve_edges_with_config(&config).unwrap();
+                assert_eq!(count(&sol), transactions);
+                assert_eq!(amount(&sol), optimal_amount);
+                // Weighting the amount doesn't trade it for more transactions.
+                let config = SolverConfig {
+                    objective: Objective::WeightedSum,
+                    objective_weights: ObjectiveWeights {
+                        alpha: 0.01,
+                        beta: 1.0,
+                    },
+                    ..config
+                };
+                let weighted = instance.solve_edges_with_config(&config).unwrap();
+                assert_eq!(count(&weighted), transactions);
+                assert_eq!(amount(&weighted), optimal_amount);
+            }
+        }
+    }
+
+    #[test]
+    fn test_min_effort_objective() {
+        init();
+        debug!("Running 'test_min_effort_objective'");
+        let instance = ProblemInstance::from(
+            Graph::from(vec![
+                ("X".to_owned(), -3),
+                ("Y".to_owned(), -1),
+                ("P".to_owned(), 2),
+                ("Q".to_owned(), 2),
+            ])
+            .with_metadata(HashMap::from([(
+                "X".to_owned(),
+                vec![("effort".to_owned(), "10".to_owned())],
+            )])),
+        );
+        let config = SolverConfig {
+            method: SolvingMethods::DPGreedySatisfaction,
+            objective: Objective::MinEffort,
+            ..Default::default()
+        };
+        let sol = instance.solve_edges_with_config(&config).unwrap();
+        debug!("Solution: {:?}", instance.solution_transactions(&sol));
+        assert_eq!(sol.as_ref().map(|s| s.len()), Some(3));
+        // X pays only once.
+        let effort = |sol| instance.solution_effort(&instance.settlement(sol));
+        assert_eq!(effort(&sol), Some(12.0));
+        assert!(effort(&instance.solve_edges_with(config.method)) >= effort(&sol));
+
+        // A and D each pay two creditors. A cheap creditor, who passes money on, saves effort
+        // only once per group, so the two cheap people have to end up in different groups and
+        // the weights decide, which of the optimal plans is chosen.
+        let mut plans = Vec::new();
+        for cheap in [["B", "C"], ["B", "E"], ["B", "F"]] {
+            let instance = ProblemInstance::from(
+                Graph::from(vec![
+                    ("A".to_owned(), -2),
+                    ("B".to_owned(), 1),
+                    ("C".to_owned(), 1),
+                    ("D".to_owned(), -2),
+                    ("E".to_owned(), 1),
+                    ("F".to_owned(), 1),
+                ])
+                .with_metadata(HashMap::from(cheap.map(|name| {
+                    (
+                        name.to_owned(),
+                        vec![("effort".to_owned(), "0.1".to_owned())],
+                    )
+                }))),
+            );
+            let sol = instance.solve_edges_with_config(&config).unwrap();
+            debug!("Solution: {:?}", instance.solution_transactions(&sol));
+            assert_eq!(sol.as_ref().map(|s| s.len()), Some(4));
+            let effort = instance
+                .solution_effort(&instance.settlement(&sol))
+                .unwrap();
+            assert!((effort - 2.2).abs() < 1e-9, "effort {effort}");
+            plans.push(instance.solution_transactions(&sol).unwrap());
+        }
+        assert_ne!(plans[0], plans[1]);
+        assert_ne!(plans[1], plans[2]);
+        assert_ne!(plans[0], plans[2]);
+    }
+
+    #[test]
+    fn test_min_transfer_cost_objective() {
+        init();
+        debug!("Running 'test_min_transfer_cost_objective'");
+        let instance = ProblemInstance::from(Graph::from(vec![
+            ("A".to_owned(), -2),
+            ("B".to_owned(), -2),
+            ("C".to_owned(), 1),
+            ("D".to_owned(), 3),
+        ]));
+        // A and B live abroad, so only transfers to D are cheap.
+        let costs = TransferCosts::parse(",C,D\nA,10,1\nB,10,1\nD,1,").unwrap();
+        let config = SolverConfig {
+            method: SolvingMethods::DPGreedySatisfaction,
+            objective: Objective::MinTransferCost,
+            transfer_costs: costs.to_owned(),
+            ..Default::default()
+        };
+        let sol = instance.solve_edges_with_config(&config).unwrap();
+        debug!("Solution: {:?}", instance.solution_transactions(&sol));
+        assert_eq!(sol.as_ref().map(|s| s.len()), Some(3));
+        assert_eq!(costs.solution_cost(&instance.settlement(&sol)), Some(3.0));
+        assert!(
+            costs.solution_cost(&instance.solve_with(config.method))
+                >= costs.solution_cost(&instance.settlement(&sol))
+        );
+        assert!(TransferCosts::parse(",C\nA,x").is_err());
+        assert!(TransferCosts::parse(",C\nA,-1").is_err());
+
+        // Only A to C, C to D and B to D are free. The creditor C passing money on to D is
+        // neither a star nor an arrangement of debtors paying creditors, so picking the cheapest
+        // of those costs at least 10.
+        let instance = ProblemInstance::from(Graph::from(vec![
+            ("A".to_owned(), -3),
+            ("B".to_owned(), -1),
+            ("C".to_owned(), 2),
+            ("D".to_owned(), 2),
+        ]));
+        let costs =
+            TransferCosts::parse(",A,B,C,D\nA,,10,0,10\nB,10,,10,0\nC,10,10,,0\nD,10,10,10,")
+                .unwrap();
+        let config = SolverConfig {
+            transfer_costs: costs.to_owned(),
+            ..config
+        };
+        let sol = instance.solve_edges_with_config(&config).unwrap();
+        assert_eq!(costs.solution_cost(&instance.settlement(&sol)), Some(0.0));
+        assert_eq!(
+            instance.solution_transactions(&sol).unwrap(),
+            vec![
+                ("A".to_owned(), "C".to_owned(), 3),
+                ("B".to_owned(), "D".to_owned(), 1),
+                ("C".to_owned(), "D".to_owned(), 1),
+            ]
+        );
+        for method in [config.method, SolvingMethods::ApproxGreedySatisfaction] {
+            assert!(costs.solution_cost(&instance.solve_with(method)) >= Some(10.0));
+        }
+    }
+
+    #[test]
+    fn test_preferred_pairs() {
+        init();
+        debug!("Running 'test_preferred_pairs'");
+        let instance = ProblemInstance::from(Graph::from(vec![
+            ("A".to_owned(), -2),
+            ("B".to_owned(), -2),
+            ("C".to_owned(), 2),
+            ("D".to_owned(), 2),
+        ]));
+        for preferred in [("A", "D"), ("D", "B"), ("A", "C")] {
+            let config = SolverConfig {
+                method: SolvingMethods::DPGreedySatisfaction,
+                constraints: Constraints {
+                    preferred: vec![(preferred.0.to_owned(), preferred.1.to_owned())],
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            let transactions = instance
+                .solution_transactions(&instance.solve_edges_with_config(&config).unwrap())
+                .unwrap();
+            debug!("Transactions: {:?}", transactions);
+            assert_eq!(transactions.len(), 2);
+            assert!(transactions.iter().any(|(from, to, _)| {
+                (from == preferred.0 && to == preferred.1)
+                    || (from == preferred.1 && to == preferred.0)
+            }));
+        }
+
+        // Only A paying D and B paying C has a preferred pair without a forbidden one.
+        let config = SolverConfig {
+            method: SolvingMethods::DPGreedySatisfaction,
+            constraints: Constraints {
+                preferred: vec![
+                    ("A".to_owned(), "D".to_owned()),
+                    ("B".to_owned(), "D".to_owned()),
+                ],
+                forbidden: vec![("B".to_owned(), "D".to_owned())],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut transactions = instance
+            .solution_transactions(&instance.solve_edges_with_config(&config).unwrap())
+            .unwrap();
+        transactions.sort();
+        assert_eq!(
+            transactions,
+            [
+                ("A".to_owned(), "D".to_owned(), 2),
+                ("B".to_owned(), "C".to_owned(), 2)
+            ]
+        );
+    }
+}
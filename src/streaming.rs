@@ -0,0 +1,240 @@
+use std::collections::BTreeMap;
+use tracing::{debug, instrument, trace};
+
+use crate::verification::Payment;
+
+/// Approximation settling balances in a single pass over a stream with bounded memory. It is
+/// meant for instances with hundreds of thousands of people, for which even creating a
+/// [`Graph`](crate::graph::Graph) with all its edges is infeasible.
+///
+/// Open balances are kept in buckets by their amount. A new balance is settled against an open
+/// balance of the same amount if there is one, and otherwise against the largest open balances.
+/// If more than `capacity` balances are open, the smallest one is settled with the largest one
+/// of the same side. Every payment settles at least one person, so there are at most n - 1
+/// payments for n people, like with 'ApproxStarExpand'.
+///
+/// Example:
+/// ```
+/// use payback::streaming::StreamingSettler;
+///
+/// let mut settler = StreamingSettler::new(1024);
+/// let mut payments = vec![];
+/// for (name, weight) in [("A", -2), ("B", -1), ("C", 1), ("D", 2)] {
+///     payments.extend(settler.push(name, weight).unwrap());
+/// }
+/// settler.finish().unwrap();
+/// assert_eq!(payments.len(), 2);
+/// ```
+#[derive(Clone, Debug)]
+pub struct StreamingSettler {
+    capacity: usize,
+    /// Names of the open balances by their absolute amount. All open balances have the same
+    /// sign, since a new balance is first settled against the other side.
+    open: BTreeMap<i64, Vec<String>>,
+    /// Number of open balances.
+    len: usize,
+    /// Whether the open balances are positive, i.e. the people receive money.
+    receiving: bool,
+}
+
+impl StreamingSettler {
+    /// Settler keeping at most `capacity` balances open. A capacity of 0 is treated as 1.
+    pub fn new(capacity: usize) -> Self {
+        StreamingSettler {
+            capacity: capacity.max(1),
+            open: BTreeMap::new(),
+            len: 0,
+            receiving: false,
+        }
+    }
+
+    /// Adds the balance of a person and returns the payments, which are settled by it. A
+    /// positive weight means the person receives money.
+    pub fn push(&mut self, name: &str, weight: i64) -> Result<Vec<Payment>, String> {
+        let mut payments = vec![];
+        if weight == 0 {
+            return Ok(payments);
+        }
+        let amount = weight
+            .checked_abs()
+            .ok_or(format!("The weight of {:?} is too large.", name))?;
+        if self.len == 0 || self.receiving == (weight > 0) {
+            self.receiving = weight > 0;
+            self.insert(name.to_owned(), amount);
+            if self.len > self.capacity {
+                payments.push(self.merge_smallest()?);
+            }
+            return Ok(payments);
+        }
+        let mut remaining = amount;
+        while remaining > 0 {
+            // An open balance of the same amount settles both people with one payment.
+            let other_amount = match self.open.get(&remaining) {
+                Some(_) => remaining,
+                None => match self.open.last_key_value() {
+                    Some((a, _)) => *a,
+                    None => break,
+                },
+            };
+            let other = self.remove(other_amount);
+            let paid = remaining.min(other_amount);
+            payments.push(self.payment(name, &other, paid));
+            remaining -= paid;
+            if other_amount > paid {
+                self.insert(other, other_amount - paid);
+            }
+        }
+        if remaining > 0 {
+            self.receiving = weight > 0;
+            self.insert(name.to_owned(), remaining);
+        }
+        trace!(name, weight, payments = payments.len(), open = self.len);
+        Ok(payments)
+    }
+
+    /// Checks that no balance is left open, i.e. that the weights add up to 0.
+    pub fn finish(self) -> Result<(), String> {
+        let open: i128 = self
+            .open
+            .iter()
+            .map(|(a, ns)| *a as i128 * ns.len() as i128)
+            .sum();
+        debug!(open_balances = self.len, "Finished streaming");
+        if open == 0 {
+            Ok(())
+        } else {
+            Err(format!(
+                "The weights add up to {} instead of 0.",
+                if self.receiving { open } else { -open }
+            ))
+        }
+    }
+
+    /// Payment between a new person and an open balance of the other side.
+    fn payment(&self, new: &str, open: &str, amount: i64) -> Payment {
+        let (from, to) = if self.receiving {
+            (new, open)
+        } else {
+            (open, new)
+        };
+        Payment {
+            from: from.to_owned(),
+            to: to.to_owned(),
+            amount,
+        }
+    }
+
+    /// Settles the smallest open balance with the largest one, which takes over its amount.
+    fn merge_smallest(&mut self) -> Result<Payment, String> {
+        let smallest_amount = *self.open.first_key_value().expect("Balances are open.").0;
+        let smallest = self.remove(smallest_amount);
+        let largest_amount = *self.open.last_key_value().expect("Balances are open.").0;
+        let largest = self.remove(largest_amount);
+        let payment = if self.receiving {
+            Payment {
+                from: largest.to_owned(),
+                to: smallest,
+                amount: smallest_amount,
+            }
+        } else {
+            Payment {
+                from: smallest,
+                to: largest.to_owned(),
+                amount: smallest_amount,
+            }
+        };
+        let merged = largest_amount
+            .checked_add(smallest_amount)
+            .ok_or("The open balances are too large to be merged.")?;
+        self.insert(largest, merged);
+        Ok(payment)
+    }
+
+    fn insert(&mut self, name: String, amount: i64) {
+        self.open.entry(amount).or_default().push(name);
+        self.len += 1;
+    }
+
+    fn remove(&mut self, amount: i64) -> String {
+        let names = self.open.get_mut(&amount).expect("Amount is open.");
+        let name = names.pop().expect("Buckets aren't empty.");
+        if names.is_empty() {
+            self.open.remove(&amount);
+        }
+        self.len -= 1;
+        name
+    }
+}
+
+/// Settles the balances with a [`StreamingSettler`] and passes every payment to `on_payment`
+/// as soon as it is known. Returns the number of payments.
+#[instrument(level = "debug", skip_all, fields(capacity))]
+pub fn settle_stream<I, F>(balances: I, capacity: usize, mut on_payment: F) -> Result<usize, String>
+where
+    I: IntoIterator<Item = Result<(String, i64), String>>,
+    F: FnMut(Payment) -> Result<(), String>,
+{
+    let mut settler = StreamingSettler::new(capacity);
+    let mut count = 0;
+    for balance in balances {
+        let (name, weight) = balance?;
+        for payment in settler.push(&name, weight)? {
+            on_payment(payment)?;
+            count += 1;
+        }
+    }
+    settler.finish()?;
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::graph::Graph;
+    use crate::streaming::settle_stream;
+    use crate::verification::verify_plan;
+    use tracing::debug;
+    use tracing_subscriber::EnvFilter;
+
+    fn init() {
+        let _ = tracing_subscriber::fmt()
+            .with_env_filter(
+                EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("debug")),
+            )
+            .with_test_writer()
+            .try_init();
+    }
+
+    #[test]
+    fn test_settle_stream() {
+        init();
+        debug!("Running 'test_settle_stream'");
+        let balances: Vec<(String, i64)> = [-5, -3, -3, -1, 2, 3, 1, 6, -4, 4]
+            .iter()
+            .enumerate()
+            .map(|(i, w)| (i.to_string(), *w))
+            .collect();
+        for capacity in [1, 2, 100] {
+            let mut payments = vec![];
+            let count = settle_stream(balances.iter().cloned().map(Ok), capacity, |p| {
+                payments.push(p);
+                Ok(())
+            })
+            .unwrap();
+            debug!("Payments with capacity {}: {:?}", capacity, payments);
+            assert_eq!(count, payments.len());
+            assert!(count < balances.len());
+            let report = verify_plan(&Graph::from(balances.to_owned()), &payments);
+            assert!(report.is_settled(), "{:?}", report);
+        }
+
+        let unbalanced = settle_stream(
+            [Ok(("A".to_owned(), -2)), Ok(("B".to_owned(), 1))],
+            8,
+            |_| Ok(()),
+        );
+        assert_eq!(
+            unbalanced,
+            Err("The weights add up to -1 instead of 0.".to_owned())
+        );
+    }
+}
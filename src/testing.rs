@@ -0,0 +1,252 @@
+//! Utilities for property based testing of solvers.
+//! Only available with the `testing` feature.
+use proptest::collection::vec;
+use proptest::prelude::*;
+use std::collections::HashSet;
+
+use crate::graph::{Graph, NamedNode};
+use crate::settlement::{Settlement, Transaction};
+
+/// Largest number of people with a non zero weight [`brute_force_solution()`] accepts.
+pub const ORACLE_MAX_VERTICES: usize = 12;
+
+/// Strategy generating graphs with between 1 and `max_nodes` vertices, whose weights add up to
+/// zero. Every vertex weight is bounded by `max_weight`, except the last one, which balances the
+/// graph.
+///
+/// Example:
+/// ```
+/// use payback::probleminstance::{ProblemInstance, SolvingMethods};
+/// use payback::testing::{balanced_graph, is_valid_solution};
+/// use proptest::prelude::*;
+///
+/// proptest!(|(graph in balanced_graph(6, 10))| {
+///     let instance = ProblemInstance::from(graph.clone());
+///     let solution = instance.solve_with(SolvingMethods::ApproxStarExpand);
+///     prop_assert!(is_valid_solution(&graph, &solution));
+/// });
+/// ```
+pub fn balanced_graph(max_nodes: usize, max_weight: i64) -> impl Strategy<Value = Graph> {
+    vec(-max_weight..=max_weight, 0..max_nodes.max(1)).prop_map(|mut weights| {
+        weights.push(-weights.iter().sum::<i64>());
+        Graph::from(weights)
+    })
+}
+
+/// Strategy generating graphs with between 2 and `max_nodes` vertices, whose weights add up to a
+/// small non zero value. Solvers should reject these graphs.
+pub fn near_balanced_graph(max_nodes: usize, max_weight: i64) -> impl Strategy<Value = Graph> {
+    (
+        vec(-max_weight..=max_weight, 1..max_nodes.max(2)),
+        prop_oneof![-3_i64..=-1, 1_i64..=3],
+    )
+        .prop_map(|(mut weights, imbalance)| {
+            weights.push(imbalance - weights.iter().sum::<i64>());
+            Graph::from(weights)
+        })
+}
+
+/// Checks whether the solution settles every vertex of the graph, i.e. for every vertex the
+/// inflow minus the outflow is equal to its weight.
+/// A missing solution is never valid.
+pub fn is_valid_solution(graph: &Graph, solution: &Option<Settlement>) -> bool {
+    let Some(settlement) = solution else {
+        return false;
+    };
+    let names: HashSet<&str> = graph.vertices.iter().map(|v| v.name.as_str()).collect();
+    settlement.people().iter().all(|p| names.contains(p))
+        && graph
+            .vertices
+            .iter()
+            .all(|v| settlement.net(&v.name) == v.weight)
+}
+
+/// Checks whether the approximated solution uses at most twice as many transactions as the
+/// optimal solution.
+pub fn within_approximation_factor(
+    approximation: &Option<Settlement>,
+    optimum: &Option<Settlement>,
+) -> bool {
+    match (approximation, optimum) {
+        (Some(approx), Some(opt)) => approx.len() <= 2 * opt.len().max(1),
+        (None, None) => true,
+        _ => false,
+    }
+}
+
+/// Number of transactions of a solution or `None`, if no solution was found.
+pub fn transaction_count(solution: &Option<Settlement>) -> Option<usize> {
+    solution.as_ref().map(|s| s.len())
+}
+
+/// Exhaustive reference solver meant as ground truth for testing other solvers on tiny
+/// instances. It tries every way to split the people into groups, whose weights add up to zero,
+/// and settles the groups of the split with the most groups. It shares no code with the
+/// solvers of the crate and is far too slow for anything but tests.
+/// Returns `None` for unsolvable graphs.
+///
+/// Panics for graphs with more than [`ORACLE_MAX_VERTICES`] people with a non zero weight.
+///
+/// Example:
+/// ```
+/// use payback::graph::Graph;
+/// use payback::probleminstance::{ProblemInstance, SolvingMethods};
+/// use payback::testing::{brute_force_solution, transaction_count};
+///
+/// let graph = Graph::from(vec![-4, -1, 2, 3]);
+/// let oracle = brute_force_solution(&graph);
+/// let solution = ProblemInstance::from(graph).solve_with(SolvingMethods::DPStarExpand);
+/// assert_eq!(transaction_count(&solution), transaction_count(&oracle));
+/// ```
+pub fn brute_force_solution(graph: &Graph) -> Option<Settlement> {
+    let people: Vec<&NamedNode> = graph.vertices.iter().filter(|v| v.weight != 0).collect();
+    assert!(
+        people.len() <= ORACLE_MAX_VERTICES,
+        "The oracle only solves instances with up to {} people, but got {}.",
+        ORACLE_MAX_VERTICES,
+        people.len()
+    );
+    if people.iter().map(|v| v.weight).sum::<i64>() != 0 {
+        return None;
+    }
+    let groups = largest_zero_sum_split(&people);
+    let mut transactions = vec![];
+    for group in groups {
+        // Debtors pay creditors in order. Every payment settles at least one of them.
+        let mut debtors: Vec<(&str, i64)> = group
+            .iter()
+            .filter(|v| v.weight < 0)
+            .map(|v| (v.name.as_str(), -v.weight))
+            .collect();
+        let mut creditors: Vec<(&str, i64)> = group
+            .iter()
+            .filter(|v| v.weight > 0)
+            .map(|v| (v.name.as_str(), v.weight))
+            .collect();
+        while let (Some((debtor, debt)), Some((creditor, credit))) =
+            (debtors.pop(), creditors.pop())
+        {
+            let amount = debt.min(credit);
+            transactions.push(Transaction {
+                payer: debtor.to_owned(),
+                payee: creditor.to_owned(),
+                amount,
+            });
+            if debt > amount {
+                debtors.push((debtor, debt - amount));
+            }
+            if credit > amount {
+                creditors.push((creditor, credit - amount));
+            }
+        }
+    }
+    Some(Settlement::new(transactions))
+}
+
+/// Split of the people into the most groups, whose weights add up to zero. Every subset
+/// containing the first person is tried as its group.
+fn largest_zero_sum_split<'a>(people: &[&'a NamedNode]) -> Vec<Vec<&'a NamedNode>> {
+    let Some((first, rest)) = people.split_first() else {
+        return vec![];
+    };
+    let mut best: Vec<Vec<&NamedNode>> = vec![people.to_vec()];
+    for mask in 0..1_u32 << rest.len() {
+        let in_group = |i: &usize| mask & (1 << i) != 0;
+        let sum: i64 = first.weight
+            + (0..rest.len())
+                .filter(in_group)
+                .map(|i| rest[i].weight)
+                .sum::<i64>();
+        // Taking all people is the initial split.
+        if sum != 0 || mask == (1 << rest.len()) - 1 {
+            continue;
+        }
+        let others: Vec<&NamedNode> = (0..rest.len())
+            .filter(|i| !in_group(i))
+            .map(|i| rest[i])
+            .collect();
+        let mut split = largest_zero_sum_split(&others);
+        if split.len() + 1 > best.len() {
+            split.push(
+                std::iter::once(*first)
+                    .chain((0..rest.len()).filter(in_group).map(|i| rest[i]))
+                    .collect(),
+            );
+            best = split;
+        }
+    }
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::probleminstance::{ProblemInstance, SolvingMethods};
+    use crate::testing::{
+        balanced_graph, brute_force_solution, is_valid_solution, near_balanced_graph,
+        transaction_count, within_approximation_factor,
+    };
+    use proptest::prelude::*;
+
+    const APPROXIMATIONS: [SolvingMethods; 5] = [
+        SolvingMethods::ApproxStarExpand,
+        SolvingMethods::ApproxGreedySatisfaction,
+        SolvingMethods::ApproxBucketedDP,
+        SolvingMethods::ApproxHierarchical,
+        SolvingMethods::ApproxGenetic,
+    ];
+    const EXACT: [SolvingMethods; 4] = [
+        SolvingMethods::BranchingPartitionStarExpand,
+        SolvingMethods::BranchingPartitionGreedySatisfaction,
+        SolvingMethods::DPStarExpand,
+        SolvingMethods::DPGreedySatisfaction,
+    ];
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(1000))]
+
+        #[test]
+        fn prop_solutions_are_balanced(graph in balanced_graph(7, 20)) {
+            let instance = ProblemInstance::from(graph.clone());
+            for method in APPROXIMATIONS.into_iter().chain(EXACT) {
+                let sol = instance.solve_with(method);
+                prop_assert!(is_valid_solution(&graph, &sol), "{:?} failed on {}", method, graph);
+            }
+        }
+
+        #[test]
+        fn prop_exact_solvers_agree(graph in balanced_graph(7, 20)) {
+            let instance = ProblemInstance::from(graph);
+            let counts = EXACT.map(|m| transaction_count(&instance.solve_with(m)));
+            prop_assert!(counts.iter().all(|c| c == &counts[0]), "{:?}", counts);
+        }
+
+        #[test]
+        fn prop_exact_solvers_match_oracle(graph in balanced_graph(7, 20)) {
+            let oracle = brute_force_solution(&graph);
+            prop_assert!(is_valid_solution(&graph, &oracle), "Oracle failed on {}", graph);
+            let instance = ProblemInstance::from(graph);
+            for method in EXACT {
+                let count = transaction_count(&instance.solve_with(method));
+                prop_assert_eq!(count, transaction_count(&oracle), "{:?}", method);
+            }
+        }
+
+        #[test]
+        fn prop_approximation_factor(graph in balanced_graph(7, 20)) {
+            let instance = ProblemInstance::from(graph);
+            let opt = instance.solve_with(SolvingMethods::DPGreedySatisfaction);
+            for method in APPROXIMATIONS {
+                prop_assert!(within_approximation_factor(&instance.solve_with(method), &opt));
+            }
+        }
+
+        #[test]
+        fn prop_near_balanced_rejected(graph in near_balanced_graph(7, 20)) {
+            let instance = ProblemInstance::from(graph);
+            prop_assert!(!instance.is_solvable());
+            for method in APPROXIMATIONS.into_iter().chain(EXACT) {
+                prop_assert!(instance.solve_with(method).is_none());
+            }
+        }
+    }
+}
@@ -0,0 +1,218 @@
+use serde_derive::Serialize;
+use std::path::Path;
+
+use crate::approximation::{greedy_satisfaction, star_expand};
+use crate::dynamic_program::dp_trace;
+use crate::exact_partitioning::partitioning_trace;
+use crate::probleminstance::{ProblemInstance, SolvingMethods};
+use crate::tree_bases::branching_trace;
+
+/// Search explored by an exact solver. The nodes are sets of people and the edges are the splits
+/// of a set considered by the solver. The nodes and edges, which lead to the proposed solution,
+/// are chosen.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct SearchTrace {
+    pub nodes: Vec<TraceNode>,
+    pub edges: Vec<TraceEdge>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct TraceNode {
+    pub id: usize,
+    /// People with a weight other than zero of the node.
+    pub people: Vec<String>,
+    /// Minimal number of transactions of the people found by the solver, if any.
+    pub transactions: Option<usize>,
+    pub chosen: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct TraceEdge {
+    pub from: usize,
+    pub to: usize,
+    /// Groups of people split off by the edge, e.g. a zero-sum subset or a partitioning.
+    pub parts: Vec<Vec<String>>,
+    pub chosen: bool,
+}
+
+impl SearchTrace {
+    /// Adds a node, which isn't chosen, and returns its id.
+    pub(crate) fn add_node(&mut self, people: Vec<String>, transactions: Option<usize>) -> usize {
+        let id = self.nodes.len();
+        self.nodes.push(TraceNode {
+            id,
+            people,
+            transactions,
+            chosen: false,
+        });
+        id
+    }
+
+    /// Adds an edge, which isn't chosen, and returns its position.
+    pub(crate) fn add_edge(&mut self, from: usize, to: usize, parts: Vec<Vec<String>>) -> usize {
+        self.edges.push(TraceEdge {
+            from,
+            to,
+            parts,
+            chosen: false,
+        });
+        self.edges.len() - 1
+    }
+
+    /// Marks the node and every node reachable through chosen edges as chosen.
+    pub(crate) fn choose_from(&mut self, root: usize) {
+        let mut open = vec![root];
+        while let Some(id) = open.pop() {
+            self.nodes[id].chosen = true;
+            open.extend(
+                self.edges
+                    .iter()
+                    .filter(|e| e.chosen && e.from == id)
+                    .map(|e| e.to),
+            );
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("Traces are serializable.")
+    }
+
+    /// Dot graph of the trace, in which the chosen nodes and edges are bold.
+    ///
+    /// Example:
+    /// ```
+    /// use payback::graph::Graph;
+    /// use payback::probleminstance::{ProblemInstance, SolvingMethods};
+    ///
+    /// let instance: ProblemInstance = Graph::from(vec![
+    ///     ("A".to_owned(), -1),
+    ///     ("B".to_owned(), 1),
+    /// ])
+    /// .into();
+    /// let trace = instance.search_trace(SolvingMethods::DPGreedySatisfaction).unwrap();
+    /// assert_eq!(
+    ///     trace.to_dot(),
+    ///     "digraph {\n    0 [ label = \"A, B\\n1\" style = bold ]\n}\n"
+    /// );
+    /// ```
+    pub fn to_dot(&self) -> String {
+        let style = |chosen: bool| if chosen { " style = bold" } else { "" };
+        let mut dot = "digraph {\n".to_owned();
+        for node in &self.nodes {
+            dot += &format!(
+                "    {} [ label = {:?}{} ]\n",
+                node.id,
+                format!(
+                    "{}\n{}",
+                    node.people.join(", "),
+                    node.transactions.map_or("-".to_owned(), |t| t.to_string())
+                ),
+                style(node.chosen)
+            );
+        }
+        for edge in &self.edges {
+            dot += &format!(
+                "    {} -> {} [ label = {:?}{} ]\n",
+                edge.from,
+                edge.to,
+                edge.parts
+                    .iter()
+                    .map(|p| format!("{{{}}}", p.join(", ")))
+                    .collect::<Vec<_>>()
+                    .join(" "),
+                style(edge.chosen)
+            );
+        }
+        dot + "}\n"
+    }
+
+    /// Writes the trace to the file as dot, if the extension is 'dot' or 'gv', and as json
+    /// otherwise.
+    pub fn write(&self, path: &Path) -> Result<(), String> {
+        let content = match path.extension().and_then(|e| e.to_str()) {
+            Some("dot" | "gv") => self.to_dot(),
+            _ => self.to_json(),
+        };
+        std::fs::write(path, content)
+            .map_err(|e| format!("Unable to write search trace {:?}: {}", path, e))
+    }
+}
+
+impl ProblemInstance {
+    /// Solves the instance with an exact method and records its search. The dynamic programs
+    /// record their table, in which every subset points to the subsets it is split into, the
+    /// branching methods their tree of zero-sum subsets and the partitioning methods every
+    /// partitioning tried. Returns `None` for approximations and unsolvable instances.
+    pub fn search_trace(&self, method: SolvingMethods) -> Option<SearchTrace> {
+        if !self.is_solvable() {
+            return None;
+        }
+        match method {
+            SolvingMethods::ApproxStarExpand
+            | SolvingMethods::ApproxGreedySatisfaction
+            | SolvingMethods::ApproxBucketedDP
+            | SolvingMethods::ApproxHierarchical
+            | SolvingMethods::ApproxGenetic => None,
+            SolvingMethods::PartitioningStarExpand => Some(partitioning_trace(self, &star_expand)),
+            SolvingMethods::PartitioningGreedySatisfaction => {
+                Some(partitioning_trace(self, &greedy_satisfaction))
+            }
+            SolvingMethods::BranchingPartitionStarExpand
+            | SolvingMethods::BranchingPartitionGreedySatisfaction => Some(branching_trace(self)),
+            SolvingMethods::DPStarExpand | SolvingMethods::DPGreedySatisfaction => dp_trace(self),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::graph::Graph;
+    use crate::probleminstance::{ProblemInstance, SolvingMethods};
+    use tracing::debug;
+    use tracing_subscriber::EnvFilter;
+
+    fn init() {
+        let _ = tracing_subscriber::fmt()
+            .with_env_filter(
+                EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("debug")),
+            )
+            .with_test_writer()
+            .try_init();
+    }
+
+    #[test]
+    fn test_search_trace() {
+        init();
+        debug!("Running 'test_search_trace'");
+        let instance = ProblemInstance::from(Graph::from(vec![
+            ("A".to_owned(), -3),
+            ("B".to_owned(), -2),
+            ("C".to_owned(), 2),
+            ("D".to_owned(), 1),
+            ("E".to_owned(), 2),
+        ]));
+        let optimum = instance.min_transactions().unwrap();
+        for method in [
+            SolvingMethods::PartitioningGreedySatisfaction,
+            SolvingMethods::BranchingPartitionStarExpand,
+            SolvingMethods::DPGreedySatisfaction,
+        ] {
+            let trace = instance.search_trace(method).unwrap();
+            debug!("Trace of {:?}: {}", method, trace.to_dot());
+            let root = &trace.nodes[0];
+            assert_eq!(root.people.len(), 5);
+            assert_eq!(root.transactions, Some(optimum));
+            assert!(root.chosen);
+            assert!(trace.edges.iter().any(|e| e.chosen));
+            assert!(trace
+                .edges
+                .iter()
+                .all(|e| e.from < trace.nodes.len() && e.to < trace.nodes.len()));
+            assert!(trace.to_json().contains("\"chosen\": true"));
+        }
+        assert_eq!(
+            instance.search_trace(SolvingMethods::ApproxStarExpand),
+            None
+        );
+    }
+}
@@ -0,0 +1,114 @@
+use csv::ReaderBuilder;
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::settlement::Settlement;
+
+/// Cost of a transaction between two people, e.g. cross-border transfers cost more than
+/// domestic ones. Pairs without a cost cost 1.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TransferCosts {
+    /// Cost by payer and payee.
+    costs: HashMap<(String, String), f64>,
+}
+
+impl TransferCosts {
+    /// Parses a csv matrix, whose first row holds the payees and whose first column holds the
+    /// payers. Empty cells are pairs without a cost.
+    ///
+    /// Example:
+    /// ```
+    /// use payback::transfer_costs::TransferCosts;
+    ///
+    /// let costs = TransferCosts::parse(",Anna,Ben\nAnna,0,5\nBen,2,").unwrap();
+    /// assert_eq!(costs.cost("Anna", "Ben"), 5.0);
+    /// assert_eq!(costs.cost("Ben", "Anna"), 2.0);
+    /// assert_eq!(costs.cost("Anna", "Carl"), 1.0);
+    /// ```
+    pub fn parse(data: &str) -> Result<Self, String> {
+        let mut records = ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .trim(csv::Trim::All)
+            .from_reader(data.as_bytes())
+            .into_records();
+        let payees: Vec<String> = match records.next() {
+            Some(header) => header
+                .map_err(|e| e.to_string())?
+                .iter()
+                .skip(1)
+                .map(str::to_owned)
+                .collect(),
+            None => return Ok(TransferCosts::default()),
+        };
+        let mut costs = HashMap::new();
+        for record in records {
+            let record = record.map_err(|e| e.to_string())?;
+            let Some(payer) = record.get(0) else {
+                continue;
+            };
+            if record.len() > payees.len() + 1 {
+                return Err(format!(
+                    "The row of {:?} has more costs than there are payees.",
+                    payer
+                ));
+            }
+            for (payee, cost) in payees.iter().zip(record.iter().skip(1)) {
+                if cost.is_empty() {
+                    continue;
+                }
+                let cost: f64 = cost.parse().map_err(|_| {
+                    format!(
+                        "Cost '{}' from {:?} to {:?} is no number.",
+                        cost, payer, payee
+                    )
+                })?;
+                if !cost.is_finite() || cost < 0.0 {
+                    return Err(format!(
+                        "Cost {} from {:?} to {:?} is negative or infinite.",
+                        cost, payer, payee
+                    ));
+                }
+                costs.insert((payer.to_owned(), payee.to_owned()), cost);
+            }
+        }
+        Ok(TransferCosts { costs })
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let data = std::fs::read_to_string(path)
+            .map_err(|e| format!("Unable to read transfer costs {:?}: {}", path, e))?;
+        Self::parse(&data).map_err(|e| format!("Invalid transfer costs {:?}: {}", path, e))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.costs.is_empty()
+    }
+
+    /// Costs ordered by payer and payee, e.g. for stable hashes.
+    pub(crate) fn sorted(&self) -> Vec<(&(String, String), &f64)> {
+        let mut costs: Vec<_> = self.costs.iter().collect();
+        costs.sort_by(|a, b| a.0.cmp(b.0));
+        costs
+    }
+
+    /// Cost of a transaction of `from` to `to`.
+    pub fn cost(&self, from: &str, to: &str) -> f64 {
+        self.costs
+            .get(&(from.to_owned(), to.to_owned()))
+            .copied()
+            .unwrap_or(1.0)
+    }
+
+    /// Total cost of the transactions of the solution.
+    pub fn solution_cost(&self, solution: &Option<Settlement>) -> Option<f64> {
+        Some(
+            solution
+                .as_ref()?
+                .transactions()
+                .iter()
+                .map(|t| self.cost(&t.payer, &t.payee))
+                .sum(),
+        )
+    }
+}
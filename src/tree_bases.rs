@@ -1,35 +1,71 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicUsize, Ordering};
 
+use crate::events::{MemoryStructure, SolverEvent, SolverObserver};
 use crate::graph::{Edge, Graph, NamedNode};
-use crate::probleminstance::{ProblemInstance, Solution};
+use crate::probleminstance::{is_cancelled, Cancel, EdgeSolution, ProblemInstance};
+use crate::trace::SearchTrace;
 use itertools::Itertools;
-use log::debug;
+use rayon::prelude::*;
+use tracing::{instrument, trace, Span};
+
+/// Partitioning found in a branch of [`best_partition_rec()`] with its edge in the search trace.
+type Branch = (Vec<Vec<NamedNode>>, Option<usize>);
 
 /// Algorithm solving the payback problem via a branching based approach.
-/// Has a runtime of O*(3^n).
+/// Has a runtime of O*(3^n). The branches are explored in parallel on the rayon thread pool.
 ///
 ///
 /// * `instance` - The problem instance which should be solved
 /// * `approx_solver` - Approximation algorithm used to solve partition, which have no zero sum
-/// subset
+///   subset
+/// * `observer` - Receives the events of the solver
+/// * `cancel` - Stops the search, once it is set. The partitions found so far are then settled
+///   and the people, who are in none of them, are settled by the approximation as one partition
 ///
 /// Example:
 /// ```
 /// use payback::graph::Graph;
-/// use payback::probleminstance::{ProblemInstance, Solution, SolvingMethods};
+/// use payback::probleminstance::{ProblemInstance, SolvingMethods};
 ///
 /// let instance: ProblemInstance = Graph::from(vec![-2, -1, 1, 2]).into();
-/// let solution: Solution = instance.solve_with(SolvingMethods::BranchingPartitionStarExpand);
+/// let solution = instance.solve_with(SolvingMethods::BranchingPartitionStarExpand);
 /// ```
+#[instrument(
+    level = "debug",
+    skip_all,
+    fields(vertices = instance.g.vertices.len(), branches, partitions)
+)]
 pub(crate) fn best_partition(
     instance: &ProblemInstance,
-    approx_solver: &dyn Fn(&ProblemInstance) -> Solution,
-) -> Solution {
+    approx_solver: &dyn Fn(&ProblemInstance) -> EdgeSolution,
+    observer: &dyn SolverObserver,
+    cancel: Cancel,
+) -> EdgeSolution {
     if !instance.is_solvable() {
         return None;
     }
-    let solution_partition: Vec<Vec<NamedNode>> = best_partition_rec(&instance.g.vertices);
-    debug!(
+    let branches = AtomicUsize::new(0);
+    let mut solution_partition: Vec<Vec<NamedNode>> =
+        best_partition_rec(&instance.g.vertices, &branches, observer, None, 0, cancel);
+    let cancelled = is_cancelled(cancel);
+    if cancelled {
+        // The partitions are disjoint zero-sum subsets, so the rest sums up to zero as well.
+        let rest = instance
+            .g
+            .vertices
+            .iter()
+            .filter(|v| v.weight != 0 && !solution_partition.iter().flatten().any(|p| p == *v))
+            .cloned()
+            .collect_vec();
+        if !rest.is_empty() {
+            solution_partition.push(rest);
+        }
+    }
+    Span::current()
+        .record("branches", branches.into_inner())
+        .record("partitions", solution_partition.len());
+    trace!(
         "Proposed solution partitioning: {:?}",
         solution_partition
             .iter()
@@ -41,9 +77,14 @@ pub(crate) fn best_partition(
             ))
             .join(", ")
     );
-    let solution: &mut HashMap<Edge, f64> = &mut HashMap::new();
+    let solution: &mut BTreeMap<Edge, i64> = &mut BTreeMap::new();
     solution_partition
         .into_iter()
+        .inspect(|s| {
+            observer.on_event(&SolverEvent::PartitionDiscovered {
+                people: s.iter().map(|v| v.name.to_owned()).collect(),
+            })
+        })
         .map(|s| approx_solver(&ProblemInstance::from(Graph::from(s))))
         .for_each(|sol| {
             match sol {
@@ -51,24 +92,87 @@ pub(crate) fn best_partition(
                 None => unreachable!("The instance is solvable and the recursion should have only added zero sum subsets."),
             }
         });
+    if cancelled {
+        observer.on_event(&SolverEvent::NewIncumbent {
+            transactions: solution.len(),
+        });
+    } else {
+        // All branches were explored, so the solution is optimal.
+        observer.on_event(&SolverEvent::BoundImproved {
+            lower_bound: solution.len(),
+        });
+    }
     Some(solution.to_owned())
 }
 
-fn best_partition_rec(vertices: &[NamedNode]) -> Vec<Vec<NamedNode>> {
-    debug!("Current vertices: {:?}", vertices);
+/// Tree of the branches explored by [`best_partition()`] as a [`SearchTrace`]. Every node is the
+/// set of people left and every edge takes a zero-sum subset of them. Pairs of opposite weights
+/// are taken without branching and are part of every edge of their node.
+pub(crate) fn branching_trace(instance: &ProblemInstance) -> SearchTrace {
+    let mut trace = SearchTrace::default();
+    best_partition_rec(
+        &instance.g.vertices,
+        &AtomicUsize::new(0),
+        &(),
+        Some(&mut trace),
+        0,
+        Cancel::default(),
+    );
+    trace.choose_from(0);
+    trace
+}
+
+/// Counts the explored branches in `branches` and adds them to the `search` if given. Only the
+/// top level of the recursion reports incumbents and memory to the `observer`, since deeper
+/// levels only see a part of the vertices. Without a `search`, the branches are explored in
+/// parallel. Their results are compared in the order of the subsets, so the result doesn't
+/// depend on the number of threads.
+///
+/// Partitionings with fewer than `need` subsets are of no use to the caller. If the best one has
+/// fewer, any partitioning with fewer subsets may be returned, which can miss vertices. The same
+/// holds for every partitioning returned after `cancel` is set.
+fn best_partition_rec(
+    vertices: &[NamedNode],
+    branches: &AtomicUsize,
+    observer: &dyn SolverObserver,
+    mut search: Option<&mut SearchTrace>,
+    need: usize,
+    cancel: Cancel,
+) -> Vec<Vec<NamedNode>> {
+    if is_cancelled(cancel) {
+        return vec![];
+    }
+    branches.fetch_add(1, Ordering::Relaxed);
+    trace!("Current vertices: {:?}", vertices);
+    let names = |vs: &[NamedNode]| {
+        vs.iter()
+            .filter(|v| v.weight != 0)
+            .map(|v| v.name.to_owned())
+            .collect_vec()
+    };
+    let node = search
+        .as_deref_mut()
+        .map(|s| s.add_node(names(vertices), Some(0)));
     if vertices.is_empty() {
         return vec![];
     }
     let mut best_branching: Vec<Vec<NamedNode>> = vec![];
-    let mut remove_verts: Vec<&NamedNode> = vec![];
+    let nonzero = vertices.iter().filter(|v| v.weight != 0).count();
+    // Vertices with weight zero don't need any transactions.
+    let mut remove_verts: Vec<&NamedNode> = vertices.iter().filter(|v| v.weight == 0).collect();
     let subsets = zero_sum_subsets(vertices);
+    // The subsets of the top level are the largest, since deeper levels see fewer vertices.
+    observer.on_event(&SolverEvent::MemoryUsed {
+        structure: MemoryStructure::ZeroSumSubsets,
+        bytes: subsets_bytes(&subsets),
+    });
     let filtered_subsets = subsets
         .iter()
         .filter(|s| match s.len() {
             0 => false,
             1 => {
                 // Remove vertices with weight zero.
-                debug!("Removing single vertex set {:?}, since this is optimal.", s);
+                trace!("Removing single vertex set {:?}, since this is optimal.", s);
                 remove_verts.push(s.first().unwrap());
                 false
             }
@@ -77,7 +181,7 @@ fn best_partition_rec(vertices: &[NamedNode]) -> Vec<Vec<NamedNode>> {
                 let u = s.first().unwrap();
                 let v = s.last().unwrap();
                 if !remove_verts.contains(&u) && !remove_verts.contains(&v) {
-                    debug!(
+                    trace!(
                         "Adding pair {:?} of opposite weights, since this is optimal.",
                         s
                     );
@@ -89,53 +193,186 @@ fn best_partition_rec(vertices: &[NamedNode]) -> Vec<Vec<NamedNode>> {
             }
             _ => true,
         })
+        .collect_vec()
+        .into_iter()
+        // Subsets containing an already removed vertex are no valid branches anymore.
+        .filter(|s| s.iter().all(|v| !remove_verts.contains(&v)))
         .collect_vec();
     if remove_verts.len() == vertices.len() {
-        debug!("Exiting recursion early since no vertices are left.");
+        trace!("Exiting recursion early since no vertices are left.");
+        observer.on_event(&SolverEvent::NewIncumbent {
+            transactions: best_branching.len(),
+        });
+        if let (Some(s), Some(node)) = (search, node) {
+            s.nodes[node].transactions = Some(nonzero - best_branching.len());
+        }
         return best_branching;
     }
-    let best_branch = filtered_subsets.into_iter().fold(vec![], |acc, s| {
-        let verts = vertices
+    let remaining = |s: &[NamedNode]| {
+        vertices
             .iter()
             .filter(|v| !s.contains(v) && !remove_verts.contains(v))
             .cloned()
-            .collect_vec();
-        let mut result = best_partition_rec(&verts);
-        result.push(s.clone());
-        if result.len() >= acc.len() {
-            result
-        } else {
-            acc
+            .collect_vec()
+    };
+    // Every further zero-sum subset needs a person of each sign, so a branch can't take more
+    // subsets than people of the rarer sign are left. Branches, which can't reach `need` or the
+    // subsets of the best branch so far, are pruned. Ties aren't pruned, so the result is the
+    // same as without pruning, if it has at least `need` many subsets.
+    let best_so_far = AtomicUsize::new(0);
+    let explore = |s: &[NamedNode], search: Option<&mut SearchTrace>| {
+        let verts = remaining(s);
+        let positive = verts.iter().filter(|v| v.weight > 0).count();
+        let upper = 1 + positive.min(verts.len() - positive);
+        let threshold = need.max(best_branching.len() + best_so_far.load(Ordering::Relaxed));
+        if best_branching.len() + upper < threshold {
+            trace!("Pruning branch {:?} with at most {} subsets.", s, upper);
+            return None;
         }
-    });
+        let child_need = threshold.saturating_sub(best_branching.len() + 1);
+        let result = best_partition_rec(&verts, branches, &(), search, child_need, cancel);
+        best_so_far.fetch_max(1 + result.len(), Ordering::Relaxed);
+        Some(result)
+    };
+    let results: Vec<Option<Branch>> = match search.as_deref_mut() {
+        Some(search) => filtered_subsets
+            .iter()
+            .map(|s| {
+                let child = search.nodes.len();
+                let result = explore(s, Some(search))?;
+                let edge = node.map(|node| {
+                    let parts = best_branching.iter().chain([*s]).map(|p| names(p));
+                    search.add_edge(node, child, parts.collect())
+                });
+                Some((result, edge))
+            })
+            .collect(),
+        None => filtered_subsets
+            .par_iter()
+            .map(|s| explore(s, None).map(|result| (result, None)))
+            .collect(),
+    };
+    let (best_branch, best_edge) = filtered_subsets
+        .into_iter()
+        .zip(results)
+        .filter_map(|(s, result)| Some((s, result?)))
+        .fold((vec![], None), |acc, (s, (mut result, edge))| {
+            result.push(s.clone());
+            if result.len() > acc.0.len() && !is_cancelled(cancel) {
+                // Every partition saves one transaction.
+                observer.on_event(&SolverEvent::NewIncumbent {
+                    transactions: nonzero - best_branching.len() - result.len(),
+                });
+            }
+            if result.len() >= acc.0.len() {
+                (result, edge)
+            } else {
+                acc
+            }
+        });
     best_branching.extend(best_branch);
-    debug!("Best branching: {:?}", best_branching);
+    if let (Some(search), Some(node)) = (search, node) {
+        search.nodes[node].transactions = Some(nonzero - best_branching.len());
+        if let Some(edge) = best_edge {
+            search.edges[edge].chosen = true;
+        }
+    }
+    trace!("Best branching: {:?}", best_branching);
     best_branching
 }
 
+/// Approximate number of bytes of the subsets including the names of their vertices.
+fn subsets_bytes(subsets: &Vec<Vec<NamedNode>>) -> usize {
+    subsets.capacity() * std::mem::size_of::<Vec<NamedNode>>()
+        + subsets
+            .iter()
+            .map(|s| {
+                s.capacity() * std::mem::size_of::<NamedNode>()
+                    + s.iter().map(|v| v.name.capacity()).sum::<usize>()
+            })
+            .sum::<usize>()
+}
+
 /// Gives all subsets whose vertex weights add up to zero and no vertex with zero weight itself is
-/// contained in the subset.
+/// contained in the subset. Subsets are ordered by their size and then by the positions of
+/// their vertices like in a powerset.
+///
+/// The subsets are found by meet in the middle: the sums of all subsets of the first half of the
+/// vertices are looked up for every subset of the second half, so only the O*(2^(n/2)) subsets
+/// of the halves and the zero-sum subsets are generated. The subsets of the halves are masks of
+/// 128 bits, so more vertices with non zero weight fall back to filtering the powerset. Sums are
+/// added up in 128 bits, so they don't overflow for any weights.
 fn zero_sum_subsets(vertices: &[NamedNode]) -> Vec<Vec<NamedNode>> {
-    vertices
-        .iter()
-        .powerset()
-        .filter(|s| s.iter().map(|n| n.weight).sum::<i64>() == 0 && s.iter().all(|v| v.weight != 0))
-        .map(|s| s.into_iter().cloned().collect_vec())
+    let nonzero = vertices.iter().filter(|v| v.weight != 0).collect_vec();
+    if nonzero.len() > u128::BITS as usize {
+        return nonzero
+            .into_iter()
+            .powerset()
+            .filter(|s| s.iter().map(|v| v.weight as i128).sum::<i128>() == 0)
+            .map(|s| s.into_iter().cloned().collect_vec())
+            .collect_vec();
+    }
+    let (first, second) = nonzero.split_at(nonzero.len() / 2);
+    let mut by_sum: HashMap<i128, Vec<u128>> = HashMap::new();
+    for (sum, mask) in subset_sums(first, 0) {
+        by_sum.entry(sum).or_default().push(mask);
+    }
+    subset_sums(second, first.len())
+        .into_iter()
+        .flat_map(|(sum, mask)| {
+            by_sum
+                .get(&-sum)
+                .into_iter()
+                .flatten()
+                .map(move |first_mask| first_mask | mask)
+        })
+        .map(|mask| {
+            (0..nonzero.len())
+                .filter(|i| mask >> i & 1 == 1)
+                .collect_vec()
+        })
+        .sorted_by(|a, b| a.len().cmp(&b.len()).then_with(|| a.cmp(b)))
+        .map(|indices| {
+            indices
+                .into_iter()
+                .map(|i| nonzero[i].clone())
+                .collect_vec()
+        })
         .collect_vec()
 }
 
+/// Sums of the weights of all subsets of the vertices with the subsets as masks. The bit of a
+/// vertex is its position plus `offset`, which must stay below 128. The sums of at most 128
+/// weights fit into 128 bits, but overflowing sums are skipped nonetheless.
+fn subset_sums(vertices: &[&NamedNode], offset: usize) -> Vec<(i128, u128)> {
+    let mut sums: Vec<(i128, u128)> = vec![(0, 0)];
+    for (i, v) in vertices.iter().enumerate() {
+        let bit = 1 << (i + offset);
+        sums.extend(
+            sums.iter()
+                .filter_map(|(sum, mask)| Some((sum.checked_add(v.weight as i128)?, mask | bit)))
+                .collect_vec(),
+        );
+    }
+    sums
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::approximation::star_expand;
+    use crate::approximation::{greedy_satisfaction, star_expand};
     use crate::graph::Graph;
-    use crate::probleminstance::ProblemInstance;
-    use crate::tree_bases::best_partition;
-    use env_logger::Env;
-    use log::debug;
+    use crate::probleminstance::{Cancel, ProblemInstance};
+    use crate::tree_bases::{best_partition, zero_sum_subsets};
+    use itertools::Itertools;
+    use tracing::debug;
+    use tracing_subscriber::EnvFilter;
 
     fn init() {
-        let _ = env_logger::Builder::from_env(Env::default().default_filter_or("debug"))
-            .is_test(true)
+        let _ = tracing_subscriber::fmt()
+            .with_env_filter(
+                EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("debug")),
+            )
+            .with_test_writer()
             .try_init();
     }
 
@@ -146,7 +383,7 @@ mod tests {
         let graph: Graph = vec![-1, -1, 1, 1, 2, -2, 3, -3].into();
         debug!("Using graph: {:?}", graph);
         let instance = ProblemInstance::from(graph);
-        let sol = best_partition(&instance, &star_expand);
+        let sol = best_partition(&instance, &star_expand, &(), Cancel::default());
         assert!(sol.is_some());
         debug!("Proposed solution by solver: {:?}", sol);
         assert_eq!(sol.unwrap().len(), 4);
@@ -154,13 +391,13 @@ mod tests {
         let graph: Graph = vec![-2, -1, 1, 1, 2, -2, 3, -3].into();
         debug!("Using graph: {:?}", graph);
         let instance = ProblemInstance::from(graph);
-        let sol = best_partition(&instance, &star_expand);
+        let sol = best_partition(&instance, &star_expand, &(), Cancel::default());
         assert!(sol.is_none());
 
         let graph: Graph = vec![6, 3, 2, 1, -4, -8].into();
         debug!("Using graph: {:?}", graph);
         let instance = ProblemInstance::from(graph);
-        let sol = best_partition(&instance, &star_expand);
+        let sol = best_partition(&instance, &star_expand, &(), Cancel::default());
         assert!(sol.is_some());
         debug!("Proposed solution by solver: {:?}", sol);
         assert_eq!(sol.unwrap().len(), 4);
@@ -168,7 +405,7 @@ mod tests {
         let graph: Graph = vec![6, 3, 2, 1, -4, -8, 0].into();
         debug!("Using graph: {:?}", graph);
         let instance = ProblemInstance::from(graph);
-        let sol = best_partition(&instance, &star_expand);
+        let sol = best_partition(&instance, &star_expand, &(), Cancel::default());
         assert!(sol.is_some());
         debug!("Proposed solution by solver: {:?}", sol);
         assert_eq!(sol.unwrap().len(), 4);
@@ -176,7 +413,7 @@ mod tests {
         let graph: Graph = vec![1, 1, 1, 1, 1, 1, -6].into();
         debug!("Using graph: {:?}", graph);
         let instance = ProblemInstance::from(graph);
-        let sol = best_partition(&instance, &star_expand);
+        let sol = best_partition(&instance, &star_expand, &(), Cancel::default());
         assert!(sol.is_some());
         debug!("Proposed solution by solver: {:?}", sol);
         assert_eq!(sol.unwrap().len(), 6);
@@ -184,9 +421,54 @@ mod tests {
         let graph: Graph = vec![9, 4, 1, -6, -6, -2].into();
         debug!("Using graph: {:?}", graph);
         let instance = ProblemInstance::from(graph);
-        let sol = best_partition(&instance, &star_expand);
+        let sol = best_partition(&instance, &star_expand, &(), Cancel::default());
         assert!(sol.is_some());
         debug!("Proposed solution by solver: {:?}", sol);
         assert_eq!(sol.unwrap().len(), 5);
+
+        // The solution doesn't depend on the number of threads.
+        let graph: Graph = vec![5, 4, 3, 2, 1, -1, -2, -3, -4, -5].into();
+        let instance = ProblemInstance::from(graph);
+        let single = rayon::ThreadPoolBuilder::new()
+            .num_threads(1)
+            .build()
+            .unwrap()
+            .install(|| best_partition(&instance, &greedy_satisfaction, &(), Cancel::default()));
+        let parallel = best_partition(&instance, &greedy_satisfaction, &(), Cancel::default());
+        assert_eq!(single, parallel);
+        assert_eq!(parallel.unwrap().len(), 5);
+
+        // Without opposite pairs, the branches are pruned by the people left.
+        let graph: Graph = vec![1, 2, -3, 1, 2, -3, 4, 5, -9, 4, 5, -9].into();
+        let instance = ProblemInstance::from(graph);
+        let sol = best_partition(&instance, &greedy_satisfaction, &(), Cancel::default());
+        assert_eq!(sol.unwrap().len(), 8);
+    }
+
+    #[test]
+    fn test_zero_sum_subsets() {
+        init();
+        debug!("Running 'test_zero_sum_subsets'");
+        for weights in [
+            vec![],
+            vec![1, -1],
+            vec![3, -1, 0, -2, 1, 2, -3],
+            vec![5, 4, 3, 2, 1, -1, -2, -3, -4, -5, 0],
+            // Sums of the halves, which overflow 64 bits.
+            vec![i64::MAX, i64::MAX, -i64::MAX, 1, -i64::MAX, -1],
+        ] {
+            let graph: Graph = weights.into();
+            let powerset = graph
+                .vertices
+                .iter()
+                .powerset()
+                .filter(|s| {
+                    s.iter().map(|n| n.weight as i128).sum::<i128>() == 0
+                        && s.iter().all(|v| v.weight != 0)
+                })
+                .map(|s| s.into_iter().cloned().collect_vec())
+                .collect_vec();
+            assert_eq!(zero_sum_subsets(&graph.vertices), powerset);
+        }
     }
 }
@@ -0,0 +1,219 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::Display;
+use std::str::FromStr;
+
+use crate::graph::{Debt, Graph, Metadata};
+
+/// Exact factor converting amounts between units, e.g. 100 from euros to cents or 0.01 from
+/// cents to euros. The factor is kept as fraction, so no precision is lost.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Scale {
+    numerator: i64,
+    denominator: i64,
+}
+
+fn gcd(a: i64, b: i64) -> i64 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+/// Parses a positive decimal number like '100', '0.01' or '2.5'.
+impl FromStr for Scale {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Expected a positive decimal number, got '{}'.", s);
+        let (integer, fraction) = s.trim().split_once('.').unwrap_or((s.trim(), ""));
+        if integer.is_empty() && fraction.is_empty()
+            || !integer
+                .chars()
+                .chain(fraction.chars())
+                .all(|c| c.is_ascii_digit())
+        {
+            return Err(invalid());
+        }
+        let denominator = u32::try_from(fraction.len())
+            .ok()
+            .and_then(|digits| 10_i64.checked_pow(digits))
+            .ok_or_else(invalid)?;
+        let numerator: i64 = format!("{}{}", integer, fraction)
+            .parse()
+            .map_err(|_| invalid())?;
+        if numerator == 0 {
+            return Err(invalid());
+        }
+        let divisor = gcd(numerator, denominator);
+        Ok(Scale {
+            numerator: numerator / divisor,
+            denominator: denominator / divisor,
+        })
+    }
+}
+
+impl Display for Scale {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.denominator == 1 {
+            write!(f, "{}", self.numerator)
+        } else {
+            write!(f, "{}/{}", self.numerator, self.denominator)
+        }
+    }
+}
+
+impl Scale {
+    /// Multiplies the amount by the factor. Fails if the result is no whole amount or
+    /// overflows.
+    ///
+    /// Example:
+    /// ```
+    /// use payback::units::Scale;
+    ///
+    /// let scale: Scale = "0.01".parse().unwrap();
+    /// assert_eq!(scale.apply(1500), Ok(15));
+    /// assert!(scale.apply(1550).is_err());
+    /// ```
+    pub fn apply(&self, amount: i64) -> Result<i64, String> {
+        let scaled = amount
+            .checked_mul(self.numerator)
+            .ok_or(format!("{} times {} is too large.", amount, self))?;
+        if scaled % self.denominator != 0 {
+            return Err(format!("{} times {} is no whole amount.", amount, self));
+        }
+        Ok(scaled / self.denominator)
+    }
+
+    /// Multiplies the weights and debts of the graph by the factor. The metadata is kept.
+    pub fn scale_graph(&self, graph: &Graph) -> Result<Graph, String> {
+        let balances = graph
+            .balances()
+            .into_iter()
+            .map(|(name, weight)| {
+                let weight = self
+                    .apply(weight)
+                    .map_err(|e| format!("Can't scale the amount of {:?}: {}", name, e))?;
+                Ok((name, weight))
+            })
+            .collect::<Result<Vec<(String, i64)>, String>>()?;
+        let scaled = Graph::from(balances).with_metadata(graph.metadata.to_owned());
+        Ok(match graph.debts() {
+            Some(debts) => scaled.with_debts(
+                debts
+                    .iter()
+                    .map(|d| {
+                        Ok(Debt {
+                            amount: self.apply(d.amount)?,
+                            ..d.to_owned()
+                        })
+                    })
+                    .collect::<Result<Vec<Debt>, String>>()?,
+            ),
+            None => scaled,
+        })
+    }
+}
+
+/// Unit of the amounts of an instance, e.g. 'hours' or 'points' for balances, which aren't
+/// money. It is given by the metadata 'unit=<LABEL>' of the people or by `default`. People
+/// without a unit have the one of the others. Fails if different units are mixed.
+///
+/// Example:
+/// ```
+/// use payback::metadata::split_metadata;
+/// use payback::units::instance_unit;
+///
+/// let (_, metadata) = split_metadata("A,-2,unit=hours\nB,2").unwrap();
+/// assert_eq!(instance_unit(&metadata, None), Ok(Some("hours".to_string())));
+/// assert!(instance_unit(&metadata, Some("points")).is_err());
+/// ```
+pub fn instance_unit(
+    metadata: &HashMap<String, Metadata>,
+    default: Option<&str>,
+) -> Result<Option<String>, String> {
+    let units: BTreeSet<&str> = metadata
+        .values()
+        .flatten()
+        .filter(|(key, _)| key.eq_ignore_ascii_case("unit"))
+        .map(|(_, unit)| unit.as_str())
+        .chain(default)
+        .filter(|unit| !unit.is_empty())
+        .collect();
+    match units.len() {
+        0 => Ok(None),
+        1 => Ok(units.first().map(|unit| unit.to_string())),
+        _ => Err(format!(
+            "The amounts have different units: {}",
+            units.into_iter().collect::<Vec<&str>>().join(", ")
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::graph::Graph;
+    use crate::metadata::split_metadata;
+    use crate::units::{instance_unit, Scale};
+    use std::collections::HashMap;
+    use tracing::debug;
+    use tracing_subscriber::EnvFilter;
+
+    fn init() {
+        let _ = tracing_subscriber::fmt()
+            .with_env_filter(
+                EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("debug")),
+            )
+            .with_test_writer()
+            .try_init();
+    }
+
+    #[test]
+    fn test_scale() {
+        init();
+        debug!("Running 'test_scale'");
+        let scale: Scale = "100".parse().unwrap();
+        assert_eq!(scale.apply(-15), Ok(-1500));
+        assert_eq!(
+            scale.apply(i64::MAX).unwrap_err(),
+            format!("{} times 100 is too large.", i64::MAX)
+        );
+        let scale: Scale = "2.50".parse().unwrap();
+        assert_eq!(scale.to_string(), "5/2");
+        assert_eq!(scale.apply(4), Ok(10));
+        for invalid in ["", ".", "0", "0.0", "-1", "1e2", "abc"] {
+            assert!(
+                invalid.parse::<Scale>().is_err(),
+                "'{}' is no scale",
+                invalid
+            );
+        }
+
+        let graph: Graph = "A,B,150\nB,C,300".to_string().try_into().unwrap();
+        let scaled = "0.01".parse::<Scale>().unwrap().scale_graph(&graph);
+        assert!(scaled.is_err());
+        let scaled = "0.1".parse::<Scale>().unwrap().scale_graph(&graph).unwrap();
+        debug!("Scaled graph: {}", scaled);
+        assert_eq!(
+            scaled.balances(),
+            [
+                ("A".to_string(), -15),
+                ("B".to_string(), -15),
+                ("C".to_string(), 30)
+            ]
+        );
+        assert_eq!(scaled.debts().unwrap()[1].amount, 30);
+
+        let (_, metadata) = split_metadata("A,-2,unit=hours\nB,2,unit=hours\nC,0").unwrap();
+        assert_eq!(
+            instance_unit(&metadata, Some("hours")),
+            Ok(Some("hours".to_string()))
+        );
+        let (_, metadata) = split_metadata("A,-2,unit=points\nB,2,unit=hours").unwrap();
+        assert_eq!(
+            instance_unit(&metadata, None).unwrap_err(),
+            "The amounts have different units: hours, points"
+        );
+        assert_eq!(instance_unit(&HashMap::new(), None), Ok(None));
+    }
+}
@@ -0,0 +1,158 @@
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt::Display;
+
+use crate::graph::Graph;
+use crate::probleminstance::ProblemInstance;
+
+/// A single payment of a proposed plan, in which `from` pays `amount` to `to`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Payment {
+    pub from: String,
+    pub to: String,
+    pub amount: i64,
+}
+
+/// Result of [`verify_plan()`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VerificationReport {
+    /// Number of payments in the plan.
+    pub transactions: usize,
+    /// Lower bound on the number of transactions needed to settle the instance.
+    pub lower_bound: usize,
+    /// Every person, whose balance is not settled by the plan, with the remaining amount.
+    /// Positive amounts still have to be received, negative amounts still have to be paid.
+    pub unsettled: Vec<(String, i64)>,
+}
+
+impl VerificationReport {
+    /// Checks if the plan settles all balances.
+    pub fn is_settled(&self) -> bool {
+        self.unsettled.is_empty()
+    }
+
+    /// Number of transactions the plan uses more than the lower bound.
+    pub fn gap(&self) -> usize {
+        self.transactions.saturating_sub(self.lower_bound)
+    }
+}
+
+impl Display for VerificationReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "Plan settles all balances: {}",
+            if self.is_settled() { "yes" } else { "no" }
+        )?;
+        for (name, amount) in &self.unsettled {
+            writeln!(f, "  {:?} is off by {}", name, amount)?;
+        }
+        writeln!(f, "Transactions: {}", self.transactions)?;
+        write!(f, "Lower bound: {} (gap: {})", self.lower_bound, self.gap())
+    }
+}
+
+/// Checks if the proposed plan settles all balances of the graph.
+/// Payments from or to people, which are not part of the graph, leave them unsettled.
+///
+/// * `graph` - The instance the plan should settle
+/// * `plan` - The proposed payments
+///
+/// Example:
+/// ```
+/// use payback::graph::Graph;
+/// use payback::verification::{verify_plan, Payment};
+///
+/// let graph = Graph::from(vec![("A".to_string(), -1), ("B".to_string(), 1)]);
+/// let plan = vec![Payment { from: "A".to_string(), to: "B".to_string(), amount: 1 }];
+/// assert!(verify_plan(&graph, &plan).is_settled());
+/// ```
+pub fn verify_plan(graph: &Graph, plan: &[Payment]) -> VerificationReport {
+    let mut residuals: Vec<(String, i64)> = graph
+        .vertices
+        .iter()
+        .map(|v| (v.name.to_owned(), v.weight))
+        .collect();
+    let mut index: HashMap<String, usize> = residuals
+        .iter()
+        .enumerate()
+        .map(|(i, (name, _))| (name.to_owned(), i))
+        .collect();
+    for payment in plan {
+        for (name, delta) in [
+            (&payment.from, payment.amount),
+            (&payment.to, -payment.amount),
+        ] {
+            let i = *index.entry(name.to_owned()).or_insert_with(|| {
+                residuals.push((name.to_owned(), 0));
+                residuals.len() - 1
+            });
+            residuals[i].1 = residuals[i].1.saturating_add(delta);
+        }
+    }
+    VerificationReport {
+        transactions: plan.iter().filter(|p| p.amount != 0).count(),
+        lower_bound: ProblemInstance::from(graph.to_owned()).transaction_lower_bound(),
+        unsettled: residuals.into_iter().filter(|(_, r)| *r != 0).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::graph::Graph;
+    use crate::verification::{verify_plan, Payment};
+    use tracing::debug;
+    use tracing_subscriber::EnvFilter;
+
+    fn init() {
+        let _ = tracing_subscriber::fmt()
+            .with_env_filter(
+                EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("debug")),
+            )
+            .with_test_writer()
+            .try_init();
+    }
+
+    fn payment(from: &str, to: &str, amount: i64) -> Payment {
+        Payment {
+            from: from.to_string(),
+            to: to.to_string(),
+            amount,
+        }
+    }
+
+    #[test]
+    fn test_verify_plan() {
+        init();
+        debug!("Running 'test_verify_plan'");
+        let graph: Graph = vec![
+            ("A".to_string(), -2),
+            ("B".to_string(), -1),
+            ("C".to_string(), 1),
+            ("D".to_string(), 2),
+        ]
+        .into();
+        let report = verify_plan(&graph, &[payment("A", "D", 2), payment("B", "C", 1)]);
+        debug!("Report: {}", report);
+        assert!(report.is_settled());
+        assert_eq!(report.transactions, 2);
+        assert_eq!(report.gap(), 0);
+
+        let report = verify_plan(
+            &graph,
+            &[
+                payment("A", "C", 1),
+                payment("A", "D", 1),
+                payment("B", "E", 1),
+            ],
+        );
+        debug!("Report: {}", report);
+        assert!(!report.is_settled());
+        assert_eq!(report.transactions, 3);
+        assert_eq!(report.gap(), 1);
+        assert_eq!(
+            report.unsettled,
+            vec![("D".to_string(), 1), ("E".to_string(), -1)]
+        );
+    }
+}
@@ -0,0 +1,184 @@
+use hmac::{Hmac, Mac};
+use serde_json::json;
+use sha2::Sha256;
+use std::thread::sleep;
+use std::time::Duration;
+use tracing::{debug, info};
+
+use crate::currency::CurrencyTransactions;
+use crate::provenance::Provenance;
+
+/// Header containing the hex encoded HMAC-SHA256 signature of the body.
+pub const SIGNATURE_HEADER: &str = "X-Payback-Signature";
+
+/// Endpoint to which solutions are posted as json.
+#[derive(Clone, Debug)]
+pub struct Webhook {
+    pub url: String,
+    /// Secret used to sign the body. Without one, no signature is sent.
+    pub secret: Option<String>,
+    /// Number of retries after a failed delivery.
+    pub retries: u32,
+}
+
+/// Converts transactions in the form of payer, payee and amount into a json list of objects with
+/// the keys 'from', 'to' and 'amount'. Transactions given with a currency additionally have the
+/// key 'currency'. With a provenance, the list is wrapped in an object with the keys 'provenance'
+/// and 'transactions'. Amounts in minor units with `decimals` many decimal places are written as
+/// decimal numbers.
+pub fn transactions_json(
+    transactions: &[CurrencyTransactions],
+    decimals: u32,
+    provenance: Option<&Provenance>,
+) -> String {
+    let list = json!(transactions
+        .iter()
+        .flat_map(|(currency, ts)| ts.iter().map(move |(from, to, amount)| {
+            let amount = match decimals {
+                0 => json!(amount),
+                _ => json!(*amount as f64 / 10_f64.powi(decimals as i32)),
+            };
+            let mut t = json!({"from": from, "to": to, "amount": amount});
+            if let Some(c) = currency {
+                t["currency"] = json!(c);
+            }
+            t
+        }))
+        .collect::<Vec<_>>());
+    match provenance {
+        Some(p) => json!({"provenance": p.to_json(), "transactions": list}),
+        None => list,
+    }
+    .to_string()
+}
+
+/// Signs the body with the secret and returns it in the form 'sha256=<hex>'.
+pub fn signature(secret: &str, body: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size.");
+    mac.update(body.as_bytes());
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+impl Webhook {
+    /// Posts the json body to the url. Failed deliveries are retried with an exponential backoff
+    /// starting at half a second. Client errors are not retried.
+    pub fn post(&self, body: &str) -> Result<(), String> {
+        let mut request = ureq::post(&self.url).set("Content-Type", "application/json");
+        if let Some(secret) = &self.secret {
+            request = request.set(SIGNATURE_HEADER, &signature(secret, body));
+        }
+        let mut attempt = 0;
+        loop {
+            let err = match request.clone().send_string(body) {
+                Ok(response) => {
+                    info!("Posted result to {} ({})", self.url, response.status());
+                    return Ok(());
+                }
+                Err(ureq::Error::Status(code, _)) if code < 500 => {
+                    return Err(format!(
+                        "Webhook {} rejected the result: {}",
+                        self.url, code
+                    ));
+                }
+                Err(e) => e,
+            };
+            if attempt >= self.retries {
+                return Err(format!("Unable to post result to {}: {}", self.url, err));
+            }
+            debug!("Posting result failed with '{}'. Retrying.", err);
+            sleep(Duration::from_millis(500 << attempt.min(10)));
+            attempt += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::probleminstance::SolvingMethods;
+    use crate::provenance::Provenance;
+    use crate::webhook::{signature, transactions_json, Webhook};
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+    use tracing::debug;
+    use tracing_subscriber::EnvFilter;
+
+    fn init() {
+        let _ = tracing_subscriber::fmt()
+            .with_env_filter(
+                EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("debug")),
+            )
+            .with_test_writer()
+            .try_init();
+    }
+
+    #[test]
+    fn test_webhook() {
+        init();
+        debug!("Running 'test_webhook'");
+        // Test case 2 of RFC 4231.
+        assert_eq!(
+            signature("Jefe", "what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        let transactions = [
+            (None, vec![("A".to_string(), "B".to_string(), 2)]),
+            (
+                Some("EUR".to_string()),
+                vec![("B".to_string(), "C".to_string(), 1)],
+            ),
+        ];
+        let body = transactions_json(&transactions, 0, None);
+        assert_eq!(
+            body,
+            r#"[{"amount":2,"from":"A","to":"B"},{"amount":1,"currency":"EUR","from":"B","to":"C"}]"#
+        );
+        let provenance = Provenance::new(SolvingMethods::DPStarExpand, b"");
+        let wrapped: serde_json::Value =
+            serde_json::from_str(&transactions_json(&transactions, 0, Some(&provenance))).unwrap();
+        assert_eq!(wrapped["provenance"]["method"], "dp-star-expand");
+        assert_eq!(wrapped["transactions"].to_string(), body);
+        assert_eq!(
+            transactions_json(&transactions[..1], 2, None),
+            r#"[{"amount":0.02,"from":"A","to":"B"}]"#
+        );
+
+        // Server failing once before accepting the result.
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let expected = body.clone();
+        let server = thread::spawn(move || {
+            let mut requests = vec![];
+            for status in ["503 Service Unavailable", "200 OK"] {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = String::new();
+                let mut buf = [0; 4096];
+                while !request.ends_with(&expected) {
+                    let n = stream.read(&mut buf).unwrap();
+                    request += &String::from_utf8_lossy(&buf[..n]);
+                }
+                requests.push(request);
+                write!(
+                    stream,
+                    "HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    status
+                )
+                .unwrap();
+            }
+            requests
+        });
+        let webhook = Webhook {
+            url,
+            secret: Some("secret".to_string()),
+            retries: 1,
+        };
+        assert!(webhook.post(&body).is_ok());
+        let requests = server.join().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert!(requests[1].to_lowercase().contains(&format!(
+            "x-payback-signature: {}",
+            signature("secret", &body)
+        )));
+    }
+}
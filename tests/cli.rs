@@ -0,0 +1,39 @@
+//! Tests of the payback binary, which need their own process, e.g. to set environment variables
+//! without racing the other tests.
+use std::path::Path;
+use std::process::Command;
+
+/// Output of `payback anonymize` of the file with the arguments and the given PAYBACK_SEED.
+fn anonymize(file: &Path, args: &[&str], seed: Option<&str>) -> String {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_payback"));
+    command.arg("anonymize").arg(file).args(args);
+    match seed {
+        Some(seed) => command.env("PAYBACK_SEED", seed),
+        None => command.env_remove("PAYBACK_SEED"),
+    };
+    let output = command.output().unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn test_anonymize_seed_from_env() {
+    let path = std::env::temp_dir().join(format!("payback-anonymize-{}", std::process::id()));
+    std::fs::write(&path, "Alice,-3\nBob,1\nCarol,2\n").unwrap();
+    // A seed exported for the solver is ignored without --perturb, but perturbs the weights like
+    // --seed with it, unless --seed overrides it.
+    let out = anonymize(&path, &[], Some("7"));
+    assert_eq!(out, anonymize(&path, &[], None));
+    assert!(!out.contains("Alice"));
+    let seeded = |seed| anonymize(&path, &["--perturb", "5", "--seed", seed], None);
+    assert_eq!(
+        anonymize(&path, &["--perturb", "5"], Some("7")),
+        seeded("7")
+    );
+    assert_eq!(
+        anonymize(&path, &["--perturb", "5", "--seed", "8"], Some("7")),
+        seeded("8")
+    );
+    assert_ne!(seeded("7"), seeded("8"));
+    std::fs::remove_file(&path).unwrap();
+}